@@ -3,6 +3,7 @@ extern crate test;
 
 extern crate jail;
 
+use jail::param;
 use jail::process::Jailed;
 use jail::{RunningJail, StoppedJail};
 use std::process::Command;
@@ -65,6 +66,16 @@ fn start_echo_helloworld_stop(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn start_stop_100_jails(b: &mut Bencher) {
+    let stopped = StoppedJail::new("/rescue");
+    b.iter(|| {
+        for running in stopped.start_many(100, "benchjail_many_{}").unwrap() {
+            running.kill().unwrap();
+        }
+    });
+}
+
 #[bench]
 fn echo_helloworld_jailed(b: &mut Bencher) {
     let running = StoppedJail::new("/rescue").start().unwrap();
@@ -99,6 +110,17 @@ fn get_ips(b: &mut Bencher) {
     running.kill().unwrap();
 }
 
+#[bench]
+fn get_ips_fast(b: &mut Bencher) {
+    let running = StoppedJail::new("/rescue")
+        .ip("127.0.1.1".parse().unwrap())
+        .ip("fe80::2".parse().unwrap())
+        .start()
+        .unwrap();
+    b.iter(|| running.ips_fast().unwrap());
+    running.kill().unwrap();
+}
+
 #[bench]
 fn get_params(b: &mut Bencher) {
     let running = StoppedJail::new("/rescue").start().unwrap();
@@ -106,6 +128,21 @@ fn get_params(b: &mut Bencher) {
     running.kill().unwrap();
 }
 
+#[bench]
+fn get_param(b: &mut Bencher) {
+    let running = StoppedJail::new("/rescue").start().unwrap();
+    b.iter(|| param::get(running.jid, "host.hostuuid").unwrap());
+    running.kill().unwrap();
+}
+
+#[bench]
+fn get_param_into(b: &mut Bencher) {
+    let running = StoppedJail::new("/rescue").start().unwrap();
+    let mut buf = param::Buffer::new();
+    b.iter(|| param::get_into(running.jid, "host.hostuuid", &mut buf).unwrap());
+    running.kill().unwrap();
+}
+
 #[bench]
 fn save(b: &mut Bencher) {
     let running = StoppedJail::new("/rescue").start().unwrap();
@@ -116,14 +153,9 @@ fn save(b: &mut Bencher) {
 #[bench]
 fn iterate_100_jails(b: &mut Bencher) {
     // create 100 jails to iterate over
-    let mut running_jails: Vec<RunningJail> = (1..100)
-        .map(|i| {
-            StoppedJail::new("/rescue")
-                .name(format!("benchjail_iterate_{}", i))
-                .start()
-                .expect("failed to start jail")
-        })
-        .collect();
+    let mut running_jails: Vec<RunningJail> = StoppedJail::new("/rescue")
+        .start_many(100, "benchjail_iterate_{}")
+        .expect("failed to start jails");
 
     b.iter(|| {
         for running in RunningJail::all() {
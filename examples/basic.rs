@@ -15,7 +15,7 @@ fn main() {
             param::Value::String("FreeBSD 42.23".to_string()),
         )
         .param("allow.raw_sockets", param::Value::Int(1))
-        .param("allow.sysvipc", param::Value::Int(1));
+        .sysvipc(param::SysVIpcMode::Inherit);
 
     stopped.hostname = Some("testjail.example.org".to_string());
 
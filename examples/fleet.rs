@@ -0,0 +1,57 @@
+use cli_table::{print_stdout, Table, WithTitle};
+use jail::report::{self, SortBy};
+
+#[derive(Table)]
+struct Row {
+    #[table(title = "JID")]
+    jid: i32,
+
+    #[table(title = "Name")]
+    name: String,
+
+    #[table(title = "Memory")]
+    memory: String,
+
+    #[table(title = "CPU %")]
+    cpu: String,
+
+    #[table(title = "Processes")]
+    processes: String,
+
+    #[table(title = "Max. Utilization")]
+    utilization: String,
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let fleet = report::fleet(SortBy::MaxUtilization).expect("could not sample jails");
+
+    let rows: Vec<Row> = fleet
+        .iter()
+        .map(|jail| {
+            let usage = |resource| {
+                jail.get(resource)
+                    .map(|usage| match usage.limit {
+                        Some(limit) => format!("{} / {}", usage.usage, limit),
+                        None => usage.usage.to_string(),
+                    })
+                    .unwrap_or_default()
+            };
+
+            Row {
+                jid: jail.jid,
+                name: jail.name.clone(),
+                memory: usage(rctl::Resource::MemoryUse),
+                cpu: usage(rctl::Resource::PercentCpu),
+                processes: usage(rctl::Resource::MaxProcesses),
+                utilization: jail
+                    .max_utilization()
+                    .map(|u| format!("{:.0}%", u * 100.0))
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    print_stdout(rows.with_title()).unwrap();
+}
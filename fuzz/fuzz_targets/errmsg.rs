@@ -0,0 +1,11 @@
+#![no_main]
+use jail::fuzzing::parse_errmsg;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(message) = parse_errmsg(data) {
+        assert!(!message.text().is_empty());
+        assert!(!message.text().contains('\0'));
+        assert!(message.text().len() <= data.len() * 3);
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use jail::fuzzing::parse_jail_conf;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        let _ = parse_jail_conf(input);
+    }
+});
@@ -0,0 +1,25 @@
+#![no_main]
+use jail::fuzzing::{decode, encode, TYPES};
+use libfuzzer_sys::fuzz_target;
+
+// The first byte selects the parameter type, the rest is the buffer returned
+// by the kernel. Decoding must never panic, and whatever decodes must survive
+// a round trip through the encoder.
+fuzz_target!(|data: &[u8]| {
+    let (selector, bytes) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let ty = TYPES[*selector as usize % TYPES.len()];
+
+    let value = match decode(ty, bytes) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let encoded = encode(&value).expect("decoded value does not encode");
+    assert_eq!(
+        decode(ty, &encoded).expect("encoded value does not decode"),
+        value
+    );
+});
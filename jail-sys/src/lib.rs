@@ -0,0 +1,582 @@
+//! Raw FreeBSD jail system calls
+//!
+//! This crate is the system call layer of the
+//! [jail](https://crates.io/crates/jail) crate: it builds the `iovec`s for
+//! `jail_set`(2) and `jail_get`(2), and wraps `jail_attach`(2) and
+//! `jail_remove`(2). It only depends on `libc` and `bitflags` and does not use
+//! `std`, so that minimal binaries, such as an init process running inside a
+//! jail, can link it without the rest of the library.
+//!
+//! Parameter values are passed as raw bytes. Typed parameters are provided by
+//! the `jail` crate, which re-exports this crate as `jail::jail_sys`.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::ffi::{CString, NulError};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::fmt;
+use core::mem;
+
+#[doc(hidden)]
+pub use libc as __libc;
+
+/// Build `libc::iovec`s for `jail_set`(2) and `jail_get`(2).
+///
+/// * `iovec!(key => value)` builds the name and value iovecs of a parameter
+///   from byte slices or vectors, and `iovec!(key => mut value)` does so for a
+///   value that is written to by `jail_get`(2).
+/// * `iovec!(key => (pointer, size))` passes a raw value, e.g. an integer.
+/// * `iovec!(key => ())` passes a parameter without a value, e.g. `persist`.
+/// * `iovec!(value)`, `iovec!(mut value)` and `iovec!(pointer, size)` build a
+///   single iovec.
+///
+/// Parameter names must be nul terminated.
+///
+/// # Examples
+///
+/// ```
+/// use jail_sys::iovec;
+///
+/// let jid: i32 = 1;
+/// let jiov: Vec<libc::iovec> = vec![
+///     iovec!(b"jid\0" => (&jid as *const _, std::mem::size_of::<i32>())),
+///     iovec!(b"persist\0" => ()),
+/// ]
+/// .into_iter()
+/// .flatten()
+/// .collect();
+///
+/// assert_eq!(jiov.len(), 4);
+/// ```
+#[macro_export]
+macro_rules! iovec {
+    ($key:expr => ($value:expr, $size:expr)) => {
+        $crate::__vec![$crate::iovec!($key), $crate::iovec!($value, $size)]
+    };
+    ($key:expr => ()) => {
+        $crate::__vec![$crate::iovec!($key), $crate::iovec!()]
+    };
+    ($key:expr => $value:expr) => {
+        $crate::__vec![$crate::iovec!($key), $crate::iovec!($value)]
+    };
+    ($key:expr => mut $value:expr) => {
+        $crate::__vec![$crate::iovec!($key), $crate::iovec!(mut $value)]
+    };
+    ($value:expr, $size:expr) => {
+        $crate::__libc::iovec {
+            iov_base: $value as *mut $crate::__libc::c_void,
+            iov_len: $size,
+        }
+    };
+    ($name:expr) => {
+        $crate::iovec!($name.as_ptr(), $name.len())
+    };
+    (mut $name:expr) => {
+        $crate::iovec!($name.as_mut_ptr(), $name.len())
+    };
+    () => {
+        $crate::iovec!(::core::ptr::null::<$crate::__libc::c_void>(), 0)
+    };
+}
+
+#[doc(hidden)]
+pub use alloc::vec as __vec;
+
+bitflags! {
+    pub struct JailFlags : i32 {
+        /// Create the Jail if it doesn't exist
+        const CREATE = 0x01;
+
+        /// Update parameters of existing Jail
+        const UPDATE = 0x02;
+
+        /// Attach to Jail upon creation
+        const ATTACH = 0x04;
+
+        /// Allow getting a dying jail
+        const DYING = 0x08;
+    }
+}
+
+/// The initial size of the `errmsg` buffer passed to the kernel.
+pub const ERRMSG_LEN: usize = 256;
+
+/// The size up to which the `errmsg` buffer is grown when a message fills
+/// it. Longer messages are returned [truncated](ErrorMessage::is_truncated).
+pub const ERRMSG_MAX: usize = 4096;
+
+/// An error message returned by the kernel in the `errmsg` parameter.
+///
+/// # Examples
+///
+/// ```
+/// use jail_sys::ErrorMessage;
+///
+/// let message = ErrorMessage::new("unknown parameter: allow.bogu", true);
+/// assert!(message.is_truncated());
+/// assert!(message.starts_with("unknown parameter"));
+/// assert_eq!(message.to_string(), "unknown parameter: allow.bogu... (truncated)");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorMessage {
+    text: Box<str>,
+    truncated: bool,
+}
+
+impl ErrorMessage {
+    /// Create a message, marking whether it was cut off by the size of the
+    /// buffer.
+    pub fn new<S: Into<Box<str>>>(text: S, truncated: bool) -> Self {
+        ErrorMessage {
+            text: text.into(),
+            truncated,
+        }
+    }
+
+    /// Decode the message the kernel wrote to an `errmsg` buffer, or `None`
+    /// if it is empty.
+    ///
+    /// The kernel always nul terminates the message, so a message filling the
+    /// whole buffer may have been cut off.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail_sys::ErrorMessage;
+    ///
+    /// let message = ErrorMessage::from_buffer(b"jail 3 not found\0\0\0").unwrap();
+    /// assert_eq!(message.text(), "jail 3 not found");
+    /// assert!(!message.is_truncated());
+    ///
+    /// assert!(ErrorMessage::from_buffer(b"cut off\0").unwrap().is_truncated());
+    /// assert_eq!(ErrorMessage::from_buffer(&[0; 8]), None);
+    /// ```
+    pub fn from_buffer(errmsg: &[u8]) -> Option<Self> {
+        let end = errmsg.iter().position(|b| *b == 0).unwrap_or(errmsg.len());
+        if end == 0 {
+            return None;
+        }
+
+        let truncated = end + 1 >= errmsg.len();
+        Some(ErrorMessage::new(
+            String::from_utf8_lossy(&errmsg[..end]),
+            truncated,
+        ))
+    }
+
+    /// The text of the message, without the truncation marker.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the message did not fit in the `errmsg` buffer and was cut
+    /// off.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl core::ops::Deref for ErrorMessage {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.truncated {
+            true => write!(f, "{}... (truncated)", self.text),
+            false => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// An error returned by a jail system call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The system call failed with the given `errno`.
+    Errno(i32),
+
+    /// `jail_set`(2) failed with the given error message.
+    JailSet(ErrorMessage),
+
+    /// `jail_get`(2) failed with the given error message.
+    JailGet(ErrorMessage),
+
+    /// A string passed to the kernel contained a nul byte.
+    Nul(NulError),
+
+    /// The system call returned an unexpected value.
+    UnexpectedReturn(i32),
+}
+
+impl Error {
+    /// Whether the error carries a message that was cut off by the size of
+    /// the `errmsg` buffer.
+    pub fn is_truncated(&self) -> bool {
+        match self {
+            Error::JailSet(message) | Error::JailGet(message) => message.is_truncated(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Errno(errno) => write!(f, "system call failed with errno {}", errno),
+            Error::JailSet(message) => write!(f, "jail_set failed: {}", message),
+            Error::JailGet(message) => write!(f, "jail_get failed: {}", message),
+            Error::Nul(e) => write!(f, "invalid string: {}", e),
+            Error::UnexpectedReturn(ret) => write!(f, "unexpected return value {}", ret),
+        }
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(e: NulError) -> Self {
+        Error::Nul(e)
+    }
+}
+
+/// Get the `errno` of the last failed system call.
+#[cfg(target_os = "freebsd")]
+fn errno() -> i32 {
+    unsafe { *libc::__error() }
+}
+
+/// Call `jail_get`(2) or `jail_set`(2) with the given iovecs and an `errmsg`
+/// buffer.
+///
+/// If the message fills the buffer, the call is repeated with a larger one,
+/// up to [ERRMSG_MAX] bytes. Failed calls have no effect, so this is safe for
+/// both system calls.
+#[cfg(target_os = "freebsd")]
+unsafe fn call(set: bool, jiov: &mut Vec<libc::iovec>, flags: JailFlags) -> Result<i32, Error> {
+    let mut errmsg: [u8; ERRMSG_LEN] = [0; ERRMSG_LEN];
+
+    let base_len = jiov.len();
+    jiov.extend(iovec!(b"errmsg\0" => mut errmsg));
+
+    let mut ret = call_buffered(set, jiov, &mut errmsg, flags);
+
+    let mut len = ERRMSG_LEN;
+    while len < ERRMSG_MAX && ret.as_ref().is_err_and(Error::is_truncated) {
+        len = (len * 4).min(ERRMSG_MAX);
+        let mut errmsg: Vec<u8> = vec![0; len];
+        ret = call_buffered(set, jiov, &mut errmsg, flags);
+    }
+
+    // Drop the iovecs pointing to errmsg before it goes out of scope.
+    jiov.truncate(base_len);
+
+    ret
+}
+
+/// Call `jail_get`(2) or `jail_set`(2), pointing the last two iovecs to the
+/// `errmsg` buffer.
+///
+/// Returns the `errno` on failure, leaving the message in `errmsg`
+/// undecoded, so callers only interested in success do not allocate.
+#[cfg(target_os = "freebsd")]
+unsafe fn syscall(
+    set: bool,
+    jiov: &mut [libc::iovec],
+    errmsg: &mut [u8],
+    flags: JailFlags,
+) -> Result<i32, i32> {
+    let len = jiov.len();
+    assert!(len >= 2, "no iovecs reserved for errmsg");
+
+    if let Some(first) = errmsg.first_mut() {
+        *first = 0;
+    }
+    jiov[len - 2] = iovec!(b"errmsg\0");
+    jiov[len - 1] = iovec!(mut errmsg);
+
+    let jid = match set {
+        true => libc::jail_set(jiov.as_mut_ptr(), len as u32, flags.bits),
+        false => libc::jail_get(jiov.as_mut_ptr(), len as u32, flags.bits),
+    };
+
+    match jid {
+        jid if jid >= 0 => Ok(jid),
+        _ => Err(errno()),
+    }
+}
+
+/// Turn a failed system call into an [Error], decoding the message in
+/// `errmsg` if the kernel set one.
+#[cfg(target_os = "freebsd")]
+fn failure(set: bool, errno: i32, errmsg: &[u8]) -> Error {
+    match (ErrorMessage::from_buffer(errmsg), set) {
+        (None, _) => Error::Errno(errno),
+        (Some(message), true) => Error::JailSet(message),
+        (Some(message), false) => Error::JailGet(message),
+    }
+}
+
+/// Call `jail_get`(2) or `jail_set`(2), pointing the last two iovecs to the
+/// `errmsg` buffer.
+#[cfg(target_os = "freebsd")]
+unsafe fn call_buffered(
+    set: bool,
+    jiov: &mut [libc::iovec],
+    errmsg: &mut [u8],
+    flags: JailFlags,
+) -> Result<i32, Error> {
+    syscall(set, jiov, errmsg, flags).map_err(|errno| failure(set, errno, errmsg))
+}
+
+/// Call `jail_set`(2) with the given iovecs, returning the `jid` of the
+/// created or updated jail.
+///
+/// An `errmsg` parameter is added to the iovecs for the call, and its content
+/// is returned as [Error::JailSet].
+///
+/// # Safety
+///
+/// The iovecs must point to valid memory of the given lengths for the
+/// duration of the call, and writable memory for values returned by the
+/// kernel.
+#[cfg(target_os = "freebsd")]
+pub unsafe fn jail_set(jiov: &mut Vec<libc::iovec>, flags: JailFlags) -> Result<i32, Error> {
+    call(true, jiov, flags)
+}
+
+/// Call `jail_get`(2) with the given iovecs, returning the `jid` of the jail.
+///
+/// An `errmsg` parameter is added to the iovecs for the call, and its content
+/// is returned as [Error::JailGet].
+///
+/// # Safety
+///
+/// The iovecs must point to valid memory of the given lengths for the
+/// duration of the call, and writable memory for values returned by the
+/// kernel.
+#[cfg(target_os = "freebsd")]
+pub unsafe fn jail_get(jiov: &mut Vec<libc::iovec>, flags: JailFlags) -> Result<i32, Error> {
+    call(false, jiov, flags)
+}
+
+/// Call `jail_get`(2) with a fixed set of iovecs and a caller-provided
+/// `errmsg` buffer, returning the `jid` of the jail.
+///
+/// Unlike [jail_get], this does not allocate: the last two iovecs are
+/// overwritten to point to `errmsg`, so callers polling parameters at a high
+/// frequency can keep both the iovecs and the buffers around between calls.
+/// The buffer is not grown, so messages filling it are returned
+/// [truncated](ErrorMessage::is_truncated).
+///
+/// # Safety
+///
+/// The iovecs must point to valid memory of the given lengths for the
+/// duration of the call, and writable memory for values returned by the
+/// kernel.
+///
+/// # Panics
+///
+/// Panics if fewer than two iovecs are given.
+#[cfg(target_os = "freebsd")]
+pub unsafe fn jail_get_buffered(
+    jiov: &mut [libc::iovec],
+    errmsg: &mut [u8],
+    flags: JailFlags,
+) -> Result<i32, Error> {
+    call_buffered(false, jiov, errmsg, flags)
+}
+
+/// Encoded `jail_set`(2) arguments that can be reused for creating several
+/// jails.
+///
+/// # Examples
+///
+/// ```
+/// use jail_sys::JailCreator;
+/// use std::ffi::CString;
+///
+/// let hostname = CString::new("host.hostname").unwrap();
+/// let params = vec![(hostname, b"example.org\0".to_vec())];
+///
+/// let mut creator = JailCreator::new("/rescue", params).expect("invalid path");
+/// for name in &["testjail_sys_a", "testjail_sys_b"] {
+///     let jid = creator.create(Some(name)).expect("could not create jail");
+///     jail_sys::jail_remove(jid).expect("could not remove jail");
+/// }
+/// ```
+#[cfg(target_os = "freebsd")]
+pub struct JailCreator {
+    // Note: the iovecs point into the heap buffers owned by the fields below,
+    // which are never resized after construction. They must therefore be kept
+    // alive for as long as the iovecs are used in a jail_set call.
+    #[allow(dead_code)]
+    raw_params: Vec<(CString, Vec<u8>)>,
+    #[allow(dead_code)]
+    path: CString,
+    jiov: Vec<libc::iovec>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl JailCreator {
+    /// Encode the arguments for persistent jails rooted at `path`, with the
+    /// given parameter names and encoded values.
+    pub fn new(path: &str, raw_params: Vec<(CString, Vec<u8>)>) -> Result<JailCreator, Error> {
+        let path = CString::new(path)?;
+
+        let mut jiov: Vec<libc::iovec> = raw_params
+            .iter()
+            .flat_map(|(key, value)| iovec!(key.as_bytes_with_nul() => value))
+            .collect();
+
+        jiov.extend(
+            vec![
+                iovec!(b"path\0" => path.as_bytes_with_nul()),
+                iovec!(b"persist\0" => ()),
+            ]
+            .into_iter()
+            .flatten(),
+        );
+
+        Ok(JailCreator {
+            raw_params,
+            path,
+            jiov,
+        })
+    }
+
+    /// Create a jail, optionally with the given name.
+    pub fn create(&mut self, name: Option<&str>) -> Result<i32, Error> {
+        let name = name.map(CString::new).transpose()?;
+
+        let base_len = self.jiov.len();
+        if let Some(ref name) = name {
+            self.jiov
+                .extend(iovec!(b"name\0" => name.as_bytes_with_nul()));
+        }
+
+        let jid = unsafe { jail_set(&mut self.jiov, JailFlags::CREATE) };
+
+        // Drop the iovecs pointing to the name before it goes out of scope.
+        self.jiov.truncate(base_len);
+        jid
+    }
+}
+
+/// Test if a jail with the given `jid` exists.
+#[cfg(target_os = "freebsd")]
+pub fn jail_exists(jid: i32) -> bool {
+    let mut errmsg: [u8; 256] = [0; 256];
+    let mut jiov = [
+        iovec!(b"jid\0"),
+        iovec!(&jid as *const _, mem::size_of::<i32>()),
+        // Reserved for errmsg.
+        iovec!(),
+        iovec!(),
+    ];
+
+    // A missing jail is the expected failure here, so its message is never
+    // decoded.
+    let retjid = unsafe { syscall(false, &mut jiov, &mut errmsg, JailFlags::empty()) };
+    retjid == Ok(jid)
+}
+
+/// Clear the persist flag of the jail with the given `jid`.
+#[cfg(target_os = "freebsd")]
+pub fn jail_clearpersist(jid: i32) -> Result<(), Error> {
+    let mut errmsg: [u8; 256] = [0; 256];
+    let mut jiov = [
+        iovec!(b"jid\0"),
+        iovec!(&jid as *const _, mem::size_of::<i32>()),
+        iovec!(b"nopersist\0"),
+        iovec!(),
+        // Reserved for errmsg.
+        iovec!(),
+        iovec!(),
+    ];
+
+    unsafe { call_buffered(true, &mut jiov, &mut errmsg, JailFlags::UPDATE) }.map(|_| ())
+}
+
+/// Set the persist flag of the jail with the given `jid`.
+#[cfg(target_os = "freebsd")]
+pub fn jail_setpersist(jid: i32) -> Result<(), Error> {
+    let mut errmsg: [u8; 256] = [0; 256];
+    let mut jiov = [
+        iovec!(b"jid\0"),
+        iovec!(&jid as *const _, mem::size_of::<i32>()),
+        iovec!(b"persist\0"),
+        iovec!(),
+        // Reserved for errmsg.
+        iovec!(),
+        iovec!(),
+    ];
+
+    unsafe { call_buffered(true, &mut jiov, &mut errmsg, JailFlags::UPDATE) }.map(|_| ())
+}
+
+/// Get the `jid` of a jail given the name or the `jid`.
+///
+/// Like `jail_getid`(3), this function attempts to parse the name into an
+/// `i32` first, which is returned without checking that a jail with this
+/// `jid` exists. Use [jail_getid_by_name] to always look up a name.
+#[cfg(target_os = "freebsd")]
+pub fn jail_getid(name: &str) -> Result<i32, Error> {
+    if let Ok(jid) = name.parse::<i32>() {
+        return Ok(jid);
+    };
+
+    jail_getid_by_name(name)
+}
+
+/// Get the `jid` of a jail given the name, failing if no jail has this name.
+#[cfg(target_os = "freebsd")]
+pub fn jail_getid_by_name(name: &str) -> Result<i32, Error> {
+    let name = CString::new(name)?;
+    let mut jiov: Vec<libc::iovec> = iovec!(b"name\0" => name.as_bytes_with_nul());
+    unsafe { jail_get(&mut jiov, JailFlags::empty()) }
+}
+
+/// Get the next `jid` given the last `jid`.
+#[cfg(target_os = "freebsd")]
+pub fn jail_nextjid(lastjid: i32) -> Result<i32, Error> {
+    let mut errmsg: [u8; 256] = [0; 256];
+    let mut jiov = [
+        iovec!(b"lastjid\0"),
+        iovec!(&lastjid as *const _, mem::size_of::<i32>()),
+        // Reserved for errmsg.
+        iovec!(),
+        iovec!(),
+    ];
+
+    unsafe { call_buffered(false, &mut jiov, &mut errmsg, JailFlags::empty()) }
+}
+
+/// Remove the jail with the given `jid`.
+///
+/// This will kill all processes belonging to the jail, and remove any children
+/// of that jail.
+#[cfg(target_os = "freebsd")]
+pub fn jail_remove(jid: i32) -> Result<(), Error> {
+    match unsafe { libc::jail_remove(jid) } {
+        0 => Ok(()),
+        -1 => Err(Error::Errno(errno())),
+        ret => Err(Error::UnexpectedReturn(ret)),
+    }
+}
+
+/// Attach the calling process to the jail with the given `jid`.
+#[cfg(target_os = "freebsd")]
+pub fn jail_attach(jid: i32) -> Result<(), Error> {
+    match unsafe { libc::jail_attach(jid) } {
+        0 => Ok(()),
+        -1 => Err(Error::Errno(errno())),
+        ret => Err(Error::UnexpectedReturn(ret)),
+    }
+}
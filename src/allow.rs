@@ -0,0 +1,200 @@
+//! Typed `allow.*` jail parameters
+//!
+//! New FreeBSD releases regularly add `allow.*` parameters. [Allow] maps all
+//! of them known to this library, and can check which are supported by the
+//! running kernel, so that jail definitions can degrade predictably on older
+//! hosts.
+use crate::{param, JailError};
+use log::trace;
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString, IntoStaticStr};
+use sysctl::{Ctl, Sysctl};
+
+const PARAM_PREFIX: &str = "security.jail.param.";
+
+/// A permission granted to a jail by an `allow.*` parameter.
+///
+/// # Examples
+///
+/// ```
+/// use jail::allow::Allow;
+///
+/// assert_eq!(Allow::Mlock.name(), "allow.mlock");
+/// assert_eq!("allow.mount.tmpfs".parse::<Allow>().unwrap(), Allow::MountTmpfs);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Display, EnumString, EnumIter, IntoStaticStr)]
+pub enum Allow {
+    /// Change the hostname of the jail.
+    #[strum(serialize = "allow.set_hostname")]
+    SetHostname,
+
+    /// Use System V IPC primitives shared with the host.
+    #[strum(serialize = "allow.sysvipc")]
+    SysvIpc,
+
+    /// Create raw sockets.
+    #[strum(serialize = "allow.raw_sockets")]
+    RawSockets,
+
+    /// Change system file flags.
+    #[strum(serialize = "allow.chflags")]
+    Chflags,
+
+    /// Mount and unmount file systems marked as jail-friendly.
+    #[strum(serialize = "allow.mount")]
+    Mount,
+
+    /// Mount and unmount `devfs`.
+    #[strum(serialize = "allow.mount.devfs")]
+    MountDevfs,
+
+    /// Mount and unmount `fdescfs`.
+    #[strum(serialize = "allow.mount.fdescfs")]
+    MountFdescfs,
+
+    /// Mount and unmount `fusefs`.
+    #[strum(serialize = "allow.mount.fusefs")]
+    MountFusefs,
+
+    /// Mount and unmount `linprocfs`.
+    #[strum(serialize = "allow.mount.linprocfs")]
+    MountLinprocfs,
+
+    /// Mount and unmount `linsysfs`.
+    #[strum(serialize = "allow.mount.linsysfs")]
+    MountLinsysfs,
+
+    /// Mount and unmount `nullfs`.
+    #[strum(serialize = "allow.mount.nullfs")]
+    MountNullfs,
+
+    /// Mount and unmount `procfs`.
+    #[strum(serialize = "allow.mount.procfs")]
+    MountProcfs,
+
+    /// Mount and unmount `tmpfs`.
+    #[strum(serialize = "allow.mount.tmpfs")]
+    MountTmpfs,
+
+    /// Mount and unmount ZFS datasets delegated to the jail.
+    #[strum(serialize = "allow.mount.zfs")]
+    MountZfs,
+
+    /// Administer file system quotas.
+    #[strum(serialize = "allow.quotas")]
+    Quotas,
+
+    /// Create sockets of any protocol family, not only IP and local sockets.
+    #[strum(serialize = "allow.socket_af")]
+    SocketAf,
+
+    /// Lock memory using `mlock`(2).
+    #[strum(serialize = "allow.mlock")]
+    Mlock,
+
+    /// Read the kernel message buffer.
+    #[strum(serialize = "allow.read_msgbuf")]
+    ReadMsgbuf,
+
+    /// Bind to reserved ports below 1024 as root.
+    #[strum(serialize = "allow.reserved_ports")]
+    ReservedPorts,
+
+    /// Debug processes of other users, without root privileges.
+    #[strum(serialize = "allow.unprivileged_proc_debug")]
+    UnprivilegedProcDebug,
+
+    /// Grant root in the jail its usual privileges.
+    #[strum(serialize = "allow.suser")]
+    Suser,
+
+    /// Use `bhyve`(8) virtual machines.
+    #[strum(serialize = "allow.vmm")]
+    Vmm,
+
+    /// Run an NFS server.
+    #[strum(serialize = "allow.nfsd")]
+    Nfsd,
+
+    /// Manipulate system extended attributes.
+    #[strum(serialize = "allow.extattr")]
+    Extattr,
+
+    /// Slew the system clock using `adjtime`(2).
+    #[strum(serialize = "allow.adjtime")]
+    Adjtime,
+
+    /// Set the system clock.
+    #[strum(serialize = "allow.settime")]
+    Settime,
+
+    /// Modify the routing table, in jails without their own network stack.
+    #[strum(serialize = "allow.routing")]
+    Routing,
+}
+
+impl Allow {
+    /// Get the name of the jail parameter.
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Check whether the running kernel supports this parameter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::allow::Allow;
+    ///
+    /// assert!(Allow::RawSockets.is_supported());
+    /// ```
+    pub fn is_supported(&self) -> bool {
+        trace!("Allow::is_supported({:?})", self);
+        param::exists(self.name())
+    }
+
+    /// Get all permissions supported by the running kernel.
+    pub fn supported() -> Vec<Allow> {
+        trace!("Allow::supported()");
+        Allow::iter().filter(Allow::is_supported).collect()
+    }
+
+    /// Get all permissions known to this library, but not supported by the
+    /// running kernel.
+    ///
+    /// Setting these on a jail fails on this host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::allow::Allow;
+    ///
+    /// for allow in Allow::missing_on_host() {
+    ///     println!("{} is not supported on this host", allow);
+    /// }
+    /// ```
+    pub fn missing_on_host() -> Vec<Allow> {
+        trace!("Allow::missing_on_host()");
+        Allow::iter().filter(|a| !a.is_supported()).collect()
+    }
+
+    /// Get the names of all `allow.*` parameters supported by the running
+    /// kernel, but not known to this library.
+    ///
+    /// These can still be set as untyped parameters using
+    /// [StoppedJail::param](../struct.StoppedJail.html#method.param).
+    pub fn unmapped_on_host() -> Result<Vec<String>, JailError> {
+        trace!("Allow::unmapped_on_host()");
+        let prefix = format!("{}allow.", PARAM_PREFIX);
+
+        Ok(Ctl::new(&format!("{}allow", PARAM_PREFIX))
+            .map_err(JailError::SysctlError)?
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(|ctl| ctl.name().ok())
+            .filter(|name| name.starts_with(&prefix) && !name.ends_with('.'))
+            .map(|name| name[PARAM_PREFIX.len()..].to_string())
+            .filter(|name| name.parse::<Allow>().is_err())
+            .collect())
+    }
+}
@@ -0,0 +1,150 @@
+//! Generation of values and jail configurations for property-based tests
+//!
+//! With the `proptest` feature, [Value] and [StoppedJail] implement
+//! [proptest::arbitrary::Arbitrary], so that `any::<StoppedJail>()` generates
+//! configurations which start on a stock FreeBSD host:
+//!
+//! ```
+//! use jail::StoppedJail;
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #![proptest_config(ProptestConfig::with_cases(4))]
+//!     fn starts(stopped in any::<StoppedJail>()) {
+//!         let running = stopped.start().expect("could not start jail");
+//!         running.kill().expect("could not stop jail");
+//!     }
+//! }
+//! # starts();
+//! ```
+use crate::param::Value;
+#[cfg(target_os = "freebsd")]
+use crate::StoppedJail;
+use proptest::prelude::*;
+use std::net;
+#[cfg(target_os = "freebsd")]
+use std::path;
+
+/// Parameters settable on every supported release, with their valid values.
+#[cfg(target_os = "freebsd")]
+fn params() -> impl Strategy<Value = Vec<(&'static str, Value)>> {
+    let allow = prop::sample::select(vec![
+        "allow.chflags",
+        "allow.mount",
+        "allow.raw_sockets",
+        "allow.set_hostname",
+        "allow.socket_af",
+        "allow.sysvipc",
+    ]);
+
+    (
+        prop::collection::vec(
+            (allow, 0..=1).prop_map(|(name, v)| (name, Value::Int(v))),
+            0..4,
+        ),
+        prop::option::of((0..=2).prop_map(Value::Int)),
+        prop::option::of((0..=4).prop_map(Value::Int)),
+        prop::option::of("FreeBSD 1[0-9]\\.[0-4]-RELEASE".prop_map(Value::String)),
+    )
+        .prop_map(|(mut params, enforce_statfs, children_max, osrelease)| {
+            params.extend(enforce_statfs.map(|v| ("enforce_statfs", v)));
+            params.extend(children_max.map(|v| ("children.max", v)));
+            params.extend(osrelease.map(|v| ("osrelease", v)));
+            params
+        })
+}
+
+/// Addresses from the documentation and unique local ranges, which are never
+/// routed.
+#[cfg(target_os = "freebsd")]
+fn ips() -> impl Strategy<Value = Vec<net::IpAddr>> {
+    let ipv4 = (1..=254u8).prop_map(|host| net::IpAddr::V4(net::Ipv4Addr::new(192, 0, 2, host)));
+    let ipv6 = any::<u16>()
+        .prop_map(|host| net::IpAddr::V6(net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, host)));
+
+    prop::collection::btree_set(prop_oneof![ipv4, ipv6], 0..4)
+        .prop_map(|ips| ips.into_iter().collect())
+}
+
+impl Arbitrary for Value {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Value>;
+
+    /// Any value except [Value::Custom], which needs a registered codec.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            any::<libc::c_int>().prop_map(Value::Int),
+            "[^\u{0}]*".prop_map(Value::String),
+            any::<i64>().prop_map(Value::S64),
+            any::<libc::c_uint>().prop_map(Value::Uint),
+            any::<libc::c_long>().prop_map(Value::Long),
+            any::<libc::c_ulong>().prop_map(Value::Ulong),
+            any::<u64>().prop_map(Value::U64),
+            any::<u8>().prop_map(Value::U8),
+            any::<u16>().prop_map(Value::U16),
+            any::<i8>().prop_map(Value::S8),
+            any::<i16>().prop_map(Value::S16),
+            any::<i32>().prop_map(Value::S32),
+            any::<u32>().prop_map(Value::U32),
+            prop::collection::vec(any::<[u8; 4]>(), 0..8).prop_map(|addrs| {
+                Value::Ipv4Addrs(
+                    addrs
+                        .into_iter()
+                        .map(net::Ipv4Addr::from)
+                        .filter(|ip| !ip.is_unspecified())
+                        .collect(),
+                )
+            }),
+            prop::collection::vec(any::<[u8; 16]>(), 0..8).prop_map(|addrs| {
+                Value::Ipv6Addrs(
+                    addrs
+                        .into_iter()
+                        .map(net::Ipv6Addr::from)
+                        .filter(|ip| !ip.is_unspecified())
+                        .collect(),
+                )
+            }),
+            prop::collection::vec(any::<u8>(), 0..64).prop_map(Value::Raw),
+        ]
+        .boxed()
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Arbitrary for StoppedJail {
+    /// The root of the generated jails, `/rescue` if not given.
+    type Parameters = Option<path::PathBuf>;
+    type Strategy = BoxedStrategy<StoppedJail>;
+
+    /// A jail with a name prefixed with `proptest_`, an optional hostname,
+    /// addresses, parameters, metadata and environment.
+    ///
+    /// Resource limits, provisioning steps and mounts are not generated, as
+    /// they depend on the configuration of the host.
+    fn arbitrary_with(root: Self::Parameters) -> Self::Strategy {
+        let root = root.unwrap_or_else(|| "/rescue".into());
+        let metadata = || prop::collection::btree_map("[a-z]{1,8}", "[a-z0-9 ]{0,16}", 0..3);
+
+        (
+            "[a-z0-9]{1,16}",
+            prop::option::of("[a-z]{1,10}(\\.[a-z]{1,10}){0,2}"),
+            ips(),
+            params(),
+            metadata(),
+            metadata(),
+        )
+            .prop_map(move |(name, hostname, ips, params, meta, env)| {
+                let mut stopped = StoppedJail::new(root.clone()).name(format!("proptest_{}", name));
+                stopped.hostname = hostname;
+                stopped.ips = ips;
+                stopped.params = params
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect();
+                stopped.meta = meta;
+                stopped.env = env;
+                stopped
+            })
+            .boxed()
+    }
+}
@@ -0,0 +1,229 @@
+//! Audit records of jail lifecycle operations
+//!
+//! With the `audit` feature enabled, every jail creation, removal, attach and
+//! parameter change performed through this library emits a [Record]. By
+//! default, records are sent to `syslog`(3) with the `authpriv` facility, so
+//! that they end up in `/var/log/auth.log` alongside other security relevant
+//! events. Applications with other requirements can install their own
+//! handler using [set_handler].
+use crate::JailError;
+use log::trace;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+use strum_macros::{Display, EnumString};
+
+/// An audited operation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Operation {
+    /// A jail was created.
+    Create,
+
+    /// A jail was removed.
+    Kill,
+
+    /// A process was attached to a jail.
+    Attach,
+
+    /// A parameter of a running jail was changed.
+    ParamSet,
+}
+
+/// A single audit record.
+///
+/// The [Display] implementation renders the record in a fixed schema of
+/// space-separated `key=value` pairs, always in the same order:
+///
+/// ```text
+/// op=<operation> jid=<jid> name="<name>" uid=<uid> euid=<euid> result=<success|failure> [detail="<detail>"] [error="<error>"]
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use jail::audit::{Operation, Record};
+///
+/// let record = Record {
+///     operation: Operation::ParamSet,
+///     jid: 42,
+///     name: Some("www".into()),
+///     uid: 1001,
+///     euid: 0,
+///     detail: Some("allow.raw_sockets".into()),
+///     error: None,
+/// };
+///
+/// assert_eq!(
+///     record.to_string(),
+///     "op=param_set jid=42 name=\"www\" uid=1001 euid=0 result=success \
+///      detail=\"allow.raw_sockets\""
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// The audited operation
+    pub operation: Operation,
+
+    /// The `jid` of the jail, or -1 if it could not be created
+    pub jid: i32,
+
+    /// The name of the jail, if known
+    pub name: Option<String>,
+
+    /// The real user ID of the calling process
+    pub uid: libc::uid_t,
+
+    /// The effective user ID of the calling process
+    pub euid: libc::uid_t,
+
+    /// Additional information, such as the name of a changed parameter or
+    /// the program run in the jail
+    pub detail: Option<String>,
+
+    /// The error the operation failed with, if any
+    pub error: Option<String>,
+}
+
+impl Record {
+    /// Whether the audited operation succeeded.
+    pub fn success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Quote a value, escaping quotes and backslashes.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "op={} jid={} name={} uid={} euid={} result={}",
+            self.operation,
+            self.jid,
+            quote(self.name.as_deref().unwrap_or("")),
+            self.uid,
+            self.euid,
+            if self.success() { "success" } else { "failure" }
+        )?;
+
+        if let Some(ref detail) = self.detail {
+            write!(f, " detail={}", quote(detail))?;
+        }
+
+        if let Some(ref error) = self.error {
+            write!(f, " error={}", quote(error))?;
+        }
+
+        Ok(())
+    }
+}
+
+type Handler = Box<dyn Fn(&Record) + Send + Sync + 'static>;
+
+fn handler() -> &'static RwLock<Option<Handler>> {
+    static HANDLER: OnceLock<RwLock<Option<Handler>>> = OnceLock::new();
+    HANDLER.get_or_init(Default::default)
+}
+
+/// Send all future records to `handler` instead of `syslog`(3).
+///
+/// # Examples
+///
+/// ```
+/// use jail::audit;
+///
+/// audit::set_handler(|record| {
+///     eprintln!("audit: {}", record);
+///     // Still log to syslog as well.
+///     audit::syslog(record);
+/// });
+/// # audit::reset_handler();
+/// ```
+pub fn set_handler<F>(handler_fn: F)
+where
+    F: Fn(&Record) + Send + Sync + 'static,
+{
+    trace!("audit::set_handler()");
+    *handler().write().expect("audit handler poisoned") = Some(Box::new(handler_fn));
+}
+
+/// Send all future records to `syslog`(3) again.
+pub fn reset_handler() {
+    trace!("audit::reset_handler()");
+    handler().write().expect("audit handler poisoned").take();
+}
+
+/// Send a record to `syslog`(3) with the `authpriv` facility and `notice`
+/// level. This is the default handler.
+pub fn syslog(record: &Record) {
+    trace!("audit::syslog({:?})", record);
+    // Records cannot contain NUL bytes, except from user-supplied strings.
+    let message = match CString::new(format!("jail: {}", record)) {
+        Ok(message) => message,
+        Err(e) => {
+            let mut bytes = e.into_vec();
+            bytes.retain(|b| *b != 0);
+            CString::new(bytes).expect("NUL bytes were removed")
+        }
+    };
+
+    unsafe {
+        libc::syslog(
+            libc::LOG_AUTHPRIV | libc::LOG_NOTICE,
+            b"%s\0".as_ptr() as *const libc::c_char,
+            message.as_ptr(),
+        );
+    }
+}
+
+/// Emit a record for an operation on the jail `jid` with the given outcome.
+pub(crate) fn emit<T>(
+    operation: Operation,
+    jid: i32,
+    name: Option<&str>,
+    detail: Option<&str>,
+    result: &Result<T, JailError>,
+) {
+    let record = Record {
+        operation,
+        jid,
+        name: name.map(String::from),
+        uid: unsafe { libc::getuid() },
+        euid: unsafe { libc::geteuid() },
+        detail: detail.map(String::from),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+
+    match *handler().read().expect("audit handler poisoned") {
+        Some(ref handler_fn) => handler_fn(&record),
+        None => syslog(&record),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_schema() {
+        let record = Record {
+            operation: Operation::Create,
+            jid: -1,
+            name: Some("we\"ird\\name".into()),
+            uid: 1001,
+            euid: 1001,
+            detail: None,
+            error: Some("Operation not permitted".into()),
+        };
+
+        assert_eq!(
+            record.to_string(),
+            "op=create jid=-1 name=\"we\\\"ird\\\\name\" uid=1001 euid=1001 \
+             result=failure error=\"Operation not permitted\""
+        );
+    }
+}
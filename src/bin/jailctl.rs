@@ -0,0 +1,403 @@
+//! jailctl: manage FreeBSD jails using libjail-rs.
+//!
+//! ```text
+//! jailctl create www --path /usr/jails/www --ip 192.0.2.1 --param securelevel=3
+//! jailctl list
+//! jailctl exec www -- ps aux
+//! jailctl limits www --set memoryuse=1g:deny
+//! jailctl export www > www.ucl
+//! jailctl stop www
+//! jailctl import --ucl www.ucl
+//! jailctl completions zsh > _jailctl
+//! ```
+//!
+//! Requires the `jailctl` feature.
+#[cfg(target_os = "freebsd")]
+mod cli {
+    use clap::{CommandFactory, Parser};
+    use jail::cli::{parse_limit, JailArgs};
+    use jail::interop::{bastille, iocage};
+    use jail::param::{self, Value};
+    use jail::process::Jailed;
+    use jail::{ucl, JailError, RunningJail, Signal, StopPolicy, StoppedJail};
+    use std::fmt::Write;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::time::Duration;
+    use std::{fs, io};
+
+    #[derive(Parser, Debug)]
+    #[clap(name = "jailctl", version, about = "Manage FreeBSD jails")]
+    pub struct Cli {
+        #[clap(subcommand)]
+        command: Subcommand,
+    }
+
+    #[derive(clap::Subcommand, Debug)]
+    enum Subcommand {
+        /// Create and start a jail
+        Create {
+            #[clap(flatten)]
+            jail: JailArgs,
+        },
+
+        /// List running jails
+        List {
+            /// Print the definitions of the jails as JSON
+            #[clap(long)]
+            json: bool,
+        },
+
+        /// Run a command in a jail
+        Exec {
+            /// The name or JID of the jail
+            jail: String,
+
+            /// The command and its arguments
+            #[clap(required = true, last = true)]
+            command: Vec<String>,
+        },
+
+        /// Stop a jail, asking its processes to exit before killing them
+        Stop {
+            /// The name or JID of the jail
+            jail: String,
+
+            /// The signal asking the processes to exit
+            #[clap(long, default_value = "TERM")]
+            signal: Signal,
+
+            /// The seconds given to the processes to exit
+            #[clap(long, default_value_t = 10)]
+            grace: u64,
+
+            /// Fail instead of killing processes that did not exit in time
+            #[clap(long)]
+            no_escalate: bool,
+        },
+
+        /// Show the resource usage and limits of a jail, or add limits
+        Limits {
+            /// The name or JID of the jail
+            jail: String,
+
+            /// Add a resource limit, as `resource=amount[/per][:action]`
+            #[clap(long)]
+            set: Vec<String>,
+        },
+
+        /// Print the definition of a running jail as UCL or JSON
+        Export {
+            /// The name or JID of the jail
+            jail: String,
+
+            /// Print JSON instead of UCL
+            #[clap(long)]
+            json: bool,
+        },
+
+        /// Create jails from UCL definitions, iocage or Bastille
+        #[clap(group(clap::ArgGroup::new("source").required(true)))]
+        Import {
+            /// A UCL file of jail definitions
+            #[clap(long, group = "source")]
+            ucl: Option<PathBuf>,
+
+            /// The ZFS pool or directory of an iocage installation
+            #[clap(long, group = "source")]
+            iocage: Option<PathBuf>,
+
+            /// The prefix of a Bastille installation
+            #[clap(long, group = "source")]
+            bastille: Option<PathBuf>,
+
+            /// Only print the imported definitions as UCL, do not start them
+            #[clap(long)]
+            dry_run: bool,
+        },
+
+        /// Print the parameters supported by the kernel as JSON
+        Params,
+
+        /// Print a completion script for a shell
+        Completions {
+            /// The shell to generate the script for
+            #[clap(arg_enum)]
+            shell: clap_complete::Shell,
+        },
+    }
+
+    fn invalid<S: Into<String>>(message: S) -> JailError {
+        JailError::IoError(io::Error::new(io::ErrorKind::InvalidInput, message.into()))
+    }
+
+    /// Look up a running jail by JID or name.
+    fn running(jail: &str) -> Result<RunningJail, JailError> {
+        match jail.parse() {
+            Ok(jid) => RunningJail::from_jid(jid)
+                .ok_or_else(|| invalid(format!("no jail with JID {}", jid))),
+            Err(_) => RunningJail::from_name_strict(jail),
+        }
+    }
+
+    /// Render a parameter value as a UCL scalar, if it has a textual form.
+    fn value(value: &Value) -> Option<String> {
+        match value.clone() {
+            Value::String(s) => Some(format!("{:?}", s)),
+            Value::Ipv4Addrs(addrs) => Some(format!(
+                "{:?}",
+                addrs
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )),
+            Value::Ipv6Addrs(addrs) => Some(format!(
+                "{:?}",
+                addrs
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )),
+            Value::Raw(_) | Value::Custom(_) => None,
+            v => v
+                .clone()
+                .unpack_i64()
+                .map(|i| i.to_string())
+                .or_else(|_| v.unpack_u64().map(|u| u.to_string()))
+                .ok(),
+        }
+    }
+
+    /// Render a jail definition in the format read by `ucl::parse_jails`.
+    fn to_ucl(stopped: &StoppedJail) -> String {
+        let mut out = String::new();
+        let name = stopped.name.clone().unwrap_or_default();
+        writeln!(out, "jail {:?} {{", name).unwrap();
+
+        if let Some(ref path) = stopped.path {
+            writeln!(out, "    path = {:?};", path.display().to_string()).unwrap();
+        }
+
+        if let Some(ref hostname) = stopped.hostname {
+            writeln!(out, "    hostname = {:?};", hostname).unwrap();
+        }
+
+        if !stopped.ips.is_empty() {
+            let ips: Vec<String> = stopped
+                .ips
+                .iter()
+                .map(|ip| format!("{:?}", ip.to_string()))
+                .collect();
+            writeln!(out, "    ips = [ {} ];", ips.join(", ")).unwrap();
+        }
+
+        let mut params: Vec<_> = stopped.params.iter().collect();
+        params.sort_by(|a, b| a.0.cmp(b.0));
+        if !params.is_empty() {
+            writeln!(out, "    params {{").unwrap();
+            for (name, v) in params {
+                match value(v) {
+                    Some(v) => writeln!(out, "        {:?} = {};", name, v).unwrap(),
+                    None => eprintln!("jailctl: cannot export parameter {}", name),
+                }
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+
+        if !stopped.limits.is_empty() {
+            writeln!(out, "    limits = [").unwrap();
+            for (resource, limit, action) in &stopped.limits {
+                writeln!(
+                    out,
+                    "        {{ resource = {:?}; amount = {:?}; action = {:?}; }},",
+                    resource.to_string(),
+                    limit.to_string(),
+                    action.to_string()
+                )
+                .unwrap();
+            }
+            writeln!(out, "    ]").unwrap();
+        }
+
+        if !stopped.mounts.is_empty() {
+            writeln!(out, "    mounts = [").unwrap();
+            for mount in &stopped.mounts {
+                let flags: String = [
+                    ("nosuid", mount.nosuid),
+                    ("noexec", mount.noexec),
+                    ("allow_sensitive", mount.allow_sensitive),
+                ]
+                .iter()
+                .filter(|(_, set)| *set)
+                .map(|(name, _)| format!(" {} = true;", name))
+                .collect();
+                writeln!(
+                    out,
+                    "        {{ fstype = {:?}; source = {:?}; target = {:?}; readonly = {};{} }},",
+                    mount.fstype,
+                    mount.source.display().to_string(),
+                    mount.target.display().to_string(),
+                    mount.readonly,
+                    flags
+                )
+                .unwrap();
+            }
+            writeln!(out, "    ]").unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn run(cli: Cli) -> Result<(), JailError> {
+        match cli.command {
+            Subcommand::Create { jail } => {
+                println!("{}", jail.into_stopped_jail()?.start()?.jid);
+            }
+
+            Subcommand::List { json: true } => {
+                let saved = RunningJail::all()
+                    .map(|running| running.save())
+                    .collect::<Result<Vec<_>, _>>()?;
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&saved).map_err(|_| JailError::SerializeFailed)?
+                );
+            }
+
+            Subcommand::List { json: false } => {
+                println!(
+                    "{:>6}  {:<20} {:<30} {:<24} PATH",
+                    "JID", "NAME", "HOSTNAME", "IP"
+                );
+                for running in RunningJail::all() {
+                    let ips: Vec<String> = running.ips()?.iter().map(|ip| ip.to_string()).collect();
+                    println!(
+                        "{:>6}  {:<20} {:<30} {:<24} {}",
+                        running.jid,
+                        running.name()?,
+                        running.hostname()?,
+                        ips.join(","),
+                        running.path()?.display()
+                    );
+                }
+            }
+
+            Subcommand::Exec { jail, command } => {
+                let running = running(&jail)?;
+                let status = Command::new(&command[0])
+                    .args(&command[1..])
+                    .jail(&running)
+                    .status()
+                    .map_err(JailError::IoError)?;
+                std::process::exit(status.code().unwrap_or(1));
+            }
+
+            Subcommand::Stop {
+                jail,
+                signal,
+                grace,
+                no_escalate,
+            } => {
+                let policy = StopPolicy::default()
+                    .signal(signal)
+                    .grace(Duration::from_secs(grace))
+                    .escalate(!no_escalate);
+                running(&jail)?.shutdown(&policy)?;
+            }
+
+            Subcommand::Limits { jail, set } => {
+                let running = running(&jail)?;
+                let name = running.name()?;
+                for l in set {
+                    let (resource, limit, action) = parse_limit(&l)?;
+                    rctl::Rule {
+                        subject: rctl::Subject::jail_name(name.clone()),
+                        resource,
+                        limit,
+                        action,
+                    }
+                    .apply()
+                    .map_err(JailError::RctlError)?;
+                }
+
+                let filter: rctl::Filter = rctl::Subject::jail_name(name).into();
+                let rules = filter.rules().map_err(JailError::RctlError)?;
+                for rule in &rules {
+                    println!("{}", rule);
+                }
+
+                let mut usage: Vec<_> = running.racct_statistics()?.into_iter().collect();
+                usage.sort_by_key(|(resource, _)| resource.to_string());
+                for (resource, amount) in usage {
+                    println!("{}={}", resource, amount);
+                }
+            }
+
+            Subcommand::Export { jail, json } => {
+                let saved = running(&jail)?.save()?;
+                match json {
+                    true => println!(
+                        "{}",
+                        serde_json::to_string_pretty(&saved)
+                            .map_err(|_| JailError::SerializeFailed)?
+                    ),
+                    false => print!("{}", to_ucl(&saved)),
+                }
+            }
+
+            Subcommand::Import {
+                ucl: file,
+                iocage: pool,
+                bastille: prefix,
+                dry_run,
+            } => {
+                let jails = match (file, pool, prefix) {
+                    (Some(file), _, _) => {
+                        ucl::parse_jails(&fs::read_to_string(file).map_err(JailError::IoError)?)?
+                    }
+                    (_, Some(pool), _) => iocage::import(pool)?,
+                    (_, _, Some(prefix)) => bastille::import(prefix)?,
+                    _ => unreachable!("clap requires a source"),
+                };
+
+                for stopped in jails {
+                    match dry_run {
+                        true => print!("{}", to_ucl(&stopped)),
+                        false => println!(
+                            "{} {}",
+                            stopped.clone().start()?.jid,
+                            stopped.name.unwrap_or_default()
+                        ),
+                    }
+                }
+            }
+
+            Subcommand::Params => println!("{}", param::schema_json()?),
+
+            Subcommand::Completions { shell } => {
+                clap_complete::generate(shell, &mut Cli::command(), "jailctl", &mut io::stdout())
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+fn main() {
+    use clap::Parser;
+
+    if let Err(e) = cli::run(cli::Cli::parse()) {
+        eprintln!("jailctl: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+fn main() {
+    eprintln!("jailctl only runs on FreeBSD");
+    std::process::exit(1);
+}
@@ -0,0 +1,128 @@
+//! Memoized identity of running jails
+//!
+//! Every accessor of [RunningJail] is a `jail_get`(2) call. [CachedJail]
+//! remembers the fields that rarely change over the lifetime of a jail, for
+//! code rendering lists of jails repeatedly.
+use crate::{JailError, RunningJail};
+use log::trace;
+use std::cell::OnceCell;
+use std::net;
+use std::path;
+
+/// A [RunningJail] with memoized `name`, `path`, `hostname` and addresses.
+///
+/// Each field is read from the kernel on first access and kept until
+/// [CachedJail::refresh] is called. The cache is not updated when the jail
+/// changes, e.g. when a parameter is set or the jail is stopped.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{param, CachedJail};
+/// # use jail::StoppedJail;
+/// # let running = StoppedJail::new("/rescue")
+/// #     .name("testjail_cached")
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let mut cached = CachedJail::new(running);
+/// assert_eq!(cached.name().unwrap(), "testjail_cached");
+///
+/// param::set(
+///     running.jid,
+///     "host.hostname",
+///     param::Value::String("renamed.example.org".into()),
+/// )
+/// .unwrap();
+/// cached.refresh();
+/// assert_eq!(cached.hostname().unwrap(), "renamed.example.org");
+/// # running.kill().expect("could not stop jail");
+/// ```
+#[cfg(target_os = "freebsd")]
+#[derive(Clone, Debug)]
+pub struct CachedJail {
+    jail: RunningJail,
+    name: OnceCell<String>,
+    path: OnceCell<path::PathBuf>,
+    hostname: OnceCell<String>,
+    ips: OnceCell<Vec<net::IpAddr>>,
+}
+
+/// Get the value of a cell, initializing it with `init` if it is empty.
+#[cfg(target_os = "freebsd")]
+fn memoize<T, F>(cell: &OnceCell<T>, init: F) -> Result<&T, JailError>
+where
+    F: FnOnce() -> Result<T, JailError>,
+{
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+
+    let value = init()?;
+    Ok(cell.get_or_init(|| value))
+}
+
+#[cfg(target_os = "freebsd")]
+impl CachedJail {
+    /// Wrap a running jail. Nothing is read until the first access.
+    pub fn new(jail: RunningJail) -> Self {
+        trace!("CachedJail::new(jail={:?})", jail);
+        CachedJail {
+            jail,
+            name: OnceCell::new(),
+            path: OnceCell::new(),
+            hostname: OnceCell::new(),
+            ips: OnceCell::new(),
+        }
+    }
+
+    /// The wrapped jail.
+    pub fn jail(&self) -> RunningJail {
+        self.jail
+    }
+
+    /// The `jid` of the jail.
+    pub fn jid(&self) -> i32 {
+        self.jail.jid
+    }
+
+    /// The memoized [RunningJail::name].
+    pub fn name(&self) -> Result<&str, JailError> {
+        trace!("CachedJail::name({:?})", self);
+        memoize(&self.name, || self.jail.name()).map(String::as_str)
+    }
+
+    /// The memoized [RunningJail::path].
+    pub fn path(&self) -> Result<&path::Path, JailError> {
+        trace!("CachedJail::path({:?})", self);
+        memoize(&self.path, || self.jail.path()).map(path::PathBuf::as_path)
+    }
+
+    /// The memoized [RunningJail::hostname].
+    pub fn hostname(&self) -> Result<&str, JailError> {
+        trace!("CachedJail::hostname({:?})", self);
+        memoize(&self.hostname, || self.jail.hostname()).map(String::as_str)
+    }
+
+    /// The memoized [RunningJail::ips].
+    pub fn ips(&self) -> Result<&[net::IpAddr], JailError> {
+        trace!("CachedJail::ips({:?})", self);
+        memoize(&self.ips, || self.jail.ips_fast()).map(Vec::as_slice)
+    }
+
+    /// Drop all memoized fields, so they are read again on next access.
+    pub fn refresh(&mut self) {
+        trace!("CachedJail::refresh({:?})", self);
+        self.name.take();
+        self.path.take();
+        self.hostname.take();
+        self.ips.take();
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl From<RunningJail> for CachedJail {
+    fn from(jail: RunningJail) -> Self {
+        CachedJail::new(jail)
+    }
+}
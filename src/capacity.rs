@@ -0,0 +1,380 @@
+//! Admission control for jails
+//!
+//! [check] compares what a [StoppedJail](../struct.StoppedJail.html) asks for
+//! against the current state of the host, and reports conflicts that would
+//! make the jail fail to start or starve other jails. It is an estimate: the
+//! host may change between the check and the start of the jail.
+use crate::{JailError, RunningJail, StoppedJail};
+use log::{trace, warn};
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::IpAddr;
+use sysctl::{Ctl, CtlValue, Sysctl};
+
+/// A likely conflict between a jail and the host.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Conflict {
+    /// The `memoryuse` limit of the jail exceeds the free memory of the
+    /// host.
+    MemoryExceedsFree {
+        /// The `memoryuse` limit of the jail, in bytes
+        requested: u64,
+
+        /// The free and inactive memory of the host, in bytes
+        free: u64,
+    },
+
+    /// The `memoryuse` limits of all running jails, including this one, add
+    /// up to more than the physical memory of the host.
+    MemoryOvercommitted {
+        /// The `memoryuse` limit of the jail, in bytes
+        requested: u64,
+
+        /// The sum of the `memoryuse` limits of the other running jails, in
+        /// bytes
+        reserved: u64,
+
+        /// The physical memory of the host, in bytes
+        physical: u64,
+    },
+
+    /// An address of the jail is already assigned to a running jail.
+    AddressInUse {
+        address: IpAddr,
+        jid: i32,
+        name: String,
+    },
+
+    /// A running jail already has the name of the jail.
+    NameInUse { name: String, jid: i32 },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conflict::MemoryExceedsFree { requested, free } => write!(
+                f,
+                "memoryuse limit of {} bytes exceeds {} bytes of free memory",
+                requested, free
+            ),
+            Conflict::MemoryOvercommitted {
+                requested,
+                reserved,
+                physical,
+            } => write!(
+                f,
+                "memoryuse limit of {} bytes with {} bytes reserved by other jails exceeds {} bytes of physical memory",
+                requested, reserved, physical
+            ),
+            Conflict::AddressInUse { address, jid, name } => {
+                write!(f, "{} is in use by jail {} ({})", address, jid, name)
+            }
+            Conflict::NameInUse { name, jid } => {
+                write!(f, "name {} is in use by jail {}", name, jid)
+            }
+        }
+    }
+}
+
+/// The result of [check].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Report {
+    /// All likely conflicts found
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Report {
+    /// Whether no conflicts were found.
+    pub fn is_ok(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.conflicts.is_empty() {
+            true => write!(f, "no conflicts"),
+            false => {
+                let conflicts: Vec<String> =
+                    self.conflicts.iter().map(Conflict::to_string).collect();
+                write!(f, "{}", conflicts.join("; "))
+            }
+        }
+    }
+}
+
+/// A running jail, as seen by the check.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "rctl"), allow(dead_code))]
+struct Neighbour {
+    jid: i32,
+    name: String,
+    ips: Vec<IpAddr>,
+
+    /// The `memoryuse` limit of the jail, in bytes
+    memory: Option<u64>,
+}
+
+/// The state of the host.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(not(feature = "rctl"), allow(dead_code))]
+struct Host {
+    physical: Option<u64>,
+    free: Option<u64>,
+    jails: Vec<Neighbour>,
+}
+
+/// Get the smallest denying per-jail `memoryuse` limit in `limits`.
+#[cfg(feature = "rctl")]
+fn memory_limit<'a, I>(limits: I) -> Result<Option<u64>, JailError>
+where
+    I: IntoIterator<Item = (&'a rctl::Resource, &'a rctl::Limit, &'a rctl::Action)>,
+{
+    let mut memory: Option<u64> = None;
+    for (resource, limit, action) in limits {
+        if *resource != rctl::Resource::MemoryUse || *action != rctl::Action::Deny {
+            continue;
+        }
+
+        let amount = match crate::racct::amount_per(limit)? {
+            (amount, None) | (amount, Some(rctl::SubjectType::Jail)) => amount as u64,
+            _ => continue,
+        };
+        memory = Some(memory.map_or(amount, |memory| memory.min(amount)));
+    }
+
+    Ok(memory)
+}
+
+/// Find the conflicts between `stopped` and `host`.
+fn conflicts(stopped: &StoppedJail, host: &Host) -> Result<Vec<Conflict>, JailError> {
+    let mut conflicts = vec![];
+    let name = stopped.name.as_deref().unwrap_or("");
+    let others: Vec<&Neighbour> = host
+        .jails
+        .iter()
+        .filter(|jail| {
+            if !name.is_empty() && jail.name == name {
+                conflicts.push(Conflict::NameInUse {
+                    name: name.into(),
+                    jid: jail.jid,
+                });
+                return false;
+            }
+
+            true
+        })
+        .collect();
+
+    for address in &stopped.ips {
+        for jail in others.iter().filter(|jail| jail.ips.contains(address)) {
+            conflicts.push(Conflict::AddressInUse {
+                address: *address,
+                jid: jail.jid,
+                name: jail.name.clone(),
+            });
+        }
+    }
+
+    #[cfg(feature = "rctl")]
+    if let Some(requested) = memory_limit(stopped.limits.iter().map(|(r, l, a)| (r, l, a)))? {
+        if let Some(free) = host.free {
+            if requested > free {
+                conflicts.push(Conflict::MemoryExceedsFree { requested, free });
+            }
+        }
+
+        if let Some(physical) = host.physical {
+            let reserved: u64 = others.iter().filter_map(|jail| jail.memory).sum();
+            if requested.saturating_add(reserved) > physical {
+                conflicts.push(Conflict::MemoryOvercommitted {
+                    requested,
+                    reserved,
+                    physical,
+                });
+            }
+        }
+    }
+
+    Ok(conflicts)
+}
+
+fn sysctl_u64(name: &str) -> Option<u64> {
+    match Ctl::new(name).ok()?.value().ok()? {
+        CtlValue::Int(i) => u64::try_from(i).ok(),
+        CtlValue::Uint(u) | CtlValue::U32(u) => Some(u.into()),
+        CtlValue::Long(l) | CtlValue::S64(l) => u64::try_from(l).ok(),
+        CtlValue::Ulong(u) | CtlValue::U64(u) => Some(u),
+        _ => None,
+    }
+}
+
+/// Get the `memoryuse` limits of all jails, keyed by jail name.
+#[cfg(all(target_os = "freebsd", feature = "rctl"))]
+fn memory_limits() -> Result<std::collections::HashMap<String, u64>, JailError> {
+    let mut limits = std::collections::HashMap::new();
+    if rctl::State::check().is_enabled() {
+        let filter = rctl::Filter::new()
+            .subject_type(&rctl::SubjectType::Jail)
+            .resource(&rctl::Resource::MemoryUse);
+        let rules = filter.rules().map_err(JailError::RctlError)?;
+        for rule in &rules {
+            if let rctl::Subject::Jail(ref jail) = rule.subject {
+                limits.entry(jail.0.clone()).or_insert_with(Vec::new).push((
+                    rule.resource,
+                    rule.limit,
+                    rule.action,
+                ));
+            }
+        }
+    }
+
+    let mut memory = std::collections::HashMap::new();
+    for (name, limits) in limits {
+        if let Some(limit) = memory_limit(limits.iter().map(|(r, l, a)| (r, l, a)))? {
+            memory.insert(name, limit);
+        }
+    }
+
+    Ok(memory)
+}
+
+/// Collect the state of the host.
+#[cfg(target_os = "freebsd")]
+fn host() -> Result<Host, JailError> {
+    trace!("capacity::host()");
+    let page_size = sysctl_u64("hw.pagesize");
+    let free = ["vm.stats.vm.v_free_count", "vm.stats.vm.v_inactive_count"]
+        .iter()
+        .map(|name| sysctl_u64(name))
+        .sum::<Option<u64>>();
+
+    #[cfg(feature = "rctl")]
+    let limits = memory_limits()?;
+
+    let mut jails = vec![];
+    for running in RunningJail::all() {
+        let name = running.name()?;
+        let ips = running.ips().unwrap_or_else(|e| {
+            warn!("could not get the addresses of jail {}: {}", running.jid, e);
+            vec![]
+        });
+        #[cfg(feature = "rctl")]
+        let memory = limits.get(&name).copied();
+        #[cfg(not(feature = "rctl"))]
+        let memory = None;
+
+        jails.push(Neighbour {
+            jid: running.jid,
+            name,
+            ips,
+            memory,
+        });
+    }
+
+    Ok(Host {
+        physical: sysctl_u64("hw.physmem"),
+        free: free.and_then(|pages| pages.checked_mul(page_size?)),
+        jails,
+    })
+}
+
+/// Check whether `stopped` is likely to conflict with the host or other
+/// jails when started.
+///
+/// The following is compared:
+///
+/// * The denying `memoryuse` limit of the jail against the free memory of
+///   the host, and, together with the limits of all running jails, against
+///   its physical memory.
+/// * The addresses of the jail against those of all running jails.
+/// * The name of the jail against those of all running jails.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{capacity, StoppedJail};
+///
+/// let stopped = StoppedJail::new("/rescue")
+///     .name("testjail_capacity")
+///     .ip("127.0.1.9".parse().unwrap());
+///
+/// let report = capacity::check(&stopped).expect("could not check capacity");
+/// assert!(report.is_ok(), "{}", report);
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn check(stopped: &StoppedJail) -> Result<Report, JailError> {
+    trace!("capacity::check({:?})", stopped);
+    Ok(Report {
+        conflicts: conflicts(stopped, &host()?)?,
+    })
+}
+
+#[cfg(all(test, feature = "rctl"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_conflicts() {
+        let gib = 1 << 30;
+        let host = Host {
+            physical: Some(8 * gib),
+            free: Some(2 * gib),
+            jails: vec![
+                Neighbour {
+                    jid: 1,
+                    name: "db".into(),
+                    ips: vec!["192.0.2.1".parse().unwrap()],
+                    memory: Some(5 * gib),
+                },
+                Neighbour {
+                    jid: 2,
+                    name: "www".into(),
+                    ips: vec!["192.0.2.2".parse().unwrap()],
+                    memory: None,
+                },
+            ],
+        };
+
+        let stopped = StoppedJail::new("/")
+            .name("www")
+            .ip("192.0.2.1".parse().unwrap())
+            .ip("192.0.2.3".parse().unwrap())
+            .limit(
+                rctl::Resource::MemoryUse,
+                rctl::Limit::amount(4 * gib as usize),
+                rctl::Action::Deny,
+            );
+
+        assert_eq!(
+            conflicts(&stopped, &host).unwrap(),
+            vec![
+                Conflict::NameInUse {
+                    name: "www".into(),
+                    jid: 2
+                },
+                Conflict::AddressInUse {
+                    address: "192.0.2.1".parse().unwrap(),
+                    jid: 1,
+                    name: "db".into()
+                },
+                Conflict::MemoryExceedsFree {
+                    requested: 4 * gib,
+                    free: 2 * gib
+                },
+                Conflict::MemoryOvercommitted {
+                    requested: 4 * gib,
+                    reserved: 5 * gib,
+                    physical: 8 * gib
+                },
+            ]
+        );
+
+        let small = StoppedJail::new("/").name("cache").limit(
+            rctl::Resource::MemoryUse,
+            rctl::Limit::amount(gib as usize),
+            rctl::Action::Deny,
+        );
+        assert!(conflicts(&small, &host).unwrap().is_empty());
+    }
+}
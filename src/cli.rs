@@ -0,0 +1,194 @@
+//! Jail definition flags for command line tools
+//!
+//! With the `cli` feature, [JailArgs] provides the flags defining a jail as
+//! `clap::Args`, so that command line tools can flatten them into their own
+//! parsers instead of translating flags to [StoppedJail] themselves.
+//!
+//! ```
+//! use clap::Parser;
+//! use jail::cli::JailArgs;
+//!
+//! #[derive(Parser)]
+//! struct Create {
+//!     #[clap(flatten)]
+//!     jail: JailArgs,
+//!
+//!     /// Print the jid of the started jail
+//!     #[clap(long)]
+//!     verbose: bool,
+//! }
+//!
+//! let create = Create::parse_from([
+//!     "create",
+//!     "www",
+//!     "--path",
+//!     "/rescue",
+//!     "--ip",
+//!     "192.0.2.1",
+//!     "--limit",
+//!     "maxproc=100",
+//! ]);
+//! let stopped = create.jail.into_stopped_jail().expect("invalid jail flags");
+//! assert_eq!(stopped.name, Some("www".into()));
+//! assert_eq!(stopped.ips, vec!["192.0.2.1".parse::<std::net::IpAddr>().unwrap()]);
+//! ```
+#[cfg(target_os = "freebsd")]
+use crate::param::Value;
+use crate::JailError;
+#[cfg(target_os = "freebsd")]
+use crate::StoppedJail;
+use log::trace;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The flags defining a jail, see the [module documentation](index.html).
+#[derive(clap::Args, Clone, Debug, PartialEq, Eq)]
+pub struct JailArgs {
+    /// The name of the jail
+    pub name: String,
+
+    /// The root directory of the jail
+    #[clap(long)]
+    pub path: PathBuf,
+
+    /// The hostname of the jail
+    #[clap(long)]
+    pub hostname: Option<String>,
+
+    /// An IP address of the jail
+    #[clap(long)]
+    pub ip: Vec<IpAddr>,
+
+    /// A parameter, as `name=value`
+    #[clap(long, value_name = "NAME=VALUE")]
+    pub param: Vec<String>,
+
+    /// A resource limit, as `resource=amount[/per][:action]`
+    #[clap(long, value_name = "RESOURCE=AMOUNT")]
+    pub limit: Vec<String>,
+}
+
+fn invalid(message: String) -> JailError {
+    JailError::IoError(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+/// Parse a parameter given as `name=value`, looking up the type of the
+/// parameter in the running kernel.
+#[cfg(target_os = "freebsd")]
+pub fn parse_param(s: &str) -> Result<(String, Value), JailError> {
+    trace!("cli::parse_param(s={:?})", s);
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| invalid(format!("invalid parameter {:?}: expected name=value", s)))?;
+    Ok((name.to_string(), Value::parse(name, value)?))
+}
+
+/// Parse a limit given as `resource=amount[/per][:action]`. The action
+/// defaults to `deny`.
+///
+/// # Examples
+///
+/// ```
+/// use jail::cli;
+///
+/// let (resource, limit, action) = cli::parse_limit("memoryuse=1073741824:log").unwrap();
+/// assert_eq!(resource, rctl::Resource::MemoryUse);
+/// assert_eq!(limit, rctl::Limit::amount(1 << 30));
+/// assert_eq!(action, rctl::Action::Log);
+/// ```
+pub fn parse_limit(s: &str) -> Result<crate::limits::Limit, JailError> {
+    trace!("cli::parse_limit(s={:?})", s);
+    let (resource, limit) = s
+        .split_once('=')
+        .ok_or_else(|| invalid(format!("invalid limit {:?}: expected resource=amount", s)))?;
+    let (amount, action) = limit.split_once(':').unwrap_or((limit, "deny"));
+
+    Ok((
+        rctl::Resource::from_str(resource)
+            .map_err(|_| invalid(format!("unknown resource {:?}", resource)))?,
+        rctl::Limit::from_str(amount)
+            .map_err(|_| invalid(format!("invalid amount {:?}", amount)))?,
+        rctl::Action::from_str(action)
+            .map_err(|_| invalid(format!("invalid action {:?}", action)))?,
+    ))
+}
+
+impl JailArgs {
+    /// Build the jail defined by the flags.
+    ///
+    /// Fails if a parameter or limit is malformed, or a parameter is not
+    /// supported by the running kernel.
+    #[cfg(target_os = "freebsd")]
+    pub fn into_stopped_jail(self) -> Result<StoppedJail, JailError> {
+        trace!("JailArgs::into_stopped_jail({:?})", self);
+        let mut stopped = StoppedJail::new(self.path).name(self.name);
+        if let Some(hostname) = self.hostname {
+            stopped = stopped.hostname(hostname);
+        }
+
+        for ip in self.ip {
+            stopped = stopped.ip(ip);
+        }
+
+        for param in &self.param {
+            let (name, value) = parse_param(param)?;
+            stopped = stopped.param(name, value);
+        }
+
+        for limit in &self.limit {
+            let (resource, limit, action) = parse_limit(limit)?;
+            stopped = stopped.limit(resource, limit, action);
+        }
+
+        Ok(stopped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser, Debug)]
+    struct Cli {
+        #[clap(flatten)]
+        jail: JailArgs,
+    }
+
+    #[test]
+    fn flags() {
+        let cli = Cli::try_parse_from([
+            "cli",
+            "www",
+            "--path",
+            "/usr/jails/www",
+            "--ip",
+            "192.0.2.1",
+            "--ip",
+            "2001:db8::1",
+            "--param",
+            "securelevel=3",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.jail.name, "www");
+        assert_eq!(cli.jail.path, PathBuf::from("/usr/jails/www"));
+        assert_eq!(cli.jail.hostname, None);
+        assert_eq!(cli.jail.ip.len(), 2);
+        assert_eq!(cli.jail.param, vec!["securelevel=3"]);
+        assert!(cli.jail.limit.is_empty());
+
+        assert!(Cli::try_parse_from(["cli", "www"]).is_err());
+        assert!(Cli::try_parse_from(["cli", "www", "--path", "/", "--ip", "nope"]).is_err());
+    }
+
+    #[test]
+    fn malformed_limits() {
+        assert!(parse_limit("maxproc").is_err());
+        assert!(parse_limit("").is_err());
+    }
+}
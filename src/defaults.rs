@@ -0,0 +1,112 @@
+//! Default jail parameter profiles
+//!
+//! A [Profile] seeds a [StoppedJail](../struct.StoppedJail.html) with a
+//! coherent set of parameters for a common use case. Any parameter can be
+//! overridden afterwards using the usual builder methods.
+use crate::param::Value;
+use log::trace;
+use std::collections::HashMap;
+use strum_macros::{Display, EnumString};
+
+/// A named set of jail parameters.
+///
+/// # Examples
+///
+/// ```
+/// use jail::defaults::Profile;
+/// use jail::param::Value;
+/// use jail::StoppedJail;
+///
+/// let stopped = StoppedJail::new("/rescue")
+///     .profile(Profile::Hardened)
+///     .param("allow.raw_sockets", Value::Int(1));
+///
+/// assert_eq!(stopped.params["securelevel"], Value::Int(3));
+/// assert_eq!(stopped.params["allow.raw_sockets"], Value::Int(1));
+/// ```
+///
+/// Profiles can also be parsed from their names:
+///
+/// ```
+/// use jail::defaults::Profile;
+/// assert_eq!("vnet-basic".parse::<Profile>().unwrap(), Profile::VnetBasic);
+/// assert_eq!(Profile::BuildJail.to_string(), "build-jail");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Profile {
+    /// A locked down jail: `securelevel` 3, no raw sockets, SysV IPC
+    /// disabled, no child jails, and the `devfsrules_jail` devfs ruleset (4).
+    Hardened,
+
+    /// A jail with its own virtual network stack and the
+    /// `devfsrules_jail_vnet` devfs ruleset (5). Raw sockets are permitted,
+    /// since they are confined to the jail's network stack.
+    VnetBasic,
+
+    /// A jail suitable for building software, e.g. with poudriere: mounting
+    /// of devfs, nullfs, procfs and tmpfs and `chflags`(2) are permitted, and
+    /// the jail has its own SysV IPC namespace.
+    BuildJail,
+}
+
+impl Profile {
+    /// Get the parameters making up this profile.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::defaults::Profile;
+    /// use jail::param::Value;
+    ///
+    /// let params = Profile::VnetBasic.params();
+    /// assert_eq!(params["vnet"], Value::Int(1));
+    /// ```
+    pub fn params(&self) -> HashMap<String, Value> {
+        trace!("Profile::params({:?})", self);
+        let params: &[(&str, libc::c_int)] = match self {
+            Profile::Hardened => &[
+                ("securelevel", 3),
+                ("devfs_ruleset", 4),
+                ("enforce_statfs", 2),
+                ("children.max", 0),
+                ("allow.raw_sockets", 0),
+                ("allow.set_hostname", 0),
+                // JAIL_SYS_DISABLE
+                ("sysvmsg", 0),
+                ("sysvsem", 0),
+                ("sysvshm", 0),
+                ("allow.chflags", 0),
+                ("allow.mount", 0),
+            ],
+            Profile::VnetBasic => &[
+                // JAIL_SYS_NEW
+                ("vnet", 1),
+                ("devfs_ruleset", 5),
+                ("enforce_statfs", 2),
+                ("children.max", 0),
+                ("allow.raw_sockets", 1),
+            ],
+            Profile::BuildJail => &[
+                ("enforce_statfs", 1),
+                ("children.max", 0),
+                ("allow.raw_sockets", 0),
+                ("allow.chflags", 1),
+                // JAIL_SYS_NEW
+                ("sysvmsg", 1),
+                ("sysvsem", 1),
+                ("sysvshm", 1),
+                ("allow.mount", 1),
+                ("allow.mount.devfs", 1),
+                ("allow.mount.nullfs", 1),
+                ("allow.mount.procfs", 1),
+                ("allow.mount.tmpfs", 1),
+            ],
+        };
+
+        params
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::Int(*value)))
+            .collect()
+    }
+}
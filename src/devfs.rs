@@ -0,0 +1,222 @@
+//! `devfs`(5) for jails
+//!
+//! The `devfs_ruleset` parameter of a jail only restricts the devices
+//! visible in `devfs` file systems mounted for the jail. Without such a
+//! mount, or `allow.mount.devfs` for the jail to mount one itself, it has no
+//! effect. A [DevfsConfig] mounts `devfs` below the jail root, applies the
+//! ruleset to it and sets the parameter to match, like the `mount.devfs`
+//! parameter of `jail`(8).
+use crate::param::Value;
+use crate::JailError;
+use log::trace;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[cfg(target_os = "freebsd")]
+use crate::mount;
+#[cfg(target_os = "freebsd")]
+use std::path::Path;
+#[cfg(target_os = "freebsd")]
+use std::process::Command;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// The `devfsrules_jail` ruleset defined in `/etc/defaults/devfs.rules`,
+/// which `jail`(8) uses by default.
+pub const DEVFSRULES_JAIL: u16 = 4;
+
+/// A `devfs` file system mounted for a jail, see
+/// [StoppedJail::devfs](../struct.StoppedJail.html#method.devfs).
+///
+/// # Examples
+///
+/// ```
+/// use jail::devfs::DevfsConfig;
+/// use jail::param::Value;
+/// use jail::StoppedJail;
+///
+/// let stopped = StoppedJail::new("/rescue").devfs(DevfsConfig::new().ruleset(5));
+/// assert_eq!(stopped.params["devfs_ruleset"], Value::Int(5));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct DevfsConfig {
+    /// The ruleset applied to the mount, 0 for none
+    pub ruleset: u16,
+
+    /// The mountpoint inside the jail
+    pub mountpoint: PathBuf,
+}
+
+impl Default for DevfsConfig {
+    fn default() -> Self {
+        DevfsConfig {
+            ruleset: DEVFSRULES_JAIL,
+            mountpoint: PathBuf::from("/dev"),
+        }
+    }
+}
+
+impl DevfsConfig {
+    /// Mount `devfs` at `/dev` with the [DEVFSRULES_JAIL] ruleset.
+    pub fn new() -> Self {
+        trace!("DevfsConfig::new()");
+        DevfsConfig::default()
+    }
+
+    /// Set the ruleset applied to the mount. It must be defined on the host,
+    /// e.g. in `/etc/devfs.rules`.
+    pub fn ruleset(mut self, ruleset: u16) -> Self {
+        trace!("DevfsConfig::ruleset({:?}, ruleset={})", self, ruleset);
+        self.ruleset = ruleset;
+        self
+    }
+
+    /// Set the mountpoint inside the jail.
+    pub fn mountpoint<P: Into<PathBuf>>(mut self, mountpoint: P) -> Self {
+        let mountpoint = mountpoint.into();
+        trace!(
+            "DevfsConfig::mountpoint({:?}, mountpoint={:?})",
+            self,
+            mountpoint
+        );
+        self.mountpoint = mountpoint;
+        self
+    }
+
+    /// Check the configuration against the parameters of the jail.
+    #[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+    pub(crate) fn validate(&self, params: &HashMap<String, Value>) -> Result<(), JailError> {
+        if !self.mountpoint.is_absolute() {
+            return Err(JailError::InvalidDevfsConfig(format!(
+                "the mountpoint {:?} is not an absolute path",
+                self.mountpoint
+            )));
+        }
+
+        match params.get("devfs_ruleset") {
+            None => Ok(()),
+            Some(Value::Int(ruleset)) if *ruleset == i32::from(self.ruleset) => Ok(()),
+            Some(value) => Err(JailError::InvalidDevfsConfig(format!(
+                "devfs_ruleset is {:?}, but devfs is mounted with ruleset {}",
+                value, self.ruleset
+            ))),
+        }
+    }
+
+    /// Validate the configuration and check that the ruleset is defined.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn check(&self, params: &HashMap<String, Value>) -> Result<(), JailError> {
+        trace!("DevfsConfig::check({:?})", self);
+        self.validate(params)?;
+
+        if self.ruleset != 0 && !ruleset_exists(self.ruleset)? {
+            return Err(JailError::InvalidDevfsConfig(format!(
+                "ruleset {} is not defined, see devfs.rules(5)",
+                self.ruleset
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Mount `devfs` below the jail root at `root` and apply the ruleset,
+    /// returning the host path of the mountpoint.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn mount(&self, root: &Path) -> Result<PathBuf, JailError> {
+        trace!("DevfsConfig::mount({:?}, root={:?})", self, root);
+        let target = crate::fs::host_path(root, &self.mountpoint)?;
+        std::fs::create_dir_all(&target).map_err(JailError::IoError)?;
+        mount::nmount("devfs", Path::new("devfs"), &target, 0, &[])?;
+
+        if self.ruleset != 0 {
+            let target_str = target.to_string_lossy();
+            let ruleset = self.ruleset.to_string();
+            let applied = devfs(&["-m", &target_str, "ruleset", &ruleset])
+                .and_then(|_| devfs(&["-m", &target_str, "rule", "applyset"]));
+            if let Err(e) = applied {
+                mount::unmount(&target).ok();
+                return Err(e);
+            }
+        }
+
+        Ok(target)
+    }
+}
+
+/// Check whether the ruleset `ruleset` is defined. Rulesets without rules
+/// are taken as undefined, as they do not hide any devices.
+#[cfg(target_os = "freebsd")]
+fn ruleset_exists(ruleset: u16) -> Result<bool, JailError> {
+    let ruleset = ruleset.to_string();
+    match devfs(&["rule", "-s", &ruleset, "show"]) {
+        Ok(rules) => Ok(!rules.trim().is_empty()),
+        Err(JailError::DevfsError(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Run `devfs` with the given arguments, returning its standard output.
+#[cfg(target_os = "freebsd")]
+fn devfs(args: &[&str]) -> Result<String, JailError> {
+    trace!("devfs(args={:?})", args);
+    let output = Command::new("devfs")
+        .args(args)
+        .output()
+        .map_err(JailError::IoError)?;
+
+    if !output.status.success() {
+        return Err(JailError::DevfsError(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Explain why the `devfs_ruleset` in `params` has no effect without a
+/// [DevfsConfig], if it has none.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+pub(crate) fn unused_ruleset(params: &HashMap<String, Value>) -> Option<String> {
+    let ruleset = match params.get("devfs_ruleset") {
+        Some(Value::Int(0)) | None => return None,
+        Some(ruleset) => ruleset,
+    };
+
+    match params.get("allow.mount.devfs") {
+        Some(Value::Int(allowed)) if *allowed != 0 => None,
+        _ => Some(format!(
+            "devfs_ruleset is {:?}, but no devfs is mounted for the jail and it may not mount \
+             one itself; use StoppedJail::devfs to mount one",
+            ruleset
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_devfs_config() {
+        let mut params = HashMap::new();
+        assert!(DevfsConfig::new().validate(&params).is_ok());
+        assert!(DevfsConfig::new()
+            .mountpoint("dev")
+            .validate(&params)
+            .is_err());
+
+        params.insert("devfs_ruleset".to_string(), Value::Int(4));
+        assert!(DevfsConfig::new().validate(&params).is_ok());
+        assert!(matches!(
+            DevfsConfig::new().ruleset(5).validate(&params),
+            Err(JailError::InvalidDevfsConfig(_))
+        ));
+
+        assert!(unused_ruleset(&params).is_some());
+        params.insert("allow.mount.devfs".to_string(), Value::Int(1));
+        assert!(unused_ruleset(&params).is_none());
+        assert!(unused_ruleset(&HashMap::new()).is_none());
+    }
+}
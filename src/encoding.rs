@@ -0,0 +1,225 @@
+//! Encoding of jail parameter values
+//!
+//! The kernel exchanges parameter values with `jail_set`(2) and `jail_get`(2)
+//! in their in-memory representation. [encode] and [decode] are the only
+//! places converting between that representation and [Value]:
+//!
+//! * integers use the byte order and width of the host, e.g. `c_long` is 8
+//!   bytes on LP64 and 4 bytes on ILP32 platforms,
+//! * addresses are stored as `in_addr` and `in6_addr` arrays, which are in
+//!   network byte order on every platform,
+//! * strings are nul terminated,
+//! * sizes reported by the kernel are `size_t` of the kernel, which is wider
+//!   than `usize` for 32-bit processes on a 64-bit kernel, see [decode_size].
+use crate::param::{Type, Value};
+use crate::JailError;
+use std::convert::{TryFrom, TryInto};
+use std::ffi::CString;
+use std::mem;
+use std::net;
+
+/// Encode a value into the representation expected by the kernel.
+///
+/// Values of [Type::Custom] are encoded by their codec, not here.
+pub(crate) fn encode(value: &Value) -> Result<Vec<u8>, JailError> {
+    Ok(match value {
+        Value::Int(v) => v.to_ne_bytes().to_vec(),
+        Value::String(s) => CString::new(s.as_str())
+            .map_err(JailError::CStringError)?
+            .into_bytes_with_nul(),
+        Value::S64(v) => v.to_ne_bytes().to_vec(),
+        Value::Uint(v) => v.to_ne_bytes().to_vec(),
+        Value::Long(v) => v.to_ne_bytes().to_vec(),
+        Value::Ulong(v) => v.to_ne_bytes().to_vec(),
+        Value::U64(v) => v.to_ne_bytes().to_vec(),
+        Value::U8(v) => vec![*v],
+        Value::U16(v) => v.to_ne_bytes().to_vec(),
+        Value::S8(v) => v.to_ne_bytes().to_vec(),
+        Value::S16(v) => v.to_ne_bytes().to_vec(),
+        Value::S32(v) => v.to_ne_bytes().to_vec(),
+        Value::U32(v) => v.to_ne_bytes().to_vec(),
+        Value::Ipv4Addrs(addrs) => addrs.iter().flat_map(net::Ipv4Addr::octets).collect(),
+        Value::Ipv6Addrs(addrs) => addrs.iter().flat_map(net::Ipv6Addr::octets).collect(),
+        Value::Raw(data) => data.clone(),
+        Value::Custom(_) => return Err(JailError::SerializeFailed),
+    })
+}
+
+/// Get the leading `N` bytes of a buffer returned by the kernel.
+fn fixed<const N: usize>(ty: Type, bytes: &[u8]) -> Result<[u8; N], JailError> {
+    bytes
+        .get(..N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            JailError::ParameterDecodeError(format!(
+                "{:?} needs {} bytes, but got {}",
+                ty,
+                N,
+                bytes.len()
+            ))
+        })
+}
+
+/// Split an address array into its elements, failing on trailing bytes.
+fn addresses<const N: usize>(ty: Type, bytes: &[u8]) -> Result<Vec<[u8; N]>, JailError> {
+    let chunks = bytes.chunks_exact(N);
+    if !chunks.remainder().is_empty() {
+        return Err(JailError::ParameterDecodeError(format!(
+            "{:?} needs a multiple of {} bytes, but got {}",
+            ty,
+            N,
+            bytes.len()
+        )));
+    }
+
+    Ok(chunks
+        .map(|chunk| chunk.try_into().expect("chunk has the exact size"))
+        .collect())
+}
+
+/// Decode a value of the given type returned by the kernel.
+///
+/// Buffers may be longer than the value they hold: strings end at the first
+/// nul byte, and unused slots of address arrays are zero and skipped.
+pub(crate) fn decode(ty: Type, bytes: &[u8]) -> Result<Value, JailError> {
+    Ok(match ty {
+        Type::Int => Value::Int(libc::c_int::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::String => {
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            Value::String(String::from_utf8_lossy(&bytes[..end]).into_owned())
+        }
+        Type::S64 => Value::S64(i64::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::Uint => Value::Uint(libc::c_uint::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::Long => Value::Long(libc::c_long::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::Ulong => Value::Ulong(libc::c_ulong::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::U64 => Value::U64(u64::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::U8 => Value::U8(u8::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::U16 => Value::U16(u16::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::S8 => Value::S8(i8::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::S16 => Value::S16(i16::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::S32 => Value::S32(i32::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::U32 => Value::U32(u32::from_ne_bytes(fixed(ty, bytes)?)),
+        Type::Ipv4Addrs => Value::Ipv4Addrs(
+            addresses::<{ mem::size_of::<libc::in_addr>() }>(ty, bytes)?
+                .into_iter()
+                .map(net::Ipv4Addr::from)
+                .filter(|ip| !ip.is_unspecified())
+                .collect(),
+        ),
+        Type::Ipv6Addrs => Value::Ipv6Addrs(
+            addresses::<{ mem::size_of::<libc::in6_addr>() }>(ty, bytes)?
+                .into_iter()
+                .map(net::Ipv6Addr::from)
+                .filter(|ip| !ip.is_unspecified())
+                .collect(),
+        ),
+        Type::Raw => Value::Raw(bytes.to_vec()),
+        Type::Custom => return Err(JailError::SerializeFailed),
+    })
+}
+
+/// Decode a `size_t` reported by the kernel, e.g. the size of a struct
+/// parameter, from its native byte order.
+///
+/// The width is taken from the buffer rather than from `usize`, since the
+/// kernel may be 64-bit while the process is not.
+pub(crate) fn decode_size(bytes: &[u8]) -> Option<usize> {
+    match bytes.len() {
+        4 => usize::try_from(u32::from_ne_bytes(bytes.try_into().ok()?)).ok(),
+        8 => usize::try_from(u64::from_ne_bytes(bytes.try_into().ok()?)).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn values() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<libc::c_int>().prop_map(Value::Int),
+            "[^\u{0}]*".prop_map(Value::String),
+            any::<i64>().prop_map(Value::S64),
+            any::<libc::c_uint>().prop_map(Value::Uint),
+            any::<libc::c_long>().prop_map(Value::Long),
+            any::<libc::c_ulong>().prop_map(Value::Ulong),
+            any::<u64>().prop_map(Value::U64),
+            any::<u8>().prop_map(Value::U8),
+            any::<u16>().prop_map(Value::U16),
+            any::<i8>().prop_map(Value::S8),
+            any::<i16>().prop_map(Value::S16),
+            any::<i32>().prop_map(Value::S32),
+            any::<u32>().prop_map(Value::U32),
+            prop::collection::vec(any::<[u8; 4]>(), 0..8).prop_map(|addrs| {
+                Value::Ipv4Addrs(
+                    addrs
+                        .into_iter()
+                        .map(net::Ipv4Addr::from)
+                        .filter(|ip| !ip.is_unspecified())
+                        .collect(),
+                )
+            }),
+            prop::collection::vec(any::<[u8; 16]>(), 0..8).prop_map(|addrs| {
+                Value::Ipv6Addrs(
+                    addrs
+                        .into_iter()
+                        .map(net::Ipv6Addr::from)
+                        .filter(|ip| !ip.is_unspecified())
+                        .collect(),
+                )
+            }),
+            prop::collection::vec(any::<u8>(), 0..64).prop_map(Value::Raw),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(value in values()) {
+            let bytes = encode(&value).unwrap();
+            prop_assert_eq!(decode(value.get_type(), &bytes).unwrap(), value);
+        }
+
+        #[test]
+        fn padded_buffers(addrs in prop::collection::vec(any::<[u8; 4]>(), 1..8), padding in 0..4usize) {
+            let addrs: Vec<net::Ipv4Addr> = addrs
+                .into_iter()
+                .map(net::Ipv4Addr::from)
+                .filter(|ip| !ip.is_unspecified())
+                .collect();
+            let mut bytes = encode(&Value::Ipv4Addrs(addrs.clone())).unwrap();
+            bytes.resize(bytes.len() + 4 * padding, 0);
+            prop_assert_eq!(decode(Type::Ipv4Addrs, &bytes).unwrap(), Value::Ipv4Addrs(addrs));
+        }
+    }
+
+    #[test]
+    fn byte_order() {
+        let ip = Value::Ipv4Addrs(vec!["192.0.2.1".parse().unwrap()]);
+        assert_eq!(encode(&ip).unwrap(), vec![192, 0, 2, 1]);
+        assert_eq!(encode(&Value::U32(1)).unwrap(), 1u32.to_ne_bytes().to_vec());
+
+        let mut string = encode(&Value::String("www".into())).unwrap();
+        assert_eq!(string, b"www\0");
+        string.extend_from_slice(b"garbage");
+        assert_eq!(
+            decode(Type::String, &string).unwrap(),
+            Value::String("www".into())
+        );
+
+        assert!(decode(Type::U64, &[0; 4]).is_err());
+        assert!(decode(Type::Ipv6Addrs, &[0; 17]).is_err());
+        assert!(encode(&Value::String("a\0b".into())).is_err());
+    }
+
+    #[test]
+    fn kernel_sizes() {
+        assert_eq!(decode_size(&16u32.to_ne_bytes()), Some(16));
+        assert_eq!(decode_size(&16u64.to_ne_bytes()), Some(16));
+        assert_eq!(decode_size(&[16]), None);
+        assert_eq!(decode_size(&[0; 16]), None);
+
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(decode_size(&(1u64 << 32).to_ne_bytes()), None);
+    }
+}
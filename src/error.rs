@@ -1,5 +1,7 @@
 use crate::param;
 use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
 /// An enum for error types of the Jail.
@@ -9,10 +11,10 @@ pub enum JailError {
     IoError(io::Error),
 
     #[error("jail_get syscall failed. The error message returned was: {0}")]
-    JailGetError(String),
+    JailGetError(jail_sys::ErrorMessage),
 
     #[error("jail_set syscall failed. The error message returned was: {0}")]
-    JailSetError(String),
+    JailSetError(jail_sys::ErrorMessage),
 
     #[error("jail_attach syscall failed. The error message returned was: {0}")]
     JailAttachError(io::Error),
@@ -57,20 +59,185 @@ pub enum JailError {
         got: param::Value,
     },
 
+    #[error("Could not parse value '{value}' of parameter '{name}'")]
+    ParameterParseError { name: String, value: String },
+
+    #[error("Could not decode parameter value: {0}")]
+    ParameterDecodeError(String),
+
+    #[error("Parameter '{name}' is {kernel} bytes in the kernel, but {host} bytes on the host")]
+    ParameterSizeMismatch {
+        name: String,
+        host: usize,
+        kernel: usize,
+    },
+
     #[error("Failed to unpack parameter.")]
     ParameterUnpackError,
 
     #[error("Could not serialize value to bytes")]
     SerializeFailed,
 
+    #[error("No codec registered for parameter '{0}'")]
+    CodecNotRegistered(String),
+
+    #[cfg(feature = "rctl")]
     #[error("RCTL Error: {0}")]
     RctlError(rctl::Error),
 
+    #[cfg(feature = "rctl")]
     #[error("Jail must have a name if RCTL limits are to be set")]
     UnnamedButLimited,
 
+    #[cfg(feature = "rctl")]
+    #[error("Invalid RCTL rule {rule:?}: {message}")]
+    InvalidRctlRule { rule: String, message: String },
+
     #[error("Error creating a CString: {0:?}")]
     CStringError(std::ffi::NulError),
+
+    #[error("Could not mount or unmount {path:?}: {source}")]
+    MountError { path: PathBuf, source: io::Error },
+
+    #[error("jail {jid} was removed, but {paths:?} are still mounted")]
+    StillMounted { jid: i32, paths: Vec<PathBuf> },
+
+    #[error("{path:?} was mounted without {missing:?}")]
+    MountFlagsIgnored { path: PathBuf, missing: Vec<String> },
+
+    #[error("refusing to mount {path:?} read-write, see Mount::allow_sensitive")]
+    SensitiveMount { path: PathBuf },
+
+    #[error("Provisioning step '{step}' failed: {message}")]
+    ProvisionError { step: String, message: String },
+
+    #[error("{hook} command {command:?} failed: {message}")]
+    ExecHookFailed {
+        hook: String,
+        command: String,
+        message: String,
+    },
+
+    #[error("Could not daemonize in jail, {step} failed: {source}")]
+    DaemonizeFailed { step: String, source: io::Error },
+
+    #[error("pidfile {path:?} is in use by another process")]
+    PidfileInUse {
+        path: PathBuf,
+        pid: Option<libc::pid_t>,
+    },
+
+    #[error("Could not expand variable {name}: {message}")]
+    VariableError { name: String, message: String },
+
+    #[error("Could not parse manifest line {line}: {message}")]
+    ManifestParseError { line: usize, message: String },
+
+    #[error("Could not parse configuration line {line}: {message}")]
+    ConfigParseError { line: usize, message: String },
+
+    #[cfg(feature = "journal")]
+    #[error("Could not parse journal line {line}: {message}")]
+    JournalParseError { line: usize, message: String },
+
+    #[cfg(feature = "zfs")]
+    #[error("zfs command failed: {0}")]
+    ZfsError(String),
+
+    #[error("devfs command failed: {0}")]
+    DevfsError(String),
+
+    #[error("Invalid devfs configuration: {0}")]
+    InvalidDevfsConfig(String),
+
+    #[cfg(feature = "ipfw")]
+    #[error("ipfw command failed: {0}")]
+    IpfwError(String),
+
+    #[error("Service '{service}' failed: {message}")]
+    ServiceError { service: String, message: String },
+
+    #[error("Could not manage users or groups: {0}")]
+    UserError(String),
+
+    #[error("jid {0} is already in use by a running or dying jail")]
+    JidInUse(i32),
+
+    #[error("no jail with jid {0} exists")]
+    NoSuchJail(i32),
+
+    #[error("Invalid jail dependencies: {0}")]
+    DependencyError(String),
+
+    #[error("{feature} requires FreeBSD {required} or later, but the host runs FreeBSD {host}")]
+    UnsupportedOnRelease {
+        feature: String,
+        required: String,
+        host: String,
+    },
+
+    #[error("Invalid jail name {name:?}: {reason}")]
+    InvalidName { name: String, reason: String },
+
+    #[error("a jail named {0} is already running")]
+    NameInUse(String),
+
+    #[error("{operation} not permitted: {reason}")]
+    NotPermitted { operation: String, reason: String },
+
+    #[error("Invalid host name {hostname:?}: {reason}")]
+    InvalidHostname { hostname: String, reason: String },
+
+    #[error("Invalid network {network:?}: {reason}")]
+    InvalidNetwork { network: String, reason: String },
+
+    #[error("no free address left in {0}")]
+    AddressPoolExhausted(String),
+
+    #[error("{0} not present on any interface; add it as an alias to a host interface")]
+    IpNotConfigured(std::net::IpAddr),
+
+    #[error("{ip} is already used by jail {name} (jid {jid})")]
+    IpInUse {
+        ip: std::net::IpAddr,
+        jid: i32,
+        name: String,
+    },
+
+    #[error("ifconfig command failed: {0}")]
+    IfconfigError(String),
+
+    #[error("ngctl command failed: {0}")]
+    NgctlError(String),
+
+    #[error("Operation not supported: {0}")]
+    NotSupported(String),
+
+    #[error("jail {jid} was not ready after {waited:?}: waiting until {condition}")]
+    NotReady {
+        jid: i32,
+        condition: String,
+        waited: Duration,
+    },
+
+    #[error("jail {jid} still runs {remaining} processes after the grace period")]
+    StopTimeout { jid: i32, remaining: usize },
+
+    #[error("jail {0} runs no processes, clearing persist would remove it")]
+    NoJailedProcesses(i32),
+
+    #[error("process {pid} does not run in jail {jid}")]
+    ProcessNotJailed { pid: u32, jid: i32 },
+
+    #[error("jail syscall failed: {0}")]
+    LegacyJailError(io::Error),
+
+    #[error("sysctl {name} failed in jail {jid}: {error}")]
+    JailSysctlError {
+        name: String,
+        jid: i32,
+        error: io::Error,
+    },
 }
 
 impl JailError {
@@ -78,3 +245,17 @@ impl JailError {
         JailError::IoError(io::Error::last_os_error())
     }
 }
+
+impl From<jail_sys::Error> for JailError {
+    fn from(e: jail_sys::Error) -> Self {
+        match e {
+            jail_sys::Error::Errno(errno) => {
+                JailError::IoError(io::Error::from_raw_os_error(errno))
+            }
+            jail_sys::Error::JailSet(message) => JailError::JailSetError(message),
+            jail_sys::Error::JailGet(message) => JailError::JailGetError(message),
+            jail_sys::Error::Nul(e) => JailError::CStringError(e),
+            e => JailError::IoError(io::Error::other(e.to_string())),
+        }
+    }
+}
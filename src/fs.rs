@@ -0,0 +1,633 @@
+//! Access to files below the root directory of a jail
+use crate::passwd;
+use crate::JailError;
+use log::trace;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// The maximum number of symbolic links followed while resolving a path,
+/// matching `MAXSYMLINKS` on FreeBSD.
+const MAXSYMLINKS: usize = 32;
+
+/// Translate a path inside a jail into a path on the host.
+///
+/// `path` is resolved as it would be by a process chrooted to `root`:
+/// symbolic links are followed relative to `root`, absolute link targets are
+/// interpreted relative to `root`, and `..` never ascends above it. The
+/// returned host path is therefore always below `root`. Components that do
+/// not exist are appended as they are.
+///
+/// Note that the jail may modify its file system concurrently, so the result
+/// is only valid as long as the jail does not replace any of the resolved
+/// components by symbolic links.
+///
+/// # Examples
+///
+/// ```
+/// use jail::fs;
+/// use std::path::{Path, PathBuf};
+///
+/// let host = fs::host_path(Path::new("/rescue"), Path::new("/../../etc/rc.conf"))
+///     .expect("could not resolve path");
+/// assert_eq!(host, PathBuf::from("/rescue/etc/rc.conf"));
+/// ```
+pub fn host_path(root: &Path, path: &Path) -> Result<PathBuf, JailError> {
+    trace!("host_path(root={:?}, path={:?})", root, path);
+    let mut resolved: Vec<OsString> = vec![];
+    let mut pending: VecDeque<OsString> = components(path);
+    let mut links = 0;
+
+    while let Some(component) = pending.pop_front() {
+        match component.to_str() {
+            Some("/") => resolved.clear(),
+            Some(".") => {}
+            Some("..") => {
+                resolved.pop();
+            }
+            _ => {
+                let candidate: PathBuf = resolved
+                    .iter()
+                    .fold(root.to_path_buf(), |p, c| p.join(c))
+                    .join(&component);
+
+                match fs::symlink_metadata(&candidate) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => {
+                        links += 1;
+                        if links > MAXSYMLINKS {
+                            return Err(JailError::IoError(io::Error::from_raw_os_error(
+                                libc::ELOOP,
+                            )));
+                        }
+
+                        let target = fs::read_link(&candidate).map_err(JailError::IoError)?;
+                        for c in components(&target).into_iter().rev() {
+                            pending.push_front(c);
+                        }
+                    }
+                    _ => resolved.push(component),
+                }
+            }
+        }
+    }
+
+    Ok(resolved.iter().fold(root.to_path_buf(), |p, c| p.join(c)))
+}
+
+/// Translate a path on the host into the path seen inside a jail.
+///
+/// `host_path` is normalized lexically before being compared to `root`.
+/// Returns `None` if the path is not below `root`.
+///
+/// # Examples
+///
+/// ```
+/// use jail::fs;
+/// use std::path::{Path, PathBuf};
+///
+/// let root = Path::new("/jails/www");
+/// assert_eq!(
+///     fs::jail_path(root, Path::new("/jails/www/usr/local/etc/nginx.conf")),
+///     Some(PathBuf::from("/usr/local/etc/nginx.conf"))
+/// );
+/// assert_eq!(fs::jail_path(root, Path::new("/jails/www/../db/etc")), None);
+/// ```
+pub fn jail_path(root: &Path, host_path: &Path) -> Option<PathBuf> {
+    trace!("jail_path(root={:?}, host_path={:?})", root, host_path);
+    let root = normalize(root);
+    normalize(host_path)
+        .strip_prefix(&root)
+        .ok()
+        .map(|relative| Path::new("/").join(relative))
+}
+
+/// Lexically normalize a path, removing `.` and resolving `..` components.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Split a path into its components, representing the root directory as
+/// `/`, the current directory as `.` and the parent directory as `..`.
+fn components(path: &Path) -> VecDeque<OsString> {
+    path.components()
+        .map(|c| match c {
+            Component::Prefix(_) | Component::RootDir => "/".into(),
+            Component::CurDir => ".".into(),
+            Component::ParentDir => "..".into(),
+            Component::Normal(name) => name.to_os_string(),
+        })
+        .collect()
+}
+
+/// An iterator over the lines appended to a file inside a jail.
+///
+/// Created by [RunningJail::tail_file](../struct.RunningJail.html#method.tail_file).
+/// Iteration blocks until a new line is available. When the file is rotated
+/// (replaced by a new file) or truncated, the new contents are followed from
+/// the beginning.
+#[derive(Debug)]
+pub struct Tail {
+    root: PathBuf,
+    path: PathBuf,
+    reader: BufReader<fs::File>,
+    inode: (u64, u64),
+    position: u64,
+    line: Vec<u8>,
+    poll_interval: Duration,
+}
+
+impl Tail {
+    /// Follow the file at `path` inside the jail rooted at `root`, starting
+    /// at its current end.
+    pub(crate) fn follow(root: &Path, path: &Path) -> Result<Tail, JailError> {
+        trace!("Tail::follow(root={:?}, path={:?})", root, path);
+        let (file, inode) = Self::open(root, path)?;
+        let mut reader = BufReader::new(file);
+        let position = reader.seek(SeekFrom::End(0)).map_err(JailError::IoError)?;
+
+        Ok(Tail {
+            root: root.to_path_buf(),
+            path: path.to_path_buf(),
+            reader,
+            inode,
+            position,
+            line: vec![],
+            poll_interval: Duration::from_millis(250),
+        })
+    }
+
+    /// Set the interval in which the file is checked for new lines.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    fn open(root: &Path, path: &Path) -> Result<(fs::File, (u64, u64)), JailError> {
+        let file = fs::File::open(host_path(root, path)?).map_err(JailError::IoError)?;
+        let metadata = file.metadata().map_err(JailError::IoError)?;
+        Ok((file, (metadata.dev(), metadata.ino())))
+    }
+
+    /// Check whether the file has been rotated or truncated, and reopen it
+    /// if so. Returns whether the file was reopened.
+    fn reopen_if_rotated(&mut self) -> Result<bool, JailError> {
+        let metadata = match host_path(&self.root, &self.path)
+            .and_then(|p| fs::metadata(p).map_err(JailError::IoError))
+        {
+            Ok(metadata) => metadata,
+            // The file may be missing for a moment during rotation.
+            Err(_) => return Ok(false),
+        };
+
+        let rotated = (metadata.dev(), metadata.ino()) != self.inode;
+        let truncated = metadata.len() < self.position;
+
+        if rotated || truncated {
+            trace!("Tail: {:?} was rotated or truncated", self.path);
+            let (file, inode) = Self::open(&self.root, &self.path)?;
+            self.reader = BufReader::new(file);
+            self.inode = inode;
+            self.position = 0;
+        }
+
+        Ok(rotated || truncated)
+    }
+}
+
+impl Iterator for Tail {
+    type Item = Result<String, JailError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Err(e) => return Some(Err(JailError::IoError(e))),
+                Ok(read) => self.position += read as u64,
+            }
+
+            if self.line.last() == Some(&b'\n') {
+                self.line.pop();
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.line.clear();
+                return Some(Ok(line));
+            }
+
+            // Reached the end of the file, possibly in the middle of a line.
+            match self.reopen_if_rotated() {
+                Err(e) => return Some(Err(e)),
+                // Emit the unterminated last line of the previous file.
+                Ok(true) if !self.line.is_empty() => {
+                    let line = String::from_utf8_lossy(&self.line).into_owned();
+                    self.line.clear();
+                    return Some(Ok(line));
+                }
+                Ok(_) => {}
+            }
+
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+/// How the owner of copied files is determined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ownership {
+    /// Copies are owned by the calling user, like with `cp(1)`.
+    Caller,
+
+    /// The numeric user and group ids of the source are preserved.
+    Preserve,
+
+    /// User and group ids are translated by name between the `passwd(5)`
+    /// and `group(5)` databases of the source and the destination. Ids
+    /// without a matching name on the other side are preserved.
+    Remap,
+}
+
+/// Options for copying files into and out of a jail.
+///
+/// Used by [RunningJail::copy_in_with](../struct.RunningJail.html#method.copy_in_with)
+/// and [RunningJail::copy_out_with](../struct.RunningJail.html#method.copy_out_with).
+///
+/// # Examples
+///
+/// ```
+/// use jail::fs::{CopyOptions, Ownership};
+///
+/// let options = CopyOptions::new().ownership(Ownership::Remap);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    ownership: Ownership,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            ownership: Ownership::Caller,
+        }
+    }
+}
+
+impl CopyOptions {
+    /// Create the default options: copies are owned by the calling user.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how the owner of copied files is determined.
+    pub fn ownership(mut self, ownership: Ownership) -> Self {
+        self.ownership = ownership;
+        self
+    }
+}
+
+/// One side of a copy: either the host, or the file system of a jail.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Side<'a> {
+    Host,
+    Jail(&'a Path),
+}
+
+impl<'a> Side<'a> {
+    fn root(&self) -> &Path {
+        match self {
+            Side::Host => Path::new("/"),
+            Side::Jail(root) => root,
+        }
+    }
+
+    /// Translate `path` into a host path without following a symbolic link
+    /// in its final component.
+    pub(crate) fn resolve(&self, path: &Path) -> Result<PathBuf, JailError> {
+        match (self, path.file_name()) {
+            (Side::Host, _) => Ok(path.to_path_buf()),
+            (Side::Jail(root), Some(name)) => {
+                let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+                Ok(host_path(root, parent)?.join(name))
+            }
+            (Side::Jail(root), None) => host_path(root, path),
+        }
+    }
+}
+
+/// Replace the file at `path` inside the jail `root` with `contents`.
+///
+/// The contents are written to a temporary file next to it, which is then
+/// renamed over `path`. A symbolic link at `path` is therefore replaced
+/// instead of followed, and readers never see a partially written file.
+pub(crate) fn write_file(
+    root: &Path,
+    path: &Path,
+    contents: &[u8],
+    mode: u32,
+) -> Result<(), JailError> {
+    trace!(
+        "write_file(root={:?}, path={:?}, mode={:o})",
+        root,
+        path,
+        mode
+    );
+    let target = Side::Jail(root).resolve(path)?;
+    let name = target.file_name().ok_or_else(|| {
+        JailError::IoError(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} does not name a file", path),
+        ))
+    })?;
+
+    let mut temporary = OsString::from(".");
+    temporary.push(name);
+    temporary.push(".tmp");
+    let temporary = target.with_file_name(temporary);
+
+    match fs::remove_file(&temporary) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(JailError::IoError(e)),
+        _ => {}
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&temporary)
+        .map_err(JailError::IoError)?;
+
+    file.write_all(contents)
+        .and_then(|_| file.set_permissions(fs::Permissions::from_mode(mode)))
+        .and_then(|_| fs::rename(&temporary, &target))
+        .map_err(|e| {
+            fs::remove_file(&temporary).ok();
+            JailError::IoError(e)
+        })
+}
+
+/// Copy `src` on one side to `dst` on the other, recursing into directories.
+///
+/// Symbolic links are copied as symbolic links and never followed, so the
+/// copy cannot be redirected outside of the jail root. If `dst` is an
+/// existing directory, `src` is copied into it.
+pub(crate) fn copy(
+    from: Side,
+    src: &Path,
+    to: Side,
+    dst: &Path,
+    options: &CopyOptions,
+) -> Result<(), JailError> {
+    trace!(
+        "copy(from={:?}, src={:?}, to={:?}, dst={:?}, options={:?})",
+        from,
+        src,
+        to,
+        dst,
+        options
+    );
+    let owners = match options.ownership {
+        Ownership::Remap => Some(Owners::new(from.root(), to.root())?),
+        _ => None,
+    };
+
+    let mut dst = dst.to_path_buf();
+    let is_dir = fs::symlink_metadata(to.resolve(&dst)?)
+        .map(|m| m.is_dir())
+        .unwrap_or(false);
+    if let (true, Some(name)) = (is_dir, src.file_name()) {
+        dst.push(name);
+    }
+
+    Copier {
+        from,
+        to,
+        ownership: options.ownership,
+        owners,
+    }
+    .copy(src, &dst)
+}
+
+/// Mapping of user and group ids between two `passwd(5)` databases.
+struct Owners {
+    users: HashMap<u32, u32>,
+    groups: HashMap<u32, u32>,
+}
+
+impl Owners {
+    fn new(from: &Path, to: &Path) -> Result<Owners, JailError> {
+        Ok(Owners {
+            users: Self::map(passwd::users(from)?, passwd::users(to)?),
+            groups: Self::map(passwd::groups(from)?, passwd::groups(to)?),
+        })
+    }
+
+    fn map(from: Vec<passwd::Entry>, to: Vec<passwd::Entry>) -> HashMap<u32, u32> {
+        let ids: HashMap<String, u32> = to.into_iter().map(|e| (e.name, e.id)).collect();
+        from.into_iter()
+            .filter_map(|e| ids.get(&e.name).map(|id| (e.id, *id)))
+            .collect()
+    }
+}
+
+struct Copier<'a> {
+    from: Side<'a>,
+    to: Side<'a>,
+    ownership: Ownership,
+    owners: Option<Owners>,
+}
+
+impl<'a> Copier<'a> {
+    fn copy(&self, src: &Path, dst: &Path) -> Result<(), JailError> {
+        trace!("Copier::copy(src={:?}, dst={:?})", src, dst);
+        let src_host = self.from.resolve(src)?;
+        let dst_host = self.to.resolve(dst)?;
+        let metadata = fs::symlink_metadata(&src_host).map_err(JailError::IoError)?;
+        let file_type = metadata.file_type();
+
+        if !file_type.is_dir() {
+            // Replace existing files and links instead of writing through them.
+            match fs::symlink_metadata(&dst_host) {
+                Ok(existing) if !existing.is_dir() => {
+                    fs::remove_file(&dst_host).map_err(JailError::IoError)?
+                }
+                _ => {}
+            }
+        }
+
+        if file_type.is_symlink() {
+            let target = fs::read_link(&src_host).map_err(JailError::IoError)?;
+            std::os::unix::fs::symlink(&target, &dst_host).map_err(JailError::IoError)?;
+            if let Some((uid, gid)) = self.owner(&metadata) {
+                std::os::unix::fs::lchown(&dst_host, Some(uid), Some(gid))
+                    .map_err(JailError::IoError)?;
+            }
+        } else if file_type.is_file() {
+            let mut input = fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&src_host)
+                .map_err(JailError::IoError)?;
+            let mut output = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&dst_host)
+                .map_err(JailError::IoError)?;
+            io::copy(&mut input, &mut output).map_err(JailError::IoError)?;
+            self.finish(&output, &metadata)?;
+        } else if file_type.is_dir() {
+            match fs::create_dir(&dst_host) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(JailError::IoError(e)),
+            }
+
+            // Open the directory without following links before descending,
+            // in case it was replaced concurrently.
+            let directory = fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NOFOLLOW | libc::O_DIRECTORY)
+                .open(&dst_host)
+                .map_err(JailError::IoError)?;
+
+            for entry in fs::read_dir(&src_host).map_err(JailError::IoError)? {
+                let name = entry.map_err(JailError::IoError)?.file_name();
+                self.copy(&src.join(&name), &dst.join(&name))?;
+            }
+
+            self.finish(&directory, &metadata)?;
+        } else {
+            return Err(JailError::NotSupported(format!(
+                "cannot copy {:?}: not a regular file, directory or symbolic link",
+                src
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Apply owner and permissions of the source to a copied file.
+    fn finish(&self, file: &fs::File, metadata: &fs::Metadata) -> Result<(), JailError> {
+        if let Some((uid, gid)) = self.owner(metadata) {
+            std::os::unix::fs::fchown(file, Some(uid), Some(gid)).map_err(JailError::IoError)?;
+        }
+
+        // Set the mode after changing the owner, which clears setuid bits.
+        file.set_permissions(fs::Permissions::from_mode(metadata.mode() & 0o7777))
+            .map_err(JailError::IoError)
+    }
+
+    fn owner(&self, metadata: &fs::Metadata) -> Option<(u32, u32)> {
+        let (uid, gid) = (metadata.uid(), metadata.gid());
+        match (self.ownership, &self.owners) {
+            (Ownership::Caller, _) => None,
+            (Ownership::Remap, Some(owners)) => Some((
+                *owners.users.get(&uid).unwrap_or(&uid),
+                *owners.groups.get(&gid).unwrap_or(&gid),
+            )),
+            _ => Some((uid, gid)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn resolve_symlinks_below_root() {
+        let root = std::env::temp_dir().join(format!("jail_fs_resolve_{}", std::process::id()));
+        fs::create_dir_all(root.join("etc")).unwrap();
+        symlink("/etc", root.join("abs")).unwrap();
+        symlink("../../../etc", root.join("etc/rel")).unwrap();
+
+        assert_eq!(
+            host_path(&root, Path::new("/abs/rc.conf")).unwrap(),
+            root.join("etc/rc.conf")
+        );
+        assert_eq!(
+            host_path(&root, Path::new("etc/rel/passwd")).unwrap(),
+            root.join("etc/passwd")
+        );
+        assert_eq!(
+            host_path(&root, Path::new("/../../etc/./hosts")).unwrap(),
+            root.join("etc/hosts")
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn resolve_symlink_loop() {
+        let root = std::env::temp_dir().join(format!("jail_fs_loop_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        symlink("/loop", root.join("loop")).unwrap();
+
+        assert!(host_path(&root, Path::new("/loop")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn copy_does_not_follow_symlinks() {
+        let base = std::env::temp_dir().join(format!("jail_fs_copy_{}", std::process::id()));
+        let root = base.join("root");
+        let outside = base.join("outside");
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret"), "secret").unwrap();
+        symlink(outside.join("secret"), root.join("etc/rc.conf")).unwrap();
+
+        let src = base.join("src");
+        fs::create_dir_all(src.join("conf")).unwrap();
+        fs::write(src.join("conf/rc.conf"), "sshd_enable=YES").unwrap();
+        fs::set_permissions(src.join("conf/rc.conf"), fs::Permissions::from_mode(0o640)).unwrap();
+
+        let options = CopyOptions::new();
+        copy(
+            Side::Host,
+            &src.join("conf/rc.conf"),
+            Side::Jail(&root),
+            Path::new("/etc/rc.conf"),
+            &options,
+        )
+        .unwrap();
+        copy(
+            Side::Host,
+            &src.join("conf"),
+            Side::Jail(&root),
+            Path::new("/etc"),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(outside.join("secret")).unwrap(),
+            "secret"
+        );
+        let copied = root.join("etc/rc.conf");
+        assert!(!fs::symlink_metadata(&copied)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "sshd_enable=YES");
+        assert_eq!(fs::metadata(&copied).unwrap().mode() & 0o777, 0o640);
+        assert!(root.join("etc/conf/rc.conf").is_file());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+//! Entry points for the fuzz targets in `fuzz/`
+//!
+//! The functions parsing data from the kernel or from configuration files are
+//! crate-internal. This module exposes them as pure functions, so they can be
+//! fuzzed with `cargo fuzz` without a jail-capable host.
+//!
+//! This module requires the `fuzzing` feature and is not part of the stable
+//! API.
+use crate::param::{Type, Value};
+use crate::{encoding, JailError};
+
+/// The parameter types handled by the built-in encoding, i.e. all but
+/// [Type::Custom].
+pub const TYPES: &[Type] = &[
+    Type::Int,
+    Type::String,
+    Type::S64,
+    Type::Uint,
+    Type::Long,
+    Type::Ulong,
+    Type::U64,
+    Type::U8,
+    Type::U16,
+    Type::S8,
+    Type::S16,
+    Type::S32,
+    Type::U32,
+    Type::Ipv4Addrs,
+    Type::Ipv6Addrs,
+    Type::Raw,
+];
+
+/// Encode a value as it is passed to `jail_set`(2).
+pub fn encode(value: &Value) -> Result<Vec<u8>, JailError> {
+    encoding::encode(value)
+}
+
+/// Decode a buffer returned by `jail_get`(2) as a value of the given type.
+pub fn decode(ty: Type, bytes: &[u8]) -> Result<Value, JailError> {
+    encoding::decode(ty, bytes)
+}
+
+/// Decode the `errmsg` buffer filled by `jail_set`(2) or `jail_get`(2).
+pub fn parse_errmsg(errmsg: &[u8]) -> Option<jail_sys::ErrorMessage> {
+    jail_sys::ErrorMessage::from_buffer(errmsg)
+}
+
+/// Parse a `jail.conf`(5) file.
+#[cfg(feature = "interop")]
+pub fn parse_jail_conf(
+    input: &str,
+) -> Result<Vec<crate::interop::jail_conf::Definition>, JailError> {
+    crate::interop::jail_conf::parse(input)
+}
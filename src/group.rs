@@ -0,0 +1,262 @@
+//! Operations on groups of running jails
+use crate::running::glob_match;
+use crate::{param, sys, JailError, RunningJail};
+use log::{trace, warn};
+use std::thread;
+
+#[cfg(feature = "rctl")]
+use std::collections::HashMap;
+
+/// How the members of a [JailGroup] are selected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Selector {
+    Meta { key: String, value: String },
+    Name(String),
+}
+
+/// A group of running jails, selected by metadata or name, to operate on
+/// all of them at once.
+///
+/// Members are looked up anew for every operation, so jails started later
+/// are included once they match.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{param, JailGroup, StoppedJail};
+///
+/// let jails: Vec<_> = (0..3)
+///     .map(|i| {
+///         StoppedJail::new("/rescue")
+///             .name(format!("testjailgroup_{}", i))
+///             .meta("tier", "web")
+///             .start()
+///             .expect("could not start jail")
+///     })
+///     .collect();
+///
+/// let group = JailGroup::tagged("tier", "web");
+/// assert_eq!(group.members().unwrap(), jails);
+///
+/// let report = group
+///     .param_set("securelevel", param::Value::Int(1))
+///     .expect("could not list members");
+/// assert!(report.is_success());
+///
+/// let report = group.restart_rolling(2).expect("could not list members");
+/// assert_eq!(report.members.len(), 3);
+/// #
+/// # for member in report.members {
+/// #     member.result.unwrap().kill().expect("could not stop jail");
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JailGroup {
+    selector: Selector,
+}
+
+/// The outcome of an operation on a single member of a [JailGroup].
+#[derive(Debug)]
+pub struct MemberResult<T> {
+    /// The `jid` of the member when the operation started
+    pub jid: i32,
+
+    /// The name of the member
+    pub name: String,
+
+    /// The result of the operation
+    pub result: Result<T, JailError>,
+}
+
+/// What an operation on a [JailGroup] did, with the members ordered by
+/// `jid`.
+#[derive(Debug)]
+pub struct GroupReport<T> {
+    /// The members the operation was applied to
+    pub members: Vec<MemberResult<T>>,
+
+    /// The names of the members left alone, since a rolling restart
+    /// stopped after a failure
+    pub skipped: Vec<String>,
+}
+
+impl<T> GroupReport<T> {
+    /// Whether the operation succeeded on all members.
+    pub fn is_success(&self) -> bool {
+        trace!("GroupReport::is_success()");
+        self.skipped.is_empty() && self.members.iter().all(|member| member.result.is_ok())
+    }
+
+    /// The members the operation failed on.
+    pub fn failed(&self) -> impl Iterator<Item = &MemberResult<T>> {
+        trace!("GroupReport::failed()");
+        self.members.iter().filter(|member| member.result.is_err())
+    }
+}
+
+/// Statistics of a member of a [JailGroup], see [JailGroup::stats].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemberStats {
+    /// The number of processes in the jail
+    pub processes: usize,
+
+    /// The `RACCT` usage of the jail, if resource accounting is enabled
+    #[cfg(feature = "rctl")]
+    pub racct: Option<HashMap<rctl::Resource, usize>>,
+}
+
+impl JailGroup {
+    /// Select the jails whose metadata `key` has the value `value`, see
+    /// [StoppedJail::meta](struct.StoppedJail.html#method.meta).
+    pub fn tagged<K, V>(key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let (key, value) = (key.into(), value.into());
+        trace!("JailGroup::tagged(key={:?}, value={:?})", key, value);
+        JailGroup {
+            selector: Selector::Meta { key, value },
+        }
+    }
+
+    /// Select the jails whose name matches a glob `pattern`, where `*`
+    /// matches any sequence of characters and `?` matches a single
+    /// character.
+    pub fn named<S: Into<String>>(pattern: S) -> Self {
+        let pattern = pattern.into();
+        trace!("JailGroup::named(pattern={:?})", pattern);
+        JailGroup {
+            selector: Selector::Name(pattern),
+        }
+    }
+
+    /// Whether a jail belongs to the group. Jails that vanish while they
+    /// are checked do not.
+    fn contains(&self, jail: &RunningJail) -> bool {
+        match self.selector {
+            Selector::Meta { ref key, ref value } => {
+                jail.meta().is_ok_and(|meta| meta.get(key) == Some(value))
+            }
+            Selector::Name(ref pattern) => jail.name().is_ok_and(|name| glob_match(pattern, &name)),
+        }
+    }
+
+    /// Get the running jails in the group, ordered by `jid`.
+    pub fn members(&self) -> Result<Vec<RunningJail>, JailError> {
+        trace!("JailGroup::members({:?})", self);
+        Ok(RunningJail::all()
+            .filter(|jail| self.contains(jail))
+            .collect())
+    }
+
+    /// Apply `f` to every member, one after another.
+    fn each<T, F>(&self, f: F) -> Result<GroupReport<T>, JailError>
+    where
+        F: Fn(&RunningJail) -> Result<T, JailError>,
+    {
+        let members = self
+            .members()?
+            .into_iter()
+            .map(|jail| MemberResult {
+                jid: jail.jid,
+                name: jail.name().unwrap_or_default(),
+                result: f(&jail),
+            })
+            .collect();
+
+        Ok(GroupReport {
+            members,
+            skipped: vec![],
+        })
+    }
+
+    /// Set a parameter on every member, see [RunningJail::param_set].
+    ///
+    /// Failures on single members are reported in the [GroupReport] rather
+    /// than stopping the operation.
+    pub fn param_set(&self, name: &str, value: param::Value) -> Result<GroupReport<()>, JailError> {
+        trace!(
+            "JailGroup::param_set({:?}, name={:?}, value={:?})",
+            self,
+            name,
+            value
+        );
+        self.each(|jail| jail.param_set(name, value.clone()))
+    }
+
+    /// Collect the statistics of every member.
+    ///
+    /// `RACCT` usage is left out for members whose usage cannot be read,
+    /// e.g. when resource accounting is disabled.
+    pub fn stats(&self) -> Result<GroupReport<MemberStats>, JailError> {
+        trace!("JailGroup::stats({:?})", self);
+        self.each(|jail| {
+            Ok(MemberStats {
+                processes: sys::jail_pids(jail.jid)?.len(),
+                #[cfg(feature = "rctl")]
+                racct: jail.racct_statistics().ok(),
+            })
+        })
+    }
+
+    /// Restart the members, at most `concurrency` at a time, see
+    /// [RunningJail::restart].
+    ///
+    /// The members are restarted in batches in the order of their `jid`.
+    /// If any member of a batch fails to restart, the remaining members are
+    /// left running and reported as skipped. A member that failed may be
+    /// stopped.
+    pub fn restart_rolling(
+        &self,
+        concurrency: usize,
+    ) -> Result<GroupReport<RunningJail>, JailError> {
+        trace!(
+            "JailGroup::restart_rolling({:?}, concurrency={})",
+            self,
+            concurrency
+        );
+        let members = self.members()?;
+        let mut report = GroupReport {
+            members: vec![],
+            skipped: vec![],
+        };
+
+        for batch in members.chunks(concurrency.max(1)) {
+            if !report.is_success() {
+                report
+                    .skipped
+                    .extend(batch.iter().map(|jail| jail.name().unwrap_or_default()));
+                continue;
+            }
+
+            let results: Vec<MemberResult<RunningJail>> = thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .iter()
+                    .map(|jail| {
+                        let jail = *jail;
+                        scope.spawn(move || MemberResult {
+                            jid: jail.jid,
+                            name: jail.name().unwrap_or_default(),
+                            result: jail.restart(),
+                        })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("restart thread panicked"))
+                    .collect()
+            });
+
+            for member in &results {
+                if let Err(ref e) = member.result {
+                    warn!("could not restart jail {}: {}", member.name, e);
+                }
+            }
+            report.members.extend(results);
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,115 @@
+//! Host names following RFC 1123
+//!
+//! The kernel accepts any string as `host.hostname`, but resolvers, mail
+//! servers and TLS certificates expect a host name made of labels of
+//! letters, digits and hyphens. These helpers check host names before they
+//! are set, see
+//! [StoppedJail::hostname_from_name](../struct.StoppedJail.html#method.hostname_from_name).
+use crate::JailError;
+use log::trace;
+
+/// The longest host name in its textual form, without a trailing dot.
+const MAX_LEN: usize = 253;
+
+/// The longest label of a host name.
+const MAX_LABEL_LEN: usize = 63;
+
+/// Check a host name against RFC 1123, returning why it is rejected.
+fn check(hostname: &str) -> Result<(), &'static str> {
+    if hostname.is_empty() {
+        return Err("host name is empty");
+    }
+
+    if hostname.len() > MAX_LEN {
+        return Err("host name is longer than 253 bytes");
+    }
+
+    for label in hostname.split('.') {
+        if label.is_empty() {
+            return Err("host name contains an empty label");
+        }
+
+        if label.len() > MAX_LABEL_LEN {
+            return Err("label is longer than 63 bytes");
+        }
+
+        if !label
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        {
+            return Err("label contains characters other than letters, digits and '-'");
+        }
+
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err("label starts or ends with '-'");
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate a host name against RFC 1123.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{hostname, JailError};
+///
+/// assert!(hostname::validate("www.example.org").is_ok());
+/// assert!(hostname::validate("3com.example").is_ok());
+/// assert!(matches!(
+///     hostname::validate("my_jail.example.org"),
+///     Err(JailError::InvalidHostname { .. })
+/// ));
+/// ```
+pub fn validate(hostname: &str) -> Result<(), JailError> {
+    trace!("hostname::validate(hostname={:?})", hostname);
+    check(hostname).map_err(|reason| JailError::InvalidHostname {
+        hostname: hostname.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Build the fully qualified domain name `<name>.<domain>`, validating it
+/// against RFC 1123.
+///
+/// # Examples
+///
+/// ```
+/// use jail::hostname;
+///
+/// assert_eq!(
+///     hostname::fqdn("www", "example.org").unwrap(),
+///     "www.example.org"
+/// );
+/// assert!(hostname::fqdn("www", "").is_err());
+/// ```
+pub fn fqdn(name: &str, domain: &str) -> Result<String, JailError> {
+    trace!("hostname::fqdn(name={:?}, domain={:?})", name, domain);
+    let fqdn = format!("{}.{}", name, domain);
+    validate(&fqdn)?;
+    Ok(fqdn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc1123() {
+        assert!(check("localhost").is_ok());
+        assert!(check("www-01.example.org").is_ok());
+        assert!(check("0.example").is_ok());
+        assert!(check(&format!("{}.org", "a".repeat(MAX_LABEL_LEN))).is_ok());
+
+        assert!(check("").is_err());
+        assert!(check("www..example.org").is_err());
+        assert!(check("www.example.org.").is_err());
+        assert!(check("-www.example.org").is_err());
+        assert!(check("www-.example.org").is_err());
+        assert!(check("www_01.example.org").is_err());
+        assert!(check("wäww.example.org").is_err());
+        assert!(check(&format!("{}.org", "a".repeat(MAX_LABEL_LEN + 1))).is_err());
+        assert!(check(&vec!["a"; 128].join(".")).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! Import of jails managed by Bastille
+//!
+//! Bastille keeps every jail in `<prefix>/jails/<name>`, with the root file
+//! system of the jail in `root`, its definition in `jail.conf` and resource
+//! limits in `rctl.conf`.
+use super::jail_conf;
+use crate::{JailError, StoppedJail};
+use log::{trace, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The default prefix of a Bastille installation.
+pub const DEFAULT_PREFIX: &str = "/usr/local/bastille";
+
+/// Import all jails from a Bastille installation at `prefix`, usually
+/// [DEFAULT_PREFIX].
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::interop::bastille;
+///
+/// for stopped in bastille::import(bastille::DEFAULT_PREFIX).expect("could not import jails") {
+///     println!("{:?}: {:?}", stopped.name, stopped.path);
+/// }
+/// ```
+pub fn import<P: AsRef<Path>>(prefix: P) -> Result<Vec<StoppedJail>, JailError> {
+    trace!("bastille::import(prefix={:?})", prefix.as_ref());
+    let mut dirs: Vec<PathBuf> = fs::read_dir(prefix.as_ref().join("jails"))
+        .map_err(JailError::IoError)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(JailError::IoError)?;
+    dirs.sort();
+
+    dirs.into_iter()
+        .filter(|dir| dir.join("jail.conf").is_file())
+        .map(import_jail)
+        .collect()
+}
+
+/// Import a single jail from its Bastille directory, e.g.
+/// `/usr/local/bastille/jails/www`.
+///
+/// Parameters interpreted by `jail`(8), like `exec.start`, are not imported,
+/// see [jail_conf::Definition::to_stopped_jail]. Neither are the file systems
+/// listed in the `fstab` of the jail: thin jails need the base release
+/// mounted before they can be started.
+pub fn import_jail<P: AsRef<Path>>(dir: P) -> Result<StoppedJail, JailError> {
+    let dir = dir.as_ref();
+    trace!("bastille::import_jail(dir={:?})", dir);
+    let conf = fs::read_to_string(dir.join("jail.conf")).map_err(JailError::IoError)?;
+    let definitions = jail_conf::parse(&conf)?;
+
+    let name = dir.file_name().map(|name| name.to_string_lossy());
+    let definition = definitions
+        .iter()
+        .find(|definition| Some(definition.name.as_str()) == name.as_deref())
+        .or_else(|| definitions.first())
+        .ok_or_else(|| {
+            JailError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no jail defined in {:?}", dir.join("jail.conf")),
+            ))
+        })?;
+
+    let mut stopped = definition.to_stopped_jail()?;
+
+    match fs::read_to_string(dir.join("fstab")) {
+        Ok(fstab)
+            if fstab
+                .lines()
+                .any(|line| !line.trim().is_empty() && !line.starts_with('#')) =>
+        {
+            warn!(
+                "file systems in {:?} are not imported and must be mounted before starting {}",
+                dir.join("fstab"),
+                definition.name
+            );
+        }
+        _ => {}
+    }
+
+    let rctl_conf = match fs::read_to_string(dir.join("rctl.conf")) {
+        Ok(rctl_conf) => rctl_conf,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(stopped),
+        Err(e) => return Err(JailError::IoError(e)),
+    };
+
+    for (index, line) in rctl_conf.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // Rules may omit the subject, which is then the jail itself.
+        let (resource, limit, action) =
+            crate::limits::parse(line).map_err(|e| JailError::ConfigParseError {
+                line: index + 1,
+                message: e.to_string(),
+            })?;
+        stopped = stopped.limit(resource, limit, action);
+    }
+
+    Ok(stopped)
+}
@@ -0,0 +1,203 @@
+//! Import of jails managed by iocage
+//!
+//! iocage keeps every jail in a dataset `<pool>/iocage/jails/<name>`, with
+//! the root file system of the jail in `root` and its properties in
+//! `config.json`.
+use crate::allow::Allow;
+use crate::param::{Type, Value};
+use crate::zfs::Dataset;
+use crate::{JailError, StoppedJail};
+use log::{debug, trace, warn};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+
+/// iocage properties that map directly to kernel parameters.
+const PARAMS: &[(&str, &str)] = &[
+    ("children_max", "children.max"),
+    ("devfs_ruleset", "devfs_ruleset"),
+    ("enforce_statfs", "enforce_statfs"),
+    ("host_domainname", "host.domainname"),
+    ("ip4", "ip4"),
+    ("ip4_saddrsel", "ip4.saddrsel"),
+    ("ip6", "ip6"),
+    ("ip6_saddrsel", "ip6.saddrsel"),
+    ("securelevel", "securelevel"),
+    ("sysvmsg", "sysvmsg"),
+    ("sysvsem", "sysvsem"),
+    ("sysvshm", "sysvshm"),
+];
+
+/// Import all jails from an iocage installation.
+///
+/// `pool_or_path` is either the name of the ZFS pool iocage is activated on,
+/// or the path of the `iocage` directory (or of the directory containing
+/// it).
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::interop::iocage;
+///
+/// for stopped in iocage::import("zroot").expect("could not import jails") {
+///     println!("{:?}: {:?}", stopped.name, stopped.path);
+/// }
+/// ```
+pub fn import<P: AsRef<Path>>(pool_or_path: P) -> Result<Vec<StoppedJail>, JailError> {
+    trace!("iocage::import(pool_or_path={:?})", pool_or_path.as_ref());
+    let jails = root(pool_or_path.as_ref())?.join("jails");
+
+    let mut dirs: Vec<PathBuf> = fs::read_dir(&jails)
+        .map_err(JailError::IoError)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(JailError::IoError)?;
+    dirs.sort();
+
+    dirs.into_iter()
+        .filter(|dir| dir.join("config.json").is_file())
+        .map(import_jail)
+        .collect()
+}
+
+/// Import a single jail from its iocage directory, e.g.
+/// `/zroot/iocage/jails/www`.
+///
+/// The jail is named after the iocage jail, not `ioc-<name>` like iocage
+/// does when starting it. Properties only interpreted by iocage, like
+/// `boot` or `exec_start`, are not imported. With `vnet` enabled, the
+/// addresses of the jail are configured on its interfaces by iocage and
+/// therefore not imported either.
+pub fn import_jail<P: AsRef<Path>>(dir: P) -> Result<StoppedJail, JailError> {
+    let dir = dir.as_ref();
+    trace!("iocage::import_jail(dir={:?})", dir);
+    let config = fs::read_to_string(dir.join("config.json")).map_err(JailError::IoError)?;
+    let config: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&config)
+        .map_err(|e| JailError::ConfigParseError {
+            line: e.line(),
+            message: e.to_string(),
+        })?;
+
+    let property = |key: &str| -> Option<String> {
+        match config.get(key)? {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            serde_json::Value::Bool(b) => Some(if *b { "1" } else { "0" }.into()),
+            _ => None,
+        }
+    };
+
+    let name = property("host_hostuuid")
+        .or_else(|| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .ok_or_else(|| {
+            JailError::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot determine the name of the jail in {:?}", dir),
+            ))
+        })?;
+
+    let mut stopped = StoppedJail::new(dir.join("root")).name(name.clone());
+    if let Some(hostname) = property("host_hostname") {
+        stopped = stopped.hostname(hostname);
+    }
+
+    let vnet = matches!(property("vnet").as_deref(), Some("on" | "1" | "yes"));
+    if vnet {
+        stopped = stopped.param("vnet", Value::Int(1));
+    }
+
+    for (key, type_) in &[("ip4_addr", Type::Ipv4Addrs), ("ip6_addr", Type::Ipv6Addrs)] {
+        let addrs = match property(key) {
+            Some(addrs) if !["", "none", "DHCP", "ACCEPT_RTADV"].contains(&addrs.as_str()) => addrs,
+            _ => continue,
+        };
+
+        if vnet {
+            debug!("not importing {} of vnet jail {}", key, name);
+            continue;
+        }
+
+        match Value::parse_as(*type_, &addrs)? {
+            Value::Ipv4Addrs(v4) => stopped.ips.extend(v4.into_iter().map(IpAddr::from)),
+            Value::Ipv6Addrs(v6) => stopped.ips.extend(v6.into_iter().map(IpAddr::from)),
+            _ => unreachable!(),
+        }
+    }
+
+    for (key, _) in config.iter() {
+        let value = match property(key) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let param = if let Some((_, param)) = PARAMS.iter().find(|(k, _)| k == key) {
+            param.to_string()
+        } else if let Some(allow) = Allow::iter().find(|a| a.name().replace('.', "_") == *key) {
+            allow.name().to_string()
+        } else if let Ok(resource) = rctl::Resource::from_str(key) {
+            if let Some(limit) = limit(&name, key, &value) {
+                stopped = stopped.limit(resource, limit.0, limit.1);
+            }
+            continue;
+        } else {
+            continue;
+        };
+
+        let value = match value.as_str() {
+            "on" | "yes" => "1",
+            "off" | "no" => "0",
+            value => value,
+        };
+
+        match Value::parse(&param, value) {
+            Ok(value) => stopped = stopped.param(param, value),
+            Err(JailError::NoSuchParameter(_)) => warn!(
+                "skipping property {} of {}: not supported by the kernel",
+                key, name
+            ),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(stopped)
+}
+
+/// Parse an iocage resource limit of the form `amount[:action]`, or `off`.
+fn limit(jail: &str, key: &str, value: &str) -> Option<(rctl::Limit, rctl::Action)> {
+    if value == "off" || value.is_empty() {
+        return None;
+    }
+
+    let (amount, action) = value.split_once(':').unwrap_or((value, "deny"));
+    match (
+        rctl::Limit::from_str(amount),
+        rctl::Action::from_str(action),
+    ) {
+        (Ok(limit), Ok(action)) => Some((limit, action)),
+        _ => {
+            warn!("skipping invalid limit {}={} of {}", key, value, jail);
+            None
+        }
+    }
+}
+
+/// Find the `iocage` directory.
+fn root(pool_or_path: &Path) -> Result<PathBuf, JailError> {
+    if pool_or_path.is_dir() {
+        return [pool_or_path.to_path_buf(), pool_or_path.join("iocage")]
+            .iter()
+            .find(|candidate| candidate.join("jails").is_dir())
+            .cloned()
+            .ok_or_else(|| {
+                JailError::IoError(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no iocage jails found in {:?}", pool_or_path),
+                ))
+            });
+    }
+
+    Dataset::named(format!("{}/iocage", pool_or_path.display())).mountpoint()
+}
@@ -0,0 +1,746 @@
+//! Parser for `jail.conf`(5) files
+//!
+//! Only the structure of the file is interpreted: parameters outside of jail
+//! sections and in the `*` section apply to all jails, and `+=` appends to a
+//! previously set value. Variables (`$name` and `${name}`) are kept verbatim
+//! by [parse] and expanded by [Definition::to_stopped_jail].
+//!
+//! A [Definition] can also be written back in `jail.conf` syntax with its
+//! [Display](std::fmt::Display) implementation.
+use crate::JailError;
+use log::trace;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[cfg(target_os = "freebsd")]
+use crate::devfs::DevfsConfig;
+#[cfg(target_os = "freebsd")]
+use crate::mount::Mount;
+#[cfg(target_os = "freebsd")]
+use crate::param::{Type, Value};
+#[cfg(target_os = "freebsd")]
+use crate::pretty;
+#[cfg(target_os = "freebsd")]
+use crate::vars::Variables;
+#[cfg(target_os = "freebsd")]
+use crate::{JailSet, StoppedJail};
+#[cfg(target_os = "freebsd")]
+use log::{debug, warn};
+#[cfg(target_os = "freebsd")]
+use std::convert::TryFrom;
+#[cfg(target_os = "freebsd")]
+use std::net::IpAddr;
+#[cfg(target_os = "freebsd")]
+use std::path::Path;
+
+/// Parameters interpreted by `jail`(8) itself rather than by the kernel.
+#[cfg(target_os = "freebsd")]
+const PSEUDO_PARAMS: &[&str] = &[
+    "exec.",
+    "mount.",
+    "command",
+    "depend",
+    "interface",
+    "ip_hostname",
+    "vnet.interface",
+];
+
+/// A jail parameter in a `jail.conf`(5) file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Param {
+    /// The parameter name, e.g. `host.hostname`
+    pub name: String,
+
+    /// The comma-separated values, or none for boolean parameters given
+    /// without a value, e.g. `persist;`
+    pub values: Vec<String>,
+}
+
+/// The definition of a single jail in a `jail.conf`(5) file, including all
+/// parameters that apply to every jail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Definition {
+    /// The name of the jail section
+    pub name: String,
+
+    /// The parameters, in the order they were first set
+    pub params: Vec<Param>,
+}
+
+impl Definition {
+    /// Get a parameter by name.
+    pub fn get(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|param| param.name == name)
+    }
+
+    /// Translate the definition into a [StoppedJail].
+    ///
+    /// Variables are expanded first, with the parameters of the definition,
+    /// its name and variables set like `$domain = example.com;` as variables
+    /// (see [vars](../../vars/index.html)).
+    ///
+    /// `exec.*` parameters are kept in [StoppedJail::exec], see
+    /// [StoppedJail::exec_hooks] to run them, `mount.devfs` is kept in
+    /// [StoppedJail::devfs], and `mount.fdescfs` and `mount.procfs` add the
+    /// [Mount::fdescfs] and [Mount::procfs] presets. Other parameters interpreted by `jail`(8)
+    /// itself and parameters not supported by the running kernel are skipped
+    /// with a warning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::interop::jail_conf;
+    ///
+    /// let definitions = jail_conf::parse(
+    ///     "www { path = /rescue; host.hostname = www.example.com; persist; }",
+    /// )
+    /// .unwrap();
+    /// let stopped = definitions[0].to_stopped_jail().unwrap();
+    ///
+    /// assert_eq!(stopped.name, Some("www".into()));
+    /// assert_eq!(stopped.hostname, Some("www.example.com".into()));
+    /// ```
+    ///
+    /// ```
+    /// use jail::devfs::DevfsConfig;
+    /// use jail::interop::jail_conf;
+    ///
+    /// let definitions = jail_conf::parse(
+    ///     r#"
+    ///     $domain = example.com;
+    ///     www {
+    ///         path = /rescue;
+    ///         host.hostname = "$name.$domain";
+    ///         exec.start = "/bin/sh /etc/rc";
+    ///         mount.devfs;
+    ///         devfs_ruleset = 5;
+    ///     }
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// let stopped = definitions[0].to_stopped_jail().unwrap();
+    ///
+    /// assert_eq!(stopped.hostname, Some("www.example.com".into()));
+    /// assert_eq!(stopped.exec["exec.start"], vec!["/bin/sh /etc/rc"]);
+    /// assert_eq!(stopped.devfs, Some(DevfsConfig::new().ruleset(5)));
+    /// ```
+    #[cfg(target_os = "freebsd")]
+    pub fn to_stopped_jail(&self) -> Result<StoppedJail, JailError> {
+        trace!("Definition::to_stopped_jail({:?})", self);
+        let mut stopped = StoppedJail {
+            name: Some(self.name.clone()),
+            ..Default::default()
+        };
+
+        let vars = self
+            .params
+            .iter()
+            .fold(Variables::new(), |vars, param| {
+                let name = param.name.strip_prefix('$').unwrap_or(&param.name);
+                vars.set(name, param.values.join(","))
+            })
+            .set("name", self.name.as_str());
+
+        for param in &self.params {
+            let values = param
+                .values
+                .iter()
+                .map(|value| vars.expand(value))
+                .collect::<Result<Vec<_>, _>>()?;
+            let param = &Param {
+                name: param.name.clone(),
+                values,
+            };
+            let value = param.values.join(",");
+            match param.name.as_str() {
+                "path" => stopped.path = Some(value.into()),
+                "name" => {}
+                "host.hostname" => stopped.hostname = Some(value),
+                "ip4.addr" => {
+                    let addrs = Value::parse_as(Type::Ipv4Addrs, &value)?.unpack_ipv4()?;
+                    stopped.ips.extend(addrs.into_iter().map(IpAddr::from));
+                }
+                "ip6.addr" => {
+                    let addrs = Value::parse_as(Type::Ipv6Addrs, &value)?.unpack_ipv6()?;
+                    stopped.ips.extend(addrs.into_iter().map(IpAddr::from));
+                }
+                "mount.devfs" => {
+                    stopped.devfs = match param.values.first().map(String::as_str) {
+                        None | Some("true") | Some("1") => Some(DevfsConfig::new()),
+                        Some(_) => None,
+                    }
+                }
+                "mount.fdescfs" | "mount.procfs" => {
+                    if let None | Some("true") | Some("1") =
+                        param.values.first().map(String::as_str)
+                    {
+                        stopped.mounts.push(match param.name.as_str() {
+                            "mount.fdescfs" => Mount::fdescfs(),
+                            _ => Mount::procfs(),
+                        });
+                    }
+                }
+                name if name.starts_with("exec.") => {
+                    stopped
+                        .exec
+                        .entry(name.into())
+                        .or_default()
+                        .extend(param.values.iter().cloned());
+                }
+                name if name.starts_with('$')
+                    || PSEUDO_PARAMS
+                        .iter()
+                        .any(|p| name == *p || (p.ends_with('.') && name.starts_with(p))) =>
+                {
+                    debug!("ignoring jail(8) parameter {} of {}", name, self.name);
+                }
+                name => match Self::value(name, &param.values) {
+                    Ok((name, value)) => {
+                        stopped.params.insert(name, value);
+                    }
+                    Err(JailError::NoSuchParameter(_)) => {
+                        warn!(
+                            "skipping parameter {} of {}: not supported by the kernel",
+                            name, self.name
+                        );
+                    }
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+
+        // Like jail(8), apply devfs_ruleset to the devfs mount.
+        if let Some(ref mut devfs) = stopped.devfs {
+            if let Some(Value::Int(ruleset)) = stopped.params.get("devfs_ruleset") {
+                devfs.ruleset = u16::try_from(*ruleset)
+                    .map_err(|_| JailError::InvalidDevfsConfig(format!("ruleset {}", ruleset)))?;
+            }
+        }
+
+        Ok(stopped)
+    }
+
+    /// Describe a [StoppedJail] as a definition, e.g. to export it to a
+    /// `jail.conf`(5) file.
+    ///
+    /// The path, host name, addresses, kernel parameters and
+    /// [StoppedJail::exec] commands are kept, and so is [StoppedJail::devfs]
+    /// if mounted at `/dev`. Everything `jail`(8) cannot express, like
+    /// resource limits, mounts or provisioning steps, is left out, as are
+    /// parameters with values of raw or custom types.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::interop::jail_conf::Definition;
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("www")
+    ///     .exec("start", "/bin/sh /etc/rc");
+    /// let definition = Definition::from_stopped_jail(&stopped).unwrap();
+    ///
+    /// assert_eq!(
+    ///     definition.to_string(),
+    ///     "www {\n\tpath = \"/rescue\";\n\texec.start = \"/bin/sh /etc/rc\";\n}\n"
+    /// );
+    /// ```
+    #[cfg(target_os = "freebsd")]
+    pub fn from_stopped_jail(stopped: &StoppedJail) -> Result<Definition, JailError> {
+        trace!("Definition::from_stopped_jail({:?})", stopped);
+        let section = stopped.name.clone().ok_or_else(|| JailError::InvalidName {
+            name: String::new(),
+            reason: "jail.conf sections need a name".into(),
+        })?;
+
+        let mut params = vec![];
+        let mut param = |name: &str, values: Vec<String>| {
+            params.push(Param {
+                name: name.into(),
+                values,
+            })
+        };
+
+        if let Some(ref path) = stopped.path {
+            param("path", vec![path.to_string_lossy().into_owned()]);
+        }
+        if let Some(ref hostname) = stopped.hostname {
+            param("host.hostname", vec![hostname.clone()]);
+        }
+
+        let (ip4, ip6): (Vec<&IpAddr>, Vec<&IpAddr>) =
+            stopped.ips.iter().partition(|ip| ip.is_ipv4());
+        for (name, ips) in [("ip4.addr", ip4), ("ip6.addr", ip6)] {
+            if !ips.is_empty() {
+                param(name, ips.iter().map(ToString::to_string).collect());
+            }
+        }
+
+        let mut kernel_params: Vec<_> = stopped.params.iter().collect();
+        kernel_params.sort_by_key(|(name, _)| name.as_str());
+        for (name, value) in kernel_params {
+            match value {
+                Value::Raw(_) | Value::Custom(_) => warn!(
+                    "skipping parameter {} of {}: not expressible in jail.conf",
+                    name, section
+                ),
+                Value::Ipv4Addrs(addrs) => {
+                    param(name, addrs.iter().map(ToString::to_string).collect())
+                }
+                Value::Ipv6Addrs(addrs) => {
+                    param(name, addrs.iter().map(ToString::to_string).collect())
+                }
+                value => param(name, vec![pretty::value(value)]),
+            }
+        }
+
+        for (name, commands) in &stopped.exec {
+            param(name, commands.clone());
+        }
+
+        match stopped.devfs {
+            Some(ref devfs) if devfs.mountpoint == Path::new("/dev") => {
+                param("mount.devfs", vec![])
+            }
+            Some(ref devfs) => warn!(
+                "skipping devfs of {} at {:?}: jail.conf only mounts devfs at /dev",
+                section, devfs.mountpoint
+            ),
+            None => {}
+        }
+
+        Ok(Definition {
+            name: section,
+            params,
+        })
+    }
+
+    /// Get the value of a parameter, resolving boolean parameters without
+    /// value, including negated ones like `allow.noraw_sockets`.
+    #[cfg(target_os = "freebsd")]
+    fn value(name: &str, values: &[String]) -> Result<(String, Value), JailError> {
+        if !values.is_empty() {
+            return Ok((name.into(), Value::parse(name, &values.join(","))?));
+        }
+
+        match Value::parse(name, "true") {
+            Err(JailError::NoSuchParameter(e)) => {
+                let (prefix, last) = match name.rsplit_once('.') {
+                    Some((prefix, last)) => (format!("{}.", prefix), last),
+                    None => (String::new(), name),
+                };
+
+                match last.strip_prefix("no") {
+                    Some(last) => {
+                        let negated = format!("{}{}", prefix, last);
+                        let value = Value::parse(&negated, "false")?;
+                        Ok((negated, value))
+                    }
+                    None => Err(JailError::NoSuchParameter(e)),
+                }
+            }
+            result => Ok((name.into(), result?)),
+        }
+    }
+}
+
+/// Quote a value for `jail.conf`(5), keeping variables to be expanded.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+        {
+            true => writeln!(f, "{} {{", self.name)?,
+            false => writeln!(f, "{} {{", quote(&self.name))?,
+        }
+
+        for param in &self.params {
+            match param.values.is_empty() {
+                true => writeln!(f, "\t{};", param.name)?,
+                false => {
+                    let values: Vec<String> = param.values.iter().map(|v| quote(v)).collect();
+                    writeln!(f, "\t{} = {};", param.name, values.join(", "))?
+                }
+            }
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    Semicolon,
+    Comma,
+    Assign,
+    Append,
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn error<S: Into<String>>(&self, message: S) -> JailError {
+        JailError::ConfigParseError {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    /// Skip whitespace and comments.
+    fn skip(&mut self) -> Result<(), JailError> {
+        loop {
+            let mut lookahead = self.chars.clone();
+            match (lookahead.next(), lookahead.next()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('#'), _) | (Some('/'), Some('/')) => {
+                    while !matches!(self.bump(), None | Some('\n')) {}
+                }
+                (Some('/'), Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            None => return Err(self.error("unterminated comment")),
+                            Some('*') if self.chars.peek() == Some(&'/') => {
+                                self.bump();
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Read a quoted string, with the opening quote already consumed.
+    fn quoted(&mut self, quote: char, word: &mut String) -> Result<(), JailError> {
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(()),
+                Some('\\') if quote == '"' => match self.bump() {
+                    Some('n') => word.push('\n'),
+                    Some('t') => word.push('\t'),
+                    Some('\n') => {}
+                    Some(c) => word.push(c),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => word.push(c),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token, usize)>, JailError> {
+        self.skip()?;
+        let line = self.line;
+        let token = match self.chars.peek().copied() {
+            None => return Ok(None),
+            Some('{') => Token::Open,
+            Some('}') => Token::Close,
+            Some(';') => Token::Semicolon,
+            Some(',') => Token::Comma,
+            Some('=') => Token::Assign,
+            Some('+') if self.chars.clone().nth(1) == Some('=') => {
+                self.bump();
+                Token::Append
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    match c {
+                        '"' | '\'' => {
+                            self.bump();
+                            self.quoted(c, &mut word)?;
+                        }
+                        '\\' => {
+                            self.bump();
+                            match self.bump() {
+                                Some('\n') | None => {}
+                                Some(c) => word.push(c),
+                            }
+                        }
+                        c if c.is_whitespace() || "{};,=".contains(c) => break,
+                        '+' if self.chars.clone().nth(1) == Some('=') => break,
+                        c => {
+                            self.bump();
+                            word.push(c);
+                        }
+                    }
+                }
+                return Ok(Some((Token::Word(word), line)));
+            }
+        };
+
+        self.bump();
+        Ok(Some((token, line)))
+    }
+}
+
+/// Set or append to a parameter.
+fn apply(params: &mut Vec<Param>, name: String, values: Vec<String>, append: bool) {
+    match params.iter_mut().find(|param| param.name == name) {
+        Some(param) if append => param.values.extend(values),
+        Some(param) => param.values = values,
+        None => params.push(Param { name, values }),
+    }
+}
+
+/// Parse the contents of a `jail.conf`(5) file into the definitions of the
+/// jails in it.
+///
+/// # Examples
+///
+/// ```
+/// use jail::interop::jail_conf;
+///
+/// let definitions = jail_conf::parse(
+///     r#"
+///     exec.clean;
+///     path = "/usr/jails/$name";
+///
+///     www {
+///         host.hostname = www.example.com;
+///         ip4.addr = 192.0.2.1;
+///         ip4.addr += "em0|192.0.2.2/24";
+///     }
+///     "#,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(definitions.len(), 1);
+/// assert_eq!(definitions[0].name, "www");
+/// assert_eq!(definitions[0].get("path").unwrap().values, vec!["/usr/jails/$name"]);
+/// assert_eq!(
+///     definitions[0].get("ip4.addr").unwrap().values,
+///     vec!["192.0.2.1", "em0|192.0.2.2/24"]
+/// );
+/// ```
+pub fn parse(input: &str) -> Result<Vec<Definition>, JailError> {
+    trace!("jail_conf::parse()");
+    let mut lexer = Lexer {
+        chars: input.chars().peekable(),
+        line: 1,
+    };
+
+    // Assignments of each jail section, replayed on top of the global
+    // parameters once all of them are known.
+    type Assignment = (String, Vec<String>, bool);
+    let mut global: Vec<Param> = vec![];
+    let mut sections: Vec<(String, Vec<Assignment>)> = vec![];
+    let mut section: Option<String> = None;
+
+    while let Some((token, line)) = lexer.next_token()? {
+        let error = |message: String| JailError::ConfigParseError { line, message };
+
+        let name = match token {
+            Token::Close if section.is_some() => {
+                section = None;
+                continue;
+            }
+            Token::Semicolon => continue,
+            Token::Word(name) => name,
+            token => return Err(error(format!("unexpected {:?}", token))),
+        };
+
+        let (append, mut values) = match lexer.next_token()? {
+            Some((Token::Open, _)) if section.is_none() => {
+                if name != "*" && !sections.iter().any(|(n, _)| *n == name) {
+                    sections.push((name.clone(), vec![]));
+                }
+                section = Some(name);
+                continue;
+            }
+            Some((Token::Semicolon, _)) => (false, None),
+            Some((Token::Assign, _)) => (false, Some(vec![])),
+            Some((Token::Append, _)) => (true, Some(vec![])),
+            token => return Err(error(format!("unexpected {:?} after {}", token, name))),
+        };
+
+        if let Some(ref mut values) = values {
+            let mut value: Option<String> = None;
+            loop {
+                match lexer.next_token()? {
+                    Some((Token::Word(word), _)) => {
+                        value = Some(match value {
+                            Some(value) => format!("{} {}", value, word),
+                            None => word,
+                        })
+                    }
+                    Some((Token::Comma, _)) => values.push(value.take().unwrap_or_default()),
+                    Some((Token::Semicolon, _)) => {
+                        values.extend(value.take());
+                        break;
+                    }
+                    token => {
+                        return Err(error(format!(
+                            "unexpected {:?} in value of {}",
+                            token, name
+                        )))
+                    }
+                }
+            }
+        }
+
+        let values = values.unwrap_or_default();
+        match section.as_deref() {
+            None | Some("*") => apply(&mut global, name, values, append),
+            Some(section) => sections
+                .iter_mut()
+                .find(|(n, _)| n == section)
+                .expect("section was added when opened")
+                .1
+                .push((name, values, append)),
+        }
+    }
+
+    if let Some(section) = section {
+        return Err(lexer.error(format!("unterminated section {}", section)));
+    }
+
+    Ok(sections
+        .into_iter()
+        .map(|(name, assignments)| {
+            let mut params = global.clone();
+            for (param, values, append) in assignments {
+                apply(&mut params, param, values, append);
+            }
+
+            Definition { name, params }
+        })
+        .collect())
+}
+
+/// Translate definitions into a [JailSet], honouring their `depend`
+/// parameters.
+///
+/// # Examples
+///
+/// ```
+/// use jail::interop::jail_conf;
+///
+/// let definitions = jail_conf::parse(
+///     "path = /rescue; db {} web { depend = db; }",
+/// )
+/// .unwrap();
+/// let set = jail_conf::to_jail_set(&definitions).unwrap();
+///
+/// assert_eq!(set.stages().unwrap(), vec![vec!["db"], vec!["web"]]);
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn to_jail_set(definitions: &[Definition]) -> Result<JailSet, JailError> {
+    trace!("jail_conf::to_jail_set({:?})", definitions);
+    let mut set = JailSet::new();
+    for definition in definitions {
+        set = set.jail(definition.to_stopped_jail()?);
+        for dependency in definition.get("depend").into_iter().flat_map(|p| &p.values) {
+            set = set.depend(definition.name.as_str(), dependency.as_str());
+        }
+    }
+
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_jail_conf() {
+        let definitions = parse(
+            r#"
+            # Global settings
+            exec.start = "/bin/sh /etc/rc";
+            allow.raw_sockets;
+            * { securelevel = 2; }
+
+            web { /* inline */ path = '/jails/web'; securelevel = 3; }
+            db {
+                // comment
+                path = /jails/"d"b;
+                exec.start += "sleep 1";
+                ip4.addr = 192.0.2.1, 192.0.2.2;
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(definitions.len(), 2);
+        let web = &definitions[0];
+        assert_eq!(web.name, "web");
+        assert_eq!(web.get("securelevel").unwrap().values, vec!["3"]);
+        assert_eq!(
+            web.get("allow.raw_sockets").unwrap().values,
+            Vec::<String>::new()
+        );
+        assert_eq!(web.get("path").unwrap().values, vec!["/jails/web"]);
+
+        let db = &definitions[1];
+        assert_eq!(db.get("securelevel").unwrap().values, vec!["2"]);
+        assert_eq!(db.get("path").unwrap().values, vec!["/jails/db"]);
+        assert_eq!(
+            db.get("exec.start").unwrap().values,
+            vec!["/bin/sh /etc/rc", "sleep 1"]
+        );
+        assert_eq!(
+            db.get("ip4.addr").unwrap().values,
+            vec!["192.0.2.1", "192.0.2.2"]
+        );
+
+        let printed = parse(&db.to_string()).unwrap();
+        assert_eq!(printed, vec![db.clone()]);
+
+        let quoted = Definition {
+            name: "a b".into(),
+            params: vec![Param {
+                name: "exec.start".into(),
+                values: vec!["echo \"$name\"\tdone\\".into()],
+            }],
+        };
+        assert_eq!(parse(&quoted.to_string()).unwrap(), vec![quoted]);
+
+        assert!(parse("web { path = /jails/web; ").is_err());
+        assert!(parse("web { path = \"/jails/web; }").is_err());
+        assert!(matches!(
+            parse("web {\n path /jails/web;\n}"),
+            Err(JailError::ConfigParseError { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,11 @@
+//! Import of jails from other jail managers
+//!
+//! Definitions of jails managed by iocage or Bastille are translated into
+//! [StoppedJail](../struct.StoppedJail.html)s, so that they can be migrated
+//! incrementally. Settings that these tools interpret themselves, e.g.
+//! commands run on start, are not imported.
+//!
+//! This module requires the `interop` feature.
+pub mod bastille;
+pub mod iocage;
+pub mod jail_conf;
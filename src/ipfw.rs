@@ -0,0 +1,293 @@
+//! Per-jail network counters using `ipfw(8)`
+//!
+//! `RACCT` does not account network traffic. With the `ipfw` feature, a pair
+//! of `ipfw` rules can be installed for a jail, one matching its outbound and
+//! one its inbound traffic, whose counters are then read back as [Traffic].
+//!
+//! The `ipfw` kernel module must be loaded. Rules are added at the rule
+//! numbers given and are not removed automatically when the jail is killed.
+use crate::{JailError, RunningJail};
+use log::trace;
+use std::fmt;
+use std::net::IpAddr;
+use std::process::Command;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// How packets are attributed to a jail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Match {
+    /// Match packets sent or received by sockets of the jail, using the
+    /// `jail` option of `ipfw`. Forwarded packets are not matched.
+    Jail,
+
+    /// Match packets from or to the addresses of the jail.
+    Addresses,
+}
+
+/// What the installed rules do with matching packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Only count the packets, and continue with the next rule.
+    Count,
+
+    /// Count and accept the packets.
+    Allow,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Count => write!(f, "count"),
+            Action::Allow => write!(f, "allow"),
+        }
+    }
+}
+
+/// Packet and byte counters of a rule.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Counters {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// The traffic of a jail.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Traffic {
+    /// Traffic sent by the jail
+    pub outbound: Counters,
+
+    /// Traffic received by the jail
+    pub inbound: Counters,
+}
+
+/// The `ipfw` rules installed for a jail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accounting {
+    /// The `jid` of the jail
+    pub jid: i32,
+
+    /// The number of the rule matching outbound traffic. Inbound traffic is
+    /// matched by the rule numbered one higher.
+    pub rule: u16,
+}
+
+/// Build the bodies of the outbound and inbound rules.
+fn rule_bodies(jid: i32, ips: &[IpAddr], by: Match, action: Action) -> [Vec<String>; 2] {
+    let body = |from: &str, to: &str, direction: &str| {
+        let mut body: Vec<String> = vec![action.to_string(), "ip".into()];
+        body.extend(["from", from, "to", to].iter().map(|s| s.to_string()));
+        if by == Match::Jail {
+            body.extend(vec!["jail".into(), jid.to_string()]);
+        }
+        body.push(direction.into());
+        body
+    };
+
+    let addresses: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+    let addresses = match by {
+        Match::Jail => "any".to_string(),
+        Match::Addresses => format!("{{ {} }}", addresses.join(" or ")),
+    };
+
+    [
+        body(&addresses, "any", "out"),
+        body("any", &addresses, "in"),
+    ]
+}
+
+/// Sum up the counters of all rules listed by `ipfw show`.
+fn parse_counters(output: &str) -> Result<Counters, JailError> {
+    let mut counters = Counters::default();
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        let fields: Vec<Option<u64>> = line
+            .split_whitespace()
+            .skip(1)
+            .take(2)
+            .map(|field| field.parse().ok())
+            .collect();
+
+        match fields[..] {
+            [Some(packets), Some(bytes)] => {
+                counters.packets += packets;
+                counters.bytes += bytes;
+            }
+            _ => {
+                let message = format!("unexpected output: {:?}", line);
+                return Err(JailError::IpfwError(message));
+            }
+        }
+    }
+
+    Ok(counters)
+}
+
+/// Run `ipfw` with the given arguments, returning its standard output.
+fn ipfw<S: AsRef<str>>(args: &[S]) -> Result<String, JailError> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    trace!("ipfw(args={:?})", args);
+    let output = Command::new("ipfw")
+        .args(&args)
+        .output()
+        .map_err(JailError::IoError)?;
+
+    if !output.status.success() {
+        return Err(JailError::IpfwError(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Accounting {
+    /// Install rules numbered `rule` and `rule + 1` counting the outbound and
+    /// inbound traffic of `jail`.
+    ///
+    /// Any existing rules with these numbers are kept; their counters are
+    /// included in [Accounting::traffic].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::ipfw::{Accounting, Action, Match};
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_ipfw")
+    /// #     .ip("127.0.1.10".parse().unwrap())
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let accounting = Accounting::install(&running, 20000, Match::Addresses, Action::Count)
+    ///     .expect("could not install rules");
+    ///
+    /// let traffic = accounting.traffic().expect("could not read counters");
+    /// println!("{} bytes sent", traffic.outbound.bytes);
+    ///
+    /// accounting.remove().expect("could not remove rules");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn install(
+        jail: &RunningJail,
+        rule: u16,
+        by: Match,
+        action: Action,
+    ) -> Result<Accounting, JailError> {
+        trace!(
+            "Accounting::install(jail={:?}, rule={}, by={:?}, action={:?})",
+            jail,
+            rule,
+            by,
+            action
+        );
+        let inbound_rule = rule
+            .checked_add(1)
+            .ok_or_else(|| JailError::IpfwError(format!("rule number {} is too high", rule)))?;
+
+        let ips = match by {
+            Match::Jail => vec![],
+            Match::Addresses => jail.ips()?,
+        };
+        if by == Match::Addresses && ips.is_empty() {
+            return Err(JailError::NotSupported(format!(
+                "jail {} has no addresses to match",
+                jail.jid
+            )));
+        }
+
+        let [outbound, inbound] = rule_bodies(jail.jid, &ips, by, action);
+        let accounting = Accounting {
+            jid: jail.jid,
+            rule,
+        };
+
+        let mut args = vec!["-q".to_string(), "add".into(), rule.to_string()];
+        args.extend(outbound);
+        ipfw(&args)?;
+
+        let mut args = vec!["-q".to_string(), "add".into(), inbound_rule.to_string()];
+        args.extend(inbound);
+        if let Err(e) = ipfw(&args) {
+            ipfw(&["-q", "delete", &rule.to_string()]).ok();
+            return Err(e);
+        }
+
+        Ok(accounting)
+    }
+
+    /// Read the counters of the rules.
+    pub fn traffic(&self) -> Result<Traffic, JailError> {
+        trace!("Accounting::traffic({:?})", self);
+        let counters = |rule: u16| parse_counters(&ipfw(&["show", &rule.to_string()])?);
+
+        Ok(Traffic {
+            outbound: counters(self.rule)?,
+            inbound: counters(self.rule + 1)?,
+        })
+    }
+
+    /// Reset the counters of the rules to zero.
+    pub fn reset(&self) -> Result<(), JailError> {
+        trace!("Accounting::reset({:?})", self);
+        ipfw(&[
+            "-q",
+            "zero",
+            &self.rule.to_string(),
+            &(self.rule + 1).to_string(),
+        ])
+        .map(|_| ())
+    }
+
+    /// Delete the rules.
+    pub fn remove(self) -> Result<(), JailError> {
+        trace!("Accounting::remove({:?})", self);
+        ipfw(&[
+            "-q",
+            "delete",
+            &self.rule.to_string(),
+            &(self.rule + 1).to_string(),
+        ])
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_rules() {
+        let ips: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+
+        let [outbound, inbound] = rule_bodies(7, &ips, Match::Addresses, Action::Count);
+        assert_eq!(
+            outbound.join(" "),
+            "count ip from { 192.0.2.1 or 2001:db8::1 } to any out"
+        );
+        assert_eq!(
+            inbound.join(" "),
+            "count ip from any to { 192.0.2.1 or 2001:db8::1 } in"
+        );
+
+        let [outbound, inbound] = rule_bodies(7, &[], Match::Jail, Action::Allow);
+        assert_eq!(outbound.join(" "), "allow ip from any to any jail 7 out");
+        assert_eq!(inbound.join(" "), "allow ip from any to any jail 7 in");
+    }
+
+    #[test]
+    fn parse_show() {
+        let output = "20000   12    3456 count ip from any to any jail 7 out\n\
+                      20000    1      64 count ip from any to any out\n";
+        assert_eq!(
+            parse_counters(output).unwrap(),
+            Counters {
+                packets: 13,
+                bytes: 3520
+            }
+        );
+        assert!(parse_counters("20000 count ip from any to any").is_err());
+    }
+}
@@ -0,0 +1,429 @@
+//! A JSON Lines journal of jail operations
+//!
+//! With the `journal` feature enabled, every jail creation, removal, attach,
+//! command execution and parameter change performed through this library can
+//! be appended as an [Entry] to a writer installed with [set_writer]. Each
+//! entry is written as a single line of JSON, so the journal can be followed
+//! with `tail -f`, filtered with `jq` or replayed line by line.
+//!
+//! Unlike [audit](../audit/index.html), which records who did what, the
+//! journal records the parameters of each operation. Nothing is recorded
+//! until a writer is installed. A journal can be applied to another host
+//! with [replay].
+//!
+//! # Examples
+//!
+//! ```
+//! use jail::journal;
+//! use std::fs::OpenOptions;
+//!
+//! let file = OpenOptions::new()
+//!     .create(true)
+//!     .append(true)
+//!     .open("/tmp/jail-journal.jsonl")
+//!     .expect("could not open journal");
+//! journal::set_writer(file);
+//!
+//! let running = jail::StoppedJail::new("/rescue")
+//!     .name("testjail_journal")
+//!     .start()
+//!     .expect("could not start jail");
+//! running.kill().expect("could not kill jail");
+//!
+//! journal::close();
+//! ```
+use crate::param::Value;
+use crate::JailError;
+#[cfg(target_os = "freebsd")]
+use crate::{RunningJail, StoppedJail};
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(target_os = "freebsd")]
+use std::collections::HashMap;
+use std::io::Write;
+#[cfg(target_os = "freebsd")]
+use std::io::{BufRead, BufReader, Read};
+#[cfg(target_os = "freebsd")]
+use std::net::IpAddr;
+#[cfg(target_os = "freebsd")]
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A journaled operation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// A jail was created.
+    Create,
+
+    /// A jail was removed.
+    Kill,
+
+    /// The current process was attached to a jail.
+    Attach,
+
+    /// A command was spawned in a jail.
+    Exec,
+
+    /// A parameter of a running jail was changed.
+    ParamSet,
+}
+
+/// A single journal entry.
+///
+/// Entries serialize to JSON objects with the fields in declaration order.
+/// Empty fields are left out:
+///
+/// ```text
+/// {"timestamp":1700000000.25,"op":"param_set","jid":42,"name":"www","params":{"allow.raw_sockets":{"Int":1}},"result":"success"}
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the operation finished, in seconds since the Unix epoch
+    pub timestamp: f64,
+
+    /// The journaled operation
+    #[serde(rename = "op")]
+    pub operation: Operation,
+
+    /// The `jid` of the jail, or -1 if it could not be created
+    pub jid: i32,
+
+    /// The name of the jail, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The parameters of a created jail, or the changed parameter
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, Value>,
+
+    /// The program and arguments of an executed command
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+
+    /// Whether the operation succeeded, serialized as `success` or
+    /// `failure`
+    #[serde(rename = "result", with = "outcome")]
+    pub success: bool,
+
+    /// The error the operation failed with, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+mod outcome {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(success: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(if *success { "success" } else { "failure" })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "success" => Ok(true),
+            "failure" => Ok(false),
+            other => Err(serde::de::Error::unknown_variant(
+                other,
+                &["success", "failure"],
+            )),
+        }
+    }
+}
+
+type Writer = Box<dyn Write + Send + 'static>;
+
+fn writer() -> &'static Mutex<Option<Writer>> {
+    static WRITER: OnceLock<Mutex<Option<Writer>>> = OnceLock::new();
+    WRITER.get_or_init(Default::default)
+}
+
+/// Append all future entries to `w`, replacing any previous writer.
+///
+/// Entries are flushed as they are written. Write errors are logged and do
+/// not fail the journaled operation.
+pub fn set_writer<W>(w: W)
+where
+    W: Write + Send + 'static,
+{
+    trace!("journal::set_writer()");
+    *writer().lock().expect("journal writer poisoned") = Some(Box::new(w));
+}
+
+/// Stop journaling, flushing and dropping the current writer.
+pub fn close() {
+    trace!("journal::close()");
+    if let Some(mut w) = writer().lock().expect("journal writer poisoned").take() {
+        if let Err(e) = w.flush() {
+            warn!("could not flush journal: {}", e);
+        }
+    }
+}
+
+/// The parameters of a jail created at `path`, as recorded in the journal.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn create_params(
+    path: &Path,
+    params: &HashMap<String, Value>,
+) -> BTreeMap<String, Value> {
+    let mut journaled: BTreeMap<_, _> = params.clone().into_iter().collect();
+    journaled.insert(
+        "path".into(),
+        Value::String(path.to_string_lossy().into_owned()),
+    );
+    journaled
+}
+
+/// Record an operation on the jail `jid` with the given outcome, if a
+/// writer is installed. `details` fills in the parameters or command of the
+/// entry, and is only called if the entry is written.
+pub(crate) fn record<T, F>(
+    operation: Operation,
+    jid: i32,
+    name: Option<&str>,
+    result: &Result<T, JailError>,
+    details: F,
+) where
+    F: FnOnce(&mut Entry),
+{
+    let mut guard = writer().lock().expect("journal writer poisoned");
+    let w = match *guard {
+        Some(ref mut w) => w,
+        None => return,
+    };
+
+    let mut entry = Entry {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs_f64())
+            .unwrap_or(0.0),
+        operation,
+        jid,
+        name: name.map(String::from),
+        params: BTreeMap::new(),
+        command: vec![],
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    details(&mut entry);
+
+    let written = serde_json::to_vec(&entry)
+        .map_err(std::io::Error::from)
+        .and_then(|mut line| {
+            line.push(b'\n');
+            w.write_all(&line)?;
+            w.flush()
+        });
+
+    if let Err(e) = written {
+        warn!("could not write journal entry {:?}: {}", entry, e);
+    }
+}
+
+/// What [replay] did with the entries of a journal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Operations that were performed
+    pub applied: usize,
+
+    /// Operations that were skipped because the host already is in the
+    /// resulting state, e.g. a jail of the same name is running
+    pub satisfied: usize,
+
+    /// Entries that were skipped because they describe failed operations,
+    /// or operations that do not change the state of a jail, i.e. attaching
+    /// and executing commands
+    pub ignored: usize,
+}
+
+/// Look up the jail an entry refers to on this host, by name if it has one
+/// and otherwise by the jail created for its `jid` during the replay.
+#[cfg(target_os = "freebsd")]
+fn resolve(entry: &Entry, created: &HashMap<i32, RunningJail>) -> Option<RunningJail> {
+    match entry.name {
+        Some(ref name) => RunningJail::from_name_strict(name).ok(),
+        None => created.get(&entry.jid).copied(),
+    }
+}
+
+/// Build the jail created by a journaled `create` operation.
+#[cfg(target_os = "freebsd")]
+fn stopped(entry: &Entry) -> Result<StoppedJail, JailError> {
+    let mut params = entry.params.clone();
+    let path = match params.remove("path") {
+        Some(Value::String(path)) => path,
+        _ => return Err(JailError::PathNotGiven),
+    };
+
+    let mut stopped = StoppedJail::new(path);
+    if let Some(Value::String(name)) = params.remove("name") {
+        stopped.name = Some(name);
+    }
+    if let Some(Value::String(hostname)) = params.remove("host.hostname") {
+        stopped.hostname = Some(hostname);
+    }
+    if let Some(Value::Ipv4Addrs(addrs)) = params.remove("ip4.addr") {
+        stopped.ips.extend(addrs.into_iter().map(IpAddr::V4));
+    }
+    if let Some(Value::Ipv6Addrs(addrs)) = params.remove("ip6.addr") {
+        stopped.ips.extend(addrs.into_iter().map(IpAddr::V6));
+    }
+
+    stopped.params = params.into_iter().collect();
+    Ok(stopped)
+}
+
+/// Apply the operations of a journal written by [set_writer] to this host,
+/// in order.
+///
+/// Operations already reflected in the state of the host are skipped, so a
+/// journal can be replayed repeatedly, e.g. after a partial failure:
+///
+/// * jails are only created if no jail of the same name is running,
+/// * jails are only killed if they are running,
+/// * parameters are only set if they differ from the journaled value.
+///
+/// Jails are matched by name. Unnamed jails can only be referred to by
+/// later entries if they were created during the same replay, since their
+/// `jid` differs between hosts. Failed operations, attaching and executing
+/// commands are not replayed.
+///
+/// Replaying stops at the first malformed line or failing operation. The
+/// replayed operations are journaled like any other.
+///
+/// # Examples
+///
+/// ```
+/// use jail::journal;
+///
+/// let journal = r#"
+/// {"timestamp":1700000000.0,"op":"create","jid":7,"name":"testjail_replay","params":{"path":{"String":"/rescue"}},"result":"success"}
+/// {"timestamp":1700000001.0,"op":"param_set","jid":7,"name":"testjail_replay","params":{"allow.raw_sockets":{"Int":1}},"result":"success"}
+/// "#;
+///
+/// let report = journal::replay(journal.as_bytes()).expect("could not replay journal");
+/// assert_eq!(report.applied, 2);
+///
+/// // Replaying again changes nothing.
+/// let report = journal::replay(journal.as_bytes()).expect("could not replay journal");
+/// assert_eq!(report.applied, 0);
+/// assert_eq!(report.satisfied, 2);
+/// # jail::RunningJail::from_name_strict("testjail_replay").unwrap().kill().unwrap();
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn replay<R: Read>(reader: R) -> Result<ReplayReport, JailError> {
+    trace!("journal::replay()");
+    let mut report = ReplayReport::default();
+    let mut created = HashMap::new();
+
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.map_err(JailError::IoError)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry: Entry =
+            serde_json::from_str(&line).map_err(|e| JailError::JournalParseError {
+                line: i + 1,
+                message: e.to_string(),
+            })?;
+
+        if !entry.success {
+            report.ignored += 1;
+            continue;
+        }
+
+        match entry.operation {
+            Operation::Create => match resolve(&entry, &created) {
+                Some(running) if entry.name.is_some() => {
+                    created.insert(entry.jid, running);
+                    report.satisfied += 1;
+                }
+                _ => {
+                    created.insert(entry.jid, stopped(&entry)?.start()?);
+                    report.applied += 1;
+                }
+            },
+            Operation::Kill => match resolve(&entry, &created) {
+                Some(running) => {
+                    running.kill()?;
+                    created.remove(&entry.jid);
+                    report.applied += 1;
+                }
+                None => report.satisfied += 1,
+            },
+            Operation::ParamSet => {
+                let running = resolve(&entry, &created).ok_or(JailError::NoSuchJail(entry.jid))?;
+                for (name, value) in &entry.params {
+                    if running.param(name).ok().as_ref() == Some(value) {
+                        report.satisfied += 1;
+                    } else {
+                        running.param_set(name, value.clone())?;
+                        report.applied += 1;
+                    }
+                }
+            }
+            Operation::Attach | Operation::Exec => report.ignored += 1,
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_schema() {
+        let mut params = BTreeMap::new();
+        params.insert("allow.raw_sockets".to_string(), Value::Int(1));
+
+        let entry = Entry {
+            timestamp: 1700000000.25,
+            operation: Operation::ParamSet,
+            jid: 42,
+            name: Some("www".into()),
+            params,
+            command: vec![],
+            success: true,
+            error: None,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"timestamp":1700000000.25,"op":"param_set","jid":42,"name":"www","params":{"allow.raw_sockets":{"Int":1}},"result":"success"}"#
+        );
+
+        let entry = Entry {
+            operation: Operation::Create,
+            jid: -1,
+            name: None,
+            params: BTreeMap::new(),
+            success: false,
+            error: Some("Operation not permitted".into()),
+            ..entry
+        };
+
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"timestamp":1700000000.25,"op":"create","jid":-1,"result":"failure","error":"Operation not permitted"}"#
+        );
+    }
+
+    #[test]
+    fn entry_roundtrip() {
+        let line = r#"{"timestamp":1700000000.5,"op":"exec","jid":3,"name":"www","command":["/bin/sh","-c","true"],"result":"success"}"#;
+        let entry: Entry = serde_json::from_str(line).unwrap();
+        assert_eq!(entry.operation, Operation::Exec);
+        assert_eq!(entry.command, vec!["/bin/sh", "-c", "true"]);
+        assert!(entry.success);
+        assert_eq!(serde_json::to_string(&entry).unwrap(), line);
+
+        assert!(serde_json::from_str::<Entry>(
+            r#"{"timestamp":0.0,"op":"kill","jid":3,"result":"maybe"}"#
+        )
+        .is_err());
+    }
+}
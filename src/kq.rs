@@ -0,0 +1,408 @@
+//! Watching processes, files and timers with `kqueue`(2)
+//!
+//! A [Kqueue] delivers the exit of processes, changes to files below a jail
+//! root and timer ticks as [Event]s, without polling. With the `rctl`
+//! feature, timers can also sample the `RACCT` usage of a jail, see
+//! [Kqueue::sample_racct].
+//!
+//! # Examples
+//!
+//! ```
+//! use jail::kq::{Event, Kqueue};
+//! use jail::process::Jailed;
+//! use jail::StoppedJail;
+//! use std::process::Command;
+//! use std::time::Duration;
+//!
+//! let running = StoppedJail::new("/rescue")
+//!     .name("testjail_kq")
+//!     .start()
+//!     .expect("could not start jail");
+//!
+//! let mut child = Command::new("/sleep")
+//!     .arg("1")
+//!     .jail(&running)
+//!     .spawn()
+//!     .expect("could not spawn process");
+//!
+//! let mut kq = Kqueue::new().expect("could not create kqueue");
+//! kq.watch_process(child.id() as i32)
+//!     .expect("could not watch process");
+//!
+//! let events = kq
+//!     .wait(Some(Duration::from_secs(5)))
+//!     .expect("could not wait for events");
+//! assert!(matches!(events[..], [Event::Exited { pid, .. }] if pid == child.id() as i32));
+//! # child.wait().unwrap();
+//! # running.kill().expect("could not stop jail");
+//! ```
+use crate::{JailError, RunningJail};
+use log::{trace, warn};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How a watched file changed, see [Kqueue::watch_path].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Change {
+    /// The file was written to
+    pub written: bool,
+
+    /// The file grew
+    pub extended: bool,
+
+    /// The attributes of the file changed
+    pub attributes: bool,
+
+    /// The file was deleted; it is no longer watched
+    pub deleted: bool,
+
+    /// The file was renamed
+    pub renamed: bool,
+}
+
+impl Change {
+    fn from_fflags(fflags: u32) -> Self {
+        Change {
+            written: fflags & libc::NOTE_WRITE != 0,
+            extended: fflags & libc::NOTE_EXTEND != 0,
+            attributes: fflags & libc::NOTE_ATTRIB != 0,
+            deleted: fflags & libc::NOTE_DELETE != 0,
+            renamed: fflags & libc::NOTE_RENAME != 0,
+        }
+    }
+}
+
+/// An event delivered by [Kqueue::wait].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A watched process exited.
+    Exited {
+        /// The process id
+        pid: i32,
+
+        /// The exit status, as returned by `waitpid`(2)
+        status: i32,
+    },
+
+    /// A watched file changed.
+    Changed {
+        /// The path of the file on the host
+        path: PathBuf,
+
+        /// How the file changed
+        change: Change,
+    },
+
+    /// A timer expired.
+    Timer {
+        /// The timer, as returned by [Kqueue::add_timer]
+        id: usize,
+
+        /// How often the timer expired since it was last delivered
+        count: usize,
+    },
+
+    /// The `RACCT` usage of a jail was sampled.
+    #[cfg(feature = "rctl")]
+    Racct {
+        /// The `jid` of the jail
+        jid: i32,
+
+        /// The usage of each resource
+        usage: HashMap<rctl::Resource, usize>,
+    },
+}
+
+/// What a timer is for.
+#[derive(Clone, Copy, Debug)]
+enum Timer {
+    Plain,
+    #[cfg(feature = "rctl")]
+    Racct(RunningJail),
+}
+
+/// A `kqueue`(2) watching processes, files and timers.
+///
+/// See the [module documentation](index.html).
+#[derive(Debug)]
+pub struct Kqueue {
+    fd: OwnedFd,
+    files: HashMap<RawFd, (File, PathBuf)>,
+    timers: HashMap<usize, Timer>,
+    next_timer: usize,
+}
+
+/// Build a `kevent` change.
+fn change(ident: usize, filter: i16, flags: u16, fflags: u32, data: i64) -> libc::kevent {
+    let mut event: libc::kevent = unsafe { std::mem::zeroed() };
+    event.ident = ident;
+    event.filter = filter;
+    event.flags = flags;
+    event.fflags = fflags;
+    event.data = data as _;
+    event
+}
+
+impl Kqueue {
+    /// Create a kqueue.
+    pub fn new() -> Result<Self, JailError> {
+        trace!("Kqueue::new()");
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 {
+            return Err(JailError::from_errno());
+        }
+
+        Ok(Kqueue {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            files: HashMap::new(),
+            timers: HashMap::new(),
+            next_timer: 0,
+        })
+    }
+
+    /// Register a change with the kqueue.
+    fn register(&self, change: libc::kevent) -> Result<(), JailError> {
+        let result = unsafe {
+            libc::kevent(
+                self.fd.as_raw_fd(),
+                &change,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        match result {
+            -1 => Err(JailError::from_errno()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deliver an [Event::Exited] when the process `pid` exits.
+    ///
+    /// The process need not be a child of the caller, e.g. any process
+    /// inside a jail can be watched.
+    pub fn watch_process(&mut self, pid: i32) -> Result<(), JailError> {
+        trace!("Kqueue::watch_process({:?}, pid={})", self, pid);
+        self.register(change(
+            pid as usize,
+            libc::EVFILT_PROC,
+            libc::EV_ADD | libc::EV_ONESHOT,
+            libc::NOTE_EXIT,
+            0,
+        ))
+    }
+
+    /// Deliver an [Event::Changed] whenever the file or directory at `path`
+    /// inside `jail` changes.
+    ///
+    /// For a directory, only changes to the directory itself are reported,
+    /// e.g. entries being added or removed, not changes to the files in it.
+    pub fn watch_path<P: AsRef<Path>>(
+        &mut self,
+        jail: &RunningJail,
+        path: P,
+    ) -> Result<(), JailError> {
+        trace!(
+            "Kqueue::watch_path({:?}, jail={:?}, path={:?})",
+            self,
+            jail,
+            path.as_ref()
+        );
+        let path = jail.host_path(path)?;
+        let file = File::open(&path).map_err(JailError::IoError)?;
+        let fd = file.as_raw_fd();
+
+        self.register(change(
+            fd as usize,
+            libc::EVFILT_VNODE,
+            libc::EV_ADD | libc::EV_CLEAR,
+            libc::NOTE_WRITE
+                | libc::NOTE_EXTEND
+                | libc::NOTE_ATTRIB
+                | libc::NOTE_DELETE
+                | libc::NOTE_RENAME,
+            0,
+        ))?;
+        self.files.insert(fd, (file, path));
+        Ok(())
+    }
+
+    /// Stop watching the file at the host path `path`, as reported in
+    /// [Event::Changed].
+    pub fn unwatch_path<P: AsRef<Path>>(&mut self, path: P) {
+        trace!("Kqueue::unwatch_path({:?}, path={:?})", self, path.as_ref());
+        // Closing the file removes it from the kqueue.
+        self.files
+            .retain(|_, (_, watched)| watched.as_path() != path.as_ref());
+    }
+
+    fn timer(&mut self, interval: Duration, timer: Timer) -> Result<usize, JailError> {
+        let id = self.next_timer;
+        let millis = interval.as_millis().clamp(1, i64::MAX as u128) as i64;
+        self.register(change(id, libc::EVFILT_TIMER, libc::EV_ADD, 0, millis))?;
+
+        self.next_timer += 1;
+        self.timers.insert(id, timer);
+        Ok(id)
+    }
+
+    /// Deliver an [Event::Timer] every `interval`, with millisecond
+    /// resolution, returning the id of the timer.
+    pub fn add_timer(&mut self, interval: Duration) -> Result<usize, JailError> {
+        trace!("Kqueue::add_timer({:?}, interval={:?})", self, interval);
+        self.timer(interval, Timer::Plain)
+    }
+
+    /// Deliver an [Event::Racct] with the `RACCT` usage of `jail` every
+    /// `interval`, returning the id of the timer.
+    ///
+    /// The timer is removed once the jail no longer exists.
+    #[cfg(feature = "rctl")]
+    pub fn sample_racct(
+        &mut self,
+        jail: &RunningJail,
+        interval: Duration,
+    ) -> Result<usize, JailError> {
+        trace!(
+            "Kqueue::sample_racct({:?}, jail={:?}, interval={:?})",
+            self,
+            jail,
+            interval
+        );
+        self.timer(interval, Timer::Racct(*jail))
+    }
+
+    /// Remove a timer added with [Kqueue::add_timer] or
+    /// [Kqueue::sample_racct].
+    pub fn remove_timer(&mut self, id: usize) -> Result<(), JailError> {
+        trace!("Kqueue::remove_timer({:?}, id={})", self, id);
+        if self.timers.remove(&id).is_none() {
+            return Ok(());
+        }
+
+        self.register(change(id, libc::EVFILT_TIMER, libc::EV_DELETE, 0, 0))
+    }
+
+    /// Block until at least one event is pending or `timeout` expires, and
+    /// return the pending events.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, JailError> {
+        trace!("Kqueue::wait({:?}, timeout={:?})", self, timeout);
+        let timeout = timeout.map(|timeout| libc::timespec {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        });
+
+        let mut received: Vec<libc::kevent> = vec![unsafe { std::mem::zeroed() }; 32];
+        let n = loop {
+            let n = unsafe {
+                libc::kevent(
+                    self.fd.as_raw_fd(),
+                    std::ptr::null(),
+                    0,
+                    received.as_mut_ptr(),
+                    received.len() as libc::c_int,
+                    timeout
+                        .as_ref()
+                        .map_or(std::ptr::null(), |timeout| timeout as *const _),
+                )
+            };
+
+            match n {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+                -1 => return Err(JailError::from_errno()),
+                n => break n as usize,
+            }
+        };
+
+        let mut events = vec![];
+        for event in &received[..n] {
+            if event.flags & libc::EV_ERROR != 0 {
+                warn!(
+                    "kevent error for {} in filter {}: {}",
+                    event.ident,
+                    event.filter,
+                    io::Error::from_raw_os_error(event.data as i32)
+                );
+                continue;
+            }
+
+            match event.filter {
+                libc::EVFILT_PROC => events.push(Event::Exited {
+                    pid: event.ident as i32,
+                    status: event.data as i32,
+                }),
+                libc::EVFILT_VNODE => {
+                    let change = Change::from_fflags(event.fflags);
+                    let fd = event.ident as RawFd;
+                    let path = match change.deleted {
+                        true => self.files.remove(&fd).map(|(_, path)| path),
+                        false => self.files.get(&fd).map(|(_, path)| path.clone()),
+                    };
+
+                    if let Some(path) = path {
+                        events.push(Event::Changed { path, change });
+                    }
+                }
+                // The other events are already taken off the queue, so an
+                // error here must not lose them.
+                libc::EVFILT_TIMER => match self.expired(event.ident, event.data as usize) {
+                    Ok(Some(event)) => events.push(event),
+                    Ok(None) => {}
+                    Err(e) => warn!("could not handle timer {}: {}", event.ident, e),
+                },
+                _ => {}
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Turn an expired timer into an event.
+    fn expired(&mut self, id: usize, count: usize) -> Result<Option<Event>, JailError> {
+        match self.timers.get(&id) {
+            None => Ok(None),
+            Some(Timer::Plain) => Ok(Some(Event::Timer { id, count })),
+            #[cfg(feature = "rctl")]
+            Some(Timer::Racct(jail)) => {
+                let jail = *jail;
+                if !RunningJail::exists(jail.jid) {
+                    self.remove_timer(id)?;
+                    return Ok(None);
+                }
+
+                match jail.racct_statistics() {
+                    Ok(usage) => Ok(Some(Event::Racct {
+                        jid: jail.jid,
+                        usage,
+                    })),
+                    Err(e) => {
+                        warn!("could not sample RACCT usage of jail {}: {}", jail.jid, e);
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vnode_changes() {
+        assert_eq!(Change::from_fflags(0), Change::default());
+
+        let change = Change::from_fflags(libc::NOTE_WRITE | libc::NOTE_EXTEND);
+        assert!(change.written && change.extended);
+        assert!(!change.deleted && !change.renamed && !change.attributes);
+
+        let change = Change::from_fflags(libc::NOTE_DELETE);
+        assert!(change.deleted && !change.written);
+    }
+}
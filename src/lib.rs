@@ -6,24 +6,113 @@
 use log::trace;
 use std::collections::HashMap;
 use std::convert;
-use std::net;
 use std::path;
 
-#[macro_use]
 mod sys;
 
+/// The raw system call layer, see the [jail_sys] crate.
+pub use jail_sys;
+
+#[cfg(feature = "proptest")]
+mod arbitrary;
+mod encoding;
 mod error;
 pub use error::JailError;
 
+mod name;
+pub use name::{namespace, set_namespace, JailName, Namespace};
+
+mod osrelease;
+mod passwd;
+mod pretty;
+mod registry;
+
+mod cached;
+pub use cached::CachedJail;
+
+#[cfg(target_os = "freebsd")]
+mod view;
+#[cfg(target_os = "freebsd")]
+pub use view::JailView;
+
 mod running;
+pub use running::DiskUsage;
+pub use running::InterfaceStats;
+pub use running::KillReport;
 pub use running::RunningJail;
 pub use running::RunningJails as RunningJailIter;
+pub use running::Uncaptured;
 
 mod stopped;
-pub use stopped::StoppedJail;
+pub use stopped::{CreatedJail, StoppedJail};
+
+mod set;
+pub use set::JailSet;
+
+#[cfg(target_os = "freebsd")]
+mod group;
+#[cfg(target_os = "freebsd")]
+pub use group::{GroupReport, JailGroup, MemberResult, MemberStats};
+
+mod stop;
+pub use stop::{Signal, StopPolicy};
 
+#[cfg(target_os = "freebsd")]
+mod shutdown;
+#[cfg(target_os = "freebsd")]
+pub use shutdown::{shutdown_all, JailShutdown, ShutdownPolicy, ShutdownReport};
+
+pub mod allow;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod capacity;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod defaults;
+pub mod devfs;
+pub mod fs;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod hostname;
+#[cfg(feature = "interop")]
+pub mod interop;
+#[cfg(feature = "ipfw")]
+pub mod ipfw;
+#[cfg(feature = "journal")]
+pub mod journal;
+#[cfg(target_os = "freebsd")]
+pub mod kq;
+#[cfg(feature = "rctl")]
+pub mod limits;
+#[cfg(all(target_os = "freebsd", feature = "zfs"))]
+pub mod migrate;
+pub mod mount;
+pub mod net;
+pub mod notify;
+pub mod oscompat;
 pub mod param;
+pub mod pidfile;
+pub mod privileges;
 pub mod process;
+pub mod provision;
+#[cfg(feature = "rctl")]
+pub mod racct;
+#[cfg(all(target_os = "freebsd", feature = "rctl"))]
+pub use racct::rctl_gc;
+#[cfg(feature = "rctl")]
+pub mod report;
+pub mod sched;
+pub mod service;
+pub mod sysctls;
+pub mod testing;
+#[cfg(feature = "ucl")]
+pub mod ucl;
+pub mod users;
+pub mod vars;
+pub mod verify;
+
+#[cfg(feature = "zfs")]
+mod zfs;
 
 #[cfg(test)]
 mod tests;
@@ -31,6 +120,7 @@ mod tests;
 /// Represents a running or stopped jail.
 #[cfg(target_os = "freebsd")]
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::large_enum_variant))]
 pub enum Jail {
     Stopped(StoppedJail),
     Running(RunningJail),
@@ -77,8 +167,17 @@ impl Jail {
     /// stopped Jail.
     pub fn stop(self) -> Result<Self, JailError> {
         trace!("Jail::stop({:?})", self);
+        self.stop_with(&StopPolicy::immediate())
+    }
+
+    /// Stop the jail, terminating its processes according to `policy`
+    ///
+    /// This calls stop_with() on a started Jail, and is a no-op for an
+    /// already stopped Jail.
+    pub fn stop_with(self, policy: &StopPolicy) -> Result<Self, JailError> {
+        trace!("Jail::stop_with({:?}, policy={:?})", self, policy);
         match self {
-            Jail::Running(r) => Ok(Jail::Stopped(r.stop()?)),
+            Jail::Running(r) => Ok(Jail::Stopped(r.stop_with(policy)?)),
             Jail::Stopped(s) => Ok(Jail::Stopped(s)),
         }
     }
@@ -120,7 +219,7 @@ impl Jail {
     }
 
     /// Get the IP Addresses of a jail
-    pub fn ips(&self) -> Result<Vec<net::IpAddr>, JailError> {
+    pub fn ips(&self) -> Result<Vec<std::net::IpAddr>, JailError> {
         trace!("Jail::ips({:?})", self);
         match self {
             Jail::Running(r) => r.ips(),
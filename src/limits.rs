@@ -0,0 +1,296 @@
+//! Resource limits in the rule syntax of `rctl`(8)
+//!
+//! [StoppedJail::limits](../struct.StoppedJail.html#structfield.limits) holds
+//! limits as `(Resource, Limit, Action)` tuples, which serialize as nested
+//! structures. The functions in this module convert them to and from the
+//! canonical rule strings used by `rctl`(8) and `rctl.conf`(5), e.g.
+//! `jail:www:memoryuse:deny=1073741824`.
+//!
+//! With the `serialize` feature, this module can be used as an alternative
+//! representation with `#[serde(with = "jail::limits")]`:
+//!
+//! ```
+//! # #[cfg(feature = "serialize")]
+//! # {
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Backup {
+//!     name: String,
+//!     #[serde(with = "jail::limits")]
+//!     limits: Vec<(rctl::Resource, rctl::Limit, rctl::Action)>,
+//! }
+//!
+//! let backup = Backup {
+//!     name: "www".into(),
+//!     limits: vec![(
+//!         rctl::Resource::MaxProcesses,
+//!         rctl::Limit::amount(100),
+//!         rctl::Action::Deny,
+//!     )],
+//! };
+//!
+//! let json = serde_json::to_string(&backup).unwrap();
+//! assert_eq!(json, r#"{"name":"www","limits":["maxproc:deny=100"]}"#);
+//!
+//! let parsed: Backup = serde_json::from_str(&json).unwrap();
+//! assert_eq!(parsed.limits, backup.limits);
+//! # }
+//! ```
+use crate::JailError;
+use log::trace;
+use std::fmt;
+use std::str::FromStr;
+
+/// A resource limit, as stored in
+/// [StoppedJail::limits](../struct.StoppedJail.html#structfield.limits).
+pub type Limit = (rctl::Resource, rctl::Limit, rctl::Action);
+
+fn invalid(rule: &str, message: String) -> JailError {
+    JailError::InvalidRctlRule {
+        rule: rule.to_string(),
+        message,
+    }
+}
+
+/// Format a limit as a rule without subject, e.g.
+/// `memoryuse:deny=1073741824`.
+pub fn format(limit: &Limit) -> String {
+    trace!("limits::format(limit={:?})", limit);
+    let (resource, amount, action) = limit;
+    format!("{}:{}={}", resource, action, amount)
+}
+
+/// Format a limit as a rule for the jail `name`, as listed by `rctl`(8).
+///
+/// # Examples
+///
+/// ```
+/// use jail::limits;
+///
+/// let limit = (
+///     rctl::Resource::MemoryUse,
+///     rctl::Limit::amount(1 << 30),
+///     rctl::Action::Deny,
+/// );
+/// assert_eq!(
+///     limits::format_rule("www", &limit),
+///     "jail:www:memoryuse:deny=1073741824"
+/// );
+/// ```
+pub fn format_rule(name: &str, limit: &Limit) -> String {
+    trace!("limits::format_rule(name={:?}, limit={:?})", name, limit);
+    format!("jail:{}:{}", name, format(limit))
+}
+
+/// A group of processes, by user or login class, that limits can be scoped
+/// to with
+/// [StoppedJail::scoped_limit](../struct.StoppedJail.html#method.scoped_limit).
+///
+/// `RCTL` matches users and login classes on the whole host, not only inside
+/// a jail. Scoped limits therefore also apply to processes of the same user
+/// or login class in other jails and on the host, and should only be used
+/// with users and login classes dedicated to a jail.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum Scope {
+    /// The processes of a user, by uid
+    User(u32),
+
+    /// The processes of a login class, see `login.conf`(5)
+    LoginClass(String),
+}
+
+impl Scope {
+    /// The `RCTL` subject of the scope.
+    pub fn subject(&self) -> rctl::Subject {
+        trace!("Scope::subject({:?})", self);
+        match self {
+            Scope::User(uid) => rctl::Subject::user_id(*uid),
+            Scope::LoginClass(class) => rctl::Subject::login_class(class.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scope::User(uid) => write!(f, "user:{}", uid),
+            Scope::LoginClass(class) => write!(f, "loginclass:{}", class),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = JailError;
+
+    /// Parse a scope as `user:<uid>` or `loginclass:<name>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("user", uid)) => uid
+                .parse()
+                .map(Scope::User)
+                .map_err(|_| invalid(s, format!("invalid uid {:?}", uid))),
+            Some(("loginclass", class)) if !class.is_empty() && !class.contains(':') => {
+                Ok(Scope::LoginClass(class.to_string()))
+            }
+            _ => Err(invalid(
+                s,
+                "expected user:<uid> or loginclass:<name>".into(),
+            )),
+        }
+    }
+}
+
+/// A resource limit scoped to a user or login class, as stored in
+/// [StoppedJail::scoped_limits](../struct.StoppedJail.html#structfield.scoped_limits).
+pub type ScopedLimit = (Scope, Limit);
+
+/// Format a scoped limit as a rule, as listed by `rctl`(8).
+///
+/// # Examples
+///
+/// ```
+/// use jail::limits::{self, Scope};
+///
+/// let limit = (
+///     rctl::Resource::MaxProcesses,
+///     rctl::Limit::amount(50),
+///     rctl::Action::Deny,
+/// );
+/// assert_eq!(
+///     limits::format_scoped_rule(&(Scope::User(1001), limit)),
+///     "user:1001:maxproc:deny=50"
+/// );
+/// ```
+pub fn format_scoped_rule(scoped: &ScopedLimit) -> String {
+    trace!("limits::format_scoped_rule(scoped={:?})", scoped);
+    let (scope, limit) = scoped;
+    format!("{}:{}", scope, format(limit))
+}
+
+/// Parse a rule, either with a jail subject like `jail:www:maxproc:deny=100`
+/// or without subject like `maxproc:deny=100`.
+///
+/// The name of the jail in the subject is not checked. Rules for other
+/// subjects, e.g. users, are rejected.
+///
+/// # Examples
+///
+/// ```
+/// use jail::limits;
+///
+/// let (resource, limit, action) = limits::parse("jail:www:pcpu:deny=50").unwrap();
+/// assert_eq!(resource, rctl::Resource::PercentCpu);
+/// assert_eq!(limit, rctl::Limit::amount(50));
+/// assert_eq!(action, rctl::Action::Deny);
+///
+/// assert_eq!(
+///     limits::parse("maxproc:log=10/jail").unwrap(),
+///     limits::parse("jail:db:maxproc:log=10/jail").unwrap()
+/// );
+/// assert!(limits::parse("user:1001:maxproc:deny=10").is_err());
+/// ```
+pub fn parse(rule: &str) -> Result<Limit, JailError> {
+    trace!("limits::parse(rule={:?})", rule);
+    let rule = rule.trim();
+    let (head, amount) = rule
+        .split_once('=')
+        .ok_or_else(|| invalid(rule, "expected resource:action=amount".into()))?;
+
+    let fields: Vec<&str> = head.split(':').collect();
+    let (resource, action) = match fields[..] {
+        [resource, action] => (resource, action),
+        ["jail", "", _, _] => return Err(invalid(rule, "jail name is empty".into())),
+        ["jail", _, resource, action] => (resource, action),
+        [subject, _, _, _] => {
+            return Err(invalid(
+                rule,
+                format!("subject {:?} is not a jail", subject),
+            ))
+        }
+        _ => {
+            return Err(invalid(
+                rule,
+                "expected [jail:name:]resource:action=amount".into(),
+            ))
+        }
+    };
+
+    Ok((
+        rctl::Resource::from_str(resource)
+            .map_err(|_| invalid(rule, format!("unknown resource {:?}", resource)))?,
+        rctl::Limit::from_str(amount)
+            .map_err(|_| invalid(rule, format!("invalid amount {:?}", amount)))?,
+        rctl::Action::from_str(action)
+            .map_err(|_| invalid(rule, format!("unknown action {:?}", action)))?,
+    ))
+}
+
+/// Serialize limits as rules without subject.
+#[cfg(feature = "serialize")]
+pub fn serialize<S>(limits: &[Limit], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(limits.iter().map(format))
+}
+
+/// Deserialize limits from rules, with or without subject.
+#[cfg(feature = "serialize")]
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Limit>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let rules: Vec<String> = serde::Deserialize::deserialize(deserializer)?;
+    rules
+        .iter()
+        .map(|rule| parse(rule).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_rules() {
+        for rule in &[
+            "",
+            "maxproc",
+            "maxproc=10",
+            "jail::maxproc:deny=10",
+            "loginclass:daemon:maxproc:deny=10",
+            "a:b:c:d:e=1",
+        ] {
+            assert!(
+                matches!(parse(rule), Err(JailError::InvalidRctlRule { .. })),
+                "{:?} was accepted",
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn scopes() {
+        assert_eq!("user:1001".parse::<Scope>().unwrap(), Scope::User(1001));
+        assert_eq!(
+            "loginclass:tenant".parse::<Scope>().unwrap(),
+            Scope::LoginClass("tenant".into())
+        );
+        assert_eq!(Scope::User(1001).to_string(), "user:1001");
+        assert_eq!(
+            Scope::LoginClass("tenant".into()).to_string(),
+            "loginclass:tenant"
+        );
+
+        for scope in &[
+            "",
+            "user:",
+            "user:root",
+            "loginclass:",
+            "jail:www",
+            "process:1",
+        ] {
+            assert!(scope.parse::<Scope>().is_err(), "{:?} was accepted", scope);
+        }
+    }
+}
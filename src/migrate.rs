@@ -0,0 +1,272 @@
+//! Moving a jail to another host
+//!
+//! A migration is a sequence of steps, each a method of [Migration]:
+//!
+//! 1. [Migration::new] looks up the ZFS dataset of the jail root.
+//! 2. [Migration::prepare] sends a snapshot of the root while the jail keeps
+//!    running. This is optional, but leaves only the changes since to be
+//!    sent while the jail is frozen.
+//! 3. [Migration::quiesce] freezes the processes of the jail, see
+//!    [RunningJail::freeze].
+//! 4. [Migration::sync] sends the changes since the last snapshot.
+//! 5. [Migration::handoff] saves the definition of the jail, kills it here
+//!    and starts it on the remote host.
+//!
+//! [Migration::abort] thaws the jail if the migration is given up before
+//! the handoff. [run] performs all steps.
+//!
+//! The library does not move data between hosts itself: the caller provides
+//! a [Transport], e.g. on top of `ssh`(1), which receives the snapshot
+//! streams and starts the jail on the remote host. Processes are not
+//! checkpointed, the jail is restarted on the remote host from its
+//! definition and root file system.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use jail::migrate::{self, Transport};
+//! use jail::{JailError, RunningJail, StoppedJail};
+//! use std::io::{self, Read};
+//! use std::process::{Command, Stdio};
+//!
+//! /// Migrate to a host reachable with `ssh`, using the same dataset names.
+//! struct Ssh {
+//!     host: String,
+//! }
+//!
+//! fn failed(what: &str) -> JailError {
+//!     JailError::IoError(io::Error::new(io::ErrorKind::Other, format!("{} failed", what)))
+//! }
+//!
+//! impl Transport for Ssh {
+//!     fn receive(&mut self, dataset: &str, stream: &mut dyn Read) -> Result<(), JailError> {
+//!         let mut recv = Command::new("ssh")
+//!             .args([self.host.as_str(), "zfs", "recv", "-F", dataset])
+//!             .stdin(Stdio::piped())
+//!             .spawn()
+//!             .map_err(JailError::IoError)?;
+//!         io::copy(stream, recv.stdin.as_mut().unwrap()).map_err(JailError::IoError)?;
+//!         drop(recv.stdin.take());
+//!         match recv.wait().map_err(JailError::IoError)?.success() {
+//!             true => Ok(()),
+//!             false => Err(failed("zfs recv")),
+//!         }
+//!     }
+//!
+//!     fn start(&mut self, definition: &StoppedJail) -> Result<i32, JailError> {
+//!         let name = definition.name.as_ref().ok_or_else(|| failed("unnamed jail"))?;
+//!         let path = definition.path.as_ref().ok_or(JailError::PathNotGiven)?;
+//!         let output = Command::new("ssh")
+//!             .args([self.host.as_str(), "jail", "-i", "-c", "persist"])
+//!             .arg(format!("name={}", name))
+//!             .arg(format!("path={}", path.display()))
+//!             .output()
+//!             .map_err(JailError::IoError)?;
+//!         String::from_utf8_lossy(&output.stdout)
+//!             .trim()
+//!             .parse()
+//!             .map_err(|_| failed("jail -c"))
+//!     }
+//! }
+//!
+//! let running = RunningJail::from_name_strict("www").expect("no such jail");
+//! let mut ssh = Ssh {
+//!     host: "other.example.org".into(),
+//! };
+//!
+//! let jid = migrate::run(&running, &mut ssh).expect("could not migrate jail");
+//! println!("www runs as jail {} on other.example.org", jid);
+//! ```
+use crate::zfs::Dataset;
+use crate::{JailError, RunningJail, StoppedJail};
+use log::{trace, warn};
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The remote end of a migration, see the [module documentation](index.html).
+pub trait Transport {
+    /// Receive a `zfs send` stream of `dataset` on the remote host, e.g. by
+    /// piping it into `zfs recv -F`.
+    ///
+    /// The first stream is a full stream, later ones are incremental from
+    /// the previously received snapshot.
+    fn receive(&mut self, dataset: &str, stream: &mut dyn Read) -> Result<(), JailError>;
+
+    /// Start the jail from its definition on the remote host, returning its
+    /// jid there.
+    fn start(&mut self, definition: &StoppedJail) -> Result<i32, JailError>;
+}
+
+/// A migration of a jail to another host, see the
+/// [module documentation](index.html).
+///
+/// Dropping a migration leaves the jail as it is, frozen or not.
+#[derive(Debug)]
+pub struct Migration {
+    jail: RunningJail,
+    dataset: Dataset,
+    prefix: String,
+    snapshots: usize,
+    sent: Option<String>,
+    frozen: bool,
+    synced: bool,
+}
+
+impl Migration {
+    /// Start migrating `jail`.
+    ///
+    /// Fails if the jail root is not the mountpoint of a ZFS dataset.
+    pub fn new(jail: &RunningJail) -> Result<Self, JailError> {
+        trace!("Migration::new(jail={:?})", jail);
+        let dataset = Dataset::at(&jail.path()?)?;
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+
+        Ok(Migration {
+            jail: *jail,
+            dataset,
+            prefix: format!("migrate-{}", started),
+            snapshots: 0,
+            sent: None,
+            frozen: false,
+            synced: false,
+        })
+    }
+
+    /// The name of the ZFS dataset of the jail root.
+    pub fn dataset(&self) -> &str {
+        trace!("Migration::dataset({:?})", self);
+        self.dataset.name()
+    }
+
+    /// Send a snapshot of the jail root while the jail keeps running.
+    ///
+    /// This can be called repeatedly, each time sending the changes since
+    /// the previous snapshot.
+    pub fn prepare<T: Transport>(&mut self, transport: &mut T) -> Result<(), JailError> {
+        trace!("Migration::prepare({:?})", self);
+        self.send(transport)
+    }
+
+    /// Freeze the processes of the jail, so that its root no longer
+    /// changes.
+    pub fn quiesce(&mut self) -> Result<(), JailError> {
+        trace!("Migration::quiesce({:?})", self);
+        self.jail.freeze()?;
+        self.frozen = true;
+        Ok(())
+    }
+
+    /// Send the changes to the jail root since the last snapshot, freezing
+    /// the jail first if [Migration::quiesce] was not called.
+    pub fn sync<T: Transport>(&mut self, transport: &mut T) -> Result<(), JailError> {
+        trace!("Migration::sync({:?})", self);
+        if !self.frozen {
+            self.quiesce()?;
+        }
+
+        self.send(transport)?;
+        self.synced = true;
+        Ok(())
+    }
+
+    /// Save the definition of the jail, kill it and start it on the remote
+    /// host, returning its jid there. Calls [Migration::sync] first unless
+    /// it was called already.
+    ///
+    /// The jail is thawed if it cannot be synced, saved or killed, and
+    /// restarted on this host if it cannot be started on the remote host.
+    pub fn handoff<T: Transport>(mut self, transport: &mut T) -> Result<i32, JailError> {
+        trace!("Migration::handoff({:?})", self);
+        let jail = self.jail;
+        let saved = match self.synced {
+            true => Ok(()),
+            false => self.sync(transport),
+        }
+        .and_then(|_| jail.save())
+        .and_then(|definition| {
+            jail.kill()?;
+            Ok(definition)
+        });
+
+        let definition = match saved {
+            Ok(definition) => definition,
+            Err(e) => {
+                self.abort()?;
+                return Err(e);
+            }
+        };
+
+        match transport.start(&definition) {
+            Ok(jid) => Ok(jid),
+            Err(e) => {
+                let name = definition.name.clone();
+                if let Err(restart) = definition.start() {
+                    warn!("could not restart jail {:?}: {}", name, restart);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Give up the migration, thawing the jail.
+    ///
+    /// The snapshots sent so far are kept on both hosts.
+    pub fn abort(self) -> Result<(), JailError> {
+        trace!("Migration::abort({:?})", self);
+        if self.frozen {
+            self.jail.thaw()?;
+        }
+
+        Ok(())
+    }
+
+    /// Take a snapshot and send it, incremental from the last one sent.
+    /// Only the last snapshot sent is kept, as the base of the next one.
+    fn send<T: Transport>(&mut self, transport: &mut T) -> Result<(), JailError> {
+        let snapshot = format!("{}-{}", self.prefix, self.snapshots);
+        self.snapshots += 1;
+        self.dataset.snapshot(&snapshot)?;
+
+        let dataset = self.dataset.name();
+        if let Err(e) = self
+            .dataset
+            .send(&snapshot, self.sent.as_deref(), |stream| {
+                transport.receive(dataset, stream)
+            })
+        {
+            self.dataset.destroy_snapshot(&snapshot).ok();
+            return Err(e);
+        }
+
+        if let Some(previous) = self.sent.replace(snapshot) {
+            if let Err(e) = self.dataset.destroy_snapshot(&previous) {
+                warn!("could not destroy snapshot {}: {}", previous, e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Migrate `jail` in one go, returning its jid on the remote host: send a
+/// snapshot while the jail runs, freeze it, send the remaining changes and
+/// hand it off to the remote host.
+///
+/// The jail is thawed if the migration fails before the handoff.
+pub fn run<T: Transport>(jail: &RunningJail, transport: &mut T) -> Result<i32, JailError> {
+    trace!("migrate::run(jail={:?})", jail);
+    let mut migration = Migration::new(jail)?;
+
+    if let Err(e) = migration
+        .prepare(transport)
+        .and_then(|_| migration.sync(transport))
+    {
+        migration.abort()?;
+        return Err(e);
+    }
+
+    migration.handoff(transport)
+}
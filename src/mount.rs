@@ -0,0 +1,810 @@
+//! File systems mounted below jail roots
+//!
+//! File systems declared on a [StoppedJail](../struct.StoppedJail.html) using
+//! [StoppedJail::mount](../struct.StoppedJail.html#method.mount) are mounted
+//! when the jail is started, and unmounted again when it is killed using
+//! [RunningJail::kill](../struct.RunningJail.html#method.kill), following
+//! the [UnmountPolicy] of the jail.
+//!
+//! The `readonly`, `nosuid` and `noexec` flags of a [Mount] are checked
+//! against the mount table once mounted, and `nullfs` mounts of sensitive
+//! host paths like `/etc` must be read-only unless explicitly allowed, see
+//! [Mount::allow_sensitive].
+use crate::allow::Allow;
+use crate::{fs, JailError};
+use log::trace;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(target_os = "freebsd")]
+use crate::{param, RunningJail};
+#[cfg(target_os = "freebsd")]
+use log::warn;
+#[cfg(target_os = "freebsd")]
+use std::ffi::CStr;
+#[cfg(target_os = "freebsd")]
+use std::process::Command;
+#[cfg(target_os = "freebsd")]
+use std::thread;
+#[cfg(target_os = "freebsd")]
+use std::time::Instant;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// A file system to mount below the jail root.
+///
+/// # Examples
+///
+/// ```
+/// use jail::mount::Mount;
+///
+/// let ports = Mount::nullfs("/usr/ports", "/usr/ports").readonly(true);
+/// assert_eq!(ports.fstype, "nullfs");
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Mount {
+    /// The file system type, e.g. `nullfs` or `tmpfs`
+    pub fstype: String,
+
+    /// The mounted host path for `nullfs`, or the name of the file system
+    /// for other types
+    pub source: PathBuf,
+
+    /// The mountpoint inside the jail
+    pub target: PathBuf,
+
+    /// Whether to mount the file system read-only
+    pub readonly: bool,
+
+    /// Whether to ignore setuid and setgid bits
+    pub nosuid: bool,
+
+    /// Whether to forbid executing files
+    pub noexec: bool,
+
+    /// Whether to allow mounting a sensitive host path like `/etc`
+    /// read-write with `nullfs`
+    pub allow_sensitive: bool,
+
+    /// Options specific to the file system type, passed to `nmount`(2),
+    /// e.g. `("size", "1g")` for `tmpfs`. Options without a value are
+    /// passed as flags.
+    pub options: Vec<(String, String)>,
+}
+
+/// The pseudo file systems with presets, which the kernel only supports
+/// once their modules are loaded.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+const PSEUDO_FILE_SYSTEMS: &[&str] = &["fdescfs", "procfs", "linprocfs", "linsysfs"];
+
+/// Host paths, and everything below them, that `nullfs` only mounts
+/// read-only into jails unless explicitly allowed. The host root itself is
+/// always sensitive.
+const SENSITIVE_PATHS: &[&str] = &["/etc", "/root", "/boot", "/usr/local/etc"];
+
+impl Mount {
+    /// Mount a file system of type `fstype` at `target` inside the jail.
+    pub fn new<F, S, T>(fstype: F, source: S, target: T) -> Self
+    where
+        F: Into<String>,
+        S: Into<PathBuf>,
+        T: Into<PathBuf>,
+    {
+        Mount {
+            fstype: fstype.into(),
+            source: source.into(),
+            target: target.into(),
+            readonly: false,
+            nosuid: false,
+            noexec: false,
+            allow_sensitive: false,
+            options: vec![],
+        }
+    }
+
+    /// Mount the host path `source` at `target` inside the jail using
+    /// `nullfs`(5).
+    pub fn nullfs<S: Into<PathBuf>, T: Into<PathBuf>>(source: S, target: T) -> Self {
+        Mount::new("nullfs", source, target)
+    }
+
+    /// Mount `fdescfs`(5) at `/dev/fd`, like the `mount.fdescfs` parameter of
+    /// `jail`(8).
+    ///
+    /// For Linux jails, add the `linrdlnk` option, so that the entries
+    /// behave like the ones of Linux.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::mount::Mount;
+    ///
+    /// let fd = Mount::fdescfs().option("linrdlnk", "");
+    /// assert_eq!(fd.target, std::path::Path::new("/dev/fd"));
+    /// ```
+    pub fn fdescfs() -> Self {
+        trace!("Mount::fdescfs()");
+        Mount::new("fdescfs", "fdescfs", "/dev/fd")
+    }
+
+    /// Mount `procfs`(5) at `/proc`, like the `mount.procfs` parameter of
+    /// `jail`(8).
+    pub fn procfs() -> Self {
+        trace!("Mount::procfs()");
+        Mount::new("procfs", "proc", "/proc")
+    }
+
+    /// Mount `linprocfs`(5) at `/proc`, for jails running a Linux userland.
+    pub fn linprocfs() -> Self {
+        trace!("Mount::linprocfs()");
+        Mount::new("linprocfs", "linprocfs", "/proc")
+    }
+
+    /// Mount `linsysfs`(5) at `/sys`, for jails running a Linux userland.
+    pub fn linsysfs() -> Self {
+        trace!("Mount::linsysfs()");
+        Mount::new("linsysfs", "linsysfs", "/sys")
+    }
+
+    /// Add an option specific to the file system type. Use an empty `value`
+    /// for flags.
+    pub fn option<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        let (name, value) = (name.into(), value.into());
+        trace!(
+            "Mount::option({:?}, name={:?}, value={:?})",
+            self,
+            name,
+            value
+        );
+        self.options.push((name, value));
+        self
+    }
+
+    /// Set whether to mount the file system read-only.
+    pub fn readonly(mut self, readonly: bool) -> Self {
+        trace!("Mount::readonly({:?}, readonly={})", self, readonly);
+        self.readonly = readonly;
+        self
+    }
+
+    /// Set whether to ignore setuid and setgid bits on the file system.
+    pub fn nosuid(mut self, nosuid: bool) -> Self {
+        trace!("Mount::nosuid({:?}, nosuid={})", self, nosuid);
+        self.nosuid = nosuid;
+        self
+    }
+
+    /// Set whether to forbid executing files on the file system.
+    pub fn noexec(mut self, noexec: bool) -> Self {
+        trace!("Mount::noexec({:?}, noexec={})", self, noexec);
+        self.noexec = noexec;
+        self
+    }
+
+    /// Set whether to allow mounting a sensitive host path read-write with
+    /// `nullfs`.
+    ///
+    /// By default, starting a jail fails with [JailError::SensitiveMount]
+    /// if it mounts `/`, or a path at or below `/etc`, `/root`, `/boot` or
+    /// `/usr/local/etc` read-write, as the jail could then change the
+    /// configuration of the host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::mount::Mount;
+    ///
+    /// let hosts = Mount::nullfs("/etc/hosts", "/etc/hosts");
+    /// assert!(hosts.validate().is_err());
+    /// assert!(hosts.clone().readonly(true).validate().is_ok());
+    /// assert!(hosts.allow_sensitive(true).validate().is_ok());
+    /// ```
+    pub fn allow_sensitive(mut self, allow_sensitive: bool) -> Self {
+        trace!(
+            "Mount::allow_sensitive({:?}, allow_sensitive={})",
+            self,
+            allow_sensitive
+        );
+        self.allow_sensitive = allow_sensitive;
+        self
+    }
+
+    /// Check that the mount does not expose a sensitive host path
+    /// read-write, see [Mount::allow_sensitive].
+    ///
+    /// The source is resolved first, so that symbolic links and `..` do not
+    /// hide a sensitive path.
+    pub fn validate(&self) -> Result<(), JailError> {
+        trace!("Mount::validate({:?})", self);
+        if self.fstype != "nullfs" || self.readonly || self.allow_sensitive {
+            return Ok(());
+        }
+
+        let source = std::fs::canonicalize(&self.source).unwrap_or_else(|_| self.source.clone());
+        let sensitive = source.parent().is_none()
+            || SENSITIVE_PATHS
+                .iter()
+                .any(|sensitive| source.starts_with(sensitive));
+
+        match sensitive {
+            true => Err(JailError::SensitiveMount {
+                path: self.source.clone(),
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// The `nmount`(2) flags of the mount.
+    #[cfg(target_os = "freebsd")]
+    fn flags(&self) -> libc::c_int {
+        [
+            (self.readonly, libc::MNT_RDONLY),
+            (self.nosuid, libc::MNT_NOSUID),
+            (self.noexec, libc::MNT_NOEXEC),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, flag)| flags | flag)
+    }
+
+    /// Mount the file system below the jail root at `root`, creating the
+    /// mountpoint if needed, and return the host path of the mountpoint.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn mount(&self, root: &Path) -> Result<PathBuf, JailError> {
+        trace!("Mount::mount({:?}, root={:?})", self, root);
+        let target = fs::host_path(root, &self.target)?;
+        std::fs::create_dir_all(&target).map_err(JailError::IoError)?;
+
+        let flags = self.flags();
+        nmount(&self.fstype, &self.source, &target, flags, &self.options)?;
+
+        // Some file systems silently drop flags they do not support.
+        if let Err(e) = require_flags(&target, flags) {
+            unmount(&target).ok();
+            return Err(e);
+        }
+
+        Ok(target)
+    }
+
+    /// Check that the kernel supports the file system, for the pseudo file
+    /// systems with presets.
+    ///
+    /// Jail-friendly file systems add an `allow.mount.<fstype>` parameter
+    /// once their module is loaded, so a missing parameter means that the
+    /// mount would fail.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn require_support(&self) -> Result<(), JailError> {
+        trace!("Mount::require_support({:?})", self);
+        let fstype = self.fstype.as_str();
+        if PSEUDO_FILE_SYSTEMS.contains(&fstype)
+            && !param::exists(&format!("allow.mount.{}", fstype))
+        {
+            return Err(JailError::NotSupported(format!(
+                "mounting {} at {:?}: the file system is not available, load it with `kldload {}`",
+                fstype, self.target, fstype
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// How file systems still in use are unmounted when a jail is killed, see
+/// [StoppedJail::unmount_policy](../struct.StoppedJail.html#method.unmount_policy).
+///
+/// A file system stays busy while processes on the host have files open
+/// below it or their working directory in it, or while the processes of
+/// the jail are still exiting. Unmounting is retried until the grace period
+/// has passed. File systems still mounted then are reported in
+/// [KillReport::still_mounted](../struct.KillReport.html#structfield.still_mounted).
+///
+/// # Examples
+///
+/// ```
+/// use jail::mount::UnmountPolicy;
+/// use std::time::Duration;
+///
+/// let policy = UnmountPolicy::default()
+///     .grace(Duration::from_secs(5))
+///     .force(true);
+/// assert!(!policy.kill_holders);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct UnmountPolicy {
+    /// How long unmounting a busy file system is retried
+    pub grace: Duration,
+
+    /// Whether to kill the processes using a busy file system, with
+    /// `fuser -c -k`. This includes processes on the host.
+    pub kill_holders: bool,
+
+    /// Whether to unmount file systems still busy after the grace period
+    /// with `MNT_FORCE`, invalidating the files open below them
+    pub force: bool,
+}
+
+impl Default for UnmountPolicy {
+    fn default() -> Self {
+        UnmountPolicy {
+            grace: Duration::from_secs(1),
+            kill_holders: false,
+            force: false,
+        }
+    }
+}
+
+impl UnmountPolicy {
+    /// Set how long unmounting a busy file system is retried.
+    pub fn grace(mut self, grace: Duration) -> Self {
+        trace!("UnmountPolicy::grace({:?}, grace={:?})", self, grace);
+        self.grace = grace;
+        self
+    }
+
+    /// Set whether to kill the processes using a busy file system.
+    pub fn kill_holders(mut self, kill_holders: bool) -> Self {
+        trace!(
+            "UnmountPolicy::kill_holders({:?}, kill_holders={})",
+            self,
+            kill_holders
+        );
+        self.kill_holders = kill_holders;
+        self
+    }
+
+    /// Set whether to force unmounting after the grace period.
+    pub fn force(mut self, force: bool) -> Self {
+        trace!("UnmountPolicy::force({:?}, force={})", self, force);
+        self.force = force;
+        self
+    }
+}
+
+/// A file system in the mount table of the host, as returned by
+/// [mounts_under].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct MountEntry {
+    /// The file system type, e.g. `nullfs` or `zfs`
+    pub fstype: String,
+
+    /// What is mounted, e.g. the host path for `nullfs` or the dataset for
+    /// `zfs`
+    pub source: PathBuf,
+
+    /// The mountpoint on the host
+    pub mountpoint: PathBuf,
+
+    /// The mountpoint inside the jail, `/` for the jail root itself
+    pub target: PathBuf,
+
+    /// Whether the file system is mounted read-only
+    pub readonly: bool,
+
+    /// Whether setuid and setgid bits are ignored
+    pub nosuid: bool,
+
+    /// Whether executing files is forbidden
+    pub noexec: bool,
+}
+
+impl MountEntry {
+    /// Describe the file system as a [Mount], e.g. to mount it again for
+    /// another jail.
+    pub fn to_mount(&self) -> Mount {
+        trace!("MountEntry::to_mount({:?})", self);
+        Mount::new(
+            self.fstype.as_str(),
+            self.source.as_path(),
+            self.target.as_path(),
+        )
+        .readonly(self.readonly)
+        .nosuid(self.nosuid)
+        .noexec(self.noexec)
+    }
+
+    /// Describe a file system of the mount table, if it is mounted at or
+    /// below `root`.
+    #[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+    fn below(
+        root: &Path,
+        fstype: &[u8],
+        source: &[u8],
+        mountpoint: &[u8],
+        readonly: bool,
+    ) -> Option<MountEntry> {
+        let mountpoint = Path::new(OsStr::from_bytes(mountpoint));
+        let target = fs::jail_path(root, mountpoint)?;
+        Some(MountEntry {
+            fstype: String::from_utf8_lossy(fstype).into_owned(),
+            source: PathBuf::from(OsStr::from_bytes(source)),
+            mountpoint: mountpoint.to_path_buf(),
+            target,
+            readonly,
+            nosuid: false,
+            noexec: false,
+        })
+    }
+}
+
+/// Get the file systems mounted at or below the root of `jail`, in the
+/// order they were mounted.
+///
+/// This reads the mount table of the host with `getfsstat`(2), so it also
+/// includes file systems mounted by other tools or by the jail itself.
+///
+/// # Examples
+///
+/// ```
+/// use jail::mount::{self, Mount};
+/// use jail::StoppedJail;
+/// # let root = std::env::temp_dir().join("testjail_mounts_under");
+/// # std::fs::create_dir_all(&root).unwrap();
+///
+/// let running = StoppedJail::new(&root)
+///     .name("testjail_mounts_under")
+///     .mount(Mount::new("tmpfs", "tmpfs", "/tmp"))
+///     .start()
+///     .expect("could not start jail");
+///
+/// let mounts = mount::mounts_under(&running).expect("could not read mounts");
+/// let tmp = mounts.last().expect("no mounts");
+/// assert_eq!(tmp.fstype, "tmpfs");
+/// assert_eq!(tmp.target, std::path::Path::new("/tmp"));
+/// # running.kill().expect("could not stop jail");
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn mounts_under(jail: &RunningJail) -> Result<Vec<MountEntry>, JailError> {
+    trace!("mount::mounts_under(jail={:?})", jail);
+    let path = jail.path()?;
+    // The mount table holds resolved paths.
+    let root = std::fs::canonicalize(&path).unwrap_or(path);
+
+    let mut entries: Vec<libc::statfs> = vec![];
+    loop {
+        let count = unsafe { libc::getfsstat(std::ptr::null_mut(), 0, libc::MNT_NOWAIT) };
+        if count < 0 {
+            return Err(JailError::from_errno());
+        }
+
+        // Leave room for file systems mounted in the meantime.
+        let capacity = count as usize + 8;
+        entries.reserve(capacity);
+        let size = capacity * std::mem::size_of::<libc::statfs>();
+        let count = unsafe {
+            libc::getfsstat(entries.as_mut_ptr(), size as libc::c_long, libc::MNT_NOWAIT)
+        };
+        if count < 0 {
+            return Err(JailError::from_errno());
+        }
+
+        // A full buffer may have been truncated.
+        if (count as usize) < capacity {
+            unsafe { entries.set_len(count as usize) };
+            break;
+        }
+    }
+
+    let bytes = |name: &[libc::c_char]| unsafe { CStr::from_ptr(name.as_ptr()) }.to_bytes();
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            let flag = |flag: libc::c_int| entry.f_flags & flag as u64 != 0;
+            MountEntry::below(
+                &root,
+                bytes(&entry.f_fstypename),
+                bytes(&entry.f_mntfromname),
+                bytes(&entry.f_mntonname),
+                flag(libc::MNT_RDONLY),
+            )
+            .map(|below| MountEntry {
+                nosuid: flag(libc::MNT_NOSUID),
+                noexec: flag(libc::MNT_NOEXEC),
+                ..below
+            })
+        })
+        .collect())
+}
+
+/// Check that the file system mounted at `target` has all of `flags` set,
+/// as read back with `statfs`(2).
+#[cfg(target_os = "freebsd")]
+fn require_flags(target: &Path, flags: libc::c_int) -> Result<(), JailError> {
+    trace!(
+        "mount::require_flags(target={:?}, flags={:#x})",
+        target,
+        flags
+    );
+    let path = cstring(target.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(JailError::MountError {
+            path: target.to_path_buf(),
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    let missing: Vec<String> = [
+        (libc::MNT_RDONLY, "readonly"),
+        (libc::MNT_NOSUID, "nosuid"),
+        (libc::MNT_NOEXEC, "noexec"),
+    ]
+    .iter()
+    .filter(|(flag, _)| flags & flag != 0 && stat.f_flags & *flag as u64 == 0)
+    .map(|(_, name)| name.to_string())
+    .collect();
+
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(JailError::MountFlagsIgnored {
+            path: target.to_path_buf(),
+            missing,
+        }),
+    }
+}
+
+/// Get the permissions a jail needs to mount file systems of the types
+/// `fstypes` itself: `allow.mount` and the `allow.mount.<fstype>` parameter
+/// of each type that has one, in order and without duplicates.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+pub(crate) fn allow_params<'a, I>(fstypes: I) -> Vec<Allow>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut allow = vec![];
+    for fstype in fstypes {
+        if let Ok(param) = format!("allow.mount.{}", fstype).parse::<Allow>() {
+            if !allow.contains(&param) {
+                allow.push(param);
+            }
+        }
+    }
+
+    if !allow.is_empty() {
+        allow.insert(0, Allow::Mount);
+    }
+    allow
+}
+
+fn cstring(bytes: &[u8]) -> Result<CString, JailError> {
+    CString::new(bytes).map_err(JailError::CStringError)
+}
+
+/// Mount a file system of type `fstype` from `source` at the host path
+/// `target`.
+///
+/// `flags` are passed to `nmount`(2), e.g. `libc::MNT_RDONLY`. For `nullfs`,
+/// `source` is the mounted host path; on FreeBSD 14 and later, it may also be
+/// a regular file or socket.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn nmount(
+    fstype: &str,
+    source: &Path,
+    target: &Path,
+    flags: libc::c_int,
+    extra: &[(String, String)],
+) -> Result<(), JailError> {
+    trace!(
+        "mount::nmount(fstype={:?}, source={:?}, target={:?}, flags={:#x}, extra={:?})",
+        fstype,
+        source,
+        target,
+        flags,
+        extra
+    );
+    let source_option: &[u8] = match fstype {
+        "nullfs" => b"target",
+        _ => b"from",
+    };
+
+    let mut options = vec![
+        Some(cstring(b"fstype")?),
+        Some(cstring(fstype.as_bytes())?),
+        Some(cstring(b"fspath")?),
+        Some(cstring(target.as_os_str().as_bytes())?),
+        Some(cstring(source_option)?),
+        Some(cstring(source.as_os_str().as_bytes())?),
+    ];
+    for (name, value) in extra {
+        options.push(Some(cstring(name.as_bytes())?));
+        // Options without a value are flags, passed with an empty iovec.
+        options.push(match value.is_empty() {
+            true => None,
+            false => Some(cstring(value.as_bytes())?),
+        });
+    }
+
+    let mut iov: Vec<libc::iovec> = options
+        .iter()
+        .map(|option| match option {
+            Some(option) => libc::iovec {
+                iov_base: option.as_ptr() as *mut libc::c_void,
+                iov_len: option.as_bytes_with_nul().len(),
+            },
+            None => libc::iovec {
+                iov_base: std::ptr::null_mut(),
+                iov_len: 0,
+            },
+        })
+        .collect();
+
+    match unsafe { libc::nmount(iov.as_mut_ptr(), iov.len() as libc::c_uint, flags) } {
+        0 => Ok(()),
+        _ => Err(JailError::MountError {
+            path: target.to_path_buf(),
+            source: io::Error::last_os_error(),
+        }),
+    }
+}
+
+/// Unmount the file system mounted at `target`.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn unmount(target: &Path) -> Result<(), JailError> {
+    trace!("mount::unmount(target={:?})", target);
+    unmount_flags(target, 0)
+}
+
+/// Unmount the file system mounted at `target` with `flags`, e.g.
+/// `libc::MNT_FORCE`.
+#[cfg(target_os = "freebsd")]
+fn unmount_flags(target: &Path, flags: libc::c_int) -> Result<(), JailError> {
+    let path = cstring(target.as_os_str().as_bytes())?;
+    match unsafe { libc::unmount(path.as_ptr(), flags) } {
+        0 => Ok(()),
+        _ => Err(JailError::MountError {
+            path: target.to_path_buf(),
+            source: io::Error::last_os_error(),
+        }),
+    }
+}
+
+/// Unmount all file systems mounted at `targets`, in reverse order.
+///
+/// All targets are tried, even if some fail; the first error is returned.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn unmount_all(targets: &[PathBuf]) -> Result<(), JailError> {
+    trace!("mount::unmount_all(targets={:?})", targets);
+    let mut result = Ok(());
+    for target in targets.iter().rev() {
+        result = result.and(unmount(target));
+    }
+
+    result
+}
+
+/// Unmount all file systems mounted at `targets`, in reverse order,
+/// following `policy`, and return the targets still mounted.
+///
+/// Targets that are no longer mounted count as unmounted.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn teardown(targets: &[PathBuf], policy: &UnmountPolicy) -> Vec<PathBuf> {
+    trace!(
+        "mount::teardown(targets={:?}, policy={:?})",
+        targets,
+        policy
+    );
+    let busy = |e: &JailError| matches!(e, JailError::MountError { source, .. } if source.raw_os_error() == Some(libc::EBUSY));
+
+    let mut still_mounted = vec![];
+    for target in targets.iter().rev() {
+        let deadline = Instant::now() + policy.grace;
+        let mut killed = false;
+
+        let mut result = unmount(target);
+        while result.as_ref().err().is_some_and(busy) && Instant::now() < deadline {
+            if policy.kill_holders && !killed {
+                kill_holders(target);
+                killed = true;
+            }
+            thread::sleep(Duration::from_millis(50));
+            result = unmount(target);
+        }
+
+        if policy.force && result.as_ref().err().is_some_and(busy) {
+            result = unmount_flags(target, libc::MNT_FORCE);
+        }
+
+        match result {
+            Ok(()) => {}
+            Err(JailError::MountError { ref source, .. })
+                if source.raw_os_error() == Some(libc::EINVAL)
+                    || source.raw_os_error() == Some(libc::ENOENT) => {}
+            Err(e) => {
+                warn!("leaving {:?} mounted: {}", target, e);
+                still_mounted.push(target.clone());
+            }
+        }
+    }
+
+    still_mounted
+}
+
+/// Kill the processes using the file system mounted at `target`.
+#[cfg(target_os = "freebsd")]
+fn kill_holders(target: &Path) {
+    trace!("mount::kill_holders(target={:?})", target);
+    let result = Command::new("fuser")
+        .arg("-c")
+        .arg("-k")
+        .arg(target)
+        .output();
+
+    match result {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => warn!(
+            "could not kill processes using {:?}: {}",
+            target,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("could not kill processes using {:?}: {}", target, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_entries_below_root() {
+        let root = Path::new("/jails/www");
+        let entry = |mountpoint: &str| {
+            MountEntry::below(root, b"nullfs", b"/usr/ports", mountpoint.as_bytes(), true)
+        };
+
+        let ports = entry("/jails/www/usr/ports").expect("mounted below root");
+        assert_eq!(ports.target, Path::new("/usr/ports"));
+        assert_eq!(
+            ports.to_mount(),
+            Mount::nullfs("/usr/ports", "/usr/ports").readonly(true)
+        );
+
+        assert_eq!(entry("/jails/www").unwrap().target, Path::new("/"));
+        assert_eq!(entry("/jails/www2/usr/ports"), None);
+        assert_eq!(entry("/usr/ports"), None);
+    }
+
+    #[test]
+    fn sensitive_mounts() {
+        let rw = |source: &str| Mount::nullfs(source, "/mnt").validate();
+        assert!(matches!(rw("/"), Err(JailError::SensitiveMount { .. })));
+        assert!(matches!(rw("/etc"), Err(JailError::SensitiveMount { .. })));
+        assert!(rw("/root/.ssh").is_err());
+        assert!(rw("/usr/local/etc/nginx").is_err());
+        assert!(rw("/etc/../boot").is_err());
+        assert!(rw("/usr/ports").is_ok());
+        assert!(rw("/etcetera").is_ok());
+
+        assert!(Mount::nullfs("/etc", "/mnt")
+            .readonly(true)
+            .validate()
+            .is_ok());
+        assert!(Mount::nullfs("/etc", "/mnt")
+            .allow_sensitive(true)
+            .validate()
+            .is_ok());
+        assert!(Mount::new("tmpfs", "/etc", "/mnt").validate().is_ok());
+    }
+
+    #[test]
+    fn allow_params_of_mounts() {
+        assert_eq!(
+            allow_params(vec!["nullfs", "tmpfs", "nullfs", "devfs"]),
+            vec![
+                Allow::Mount,
+                Allow::MountNullfs,
+                Allow::MountTmpfs,
+                Allow::MountDevfs
+            ]
+        );
+        assert_eq!(allow_params(vec!["ufs"]), vec![]);
+        assert_eq!(allow_params(vec![]), vec![]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Validated jail names
+use crate::{registry, JailError};
+use log::trace;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// The longest name accepted by the kernel, `MAXHOSTNAMELEN` without the
+/// terminating nul byte.
+pub(crate) const MAX_LEN: usize = 255;
+
+/// Check a name against the rules of `jail_set`(2), returning why it is
+/// rejected.
+fn validate(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("name is empty");
+    }
+
+    if name.len() > MAX_LEN {
+        return Err("name is longer than 255 bytes");
+    }
+
+    if name.contains('\0') {
+        return Err("name contains a nul byte");
+    }
+
+    // The kernel resolves dotted names in the hierarchy of jails, so "a.b"
+    // names the child "b" of jail "a".
+    if name.contains('.') {
+        return Err("name contains '.', which separates the names of nested jails");
+    }
+
+    // Numeric names are reserved for jids, and would be parsed as such when
+    // looking up the jail.
+    if name.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("name is numeric, which is reserved for jids");
+    }
+
+    Ok(())
+}
+
+/// A jail name accepted by the kernel.
+///
+/// Names must be non-empty, at most 255 bytes long, must not contain `.`
+/// (which separates the names of nested jails) and must not be numeric (which
+/// is reserved for jids). Invalid names fail with [JailError::InvalidName]
+/// when converted, instead of being rejected by the kernel on start.
+///
+/// [StoppedJail::name](struct.StoppedJail.html#method.name) accepts a
+/// `JailName` as well as a string, which is validated on start.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{JailError, JailName};
+/// use std::convert::TryFrom;
+///
+/// let name = JailName::try_from("www").unwrap();
+/// assert_eq!(name.as_str(), "www");
+///
+/// assert!(matches!(
+///     JailName::try_from("www.example.org"),
+///     Err(JailError::InvalidName { .. })
+/// ));
+/// assert!("42".parse::<JailName>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JailName(String);
+
+impl JailName {
+    /// Validate a name.
+    pub fn new<S: Into<String>>(name: S) -> Result<JailName, JailError> {
+        let name = name.into();
+        trace!("JailName::new(name={:?})", name);
+        match validate(&name) {
+            Ok(()) => Ok(JailName(name)),
+            Err(reason) => Err(JailError::InvalidName {
+                name,
+                reason: reason.to_string(),
+            }),
+        }
+    }
+
+    /// The name as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for JailName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for JailName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for JailName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for JailName {
+    type Err = JailError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        JailName::new(name)
+    }
+}
+
+impl TryFrom<&str> for JailName {
+    type Error = JailError;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        JailName::new(name)
+    }
+}
+
+impl TryFrom<String> for JailName {
+    type Error = JailError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        JailName::new(name)
+    }
+}
+
+impl From<JailName> for String {
+    fn from(name: JailName) -> String {
+        name.0
+    }
+}
+
+/// A prefix for the names of the jails managed by one tool, e.g. `myapp-`,
+/// see [set_namespace].
+///
+/// # Examples
+///
+/// ```
+/// use jail::Namespace;
+///
+/// let namespace = Namespace::new("myapp-").unwrap();
+/// assert_eq!(namespace.qualify("www"), "myapp-www");
+/// assert_eq!(namespace.qualify("myapp-www"), "myapp-www");
+/// assert_eq!(namespace.local("myapp-www"), Some("www"));
+/// assert_eq!(namespace.local("other-www"), None);
+///
+/// assert!(Namespace::new("my.app-").is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Namespace(String);
+
+impl Namespace {
+    /// Validate a prefix, which must not be empty or contain `.` or nul
+    /// bytes, so that prefixed names are valid names.
+    pub fn new<S: Into<String>>(prefix: S) -> Result<Namespace, JailError> {
+        let prefix = prefix.into();
+        trace!("Namespace::new(prefix={:?})", prefix);
+        let reason = match prefix.is_empty() {
+            true => Err("prefix is empty"),
+            false => validate(&format!("{}x", prefix)),
+        };
+
+        match reason {
+            Ok(()) => Ok(Namespace(prefix)),
+            Err(reason) => Err(JailError::InvalidName {
+                name: prefix,
+                reason: reason.to_string(),
+            }),
+        }
+    }
+
+    /// The prefix of the names.
+    pub fn prefix(&self) -> &str {
+        &self.0
+    }
+
+    /// Get the name of a jail in the namespace, leaving names that already
+    /// carry the prefix alone.
+    pub fn qualify(&self, name: &str) -> String {
+        trace!("Namespace::qualify({:?}, name={:?})", self, name);
+        match self.local(name) {
+            Some(_) => name.to_string(),
+            None => format!("{}{}", self.0, name),
+        }
+    }
+
+    /// Get the name of a jail in the namespace without the prefix, or `None`
+    /// if the jail is not in the namespace.
+    pub fn local<'a>(&self, name: &'a str) -> Option<&'a str> {
+        trace!("Namespace::local({:?}, name={:?})", self, name);
+        name.strip_prefix(self.0.as_str())
+            .filter(|local| !local.is_empty())
+    }
+}
+
+/// Set the namespace of the jails managed by this process, or `None` to
+/// use names as given.
+///
+/// With a namespace, [StoppedJail::start](struct.StoppedJail.html#method.start)
+/// prefixes the names of jails, and
+/// [RunningJail::from_name_strict](struct.RunningJail.html#method.from_name_strict)
+/// looks names up in the namespace. Starting a jail fails with
+/// [JailError::NameInUse] if its name is taken, so tools using different
+/// namespaces can share a host without touching each other's jails. Names
+/// read from the kernel, e.g. by [RunningJail::name](struct.RunningJail.html#method.name),
+/// carry the prefix.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{Namespace, StoppedJail};
+///
+/// jail::set_namespace(Some(Namespace::new("testns-").unwrap()));
+/// let running = StoppedJail::new("/rescue")
+///     .name("www")
+///     .start()
+///     .expect("could not start jail");
+/// assert_eq!(running.name().unwrap(), "testns-www");
+///
+/// let found = jail::RunningJail::all()
+///     .find_in_namespace()
+///     .expect("could not search jails");
+/// assert!(found.contains(&running));
+/// # running.kill().expect("could not stop jail");
+/// # jail::set_namespace(None);
+/// ```
+pub fn set_namespace(namespace: Option<Namespace>) {
+    trace!("set_namespace(namespace={:?})", namespace);
+    registry::set_namespace(namespace);
+}
+
+/// Get the namespace of the jails managed by this process, see
+/// [set_namespace].
+pub fn namespace() -> Option<Namespace> {
+    trace!("namespace()");
+    registry::namespace()
+}
+
+/// Get a name in the namespace of this process, if any.
+pub(crate) fn qualify(name: &str) -> String {
+    match registry::namespace() {
+        Some(namespace) => namespace.qualify(name),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_rules() {
+        assert!(validate("www").is_ok());
+        assert!(validate("1www").is_ok());
+        assert!(validate("www-01_a").is_ok());
+        assert!(validate(&"a".repeat(MAX_LEN)).is_ok());
+
+        assert!(validate("").is_err());
+        assert!(validate("42").is_err());
+        assert!(validate("www.example.org").is_err());
+        assert!(validate("a\0b").is_err());
+        assert!(validate(&"a".repeat(MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn namespaces() {
+        let namespace = Namespace::new("app-").unwrap();
+        assert_eq!(namespace.qualify("www"), "app-www");
+        assert_eq!(namespace.qualify("app-www"), "app-www");
+        assert_eq!(namespace.qualify("app-"), "app-app-");
+        assert_eq!(namespace.local("app-"), None);
+        assert_eq!(namespace.local("www"), None);
+
+        assert!(Namespace::new("").is_err());
+        assert!(Namespace::new("a.b-").is_err());
+        assert!(Namespace::new("4").is_ok());
+        assert!(Namespace::new("a".repeat(MAX_LEN)).is_err());
+    }
+}
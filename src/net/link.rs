@@ -0,0 +1,798 @@
+//! Links connecting VNET jails to the network of the host
+#[cfg(target_os = "freebsd")]
+use crate::param;
+use crate::{JailError, RunningJail};
+use log::trace;
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(target_os = "freebsd")]
+use std::process::Command;
+
+/// A way of connecting a VNET jail to the network of the host.
+///
+/// Implemented by [EpairOptions], [Vlan] and [Netgraph], so that the
+/// topology of a jail can be chosen at runtime, e.g. as a
+/// `Box<dyn JailLink>`.
+///
+/// # Examples
+///
+/// ```
+/// use jail::net::{EpairOptions, JailLink, Vlan};
+/// # use jail::{param, StoppedJail};
+/// # let running = StoppedJail::new("/rescue")
+/// #     .name("testjail_link")
+/// #     .param("vnet", param::Value::Int(1))
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let trunk = std::env::var("TRUNK").ok();
+/// let link: Box<dyn JailLink> = match trunk {
+///     Some(trunk) => Box::new(Vlan::new(trunk, 100).jail_name("eth0")),
+///     None => Box::new(EpairOptions::default().jail_name("eth0")),
+/// };
+///
+/// let attached = link.attach(&running).expect("could not attach jail");
+/// assert_eq!(attached.jail, "eth0");
+/// link.detach(attached).expect("could not detach jail");
+/// # running.kill().expect("could not stop jail");
+/// ```
+pub trait JailLink: fmt::Debug {
+    /// Create an interface, move it into the VNET jail `jail` and bring it
+    /// up.
+    fn attach(&self, jail: &RunningJail) -> Result<Link, JailError>;
+
+    /// Destroy the interface of a link created by [JailLink::attach].
+    fn detach(&self, link: Link) -> Result<(), JailError>;
+}
+
+/// An interface of a jail created by [JailLink::attach].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// The `jid` of the jail
+    pub jid: i32,
+
+    /// The interface or `netgraph`(4) node on the host side of the link,
+    /// if any
+    pub host: Option<String>,
+
+    /// The name of the interface inside the jail
+    pub jail: String,
+}
+
+/// Run `program` with the given arguments, returning its standard output.
+/// Failures are reported with the standard error passed to `error`.
+#[cfg(target_os = "freebsd")]
+fn run<S: AsRef<str>>(
+    program: &str,
+    args: &[S],
+    error: fn(String) -> JailError,
+) -> Result<String, JailError> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    trace!("run(program={:?}, args={:?})", program, args);
+    let output = Command::new(program)
+        .args(&args)
+        .output()
+        .map_err(JailError::IoError)?;
+
+    if !output.status.success() {
+        return Err(error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(target_os = "freebsd")]
+pub(super) fn ifconfig<S: AsRef<str>>(args: &[S]) -> Result<String, JailError> {
+    run("ifconfig", args, JailError::IfconfigError)
+}
+
+#[cfg(target_os = "freebsd")]
+fn ngctl<S: AsRef<str>>(args: &[S]) -> Result<String, JailError> {
+    run("ngctl", args, JailError::NgctlError)
+}
+
+/// Fail unless the jail has its own network stack.
+#[cfg(target_os = "freebsd")]
+fn require_vnet(jail: &RunningJail) -> Result<(), JailError> {
+    match jail.param("vnet")? {
+        param::Value::Int(1) => Ok(()),
+        _ => Err(JailError::NotSupported(format!(
+            "jail {} does not have its own network stack",
+            jail.jid
+        ))),
+    }
+}
+
+/// Move an interface of the host into a jail, rename it to `name` if given,
+/// apply the IPv6 settings and bring it up, returning its name inside the
+/// jail.
+#[cfg(target_os = "freebsd")]
+fn move_into(
+    jid: i32,
+    interface: &str,
+    name: Option<&str>,
+    ipv6: &Ipv6Options,
+) -> Result<String, JailError> {
+    ifconfig(&[interface, "vnet", &jid.to_string()])?;
+
+    let interface = match name {
+        Some(name) => {
+            ifconfig(&["-j", &jid.to_string(), interface, "name", name])?;
+            name
+        }
+        None => interface,
+    };
+
+    // Before the interface is up, so that no router advertisement is
+    // accepted in between.
+    ipv6.apply_jid(jid, interface)?;
+    ifconfig(&["-j", &jid.to_string(), interface, "up"])?;
+    Ok(interface.to_string())
+}
+
+/// IPv6 settings of an interface inside a VNET jail, as set by the `inet6`
+/// flags of `ifconfig`(8).
+///
+/// Fresh VNET jails accept router advertisements and configure link-local
+/// addresses on all interfaces, depending on the `net.inet6.ip6` sysctls
+/// of the jail. Settings left at `None` keep these defaults.
+///
+/// The links of [JailLink] apply their settings before the interface is
+/// brought up. [Ipv6Options::apply] changes the interfaces of running jails.
+///
+/// # Examples
+///
+/// ```
+/// use jail::net::{EpairOptions, Ipv6Options};
+///
+/// // Static addresses only, no SLAAC.
+/// let options = EpairOptions::default()
+///     .jail_name("eth0")
+///     .ipv6(Ipv6Options::default().accept_rtadv(false));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ipv6Options {
+    /// Whether router advertisements are accepted, i.e. SLAAC and default
+    /// routes
+    pub accept_rtadv: Option<bool>,
+
+    /// Whether a link-local address is configured automatically
+    pub auto_linklocal: Option<bool>,
+
+    /// Whether IPv6 is enabled on the interface
+    pub enabled: Option<bool>,
+}
+
+impl Ipv6Options {
+    /// Set whether router advertisements are accepted.
+    pub fn accept_rtadv(mut self, accept: bool) -> Self {
+        trace!("Ipv6Options::accept_rtadv({:?}, accept={})", self, accept);
+        self.accept_rtadv = Some(accept);
+        self
+    }
+
+    /// Set whether a link-local address is configured automatically.
+    pub fn auto_linklocal(mut self, auto: bool) -> Self {
+        trace!("Ipv6Options::auto_linklocal({:?}, auto={})", self, auto);
+        self.auto_linklocal = Some(auto);
+        self
+    }
+
+    /// Set whether IPv6 is enabled on the interface.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        trace!("Ipv6Options::enabled({:?}, enabled={})", self, enabled);
+        self.enabled = Some(enabled);
+        self
+    }
+
+    /// The `ifconfig`(8) flags for the settings.
+    fn flags(&self) -> Vec<&'static str> {
+        let flag = |value: Option<bool>, on: &'static str, off: &'static str| {
+            value.map(|value| if value { on } else { off })
+        };
+
+        vec![
+            flag(self.accept_rtadv, "accept_rtadv", "-accept_rtadv"),
+            flag(self.auto_linklocal, "auto_linklocal", "-auto_linklocal"),
+            // The flag disables IPv6, so it is inverted.
+            flag(self.enabled, "-ifdisabled", "ifdisabled"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn apply_jid(&self, jid: i32, interface: &str) -> Result<(), JailError> {
+        let flags = self.flags();
+        if flags.is_empty() {
+            return Ok(());
+        }
+
+        let jid = jid.to_string();
+        let mut args = vec!["-j", &jid, interface, "inet6"];
+        args.extend(flags);
+        ifconfig(&args).map(|_| ())
+    }
+
+    /// Apply the settings to an interface of a running VNET jail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::net::Ipv6Options;
+    /// # use jail::{param, StoppedJail};
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_ipv6_options")
+    /// #     .param("vnet", param::Value::Int(1))
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// Ipv6Options::default()
+    ///     .enabled(false)
+    ///     .apply(&running, "lo0")
+    ///     .expect("could not disable IPv6");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    #[cfg(target_os = "freebsd")]
+    pub fn apply(&self, jail: &RunningJail, interface: &str) -> Result<(), JailError> {
+        trace!(
+            "Ipv6Options::apply({:?}, jail={:?}, interface={:?})",
+            self,
+            jail,
+            interface
+        );
+        require_vnet(jail)?;
+        self.apply_jid(jail.jid, interface)
+    }
+}
+
+/// An Ethernet MAC address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// Derive a stable, locally administered unicast address from the name
+    /// of a jail. `side` tells apart the interfaces of an `epair`(4): the
+    /// addresses derived for side 0 and side 1 differ only in the lowest
+    /// bit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::net::MacAddr;
+    ///
+    /// let host = MacAddr::from_name("www", 0);
+    /// assert_eq!(host, MacAddr::from_name("www", 0));
+    /// assert_ne!(host, MacAddr::from_name("www", 1));
+    /// assert_ne!(host, MacAddr::from_name("db", 0));
+    ///
+    /// // Locally administered and unicast.
+    /// assert_eq!(host.0[0] & 0x03, 0x02);
+    /// ```
+    pub fn from_name(name: &str, side: u8) -> MacAddr {
+        trace!("MacAddr::from_name(name={:?}, side={})", name, side);
+        let digest = Sha256::digest(name.as_bytes());
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&digest[..6]);
+        mac[0] = (mac[0] & 0xfc) | 0x02;
+        mac[5] = (mac[5] & 0xfe) | (side & 0x01);
+        MacAddr(mac)
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let octets: Vec<String> = self.0.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "{}", octets.join(":"))
+    }
+}
+
+/// Parse a MAC address of six colon-separated hexadecimal octets.
+impl FromStr for MacAddr {
+    type Err = JailError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let octets: Vec<u8> = s
+            .split(':')
+            .map(|octet| match octet.len() {
+                1 | 2 => u8::from_str_radix(octet, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| JailError::NotSupported(format!("invalid MAC address {:?}", s)))?;
+
+        let octets: [u8; 6] = octets
+            .try_into()
+            .map_err(|_| JailError::NotSupported(format!("invalid MAC address {:?}", s)))?;
+        Ok(MacAddr(octets))
+    }
+}
+
+/// How the MAC addresses of an `epair`(4) are chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MacPolicy {
+    /// Keep the addresses assigned by the kernel.
+    #[default]
+    Kernel,
+
+    /// Derive the addresses from the name of the jail, see
+    /// [MacAddr::from_name]. The host side uses side 0, the jail side 1.
+    FromName,
+
+    /// Use the given addresses.
+    Fixed { host: MacAddr, jail: MacAddr },
+}
+
+/// Names and MAC addresses of an `epair`(4) created by [Epair::create].
+///
+/// By default, the interfaces keep the names and addresses chosen by the
+/// kernel, e.g. `epair0a` and `epair0b`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EpairOptions {
+    /// The name of the interface on the host
+    pub host_name: Option<String>,
+
+    /// The name of the interface inside the jail
+    pub jail_name: Option<String>,
+
+    /// How the MAC addresses are chosen
+    pub mac: MacPolicy,
+
+    /// The `if_bridge`(4) interface the host side is added to
+    pub bridge: Option<String>,
+
+    /// The IPv6 settings of the interface inside the jail
+    pub ipv6: Ipv6Options,
+}
+
+impl EpairOptions {
+    /// Rename the interface on the host, e.g. to `www0`.
+    pub fn host_name<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
+        trace!("EpairOptions::host_name({:?}, name={:?})", self, name);
+        self.host_name = Some(name.into());
+        self
+    }
+
+    /// Rename the interface inside the jail, e.g. to `eth0` or `jail0`.
+    pub fn jail_name<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
+        trace!("EpairOptions::jail_name({:?}, name={:?})", self, name);
+        self.jail_name = Some(name.into());
+        self
+    }
+
+    /// Set how the MAC addresses are chosen.
+    pub fn mac(mut self, mac: MacPolicy) -> Self {
+        trace!("EpairOptions::mac({:?}, mac={:?})", self, mac);
+        self.mac = mac;
+        self
+    }
+
+    /// Add the interface on the host to a bridge, e.g. `bridge0`.
+    pub fn bridge<S: Into<String> + fmt::Debug>(mut self, bridge: S) -> Self {
+        trace!("EpairOptions::bridge({:?}, bridge={:?})", self, bridge);
+        self.bridge = Some(bridge.into());
+        self
+    }
+
+    /// Set the IPv6 settings of the interface inside the jail.
+    pub fn ipv6(mut self, ipv6: Ipv6Options) -> Self {
+        trace!("EpairOptions::ipv6({:?}, ipv6={:?})", self, ipv6);
+        self.ipv6 = ipv6;
+        self
+    }
+}
+
+/// An `epair`(4) connecting a VNET jail to the host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Epair {
+    /// The `jid` of the jail
+    pub jid: i32,
+
+    /// The name of the interface on the host
+    pub host: String,
+
+    /// The name of the interface inside the jail
+    pub jail: String,
+}
+
+/// The name of the other side of an `epair`(4) interface ending in `a`.
+fn peer(host: &str) -> Result<String, JailError> {
+    match host.strip_suffix('a') {
+        Some(unit) if unit.starts_with("epair") => Ok(format!("{}b", unit)),
+        _ => Err(JailError::IfconfigError(format!(
+            "unexpected epair interface {:?}",
+            host
+        ))),
+    }
+}
+
+impl Epair {
+    /// Create an `epair`(4), move one side into the VNET jail `jail`, and
+    /// name and address both sides as given by `options`. Both interfaces
+    /// are brought up.
+    ///
+    /// If any step fails, the interfaces are destroyed again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::net::{Epair, EpairOptions, MacPolicy};
+    /// # use jail::{param, StoppedJail};
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_epair")
+    /// #     .param("vnet", param::Value::Int(1))
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let options = EpairOptions::default()
+    ///     .jail_name("eth0")
+    ///     .mac(MacPolicy::FromName);
+    /// let epair = Epair::create(&running, &options).expect("could not create epair");
+    /// assert_eq!(epair.jail, "eth0");
+    ///
+    /// epair.destroy().expect("could not destroy epair");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    #[cfg(target_os = "freebsd")]
+    pub fn create(jail: &RunningJail, options: &EpairOptions) -> Result<Epair, JailError> {
+        trace!("Epair::create(jail={:?}, options={:?})", jail, options);
+        require_vnet(jail)?;
+
+        let macs = match options.mac {
+            MacPolicy::Kernel => None,
+            MacPolicy::FromName => {
+                let name = jail.name()?;
+                Some((MacAddr::from_name(&name, 0), MacAddr::from_name(&name, 1)))
+            }
+            MacPolicy::Fixed { host, jail } => Some((host, jail)),
+        };
+
+        let host = ifconfig(&["epair", "create"])?;
+        let mut epair = Epair {
+            jid: jail.jid,
+            jail: peer(&host)?,
+            host,
+        };
+
+        let setup = |epair: &mut Epair| -> Result<(), JailError> {
+            if let Some((host_mac, jail_mac)) = macs {
+                ifconfig(&[&epair.host, "ether", &host_mac.to_string()])?;
+                ifconfig(&[&epair.jail, "ether", &jail_mac.to_string()])?;
+            }
+
+            if let Some(ref name) = options.host_name {
+                ifconfig(&[&epair.host, "name", name])?;
+                epair.host = name.clone();
+            }
+
+            if let Some(ref bridge) = options.bridge {
+                ifconfig(&[bridge, "addm", &epair.host])?;
+            }
+
+            ifconfig(&[&epair.host, "up"])?;
+            epair.jail = move_into(
+                epair.jid,
+                &epair.jail,
+                options.jail_name.as_deref(),
+                &options.ipv6,
+            )?;
+            Ok(())
+        };
+
+        if let Err(e) = setup(&mut epair) {
+            epair.clone().destroy().ok();
+            return Err(e);
+        }
+
+        Ok(epair)
+    }
+
+    /// Destroy both interfaces.
+    #[cfg(target_os = "freebsd")]
+    pub fn destroy(self) -> Result<(), JailError> {
+        trace!("Epair::destroy({:?})", self);
+        // Destroying one side destroys the pair, wherever the other side is.
+        ifconfig(&[&self.host, "destroy"]).map(|_| ())
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl JailLink for EpairOptions {
+    fn attach(&self, jail: &RunningJail) -> Result<Link, JailError> {
+        trace!("EpairOptions::attach({:?}, jail={:?})", self, jail);
+        Epair::create(jail, self).map(|epair| Link {
+            jid: epair.jid,
+            host: Some(epair.host),
+            jail: epair.jail,
+        })
+    }
+
+    fn detach(&self, link: Link) -> Result<(), JailError> {
+        trace!("EpairOptions::detach({:?}, link={:?})", self, link);
+        match link.host {
+            Some(host) => Epair {
+                jid: link.jid,
+                host,
+                jail: link.jail,
+            }
+            .destroy(),
+            None => Err(JailError::NotSupported(format!(
+                "link {:?} has no interface on the host",
+                link.jail
+            ))),
+        }
+    }
+}
+
+/// A `vlan`(4) interface of a trunk, moved into a VNET jail.
+///
+/// The interface is named `<trunk>.<tag>` on the host, e.g. `ix0.100`, and
+/// can be renamed inside the jail. The trunk interface must be up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vlan {
+    /// The parent interface carrying the tagged traffic
+    pub trunk: String,
+
+    /// The VLAN ID, from 1 to 4094
+    pub tag: u16,
+
+    /// The name of the interface inside the jail
+    pub jail_name: Option<String>,
+
+    /// The IPv6 settings of the interface inside the jail
+    pub ipv6: Ipv6Options,
+}
+
+impl Vlan {
+    /// Tag the traffic of the jail with `tag` on `trunk`.
+    pub fn new<S: Into<String> + fmt::Debug>(trunk: S, tag: u16) -> Vlan {
+        trace!("Vlan::new(trunk={:?}, tag={})", trunk, tag);
+        Vlan {
+            trunk: trunk.into(),
+            tag,
+            jail_name: None,
+            ipv6: Ipv6Options::default(),
+        }
+    }
+
+    /// Rename the interface inside the jail, e.g. to `eth0`.
+    pub fn jail_name<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
+        trace!("Vlan::jail_name({:?}, name={:?})", self, name);
+        self.jail_name = Some(name.into());
+        self
+    }
+
+    /// Set the IPv6 settings of the interface inside the jail.
+    pub fn ipv6(mut self, ipv6: Ipv6Options) -> Self {
+        trace!("Vlan::ipv6({:?}, ipv6={:?})", self, ipv6);
+        self.ipv6 = ipv6;
+        self
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl JailLink for Vlan {
+    fn attach(&self, jail: &RunningJail) -> Result<Link, JailError> {
+        trace!("Vlan::attach({:?}, jail={:?})", self, jail);
+        require_vnet(jail)?;
+        if !(1..=4094).contains(&self.tag) {
+            return Err(JailError::NotSupported(format!(
+                "VLAN ID {} is not between 1 and 4094",
+                self.tag
+            )));
+        }
+
+        let interface = format!("{}.{}", self.trunk, self.tag);
+        ifconfig(&[&interface, "create"])?;
+
+        match move_into(jail.jid, &interface, self.jail_name.as_deref(), &self.ipv6) {
+            Ok(name) => Ok(Link {
+                jid: jail.jid,
+                host: None,
+                jail: name,
+            }),
+            Err(e) => {
+                // The interface may have been moved before failing.
+                ifconfig(&[&interface, "destroy"])
+                    .or_else(|_| ifconfig(&["-j", &jail.jid.to_string(), &interface, "destroy"]))
+                    .ok();
+                Err(e)
+            }
+        }
+    }
+
+    fn detach(&self, link: Link) -> Result<(), JailError> {
+        trace!("Vlan::detach({:?}, link={:?})", self, link);
+        ifconfig(&["-j", &link.jid.to_string(), &link.jail, "destroy"]).map(|_| ())
+    }
+}
+
+/// An `ng_eiface`(4) connected to an existing `ng_bridge`(4) node, moved
+/// into a VNET jail.
+///
+/// The bridge node is typically connected to the `lower` and `upper` hooks
+/// of a physical interface. The eiface is connected to the first free
+/// `link` hook of the bridge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Netgraph {
+    /// The name of the `ng_bridge`(4) node
+    pub bridge: String,
+
+    /// The name of the interface inside the jail
+    pub jail_name: Option<String>,
+
+    /// The IPv6 settings of the interface inside the jail
+    pub ipv6: Ipv6Options,
+}
+
+impl Netgraph {
+    /// Connect jails to the `ng_bridge`(4) node named `bridge`.
+    pub fn new<S: Into<String> + fmt::Debug>(bridge: S) -> Netgraph {
+        trace!("Netgraph::new(bridge={:?})", bridge);
+        Netgraph {
+            bridge: bridge.into(),
+            jail_name: None,
+            ipv6: Ipv6Options::default(),
+        }
+    }
+
+    /// Rename the interface inside the jail, e.g. to `eth0`.
+    pub fn jail_name<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
+        trace!("Netgraph::jail_name({:?}, name={:?})", self, name);
+        self.jail_name = Some(name.into());
+        self
+    }
+
+    /// Set the IPv6 settings of the interface inside the jail.
+    pub fn ipv6(mut self, ipv6: Ipv6Options) -> Self {
+        trace!("Netgraph::ipv6({:?}, ipv6={:?})", self, ipv6);
+        self.ipv6 = ipv6;
+        self
+    }
+}
+
+/// The number of `link` hooks of a bridge tried before giving up.
+#[cfg(target_os = "freebsd")]
+const MAX_BRIDGE_LINKS: usize = 1024;
+
+/// Extract the interface name from the response of `ngctl msg <node>
+/// getifname`.
+fn parse_ifname(response: &str) -> Option<String> {
+    let args = response
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Args:"))?;
+    let name = args.trim().strip_prefix('"')?.strip_suffix('"')?;
+    match name.is_empty() {
+        true => None,
+        false => Some(name.to_string()),
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl JailLink for Netgraph {
+    fn attach(&self, jail: &RunningJail) -> Result<Link, JailError> {
+        trace!("Netgraph::attach({:?}, jail={:?})", self, jail);
+        require_vnet(jail)?;
+
+        let bridge = format!("{}:", self.bridge);
+        let mut hook = None;
+        for n in 0..MAX_BRIDGE_LINKS {
+            let link = format!("link{}", n);
+            match ngctl(&["mkpeer", &bridge, "eiface", &link, "ether"]) {
+                Ok(_) => {
+                    hook = Some(format!("{}{}", bridge, link));
+                    break;
+                }
+                // The hook is already connected.
+                Err(JailError::NgctlError(ref e)) if e.contains("exists") => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let node = hook.ok_or_else(|| {
+            JailError::NgctlError(format!("bridge {} has no free link hook", self.bridge))
+        })?;
+
+        let setup = || -> Result<String, JailError> {
+            let response = ngctl(&["msg", &node, "getifname"])?;
+            let interface = parse_ifname(&response).ok_or_else(|| {
+                JailError::NgctlError(format!("unexpected response {:?}", response))
+            })?;
+            move_into(jail.jid, &interface, self.jail_name.as_deref(), &self.ipv6)
+        };
+
+        match setup() {
+            Ok(name) => Ok(Link {
+                jid: jail.jid,
+                host: Some(node),
+                jail: name,
+            }),
+            Err(e) => {
+                ngctl(&["shutdown", &node]).ok();
+                Err(e)
+            }
+        }
+    }
+
+    fn detach(&self, link: Link) -> Result<(), JailError> {
+        trace!("Netgraph::detach({:?}, link={:?})", self, link);
+        match link.host {
+            // The node stays in the netgraph of the host, even though its
+            // interface was moved into the jail.
+            Some(node) => ngctl(&["shutdown", &node]).map(|_| ()),
+            None => Err(JailError::NotSupported(format!(
+                "link {:?} has no netgraph node",
+                link.jail
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mac_addresses() {
+        let mac: MacAddr = "02:00:5e:10:0:ff".parse().unwrap();
+        assert_eq!(mac, MacAddr([0x02, 0x00, 0x5e, 0x10, 0x00, 0xff]));
+        assert_eq!(mac.to_string(), "02:00:5e:10:00:ff");
+
+        for invalid in &[
+            "",
+            "02:00:5e:10:00",
+            "02:00:5e:10:00:ff:00",
+            "02:00:5e:10:00:fff",
+            "g2:00:5e:10:00:ff",
+        ] {
+            assert!(
+                invalid.parse::<MacAddr>().is_err(),
+                "{:?} was accepted",
+                invalid
+            );
+        }
+
+        let (a, b) = (MacAddr::from_name("www", 0), MacAddr::from_name("www", 1));
+        assert_eq!(a.0[..5], b.0[..5]);
+        assert_eq!((a.0[5] & 1, b.0[5] & 1), (0, 1));
+    }
+
+    #[test]
+    fn epair_peer() {
+        assert_eq!(peer("epair0a").unwrap(), "epair0b");
+        assert_eq!(peer("epair12a").unwrap(), "epair12b");
+        assert!(peer("epair0b").is_err());
+        assert!(peer("lo0").is_err());
+    }
+
+    #[test]
+    fn ipv6_flags() {
+        assert!(Ipv6Options::default().flags().is_empty());
+        assert_eq!(
+            Ipv6Options::default()
+                .accept_rtadv(false)
+                .auto_linklocal(true)
+                .enabled(false)
+                .flags(),
+            vec!["-accept_rtadv", "auto_linklocal", "ifdisabled"]
+        );
+        assert_eq!(
+            Ipv6Options::default().enabled(true).flags(),
+            vec!["-ifdisabled"]
+        );
+    }
+
+    #[test]
+    fn netgraph_ifname() {
+        let response = "Rec'd response \"getifname\" (1) from \"[2a]:\":\nArgs:\t\"ngeth0\"\n";
+        assert_eq!(parse_ifname(response), Some("ngeth0".to_string()));
+        assert_eq!(parse_ifname("Args:\t\"\""), None);
+        assert_eq!(parse_ifname("No response"), None);
+    }
+}
@@ -0,0 +1,99 @@
+//! Loopback aliases of jails sharing the network stack of the host
+//!
+//! A jail without its own network stack can only bind to its own addresses,
+//! and those must be configured on a host interface. Jails commonly get
+//! private loopback-style addresses like `127.0.1.1`, which have to be added
+//! as aliases of `lo0` first, or services in the jail fail to bind.
+//!
+//! With [StoppedJail::loopback_aliases](../../struct.StoppedJail.html#method.loopback_aliases),
+//! the aliases are added when the jail is started and removed when it is
+//! killed. They are reference counted across the jails started by this
+//! process, so an alias shared by several jails is only removed with the
+//! last of them. Addresses already configured on the host are left alone.
+#[cfg(target_os = "freebsd")]
+use super::link::ifconfig;
+#[cfg(target_os = "freebsd")]
+use crate::{registry, sys, JailError};
+#[cfg(target_os = "freebsd")]
+use log::trace;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// The loopback interface of the host.
+#[cfg(target_os = "freebsd")]
+const LOOPBACK_INTERFACE: &str = "lo0";
+
+/// Check whether `ip` is a loopback address to add as an alias of `lo0`:
+/// an IPv4 address in `127.0.0.0/8` other than `127.0.0.1`, which `lo0`
+/// always has.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+pub(crate) fn is_loopback_alias(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() && *ip != Ipv4Addr::LOCALHOST,
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// Add the loopback addresses among `ips` as aliases of `lo0`, returning
+/// the ones now held for the jail, to be passed to [remove_aliases] once it
+/// is removed.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn add_aliases(ips: &[IpAddr]) -> Result<Vec<IpAddr>, JailError> {
+    trace!("loopback::add_aliases(ips={:?})", ips);
+    let aliases: Vec<IpAddr> = ips.iter().copied().filter(is_loopback_alias).collect();
+    if aliases.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let host = sys::interface_addresses()?;
+    let mut held = vec![];
+    for ip in aliases {
+        let add = || match host.contains(&ip) {
+            true => Ok(false),
+            false => ifconfig(&[LOOPBACK_INTERFACE, "inet", &format!("{}/32", ip), "alias"])
+                .map(|_| true),
+        };
+
+        match registry::acquire_alias(ip, add) {
+            Ok(true) => held.push(ip),
+            Ok(false) => {}
+            Err(e) => {
+                remove_aliases(&held).ok();
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(held)
+}
+
+/// Release the aliases held for a jail, removing those no other jail holds
+/// from `lo0`. All aliases are released, even if removing one fails.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn remove_aliases(ips: &[IpAddr]) -> Result<(), JailError> {
+    trace!("loopback::remove_aliases(ips={:?})", ips);
+    let mut result = Ok(());
+    for ip in ips {
+        let remove =
+            || ifconfig(&[LOOPBACK_INTERFACE, "inet", &ip.to_string(), "-alias"]).map(|_| ());
+        if let Err(e) = registry::release_alias(*ip, remove) {
+            result = result.and(Err(e));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_aliases() {
+        let alias = |ip: &str| is_loopback_alias(&ip.parse().unwrap());
+        assert!(alias("127.0.1.1"));
+        assert!(alias("127.255.255.254"));
+        assert!(!alias("127.0.0.1"));
+        assert!(!alias("192.0.2.1"));
+        assert!(!alias("::1"));
+    }
+}
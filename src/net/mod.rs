@@ -0,0 +1,724 @@
+//! Networking of jails
+//!
+//! [IpPool] hands out free addresses of a network to new jails.
+//!
+//! A [JailLink] connects a VNET jail to the network of the host: an
+//! `epair`(4), optionally added to a bridge ([EpairOptions]), a `vlan`(4)
+//! interface of a trunk ([Vlan]), or an `ng_eiface`(4) connected to an
+//! `ng_bridge`(4) node ([Netgraph]).
+//!
+//! [sockets] lists the TCP and UDP sockets of the host and its jails from the
+//! `net.inet.tcp.pcblist` and `net.inet.udp.pcblist` sysctls, without parsing
+//! the output of `netstat`(1) or `sockstat`(1).
+//!
+//! The kernel does not export the jail of a socket. Instead, the lists are
+//! also read from inside each jail by a forked child process attached to it,
+//! where only the sockets of the jail and its children are visible. Each
+//! socket is attributed to the innermost jail that sees it.
+use crate::registry;
+#[cfg(target_os = "freebsd")]
+use crate::{sys, StoppedJail};
+use crate::{JailError, RunningJail};
+use log::trace;
+#[cfg(target_os = "freebsd")]
+use log::warn;
+use std::convert::TryInto;
+use std::ffi::CStr;
+use std::fmt;
+use std::io;
+use std::net;
+use std::str::FromStr;
+
+#[cfg(target_os = "freebsd")]
+use std::collections::HashMap;
+
+mod link;
+pub(crate) mod loopback;
+pub use link::{
+    Epair, EpairOptions, Ipv6Options, JailLink, Link, MacAddr, MacPolicy, Netgraph, Vlan,
+};
+
+/// Offsets into the `xinpgen`, `xinpcb` and `xsocket` structures exported
+/// since FreeBSD 12.0, which start with their length and keep their layout
+/// stable using spare fields.
+mod layout {
+    /// `xinpgen.xig_len`, which also starts every record.
+    pub const LEN: usize = 0;
+
+    /// The `xinpcb` inside a `xtcpcb`, after `xt_len`.
+    pub const XTCPCB_INP: usize = 8;
+
+    /// The `xsocket` inside a `xinpcb`, after `xi_len`.
+    pub const XINPCB_SOCKET: usize = 8;
+
+    pub const XSO_LEN: usize = 0;
+    pub const XSO_SO: usize = 8;
+    pub const XSO_FAMILY: usize = 100;
+    pub const SO_UID: usize = 120;
+    pub const SO_OPTIONS: usize = 158;
+    pub const SO_STATE: usize = 162;
+
+    /// Fields of the `in_conninfo` following the `xsocket`.
+    pub const IE_FPORT: usize = 4;
+    pub const IE_LPORT: usize = 6;
+    pub const IE_FADDR: usize = 8;
+    pub const IE_LADDR: usize = 24;
+    pub const IN_CONNINFO_LEN: usize = 44;
+
+    /// `so_options` flag of listening sockets.
+    pub const SO_ACCEPTCONN: u16 = 0x0002;
+
+    /// `so_state` flag of connected sockets.
+    pub const SS_ISCONNECTED: u16 = 0x0002;
+}
+
+/// A transport protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    /// The sysctl listing the sockets of the protocol.
+    #[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+    fn pcblist(self) -> &'static CStr {
+        let name: &'static [u8] = match self {
+            Protocol::Tcp => b"net.inet.tcp.pcblist\0",
+            Protocol::Udp => b"net.inet.udp.pcblist\0",
+        };
+        CStr::from_bytes_with_nul(name).expect("sysctl name is nul-terminated")
+    }
+}
+
+/// A socket of the host or of a jail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Socket {
+    /// The jail the socket belongs to, or `None` for the host.
+    pub jail: Option<RunningJail>,
+
+    /// The transport protocol
+    pub protocol: Protocol,
+
+    /// The local address, which is unspecified for wildcard sockets
+    pub local: net::SocketAddr,
+
+    /// The remote address of connected sockets
+    pub remote: Option<net::SocketAddr>,
+
+    /// Whether the socket accepts connections
+    pub listening: bool,
+
+    /// The user that created the socket
+    pub uid: u32,
+}
+
+fn invalid(message: &str) -> JailError {
+    JailError::IoError(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected socket list: {}", message),
+    ))
+}
+
+fn field<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], JailError> {
+    bytes
+        .get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("truncated record"))
+}
+
+fn address(family: i32, addr: [u8; 16], port: [u8; 2]) -> net::SocketAddr {
+    let port = u16::from_be_bytes(port);
+    match family {
+        // IPv4 addresses are stored in the last four bytes.
+        libc::AF_INET => {
+            let v4: [u8; 4] = addr[12..].try_into().expect("slice has four bytes");
+            net::SocketAddr::from((net::Ipv4Addr::from(v4), port))
+        }
+        _ => net::SocketAddr::from((net::Ipv6Addr::from(addr), port)),
+    }
+}
+
+/// Parse a protocol control block list, returning the kernel address of each
+/// socket, which identifies it across lists, and the socket.
+fn parse(protocol: Protocol, bytes: &[u8]) -> Result<Vec<(u64, Socket)>, JailError> {
+    let header = u64::from_ne_bytes(field(bytes, layout::LEN)?) as usize;
+    let mut sockets = vec![];
+    let mut offset = header;
+
+    loop {
+        let record = bytes
+            .get(offset..)
+            .ok_or_else(|| invalid("record out of bounds"))?;
+        let len = u64::from_ne_bytes(field(record, layout::LEN)?) as usize;
+
+        // The list ends with another `xinpgen`.
+        if len <= header {
+            return Ok(sockets);
+        }
+
+        let record = record
+            .get(..len)
+            .ok_or_else(|| invalid("record out of bounds"))?;
+        let xso = match protocol {
+            Protocol::Tcp => record.get(layout::XTCPCB_INP + layout::XINPCB_SOCKET..),
+            Protocol::Udp => record.get(layout::XINPCB_SOCKET..),
+        }
+        .ok_or_else(|| invalid("record out of bounds"))?;
+
+        let xso_len = u64::from_ne_bytes(field(xso, layout::XSO_LEN)?) as usize;
+        let so = u64::from_ne_bytes(field(xso, layout::XSO_SO)?);
+        let family = i32::from_ne_bytes(field(xso, layout::XSO_FAMILY)?);
+        let uid = u32::from_ne_bytes(field(xso, layout::SO_UID)?);
+        let options = u16::from_ne_bytes(field(xso, layout::SO_OPTIONS)?);
+        let state = u16::from_ne_bytes(field(xso, layout::SO_STATE)?);
+
+        let inc = xso
+            .get(xso_len..xso_len + layout::IN_CONNINFO_LEN)
+            .ok_or_else(|| invalid("record out of bounds"))?;
+        let local = address(
+            family,
+            field(inc, layout::IE_LADDR)?,
+            field(inc, layout::IE_LPORT)?,
+        );
+        let remote = address(
+            family,
+            field(inc, layout::IE_FADDR)?,
+            field(inc, layout::IE_FPORT)?,
+        );
+
+        sockets.push((
+            so,
+            Socket {
+                jail: None,
+                protocol,
+                local,
+                remote: if state & layout::SS_ISCONNECTED != 0 || remote.port() != 0 {
+                    Some(remote)
+                } else {
+                    None
+                },
+                listening: options & layout::SO_ACCEPTCONN != 0,
+                uid,
+            },
+        ));
+
+        offset += len;
+    }
+}
+
+/// Read the sockets of all protocols visible to this process.
+#[cfg(target_os = "freebsd")]
+fn visible() -> Result<Vec<(u64, Socket)>, JailError> {
+    let mut sockets = vec![];
+    for protocol in [Protocol::Tcp, Protocol::Udp] {
+        let bytes = sys::pcblist(protocol.pcblist()).ok_or_else(JailError::from_errno)?;
+        sockets.extend(parse(protocol, &bytes)?);
+    }
+    Ok(sockets)
+}
+
+/// Read the sockets of all protocols visible inside a jail.
+#[cfg(target_os = "freebsd")]
+fn visible_in(jail: RunningJail) -> Result<Vec<(u64, Socket)>, JailError> {
+    let output = sys::attach_in_fork(jail.jid, || {
+        let tcp = sys::pcblist(Protocol::Tcp.pcblist())?;
+        let udp = sys::pcblist(Protocol::Udp.pcblist())?;
+
+        let mut output = (tcp.len() as u64).to_ne_bytes().to_vec();
+        output.extend(tcp);
+        output.extend(udp);
+        Some(output)
+    })?;
+
+    let tcp_len = u64::from_ne_bytes(field(&output, 0)?) as usize;
+    let (tcp, udp) = output[8..].split_at(tcp_len.min(output.len() - 8));
+
+    let mut sockets = parse(Protocol::Tcp, tcp)?;
+    sockets.extend(parse(Protocol::Udp, udp)?);
+    Ok(sockets)
+}
+
+/// List the TCP and UDP sockets of the host and all running jails.
+///
+/// Sockets of jails with their own network stack are included, although the
+/// host cannot see them. Sockets of jails that cannot be attached to, e.g.
+/// because they are dying, are attributed to the host.
+///
+/// # Examples
+///
+/// ```
+/// use jail::net;
+///
+/// for socket in net::sockets().expect("could not list sockets") {
+///     if socket.listening && socket.local.port() == 80 {
+///         match socket.jail {
+///             Some(jail) => println!("jail {} is listening on port 80", jail.jid),
+///             None => println!("the host is listening on port 80"),
+///         }
+///     }
+/// }
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn sockets() -> Result<Vec<Socket>, JailError> {
+    trace!("net::sockets()");
+    let mut sockets: Vec<(u64, Socket)> = visible()?;
+
+    // The sockets visible in a jail include those of its children, so the
+    // innermost jail seeing a socket sees the fewest sockets.
+    let mut owners: HashMap<u64, (usize, RunningJail)> = HashMap::new();
+
+    for jail in RunningJail::all() {
+        let seen = match visible_in(jail) {
+            Ok(seen) => seen,
+            Err(JailError::JailAttachError(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        let count = seen.len();
+        for (so, socket) in seen {
+            let owner = owners.entry(so).or_insert((count, jail));
+            if count < owner.0 || (count == owner.0 && jail.jid > owner.1.jid) {
+                *owner = (count, jail);
+            }
+
+            // Sockets of jails with their own network stack.
+            if !sockets.iter().any(|(known, _)| *known == so) {
+                sockets.push((so, socket));
+            }
+        }
+    }
+
+    Ok(sockets
+        .into_iter()
+        .map(|(so, mut socket)| {
+            socket.jail = owners.get(&so).map(|(_, jail)| *jail);
+            socket
+        })
+        .collect())
+}
+
+/// Check that addresses are configured on a host interface and not used by
+/// another jail, given the addresses of the host and the `(jid, name,
+/// addresses)` of the running jails.
+fn check_ips(
+    ips: &[net::IpAddr],
+    host: &[net::IpAddr],
+    jails: &[(i32, String, Vec<net::IpAddr>)],
+) -> Result<(), JailError> {
+    for ip in ips {
+        if let Some((jid, name, _)) = jails.iter().find(|(_, _, used)| used.contains(ip)) {
+            return Err(JailError::IpInUse {
+                ip: *ip,
+                jid: *jid,
+                name: name.clone(),
+            });
+        }
+
+        if !host.contains(ip) {
+            return Err(JailError::IpNotConfigured(*ip));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the addresses of a jail sharing the network stack of the host
+/// are configured on a host interface and not used by another jail, see
+/// [StoppedJail::check_ips](../struct.StoppedJail.html#method.check_ips).
+///
+/// This fails with an explanation where the kernel would fail with
+/// `EADDRNOTAVAIL` or `EADDRINUSE`.
+///
+/// # Examples
+///
+/// ```
+/// use jail::net;
+///
+/// assert!(net::verify_ips(&["127.0.0.1".parse().unwrap()]).is_ok());
+/// assert!(net::verify_ips(&["192.0.2.254".parse().unwrap()]).is_err());
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn verify_ips(ips: &[net::IpAddr]) -> Result<(), JailError> {
+    trace!("net::verify_ips(ips={:?})", ips);
+    if ips.is_empty() {
+        return Ok(());
+    }
+
+    let host = sys::interface_addresses()?;
+    let jails: Vec<(i32, String, Vec<net::IpAddr>)> = RunningJail::all()
+        .filter_map(|jail| Some((jail.jid, jail.name().ok()?, jail.ips().ok()?)))
+        .collect();
+
+    check_ips(ips, &host, &jails)
+}
+
+/// A network from which addresses are allocated to jails.
+///
+/// [IpPool::allocate] returns the lowest address of the network that is
+/// neither used by a running jail nor reserved by an earlier allocation in
+/// this process. The network and broadcast addresses of IPv4 networks and
+/// the subnet-router anycast address of IPv6 networks are never handed out.
+///
+/// Allocated addresses stay reserved until a jail using them is started
+/// through this library, or they are given back with [IpPool::release].
+/// Concurrent allocations from any pool never return the same address.
+///
+/// # Examples
+///
+/// ```
+/// use jail::net::IpPool;
+/// use jail::StoppedJail;
+///
+/// let pool: IpPool = "192.0.2.0/28".parse().unwrap();
+///
+/// let stopped = pool
+///     .assign(StoppedJail::new("/rescue").name("testjail_ip_pool"))
+///     .expect("no free address");
+/// assert!(pool.contains(stopped.ips[0]));
+///
+/// let running = stopped.start().expect("could not start jail");
+/// assert!(pool.in_use().unwrap().contains(&running.ips().unwrap()[0]));
+/// running.kill().expect("could not kill jail");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpPool {
+    network: net::IpAddr,
+    prefix: u8,
+}
+
+fn bits(ip: net::IpAddr) -> u8 {
+    match ip {
+        net::IpAddr::V4(_) => 32,
+        net::IpAddr::V6(_) => 128,
+    }
+}
+
+fn to_u128(ip: net::IpAddr) -> u128 {
+    match ip {
+        net::IpAddr::V4(ip) => u32::from(ip).into(),
+        net::IpAddr::V6(ip) => ip.into(),
+    }
+}
+
+impl IpPool {
+    /// Create a pool of the addresses in `network/prefix`.
+    ///
+    /// Fails if the prefix is longer than the address, or `network` has bits
+    /// set outside the prefix.
+    pub fn new(network: net::IpAddr, prefix: u8) -> Result<IpPool, JailError> {
+        trace!("IpPool::new(network={}, prefix={})", network, prefix);
+        let invalid = |reason: &str| JailError::InvalidNetwork {
+            network: format!("{}/{}", network, prefix),
+            reason: reason.to_string(),
+        };
+
+        if prefix > bits(network) {
+            return Err(invalid("prefix is longer than the address"));
+        }
+
+        let pool = IpPool { network, prefix };
+        if to_u128(network) & pool.host_mask() != 0 {
+            return Err(invalid("address has bits set outside the prefix"));
+        }
+
+        Ok(pool)
+    }
+
+    /// The first address of the network.
+    pub fn network(&self) -> net::IpAddr {
+        self.network
+    }
+
+    /// The length of the network prefix in bits.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    fn host_mask(&self) -> u128 {
+        u128::MAX
+            .checked_shr(u32::from(128 - bits(self.network) + self.prefix))
+            .unwrap_or(0)
+    }
+
+    /// Whether `ip` belongs to the network.
+    pub fn contains(&self, ip: net::IpAddr) -> bool {
+        bits(ip) == bits(self.network) && to_u128(ip) & !self.host_mask() == to_u128(self.network)
+    }
+
+    /// The addresses that may be handed out, in ascending order.
+    fn hosts(&self) -> impl Iterator<Item = net::IpAddr> {
+        let network = self.network;
+        let first = to_u128(network);
+        let last = first | self.host_mask();
+        let (first, last) = match (network, bits(network) - self.prefix) {
+            // Point-to-point links and single addresses use all of them.
+            (_, 0) | (net::IpAddr::V4(_), 1) => (first, last),
+            // Skip the network and broadcast addresses.
+            (net::IpAddr::V4(_), _) => (first + 1, last - 1),
+            // Skip the subnet-router anycast address.
+            (net::IpAddr::V6(_), _) => (first + 1, last),
+        };
+
+        (first..=last).map(move |ip| match network {
+            net::IpAddr::V4(_) => net::IpAddr::V4((ip as u32).into()),
+            net::IpAddr::V6(_) => net::IpAddr::V6(ip.into()),
+        })
+    }
+
+    /// The addresses of the network used by running jails.
+    #[cfg(target_os = "freebsd")]
+    pub fn in_use(&self) -> Result<Vec<net::IpAddr>, JailError> {
+        trace!("IpPool::in_use({})", self);
+        let mut used = vec![];
+        for running in RunningJail::all() {
+            match running.ips() {
+                Ok(ips) => used.extend(ips.into_iter().filter(|ip| self.contains(*ip))),
+                Err(e) => warn!("could not get the addresses of jail {}: {}", running.jid, e),
+            }
+        }
+
+        used.sort();
+        used.dedup();
+        Ok(used)
+    }
+
+    /// Reserve the lowest free address of the network.
+    ///
+    /// Fails with [JailError::AddressPoolExhausted] if all addresses are
+    /// used or reserved.
+    #[cfg(target_os = "freebsd")]
+    pub fn allocate(&self) -> Result<net::IpAddr, JailError> {
+        trace!("IpPool::allocate({})", self);
+        let used = self.in_use()?;
+        registry::reserve_ip(self.hosts().filter(|ip| used.binary_search(ip).is_err()))
+            .ok_or_else(|| JailError::AddressPoolExhausted(self.to_string()))
+    }
+
+    /// Add a free address of the network to a jail definition.
+    #[cfg(target_os = "freebsd")]
+    pub fn assign(&self, stopped: StoppedJail) -> Result<StoppedJail, JailError> {
+        trace!("IpPool::assign({}, stopped={:?})", self, stopped);
+        Ok(stopped.ip(self.allocate()?))
+    }
+
+    /// Give back an allocated address that will not be used.
+    pub fn release(&self, ip: net::IpAddr) {
+        trace!("IpPool::release({}, ip={})", self, ip);
+        registry::release_ips(&[ip]);
+    }
+}
+
+impl fmt::Display for IpPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Parse a network in CIDR notation, e.g. `192.0.2.0/24` or
+/// `2001:db8::/64`.
+impl FromStr for IpPool {
+    type Err = JailError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| JailError::InvalidNetwork {
+            network: s.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (network, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| invalid("expected address/prefix"))?;
+        IpPool::new(
+            network.parse().map_err(|_| invalid("invalid address"))?,
+            prefix.parse().map_err(|_| invalid("invalid prefix"))?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_conflicts() {
+        let ip = |s: &str| -> net::IpAddr { s.parse().unwrap() };
+        let host = [ip("192.0.2.1"), ip("192.0.2.2"), ip("2001:db8::1")];
+        let jails = vec![(3, "www".to_string(), vec![ip("192.0.2.2")])];
+
+        assert!(check_ips(&[ip("192.0.2.1"), ip("2001:db8::1")], &host, &jails).is_ok());
+        assert!(matches!(
+            check_ips(&[ip("192.0.2.5")], &host, &jails),
+            Err(JailError::IpNotConfigured(missing)) if missing == ip("192.0.2.5")
+        ));
+        assert!(matches!(
+            check_ips(&[ip("192.0.2.1"), ip("192.0.2.2")], &host, &jails),
+            Err(JailError::IpInUse { jid: 3, .. })
+        ));
+    }
+
+    const XINPGEN_LEN: usize = 64;
+    const XSOCKET_LEN: usize = 240;
+
+    fn xinpgen() -> Vec<u8> {
+        let mut bytes = vec![0; XINPGEN_LEN];
+        bytes[..8].copy_from_slice(&(XINPGEN_LEN as u64).to_ne_bytes());
+        bytes
+    }
+
+    fn xinpcb(
+        so: u64,
+        family: i32,
+        local: ([u8; 16], u16),
+        remote: ([u8; 16], u16),
+        options: u16,
+    ) -> Vec<u8> {
+        let len = 8 + XSOCKET_LEN + layout::IN_CONNINFO_LEN + 4;
+        let mut bytes = vec![0; len];
+        bytes[..8].copy_from_slice(&(len as u64).to_ne_bytes());
+
+        let xso = &mut bytes[layout::XINPCB_SOCKET..];
+        xso[..8].copy_from_slice(&(XSOCKET_LEN as u64).to_ne_bytes());
+        xso[layout::XSO_SO..][..8].copy_from_slice(&so.to_ne_bytes());
+        xso[layout::XSO_FAMILY..][..4].copy_from_slice(&family.to_ne_bytes());
+        xso[layout::SO_UID..][..4].copy_from_slice(&80u32.to_ne_bytes());
+        xso[layout::SO_OPTIONS..][..2].copy_from_slice(&options.to_ne_bytes());
+
+        let inc = &mut xso[XSOCKET_LEN..];
+        inc[layout::IE_FPORT..][..2].copy_from_slice(&remote.1.to_be_bytes());
+        inc[layout::IE_LPORT..][..2].copy_from_slice(&local.1.to_be_bytes());
+        inc[layout::IE_FADDR..][..16].copy_from_slice(&remote.0);
+        inc[layout::IE_LADDR..][..16].copy_from_slice(&local.0);
+        bytes
+    }
+
+    fn v4(addr: [u8; 4]) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[12..].copy_from_slice(&addr);
+        bytes
+    }
+
+    #[test]
+    fn parse_pcblist() {
+        let mut tcp = xinpgen();
+        let mut listener = vec![0; 8];
+        listener.extend(xinpcb(
+            0xdead,
+            libc::AF_INET,
+            (v4([0, 0, 0, 0]), 80),
+            ([0; 16], 0),
+            layout::SO_ACCEPTCONN,
+        ));
+        let len = listener.len() + 32;
+        listener.resize(len, 0);
+        listener[..8].copy_from_slice(&(len as u64).to_ne_bytes());
+        tcp.extend(listener);
+        tcp.extend(xinpgen());
+
+        let sockets = parse(Protocol::Tcp, &tcp).unwrap();
+        assert_eq!(sockets.len(), 1);
+        let (so, socket) = &sockets[0];
+        assert_eq!(*so, 0xdead);
+        assert!(socket.listening);
+        assert_eq!(socket.local, "0.0.0.0:80".parse().unwrap());
+        assert_eq!(socket.remote, None);
+        assert_eq!(socket.uid, 80);
+
+        let mut udp = xinpgen();
+        let ip6: net::Ipv6Addr = "2001:db8::1".parse().unwrap();
+        udp.extend(xinpcb(
+            0xbeef,
+            libc::AF_INET6,
+            (ip6.octets(), 53),
+            (
+                "2001:db8::2".parse::<net::Ipv6Addr>().unwrap().octets(),
+                4242,
+            ),
+            0,
+        ));
+        udp.extend(xinpgen());
+
+        let sockets = parse(Protocol::Udp, &udp).unwrap();
+        assert_eq!(sockets[0].1.local, "[2001:db8::1]:53".parse().unwrap());
+        assert_eq!(
+            sockets[0].1.remote,
+            Some("[2001:db8::2]:4242".parse().unwrap())
+        );
+        assert!(!sockets[0].1.listening);
+
+        assert!(parse(Protocol::Udp, &udp[..XINPGEN_LEN + 100]).is_err());
+        assert!(parse(Protocol::Udp, &xinpgen()[..4]).is_err());
+    }
+
+    fn pool(s: &str) -> IpPool {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn pool_hosts() {
+        let hosts: Vec<_> = pool("192.0.2.0/30")
+            .hosts()
+            .map(|ip| ip.to_string())
+            .collect();
+        assert_eq!(hosts, vec!["192.0.2.1", "192.0.2.2"]);
+
+        let hosts: Vec<_> = pool("192.0.2.0/31")
+            .hosts()
+            .map(|ip| ip.to_string())
+            .collect();
+        assert_eq!(hosts, vec!["192.0.2.0", "192.0.2.1"]);
+
+        assert_eq!(pool("192.0.2.7/32").hosts().count(), 1);
+        assert_eq!(
+            pool("0.0.0.0/0").hosts().next(),
+            Some("0.0.0.1".parse().unwrap())
+        );
+
+        let mut hosts = pool("2001:db8::/64").hosts();
+        assert_eq!(hosts.next(), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(pool("::/0").hosts().next(), Some("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn pool_contains() {
+        let v4 = pool("192.0.2.0/24");
+        assert!(v4.contains("192.0.2.255".parse().unwrap()));
+        assert!(!v4.contains("192.0.3.0".parse().unwrap()));
+        assert!(!v4.contains("::ffff:192.0.2.1".parse().unwrap()));
+        assert!(pool("::/0").contains("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn pool_parse() {
+        assert_eq!(pool("192.0.2.0/24").to_string(), "192.0.2.0/24");
+        for invalid in &[
+            "192.0.2.0",
+            "192.0.2.1/24",
+            "192.0.2.0/33",
+            "2001:db8::/129",
+            "x/8",
+        ] {
+            assert!(
+                matches!(
+                    invalid.parse::<IpPool>(),
+                    Err(JailError::InvalidNetwork { .. })
+                ),
+                "{:?} was accepted",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn pool_reservations() {
+        let pool = pool("198.51.100.0/30");
+        let first = registry::reserve_ip(pool.hosts());
+        let second = registry::reserve_ip(pool.hosts());
+        assert_eq!(first, Some("198.51.100.1".parse().unwrap()));
+        assert_eq!(second, Some("198.51.100.2".parse().unwrap()));
+        assert_eq!(registry::reserve_ip(pool.hosts()), None);
+
+        pool.release(first.unwrap());
+        assert_eq!(registry::reserve_ip(pool.hosts()), first);
+    }
+}
@@ -0,0 +1,199 @@
+//! Waiting for jails to become ready
+//!
+//! [StoppedJail::start](../struct.StoppedJail.html#method.start) returns as
+//! soon as the jail exists, while its services are still starting. Instead of
+//! sleeping for a guessed amount of time, a supervisor describes the signal
+//! that the jail is ready as [Readiness], similar to `sd_notify`(3), and waits
+//! for it with [Waiter].
+use crate::{JailError, RunningJail};
+use log::trace;
+use std::fmt;
+use std::net;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(target_os = "freebsd")]
+use std::os::unix::net::UnixStream;
+#[cfg(target_os = "freebsd")]
+use std::process::Command;
+#[cfg(target_os = "freebsd")]
+use std::thread;
+#[cfg(target_os = "freebsd")]
+use std::time::Instant;
+
+/// A signal that a jail is ready.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    /// A file exists at the given path inside the jail, e.g. a pid file.
+    File(PathBuf),
+
+    /// A TCP connection to the address can be established from the host.
+    Tcp(net::SocketAddr),
+
+    /// A connection to the Unix domain socket at the given path inside the
+    /// jail can be established.
+    Unix(PathBuf),
+
+    /// A command run inside the jail exits successfully, e.g. a health
+    /// check.
+    Command { program: String, args: Vec<String> },
+}
+
+impl Readiness {
+    /// Check once whether the signal is given. Failures to check, e.g.
+    /// because the path cannot be resolved, count as not ready.
+    ///
+    /// `timeout` bounds the connection attempt of [Readiness::Tcp].
+    #[cfg(target_os = "freebsd")]
+    pub fn check(&self, jail: &RunningJail, timeout: Duration) -> bool {
+        trace!("Readiness::check({:?}, jail={:?})", self, jail);
+        match self {
+            Readiness::File(path) => jail.host_path(path).is_ok_and(|path| path.exists()),
+            Readiness::Tcp(addr) => net::TcpStream::connect_timeout(addr, timeout).is_ok(),
+            Readiness::Unix(path) => jail
+                .host_path(path)
+                .is_ok_and(|path| UnixStream::connect(path).is_ok()),
+            Readiness::Command { program, args } => {
+                jail.exec(Command::new(program).args(args)).is_ok()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Readiness::File(path) => write!(f, "file {:?} exists", path),
+            Readiness::Tcp(addr) => write!(f, "{} accepts connections", addr),
+            Readiness::Unix(path) => write!(f, "socket {:?} accepts connections", path),
+            Readiness::Command { program, args } => {
+                write!(f, "{}", program)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, " succeeds")
+            }
+        }
+    }
+}
+
+/// Wait for a jail to signal that it is ready.
+///
+/// The signal is checked every [Waiter::poll_interval] until it is given, the
+/// [Waiter::timeout] expires or the jail is removed.
+///
+/// # Examples
+///
+/// ```
+/// use jail::notify::{Readiness, Waiter};
+/// use jail::StoppedJail;
+/// use std::time::Duration;
+///
+/// let running = StoppedJail::new("/rescue")
+///     .name("testjail_notify")
+///     .start()
+///     .expect("could not start jail");
+///
+/// let waited = Waiter::new(Readiness::Command {
+///     program: "/test".into(),
+///     args: vec!["-x".into(), "/sh".into()],
+/// })
+/// .timeout(Duration::from_secs(10))
+/// .wait(&running)
+/// .expect("jail did not become ready");
+/// println!("ready after {:?}", waited);
+/// # running.kill().expect("could not stop jail");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Waiter {
+    readiness: Readiness,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl Waiter {
+    /// Wait for `readiness`, for 30 seconds by default.
+    pub fn new(readiness: Readiness) -> Self {
+        trace!("Waiter::new(readiness={:?})", readiness);
+        Waiter {
+            readiness,
+            timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+
+    /// Set how long to wait before failing with [JailError::NotReady].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        trace!("Waiter::timeout({:?}, timeout={:?})", self, timeout);
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the interval in which the signal is checked.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        trace!("Waiter::poll_interval({:?}, interval={:?})", self, interval);
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Block until the jail is ready, returning how long it took.
+    ///
+    /// Fails with [JailError::NotReady] when the timeout expires, and with
+    /// [JailError::NoSuchJail] when the jail is removed while waiting.
+    #[cfg(target_os = "freebsd")]
+    pub fn wait(&self, jail: &RunningJail) -> Result<Duration, JailError> {
+        trace!("Waiter::wait({:?}, jail={:?})", self, jail);
+        let start = Instant::now();
+
+        loop {
+            let remaining = self.timeout.saturating_sub(start.elapsed());
+            let attempt = self
+                .poll_interval
+                .min(remaining)
+                .max(Duration::from_millis(1));
+            if self.readiness.check(jail, attempt) {
+                return Ok(start.elapsed());
+            }
+
+            if !RunningJail::exists(jail.jid) {
+                return Err(JailError::NoSuchJail(jail.jid));
+            }
+
+            let remaining = self.timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err(JailError::NotReady {
+                    jid: jail.jid,
+                    condition: self.readiness.to_string(),
+                    waited: start.elapsed(),
+                });
+            }
+
+            thread::sleep(self.poll_interval.min(remaining));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe() {
+        assert_eq!(
+            Readiness::File("/var/run/nginx.pid".into()).to_string(),
+            "file \"/var/run/nginx.pid\" exists"
+        );
+        assert_eq!(
+            Readiness::Tcp("192.0.2.1:80".parse().unwrap()).to_string(),
+            "192.0.2.1:80 accepts connections"
+        );
+        assert_eq!(
+            Readiness::Command {
+                program: "/usr/local/bin/pg_isready".into(),
+                args: vec!["-q".into()],
+            }
+            .to_string(),
+            "/usr/local/bin/pg_isready -q succeeds"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Gating of jail features by the FreeBSD release of the host
+//!
+//! Jail parameters were added over many releases. Instead of letting
+//! `jail_set`(2) fail with a terse "unknown parameter", the library checks the
+//! [Feature]s a jail uses against the `kern.osreldate` of the host and fails
+//! with [JailError::UnsupportedOnRelease](../enum.JailError.html) naming the
+//! release that is required.
+//!
+//! The host release is detected once and cached for the lifetime of the
+//! process. If it cannot be detected, all features are assumed to be
+//! supported and the kernel has the final say.
+//!
+//! Appliances running old releases may not accept the parameters the library
+//! passes to `jail_set`(2). For these, jails can be created with the original
+//! `jail`(2) system call instead, see [set_backend].
+use crate::allow::Allow;
+use crate::{osrelease, param, JailError};
+use log::trace;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use sysctl::{Ctl, CtlValue, Sysctl};
+
+/// A jail feature that is only available from a certain FreeBSD release on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Virtual network stacks, i.e. the `vnet` parameter. `VIMAGE` is part of
+    /// the `GENERIC` kernel since FreeBSD 12.0.
+    Vnet,
+
+    /// Source address selection, i.e. the `ip4.saddrsel` and `ip6.saddrsel`
+    /// parameters.
+    IpSaddrSel,
+
+    /// Per-jail System V IPC modes, i.e. the `sysvmsg`, `sysvsem` and
+    /// `sysvshm` parameters.
+    SysVIpcModes,
+
+    /// `nullfs`(5) mounts of single files, as used by
+    /// [SyslogForward::host_socket](../provision/struct.SyslogForward.html).
+    NullfsFileMounts,
+
+    /// The `meta` and `env` parameters.
+    Metadata,
+
+    /// An `allow.*` parameter that is not available on all supported
+    /// releases.
+    Allow(Allow),
+}
+
+impl Feature {
+    /// The first `osreldate` supporting the feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::oscompat::Feature;
+    ///
+    /// assert_eq!(Feature::Vnet.min_osreldate(), 1200000);
+    /// ```
+    pub fn min_osreldate(&self) -> u32 {
+        match self {
+            Feature::Vnet => 1_200_000,
+            Feature::IpSaddrSel => 900_000,
+            Feature::SysVIpcModes => 1_100_000,
+            Feature::NullfsFileMounts => 1_400_000,
+            Feature::Metadata => 1_500_000,
+            Feature::Allow(allow) => match allow {
+                Allow::Nfsd | Allow::Extattr | Allow::Adjtime | Allow::Settime => 1_400_000,
+                Allow::Routing => 1_500_000,
+                Allow::MountFusefs
+                | Allow::Suser
+                | Allow::UnprivilegedProcDebug
+                | Allow::ReservedPorts
+                | Allow::Vmm => 1_200_000,
+                _ => 0,
+            },
+        }
+    }
+
+    /// Get the feature a jail parameter depends on, if it is not available
+    /// on all supported releases.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::allow::Allow;
+    /// use jail::oscompat::Feature;
+    ///
+    /// assert_eq!(Feature::of_param("vnet"), Some(Feature::Vnet));
+    /// assert_eq!(
+    ///     Feature::of_param("allow.routing"),
+    ///     Some(Feature::Allow(Allow::Routing))
+    /// );
+    /// assert_eq!(Feature::of_param("host.hostname"), None);
+    /// ```
+    pub fn of_param(name: &str) -> Option<Feature> {
+        let feature = match name {
+            "vnet" => Feature::Vnet,
+            "ip4.saddrsel" | "ip6.saddrsel" => Feature::IpSaddrSel,
+            "sysvmsg" | "sysvsem" | "sysvshm" => Feature::SysVIpcModes,
+            "meta" | "env" => Feature::Metadata,
+            name => Feature::Allow(name.parse().ok()?),
+        };
+
+        match feature.min_osreldate() {
+            0 => None,
+            _ => Some(feature),
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Feature::Vnet => write!(f, "vnet"),
+            Feature::IpSaddrSel => write!(f, "source address selection"),
+            Feature::SysVIpcModes => write!(f, "System V IPC modes"),
+            Feature::NullfsFileMounts => write!(f, "nullfs file mounts"),
+            Feature::Metadata => write!(f, "jail metadata"),
+            Feature::Allow(allow) => write!(f, "{}", allow),
+        }
+    }
+}
+
+/// The system call jails are created with, see [set_backend].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// `jail_set`(2), taking any parameter as a name and value pair.
+    #[default]
+    JailSet,
+
+    /// The original `jail`(2), taking a versioned struct with only the path,
+    /// name, host name and IP addresses of the jail.
+    ///
+    /// Jails are created in a forked child, since `jail`(2) also attaches
+    /// the calling process, and are then made persistent with `jail_set`(2).
+    /// Starting a jail with any other parameter fails with
+    /// [JailError::NotSupported](../enum.JailError.html).
+    Legacy,
+}
+
+static BACKEND: AtomicU8 = AtomicU8::new(0);
+
+/// Select the system call jails are created with, for all jails started by
+/// this process from now on.
+///
+/// # Examples
+///
+/// ```
+/// use jail::oscompat::{self, Backend};
+///
+/// oscompat::set_backend(Backend::Legacy);
+/// assert_eq!(oscompat::backend(), Backend::Legacy);
+/// # oscompat::set_backend(Backend::JailSet);
+/// ```
+pub fn set_backend(backend: Backend) {
+    trace!("oscompat::set_backend(backend={:?})", backend);
+    BACKEND.store(backend as u8, Ordering::SeqCst);
+}
+
+/// Get the system call jails are created with, see [set_backend].
+pub fn backend() -> Backend {
+    trace!("oscompat::backend()");
+    match BACKEND.load(Ordering::SeqCst) {
+        1 => Backend::Legacy,
+        _ => Backend::JailSet,
+    }
+}
+
+/// Format an `osreldate` as a release, e.g. `14.0`.
+pub(crate) fn release(osreldate: u32) -> String {
+    match osrelease::version(osreldate as i32) {
+        Some((major, minor)) => format!("{}.{}", major, minor),
+        None => osreldate.to_string(),
+    }
+}
+
+/// Check whether `feature` is supported by a host with the given `osreldate`.
+fn check(feature: Feature, host: Option<u32>) -> Result<(), JailError> {
+    match host {
+        Some(host) if host < feature.min_osreldate() => Err(JailError::UnsupportedOnRelease {
+            feature: feature.to_string(),
+            required: release(feature.min_osreldate()),
+            host: release(host),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Get the `kern.osreldate` of the host, or `None` if it cannot be detected.
+///
+/// The value is read once and cached.
+///
+/// # Examples
+///
+/// ```
+/// use jail::oscompat;
+///
+/// if let Some(osreldate) = oscompat::osreldate() {
+///     println!("running on osreldate {}", osreldate);
+/// }
+/// ```
+pub fn osreldate() -> Option<u32> {
+    trace!("oscompat::osreldate()");
+    static OSRELDATE: OnceLock<Option<u32>> = OnceLock::new();
+
+    *OSRELDATE.get_or_init(|| match Ctl::new("kern.osreldate").ok()?.value().ok()? {
+        CtlValue::Int(i) if i > 0 => Some(i as u32),
+        _ => None,
+    })
+}
+
+/// Check whether the host supports a feature.
+///
+/// # Examples
+///
+/// ```
+/// use jail::oscompat::{self, Feature};
+///
+/// if !oscompat::supports(Feature::Vnet) {
+///     println!("this host cannot run vnet jails");
+/// }
+/// ```
+pub fn supports(feature: Feature) -> bool {
+    trace!("oscompat::supports(feature={:?})", feature);
+    check(feature, osreldate()).is_ok()
+}
+
+/// Fail with [JailError::UnsupportedOnRelease](../enum.JailError.html) if the
+/// host does not support a feature.
+///
+/// # Examples
+///
+/// ```
+/// use jail::oscompat::{self, Feature};
+///
+/// oscompat::require(Feature::IpSaddrSel).expect("FreeBSD 9.0 or later required");
+/// ```
+pub fn require(feature: Feature) -> Result<(), JailError> {
+    trace!("oscompat::require(feature={:?})", feature);
+    check(feature, osreldate())
+}
+
+/// Check that the host supports all parameters of a jail.
+pub(crate) fn require_params(params: &HashMap<String, param::Value>) -> Result<(), JailError> {
+    trace!("oscompat::require_params(params={:?})", params);
+    let mut features: Vec<Feature> = params
+        .keys()
+        .filter_map(|name| Feature::of_param(name))
+        .collect();
+    features.sort_by_key(Feature::min_osreldate);
+
+    features.into_iter().try_for_each(require)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gate_features() {
+        assert!(check(Feature::Vnet, Some(1_104_000)).is_err());
+        assert!(check(Feature::Vnet, Some(1_200_000)).is_ok());
+        assert!(check(Feature::Metadata, None).is_ok());
+        assert_eq!(Feature::of_param("allow.mount"), None);
+        assert_eq!(Feature::of_param("sysvshm"), Some(Feature::SysVIpcModes));
+
+        match check(Feature::Allow(Allow::Nfsd), Some(1_304_000)) {
+            Err(JailError::UnsupportedOnRelease {
+                feature,
+                required,
+                host,
+            }) => {
+                assert_eq!(feature, "allow.nfsd");
+                assert_eq!(required, "14.0");
+                assert_eq!(host, "13.4");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
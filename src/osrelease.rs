@@ -0,0 +1,84 @@
+//! Validation of the `osrelease` and `osreldate` jail parameters
+//!
+//! Userland, most notably `pkg`(8), derives the ABI from both values, so
+//! they need to agree with each other.
+use log::trace;
+use sysctl::{Ctl, CtlValue, Sysctl};
+
+/// Parse the major and minor version from an `osrelease` string such as
+/// `12.4-RELEASE` or `14.1-RELEASE-p3`.
+pub(crate) fn parse(osrelease: &str) -> Option<(u32, u32)> {
+    let (version, branch) = osrelease.split_once('-')?;
+    let (major, minor) = version.split_once('.')?;
+
+    if branch.is_empty()
+        || !branch
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Get the major and minor version encoded in an `osreldate` value such as
+/// `1204000`, or `None` if it is out of range.
+pub(crate) fn version(osreldate: i32) -> Option<(u32, u32)> {
+    match osreldate {
+        d if d >= 100_000 => Some(((d / 100_000) as u32, ((d / 1000) % 100) as u32)),
+        _ => None,
+    }
+}
+
+/// Check that `osrelease` and `osreldate` describe the same version,
+/// returning a description of the mismatch if they do not.
+pub(crate) fn check(osrelease: &str, osreldate: i32) -> Result<(), String> {
+    trace!("check(osrelease={:?}, osreldate={})", osrelease, osreldate);
+    let release = parse(osrelease)
+        .ok_or_else(|| format!("osrelease {:?} is not of the form X.Y-BRANCH", osrelease))?;
+    let date = version(osreldate).ok_or_else(|| format!("osreldate {} is invalid", osreldate))?;
+
+    if release != date {
+        return Err(format!(
+            "osrelease {:?} is version {}.{}, but osreldate {} is version {}.{}",
+            osrelease, release.0, release.1, osreldate, date.0, date.1
+        ));
+    }
+
+    Ok(())
+}
+
+/// Get the `osrelease` and `osreldate` of the host.
+pub(crate) fn host() -> Option<(String, i32)> {
+    let osrelease = match Ctl::new("kern.osrelease").ok()?.value().ok()? {
+        CtlValue::String(s) => s,
+        _ => return None,
+    };
+
+    let osreldate = match Ctl::new("kern.osreldate").ok()?.value().ok()? {
+        CtlValue::Int(i) => i,
+        _ => return None,
+    };
+
+    Some((osrelease, osreldate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_consistency() {
+        assert_eq!(parse("12.4-RELEASE"), Some((12, 4)));
+        assert_eq!(parse("14.1-RELEASE-p3"), Some((14, 1)));
+        assert_eq!(parse("15.0-CURRENT"), Some((15, 0)));
+        assert_eq!(parse("12-RELEASE"), None);
+        assert_eq!(parse("12.4"), None);
+
+        assert!(check("12.4-RELEASE", 1204000).is_ok());
+        assert!(check("13.2-STABLE", 1302509).is_ok());
+        assert!(check("12.4-RELEASE", 1302000).is_err());
+        assert!(check("12.4-RELEASE", 1204).is_err());
+    }
+}
@@ -1,19 +1,19 @@
 //! Module for inspection and manipulation of jail parameters
-use crate::sys::JailFlags;
-use crate::JailError;
-use byteorder::{ByteOrder, LittleEndian, NetworkEndian, WriteBytesExt};
-use log::trace;
-use std::collections::HashMap;
-use std::ffi::{CStr, CString};
+use crate::{encoding, JailError};
+use jail_sys::{iovec, JailFlags};
+use log::{trace, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::ffi::CString;
 use std::iter::FromIterator;
 use std::mem;
 use std::net;
-use std::slice;
+use std::sync::{Arc, OnceLock, RwLock};
 use strum_macros::EnumDiscriminants;
 use sysctl::{Ctl, CtlFlags, CtlType, CtlValue, Sysctl};
 
 #[cfg(feature = "serialize")]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[cfg(target_os = "freebsd")]
 impl Type {
@@ -178,6 +178,8 @@ impl From<Type> for CtlType {
             Type::Ulong => CtlType::Ulong,
             Type::Ipv4Addrs => CtlType::Struct,
             Type::Ipv6Addrs => CtlType::Struct,
+            Type::Raw => CtlType::Struct,
+            Type::Custom => CtlType::Struct,
         }
     }
 }
@@ -185,7 +187,7 @@ impl From<Type> for CtlType {
 /// An enum representing the value of a parameter.
 #[derive(EnumDiscriminants, Clone, PartialEq, Eq, Debug, Hash)]
 #[strum_discriminants(name(Type), derive(PartialOrd, Ord, Hash))]
-#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum Value {
     Int(libc::c_int),
     String(String),
@@ -227,6 +229,124 @@ pub enum Value {
     /// ]);
     /// ```
     Ipv6Addrs(Vec<net::Ipv6Addr>),
+
+    /// Represent an opaque parameter value as raw bytes.
+    ///
+    /// Parameters of a structure type other than `ip4.addr` and `ip6.addr`
+    /// (e.g. those exported by kernel modules) are passed through unchanged.
+    /// The length of the buffer is determined from the parameter's MIB.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use jail::param::Value;
+    /// let opaque = Value::Raw(vec![0xde, 0xad, 0xbe, 0xef]);
+    /// assert_eq!(opaque.as_bytes().unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+    /// ```
+    Raw(Vec<u8>),
+
+    /// Represent a value decoded by a [Codec] registered for the parameter.
+    ///
+    /// See [register_codec] for an example.
+    Custom(Custom),
+}
+
+/// A typed parameter value produced by a [Codec].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Custom {
+    /// The parameter name the [Codec] was registered under.
+    pub param: String,
+
+    /// The named fields of the decoded value.
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Conversion between the raw bytes of a jail parameter and a [Value].
+///
+/// Codecs allow parameters exported by out-of-tree kernel modules to be
+/// handled as typed values. See [register_codec].
+pub trait Codec: Send + Sync {
+    /// Decode the bytes returned by the kernel into a value.
+    fn decode(&self, bytes: &[u8]) -> Result<Value, JailError>;
+
+    /// Encode a value into the bytes expected by the kernel.
+    fn encode(&self, value: &Value) -> Result<Vec<u8>, JailError>;
+}
+
+type CodecRegistry = RwLock<HashMap<String, Arc<dyn Codec>>>;
+
+fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+    CODECS.get_or_init(Default::default)
+}
+
+/// Register a [Codec] for the parameter `name`.
+///
+/// Once registered, [get] decodes and [set] encodes the parameter using the
+/// codec instead of the built-in conversions. Any previously registered codec
+/// for the same parameter is returned.
+///
+/// # Examples
+///
+/// ```
+/// use jail::param::{self, Codec, Custom, Value};
+/// use jail::JailError;
+/// use std::collections::BTreeMap;
+///
+/// struct Pair;
+///
+/// impl Codec for Pair {
+///     fn decode(&self, bytes: &[u8]) -> Result<Value, JailError> {
+///         let mut fields = BTreeMap::new();
+///         fields.insert("first".into(), Value::U8(bytes[0]));
+///         fields.insert("second".into(), Value::U8(bytes[1]));
+///         Ok(Value::Custom(Custom {
+///             param: "mymodule.pair".into(),
+///             fields,
+///         }))
+///     }
+///
+///     fn encode(&self, value: &Value) -> Result<Vec<u8>, JailError> {
+///         match value {
+///             Value::Custom(c) => Ok(vec![
+///                 c.fields["first"].clone().unpack_u64()? as u8,
+///                 c.fields["second"].clone().unpack_u64()? as u8,
+///             ]),
+///             _ => Err(JailError::SerializeFailed),
+///         }
+///     }
+/// }
+///
+/// param::register_codec("mymodule.pair", Pair);
+///
+/// let value = Pair.decode(&[1, 2]).unwrap();
+/// assert_eq!(value.as_bytes().unwrap(), vec![1, 2]);
+/// # param::unregister_codec("mymodule.pair");
+/// ```
+pub fn register_codec<C: Codec + 'static>(name: &str, codec: C) -> Option<Arc<dyn Codec>> {
+    trace!("register_codec(name={:?})", name);
+    codecs()
+        .write()
+        .expect("codec registry poisoned")
+        .insert(name.to_string(), Arc::new(codec))
+}
+
+/// Remove the [Codec] registered for the parameter `name`, returning it.
+pub fn unregister_codec(name: &str) -> Option<Arc<dyn Codec>> {
+    trace!("unregister_codec(name={:?})", name);
+    codecs()
+        .write()
+        .expect("codec registry poisoned")
+        .remove(name)
+}
+
+fn codec(name: &str) -> Option<Arc<dyn Codec>> {
+    codecs()
+        .read()
+        .expect("codec registry poisoned")
+        .get(name)
+        .cloned()
 }
 
 impl Value {
@@ -250,62 +370,119 @@ impl Value {
         self.into()
     }
 
+    /// Parse the textual representation of a value of the parameter `name`,
+    /// as used by `jail`(8) and `jail.conf`(5).
+    ///
+    /// The type of the parameter is looked up in the running kernel, see
+    /// [Value::parse_as].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::param::Value;
+    ///
+    /// assert_eq!(Value::parse("securelevel", "3").unwrap(), Value::Int(3));
+    /// assert_eq!(
+    ///     Value::parse("host.hostname", "example.com").unwrap(),
+    ///     Value::String("example.com".into())
+    /// );
+    /// ```
+    #[cfg(target_os = "freebsd")]
+    pub fn parse(name: &str, s: &str) -> Result<Value, JailError> {
+        trace!("Value::parse(name={:?}, s={:?})", name, s);
+        Value::parse_as(Type::of_param(name)?, s).map_err(|e| match e {
+            JailError::ParameterParseError { value, .. } => JailError::ParameterParseError {
+                name: name.into(),
+                value,
+            },
+            e => e,
+        })
+    }
+
+    /// Parse the textual representation of a value of the given type.
+    ///
+    /// In addition to plain numbers, integers accept `true` and `false` for
+    /// boolean parameters and `disable`, `new` and `inherit` for parameters
+    /// like `vnet` or `sysvshm`. Addresses are separated by commas and may be
+    /// given as `interface|address/prefix`, of which only the address is
+    /// kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::param::{Type, Value};
+    ///
+    /// assert_eq!(Value::parse_as(Type::Int, "inherit").unwrap(), Value::Int(2));
+    /// assert_eq!(
+    ///     Value::parse_as(Type::Ipv4Addrs, "em0|192.0.2.1/24,192.0.2.2").unwrap(),
+    ///     Value::Ipv4Addrs(vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()])
+    /// );
+    /// assert!(Value::parse_as(Type::U8, "256").is_err());
+    /// ```
+    pub fn parse_as(type_: Type, s: &str) -> Result<Value, JailError> {
+        trace!("Value::parse_as(type_={:?}, s={:?})", type_, s);
+        let invalid = || JailError::ParameterParseError {
+            name: format!("{:?}", type_),
+            value: s.into(),
+        };
+
+        fn number<T: std::str::FromStr>(
+            s: &str,
+            invalid: impl Fn() -> JailError,
+        ) -> Result<T, JailError> {
+            s.trim().parse().map_err(|_| invalid())
+        }
+
+        fn addresses<T: std::str::FromStr>(
+            s: &str,
+            invalid: impl Fn() -> JailError,
+        ) -> Result<Vec<T>, JailError> {
+            s.split(',')
+                .map(str::trim)
+                .filter(|addr| !addr.is_empty())
+                .map(|addr| {
+                    let addr = addr.rsplit('|').next().unwrap_or(addr);
+                    let addr = addr.split('/').next().unwrap_or(addr);
+                    addr.parse().map_err(|_| invalid())
+                })
+                .collect()
+        }
+
+        Ok(match type_ {
+            Type::Int => Value::Int(match s.trim() {
+                "false" | "disable" => 0,
+                "true" | "new" => 1,
+                "inherit" => 2,
+                s => number(s, invalid)?,
+            }),
+            Type::String => Value::String(s.into()),
+            Type::S64 => Value::S64(number(s, invalid)?),
+            Type::Uint => Value::Uint(number(s, invalid)?),
+            Type::Long => Value::Long(number(s, invalid)?),
+            Type::Ulong => Value::Ulong(number(s, invalid)?),
+            Type::U64 => Value::U64(number(s, invalid)?),
+            Type::U8 => Value::U8(number(s, invalid)?),
+            Type::U16 => Value::U16(number(s, invalid)?),
+            Type::S8 => Value::S8(number(s, invalid)?),
+            Type::S16 => Value::S16(number(s, invalid)?),
+            Type::S32 => Value::S32(number(s, invalid)?),
+            Type::U32 => Value::U32(number(s, invalid)?),
+            Type::Ipv4Addrs => Value::Ipv4Addrs(addresses(s, invalid)?),
+            Type::Ipv6Addrs => Value::Ipv6Addrs(addresses(s, invalid)?),
+            Type::Raw | Type::Custom => return Err(invalid()),
+        })
+    }
+
     /// Format the value into a vector of bytes as expected by the jail
     /// parameter API.
     pub fn as_bytes(&self) -> Result<Vec<u8>, JailError> {
         trace!("Value::as_bytes({:?})", self);
-        let mut bytes: Vec<u8> = vec![];
-
-        // Some conversions are identity on 64 bit, but not on 32 bit and vice versa
-        #[cfg_attr(feature = "cargo-clippy", allow(clippy::useless_conversion))]
         match self {
-            Value::String(s) => {
-                bytes = CString::new(s.as_str())
-                    .expect("Could not create CString from value")
-                    .to_bytes_with_nul()
-                    .to_vec();
-                Ok(())
-            }
-            Value::U8(v) => bytes.write_u8(*v),
-            Value::S8(v) => bytes.write_i8(*v),
-            Value::U16(v) => bytes.write_u16::<LittleEndian>(*v),
-            Value::U32(v) => bytes.write_u32::<LittleEndian>(*v),
-            Value::U64(v) => bytes.write_u64::<LittleEndian>(*v),
-            Value::S16(v) => bytes.write_i16::<LittleEndian>(*v),
-            Value::S32(v) => bytes.write_i32::<LittleEndian>(*v),
-            Value::S64(v) => bytes.write_i64::<LittleEndian>(*v),
-            Value::Int(v) => {
-                bytes.write_int::<LittleEndian>((*v).into(), mem::size_of::<libc::c_int>())
-            }
-            Value::Long(v) => {
-                bytes.write_int::<LittleEndian>((*v).into(), mem::size_of::<libc::c_long>())
-            }
-            Value::Uint(v) => {
-                bytes.write_uint::<LittleEndian>((*v).into(), mem::size_of::<libc::c_uint>())
-            }
-            Value::Ulong(v) => {
-                bytes.write_uint::<LittleEndian>((*v).into(), mem::size_of::<libc::c_ulong>())
-            }
-            Value::Ipv4Addrs(addrs) => {
-                for addr in addrs {
-                    let s_addr = nix::sys::socket::Ipv4Addr::from_std(&addr).0.s_addr;
-                    let host_u32 = u32::from_be(s_addr);
-                    bytes
-                        .write_u32::<NetworkEndian>(host_u32)
-                        .map_err(|_| JailError::SerializeFailed)?;
-                }
-                Ok(())
-            }
-            Value::Ipv6Addrs(addrs) => {
-                for addr in addrs {
-                    bytes.extend_from_slice(&addr.octets());
-                }
-                Ok(())
-            }
+            Value::Custom(custom) => codec(&custom.param)
+                .ok_or_else(|| JailError::CodecNotRegistered(custom.param.clone()))?
+                .encode(self),
+            value => encoding::encode(value),
         }
-        .map_err(|_| JailError::SerializeFailed)?;
-
-        Ok(bytes)
     }
 
     /// Attempt to unpack the Vector of IPv4 addresses contained in this value
@@ -400,6 +577,28 @@ impl Value {
         }
     }
 
+    /// Attempt to unpack the raw bytes contained in this parameter Value.
+    ///
+    /// ```
+    /// use jail::param::Value;
+    /// let value = Value::Raw(vec![1, 2, 3]);
+    /// assert_eq!(value.unpack_raw().unwrap(), vec![1, 2, 3]);
+    /// ```
+    ///
+    /// Attempting to unwrap a different value will fail:
+    /// ```should_panic
+    /// use jail::param::Value;
+    /// let not_raw = Value::U8(42);
+    /// not_raw.unpack_raw().unwrap();
+    /// ```
+    pub fn unpack_raw(self) -> Result<Vec<u8>, JailError> {
+        trace!("Value::unpack_raw({:?})", self);
+        match self {
+            Value::Raw(v) => Ok(v),
+            _ => Err(JailError::ParameterUnpackError),
+        }
+    }
+
     /// Attempt to unpack any unsigned integer Value into a 64 bit unsigned
     /// integer.
     ///
@@ -477,9 +676,243 @@ impl Value {
     }
 }
 
+/// The access of a jail to a System V IPC primitive.
+///
+/// Used for the `sysvmsg`, `sysvsem` and `sysvshm` parameters, see [SysVIpc].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum SysVIpcMode {
+    /// The jail cannot use the primitive.
+    Disable,
+
+    /// The jail has its own objects, isolated from the host and other jails.
+    New,
+
+    /// The jail shares the objects of its parent, usually the host.
+    Inherit,
+}
+
+impl From<SysVIpcMode> for Value {
+    fn from(mode: SysVIpcMode) -> Value {
+        // JAIL_SYS_DISABLE, JAIL_SYS_NEW and JAIL_SYS_INHERIT
+        Value::Int(match mode {
+            SysVIpcMode::Disable => 0,
+            SysVIpcMode::New => 1,
+            SysVIpcMode::Inherit => 2,
+        })
+    }
+}
+
+impl TryFrom<Value> for SysVIpcMode {
+    type Error = JailError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(0) => Ok(SysVIpcMode::Disable),
+            Value::Int(1) => Ok(SysVIpcMode::New),
+            Value::Int(2) => Ok(SysVIpcMode::Inherit),
+            _ => Err(JailError::ParameterUnpackError),
+        }
+    }
+}
+
+/// The access of a jail to System V message queues, semaphores and shared
+/// memory.
+///
+/// # Examples
+///
+/// Isolate message queues and semaphores, but share memory with the host:
+///
+/// ```
+/// use jail::param::{SysVIpc, SysVIpcMode};
+///
+/// let ipc = SysVIpc::all(SysVIpcMode::New).shm(SysVIpcMode::Inherit);
+///
+/// assert_eq!(ipc.msg, SysVIpcMode::New);
+/// assert_eq!(ipc.shm, SysVIpcMode::Inherit);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct SysVIpc {
+    /// Access to message queues (`sysvmsg`)
+    pub msg: SysVIpcMode,
+
+    /// Access to semaphores (`sysvsem`)
+    pub sem: SysVIpcMode,
+
+    /// Access to shared memory (`sysvshm`)
+    pub shm: SysVIpcMode,
+}
+
+impl SysVIpc {
+    /// Use the same mode for all primitives.
+    pub fn all(mode: SysVIpcMode) -> Self {
+        SysVIpc {
+            msg: mode,
+            sem: mode,
+            shm: mode,
+        }
+    }
+
+    /// Set the access to message queues.
+    pub fn msg(mut self, mode: SysVIpcMode) -> Self {
+        self.msg = mode;
+        self
+    }
+
+    /// Set the access to semaphores.
+    pub fn sem(mut self, mode: SysVIpcMode) -> Self {
+        self.sem = mode;
+        self
+    }
+
+    /// Set the access to shared memory.
+    pub fn shm(mut self, mode: SysVIpcMode) -> Self {
+        self.shm = mode;
+        self
+    }
+
+    /// Get the jail parameters.
+    ///
+    /// Kernels older than FreeBSD 11 do not support the per-primitive
+    /// parameters, only `allow.sysvipc`, which corresponds to sharing all
+    /// primitives with the host. On those, `allow.sysvipc` is set if any
+    /// primitive is inherited, and isolation is not available.
+    pub(crate) fn params(&self) -> HashMap<String, Value> {
+        trace!("SysVIpc::params({:?})", self);
+        let modes = [
+            ("sysvmsg", self.msg),
+            ("sysvsem", self.sem),
+            ("sysvshm", self.shm),
+        ];
+
+        if exists("sysvmsg") {
+            return modes
+                .iter()
+                .map(|(name, mode)| (name.to_string(), (*mode).into()))
+                .collect();
+        }
+
+        let inherit = modes.iter().any(|(_, mode)| *mode == SysVIpcMode::Inherit);
+        let exact = modes.iter().all(|(_, mode)| *mode == self.msg) && self.msg != SysVIpcMode::New;
+        if !exact {
+            warn!(
+                "{:?} cannot be represented by allow.sysvipc on this kernel, using allow.sysvipc={}",
+                self, inherit as i32
+            );
+        }
+
+        let mut params = HashMap::new();
+        params.insert("allow.sysvipc".into(), Value::Int(inherit as i32));
+        params
+    }
+}
+
+impl From<SysVIpcMode> for SysVIpc {
+    fn from(mode: SysVIpcMode) -> Self {
+        SysVIpc::all(mode)
+    }
+}
+
+/// Check whether the running kernel supports a jail parameter.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn exists(name: &str) -> bool {
+    trace!("exists({:?})", name);
+    Ctl::new(&format!("security.jail.param.{}", name)).is_ok()
+}
+
+/// The type, flags and size of parameters, keyed by name.
+#[cfg(target_os = "freebsd")]
+type InfoCache = RwLock<HashMap<String, (CtlType, CtlFlags, usize)>>;
+
+#[cfg(target_os = "freebsd")]
+fn info_cache() -> &'static InfoCache {
+    static INFO: OnceLock<InfoCache> = OnceLock::new();
+    INFO.get_or_init(Default::default)
+}
+
+#[cfg(target_os = "freebsd")]
+fn jail_max_af_ips_cache() -> &'static RwLock<Option<usize>> {
+    static JAIL_MAX_AF_IPS: OnceLock<RwLock<Option<usize>>> = OnceLock::new();
+    JAIL_MAX_AF_IPS.get_or_init(Default::default)
+}
+
+/// Drop the cached parameter information and `security.jail.jail_max_af_ips`.
+///
+/// The type and size of every parameter, as well as the maximum number of
+/// addresses per family, are read from `sysctl`(3) once and cached for the
+/// lifetime of the process. Invalidate the cache after loading a kernel
+/// module that adds parameters, or after changing
+/// `security.jail.jail_max_af_ips`.
+///
+/// # Examples
+/// ```
+/// use jail::param;
+///
+/// param::invalidate_cache();
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn invalidate_cache() {
+    trace!("invalidate_cache()");
+    info_cache()
+        .write()
+        .expect("parameter info cache poisoned")
+        .clear();
+    *jail_max_af_ips_cache()
+        .write()
+        .expect("jail_max_af_ips cache poisoned") = None;
+}
+
+/// Get `security.jail.jail_max_af_ips`, the maximum number of addresses per
+/// family, which is the length of the `ip4.addr` and `ip6.addr` arrays.
+#[cfg(target_os = "freebsd")]
+fn jail_max_af_ips() -> Result<usize, JailError> {
+    trace!("jail_max_af_ips()");
+    if let Some(max) = *jail_max_af_ips_cache()
+        .read()
+        .expect("jail_max_af_ips cache poisoned")
+    {
+        return Ok(max);
+    }
+
+    let max = match Ctl::new("security.jail.jail_max_af_ips")
+        .map_err(JailError::JailMaxAfIpsFailed)?
+        .value()
+        .map_err(JailError::JailMaxAfIpsFailed)?
+    {
+        CtlValue::Uint(u) => u as usize,
+        _ => panic!("security.jail.jail_max_af_ips has the wrong type."),
+    };
+
+    *jail_max_af_ips_cache()
+        .write()
+        .expect("jail_max_af_ips cache poisoned") = Some(max);
+    Ok(max)
+}
+
+/// Get the type, flags and size of a parameter, using the cache.
 #[cfg(target_os = "freebsd")]
 fn info(name: &str) -> Result<(CtlType, CtlFlags, usize), JailError> {
     trace!("info({:?})", name);
+    if let Some(info) = info_cache()
+        .read()
+        .expect("parameter info cache poisoned")
+        .get(name)
+    {
+        return Ok(*info);
+    }
+
+    let info = read_info(name)?;
+    info_cache()
+        .write()
+        .expect("parameter info cache poisoned")
+        .insert(name.to_string(), info);
+    Ok(info)
+}
+
+#[cfg(target_os = "freebsd")]
+fn read_info(name: &str) -> Result<(CtlType, CtlFlags, usize), JailError> {
+    trace!("read_info({:?})", name);
     // Get parameter type
     let ctlname = format!("security.jail.param.{}", name);
 
@@ -489,7 +922,6 @@ fn info(name: &str) -> Result<(CtlType, CtlFlags, usize), JailError> {
     let paramtype = ctl.value_type().map_err(JailError::ParameterTypeError)?;
 
     let typesize = match paramtype {
-        CtlType::Int => mem::size_of::<libc::c_int>(),
         CtlType::String => {
             let length = match ctl.value().map_err(JailError::ParameterStringLengthError)? {
                 CtlValue::String(l) => l,
@@ -500,36 +932,151 @@ fn info(name: &str) -> Result<(CtlType, CtlFlags, usize), JailError> {
                 .parse::<usize>()
                 .map_err(|_| JailError::ParameterLengthNaN(length.to_string()))?
         }
-
-        CtlType::S64 => mem::size_of::<i64>(),
-        CtlType::Uint => mem::size_of::<libc::c_uint>(),
-        CtlType::Long => mem::size_of::<libc::c_long>(),
-        CtlType::Ulong => mem::size_of::<libc::c_ulong>(),
-        CtlType::U64 => mem::size_of::<u64>(),
-        CtlType::U8 => mem::size_of::<u8>(),
-        CtlType::U16 => mem::size_of::<u16>(),
-        CtlType::S8 => mem::size_of::<i8>(),
-        CtlType::S16 => mem::size_of::<i16>(),
-        CtlType::S32 => mem::size_of::<i32>(),
-        CtlType::U32 => mem::size_of::<u32>(),
         CtlType::Struct => match ctl.value().map_err(JailError::ParameterStructLengthError)? {
-            CtlValue::Struct(data) => {
-                assert!(
-                    data.len() >= mem::size_of::<usize>(),
-                    "Error: struct sysctl returned too few bytes."
-                );
-                LittleEndian::read_uint(&data, mem::size_of::<usize>()) as usize
-            }
+            CtlValue::Struct(data) => encoding::decode_size(&data).ok_or_else(|| {
+                JailError::ParameterDecodeError(format!(
+                    "struct parameter {} reported a size of {} bytes",
+                    name,
+                    data.len()
+                ))
+            })?,
             _ => panic!("param sysctl reported to be struct, but isn't"),
         },
-        _ => return Err(JailError::ParameterTypeUnsupported(paramtype)),
+        numeric => {
+            let host = match numeric {
+                CtlType::Int => mem::size_of::<libc::c_int>(),
+                CtlType::S64 => mem::size_of::<i64>(),
+                CtlType::Uint => mem::size_of::<libc::c_uint>(),
+                CtlType::Long => mem::size_of::<libc::c_long>(),
+                CtlType::Ulong => mem::size_of::<libc::c_ulong>(),
+                CtlType::U64 => mem::size_of::<u64>(),
+                CtlType::U8 => mem::size_of::<u8>(),
+                CtlType::U16 => mem::size_of::<u16>(),
+                CtlType::S8 => mem::size_of::<i8>(),
+                CtlType::S16 => mem::size_of::<i16>(),
+                CtlType::S32 => mem::size_of::<i32>(),
+                CtlType::U32 => mem::size_of::<u32>(),
+                _ => return Err(JailError::ParameterTypeUnsupported(paramtype)),
+            };
+
+            // A 32-bit process on a 64-bit kernel sees a wider long.
+            let kernel = kernel_size(&ctlname)?;
+            if kernel != host {
+                return Err(JailError::ParameterSizeMismatch {
+                    name: name.to_string(),
+                    host,
+                    kernel,
+                });
+            }
+
+            host
+        }
     };
 
     Ok((paramtype, flags, typesize))
 }
 
+/// Get the size of the value of a sysctl, as reported by the kernel.
 #[cfg(target_os = "freebsd")]
-fn ctltype_to_type(name: &str, ctl_type: CtlType) -> Result<Type, JailError> {
+fn kernel_size(ctlname: &str) -> Result<usize, JailError> {
+    let name = CString::new(ctlname).map_err(JailError::CStringError)?;
+    let mut size: libc::size_t = 0;
+    let ret = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            std::ptr::null_mut(),
+            &mut size,
+            std::ptr::null(),
+            0,
+        )
+    };
+
+    match ret {
+        0 => Ok(size),
+        _ => Err(JailError::from_errno()),
+    }
+}
+
+/// The sizes of the C types jail parameters are made of, as seen by this
+/// process and by the kernel. See [host_abi].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HostAbi {
+    /// The size of `int` in bytes
+    pub int: usize,
+
+    /// The size of `long` in bytes
+    pub long: usize,
+
+    /// The size of a pointer in bytes, which includes the capability
+    /// metadata on CheriBSD
+    pub pointer: usize,
+
+    /// The size of `size_t` in bytes
+    pub size_t: usize,
+
+    /// The size of `size_t` in the kernel, as reported for struct
+    /// parameters, or `None` if no struct parameter could be read
+    pub kernel_size_t: Option<usize>,
+
+    /// `hw.machine_arch`, e.g. `armv7` or `aarch64c`
+    pub machine_arch: Option<String>,
+}
+
+impl HostAbi {
+    /// Whether this is a 32-bit process on a 64-bit kernel.
+    pub fn is_compat32(&self) -> bool {
+        trace!("HostAbi::is_compat32({:?})", self);
+        self.kernel_size_t.is_some_and(|size| size > self.size_t)
+    }
+
+    /// Whether pointers are capabilities, as in pure-capability CheriBSD
+    /// processes, so that pointers are wider than `size_t`.
+    pub fn is_purecap(&self) -> bool {
+        trace!("HostAbi::is_purecap({:?})", self);
+        self.pointer > self.size_t
+    }
+}
+
+/// Describe the sizes of the C types jail parameters are encoded with.
+///
+/// The sizes of this process are fixed at compile time, the kernel sizes are
+/// detected once and cached. Parameters whose size in the kernel differs
+/// from the host fail with
+/// [JailError::ParameterSizeMismatch](../enum.JailError.html) instead of
+/// being passed with a wrongly sized buffer.
+///
+/// # Examples
+///
+/// ```
+/// use jail::param;
+///
+/// let abi = param::host_abi();
+/// assert_eq!(abi.int, 4);
+/// if abi.is_compat32() {
+///     println!("32-bit process on a 64-bit kernel");
+/// }
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn host_abi() -> &'static HostAbi {
+    trace!("host_abi()");
+    static HOST_ABI: OnceLock<HostAbi> = OnceLock::new();
+
+    HOST_ABI.get_or_init(|| HostAbi {
+        int: mem::size_of::<libc::c_int>(),
+        long: mem::size_of::<libc::c_long>(),
+        pointer: mem::size_of::<*const libc::c_void>(),
+        size_t: mem::size_of::<libc::size_t>(),
+        kernel_size_t: ["ip4.addr", "ip6.addr"]
+            .iter()
+            .find_map(|name| kernel_size(&format!("security.jail.param.{}", name)).ok()),
+        machine_arch: Ctl::new("hw.machine_arch")
+            .and_then(|ctl| ctl.value_string())
+            .ok(),
+    })
+}
+
+#[cfg(target_os = "freebsd")]
+pub(crate) fn ctltype_to_type(name: &str, ctl_type: CtlType) -> Result<Type, JailError> {
     trace!("ctltype_to_type({:?}, ctl_type={:?})", name, ctl_type);
     let param_type = match ctl_type {
         CtlType::Int => Type::Int,
@@ -548,7 +1095,7 @@ fn ctltype_to_type(name: &str, ctl_type: CtlType) -> Result<Type, JailError> {
         CtlType::Struct => match name {
             "ip4.addr" => Type::Ipv4Addrs,
             "ip6.addr" => Type::Ipv6Addrs,
-            _ => return Err(JailError::ParameterTypeUnsupported(ctl_type)),
+            _ => Type::Raw,
         },
         _ => return Err(JailError::ParameterTypeUnsupported(ctl_type)),
     };
@@ -558,6 +1105,9 @@ fn ctltype_to_type(name: &str, ctl_type: CtlType) -> Result<Type, JailError> {
 
 /// Get a jail parameter given the jid and the parameter name.
 ///
+/// This allocates the value buffer for every call. Use [get_into] to read
+/// parameters repeatedly.
+///
 /// # Examples
 /// ```
 /// use jail::param;
@@ -578,135 +1128,266 @@ fn ctltype_to_type(name: &str, ctl_type: CtlType) -> Result<Type, JailError> {
 #[cfg(target_os = "freebsd")]
 pub fn get(jid: i32, name: &str) -> Result<Value, JailError> {
     trace!("get(jid={}, name={:?})", jid, name);
-    let (paramtype, _, typesize) = info(name)?;
+    get_into(jid, name, &mut Buffer::new())
+}
 
-    // ip4.addr and ip6.addr are arrays, which can be up to
-    // security.jail.jail_max_af_ips long:
-    let jail_max_af_ips = match Ctl::new("security.jail.jail_max_af_ips")
-        .map_err(JailError::JailMaxAfIpsFailed)?
-        .value()
-        .map_err(JailError::JailMaxAfIpsFailed)?
-    {
-        CtlValue::Uint(u) => u as usize,
-        _ => panic!("security.jail.jail_max_af_ips has the wrong type."),
-    };
+/// The type, size and encoded name of a parameter, as cached by [Buffer].
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+struct Layout {
+    name: CString,
+    ty: Type,
+    size: usize,
+}
 
-    let valuesize = match name {
-        "ip4.addr" => typesize * jail_max_af_ips,
-        "ip6.addr" => typesize * jail_max_af_ips,
-        _ => typesize,
-    };
+/// Reusable buffers for reading jail parameters with [get_into].
+///
+/// A buffer holds the value and `errmsg` memory passed to `jail_get`(2), and
+/// caches the type and size of every parameter it has read, so repeated
+/// reads neither allocate nor query `sysctl`(3).
+///
+/// The layouts are taken from the cache of [invalidate_cache] at the time of
+/// the first read. Create a new buffer after invalidating it.
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct Buffer {
+    value: Vec<u8>,
+    errmsg: [u8; 256],
+    layouts: HashMap<String, Layout>,
+}
 
-    let paramname = CString::new(name).expect("Could not convert parameter name to CString");
+#[cfg(target_os = "freebsd")]
+impl Default for Buffer {
+    fn default() -> Self {
+        Buffer {
+            value: Vec::new(),
+            errmsg: [0; 256],
+            layouts: HashMap::new(),
+        }
+    }
+}
 
-    let mut value: Vec<u8> = vec![0; valuesize];
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
+#[cfg(target_os = "freebsd")]
+impl Buffer {
+    /// Create an empty buffer.
+    pub fn new() -> Self {
+        trace!("Buffer::new()");
+        Buffer::default()
+    }
 
-    let mut jiov: Vec<libc::iovec> = vec![
-        iovec!(b"jid\0"),
-        iovec!(&jid as *const _, mem::size_of::<i32>()),
-        iovec!(paramname.as_ptr(), paramname.as_bytes().len() + 1),
-        iovec!(value.as_mut_ptr(), valuesize),
-        iovec!(b"errmsg\0"),
-        iovec!(errmsg.as_mut_ptr(), errmsg.len()),
-    ];
+    /// The raw bytes of the last value read into the buffer, as returned by
+    /// the kernel.
+    ///
+    /// # Examples
+    /// ```
+    /// use jail::param;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_getparam_bytes")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// # let jid = jail.jid;
+    ///
+    /// let mut buf = param::Buffer::new();
+    /// param::get_into(jid, "host.hostuuid", &mut buf)
+    ///     .expect("could not get parameter");
+    ///
+    /// assert!(!buf.bytes().is_empty());
+    /// #
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    pub fn bytes(&self) -> &[u8] {
+        &self.value
+    }
 
-    let jid = unsafe {
-        libc::jail_get(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::empty().bits(),
-        )
-    };
+    /// Look up the layout of a parameter, caching it on first use.
+    fn layout(&mut self, name: &str) -> Result<(), JailError> {
+        if self.layouts.contains_key(name) {
+            return Ok(());
+        }
 
-    let err = unsafe { CStr::from_ptr(errmsg.as_ptr() as *mut libc::c_char) }
-        .to_string_lossy()
-        .to_string();
+        let (paramtype, _, typesize) = info(name)?;
+        let size = match name {
+            "ip4.addr" | "ip6.addr" => typesize * jail_max_af_ips()?,
+            _ => typesize,
+        };
 
-    let value = match jid {
-        e if e < 0 => match errmsg[0] {
-            0 => Err(JailError::from_errno()),
-            _ => Err(JailError::JailGetError(err)),
-        },
-        _ => Ok(value),
-    }?;
-
-    // Wrap in Enum and return
-    match ctltype_to_type(name, paramtype)? {
-        Type::Int => Ok(Value::Int(
-            LittleEndian::read_int(&value, mem::size_of::<libc::c_int>()) as libc::c_int,
-        )),
-        Type::S64 => Ok(Value::S64(LittleEndian::read_i64(&value))),
-        Type::Uint => Ok(Value::Uint(
-            LittleEndian::read_uint(&value, mem::size_of::<libc::c_uint>()) as libc::c_uint,
-        )),
-        Type::Long => Ok(Value::Long(
-            LittleEndian::read_int(&value, mem::size_of::<libc::c_long>()) as libc::c_long,
-        )),
-        Type::Ulong => Ok(Value::Ulong(LittleEndian::read_uint(
-            &value,
-            mem::size_of::<libc::c_ulong>(),
-        ) as libc::c_ulong)),
-        Type::U64 => Ok(Value::U64(LittleEndian::read_u64(&value))),
-        Type::U8 => Ok(Value::U8(value[0])),
-        Type::U16 => Ok(Value::U16(LittleEndian::read_u16(&value))),
-        Type::S8 => Ok(Value::S8(value[0] as i8)),
-        Type::S16 => Ok(Value::S16(LittleEndian::read_i16(&value))),
-        Type::S32 => Ok(Value::S32(LittleEndian::read_i32(&value))),
-        Type::U32 => Ok(Value::U32(LittleEndian::read_u32(&value))),
-        Type::String => Ok(Value::String({
-            unsafe { CStr::from_ptr(value.as_ptr() as *mut libc::c_char) }
-                .to_string_lossy()
-                .into_owned()
-        })),
-        Type::Ipv4Addrs => {
-            // Make sure we got the right data size
-            let addrsize = mem::size_of::<libc::in_addr>();
-            let count = valuesize / addrsize;
-
-            assert_eq!(
-                0,
-                typesize % addrsize,
-                "Error: memory size mismatch. Length of data \
-                 retrieved is not a multiple of the size of in_addr."
-            );
+        let layout = Layout {
+            name: CString::new(name).map_err(JailError::CStringError)?,
+            ty: ctltype_to_type(name, paramtype)?,
+            size,
+        };
+        self.layouts.insert(name.to_string(), layout);
+        Ok(())
+    }
 
-            #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
-            let ips: Vec<net::Ipv4Addr> =
-                unsafe { slice::from_raw_parts(value.as_ptr() as *const libc::in_addr, count) }
-                    .iter()
-                    .map(|in_addr| u32::from_be(in_addr.s_addr))
-                    .map(net::Ipv4Addr::from)
-                    .filter(|ip| !ip.is_unspecified())
-                    .collect();
+    /// Read a parameter into the value buffer, returning its type.
+    fn read(&mut self, jid: i32, name: &str, flags: JailFlags) -> Result<Type, JailError> {
+        self.layout(name)?;
+        let layout = &self.layouts[name];
 
-            Ok(Value::Ipv4Addrs(ips))
-        }
-        Type::Ipv6Addrs => {
-            // Make sure we got the right data size
-            let addrsize = mem::size_of::<libc::in6_addr>();
-            let count = valuesize / addrsize;
-
-            assert_eq!(
-                0,
-                typesize % addrsize,
-                "Error: memory size mismatch. Length of data \
-                 retrieved is not a multiple of the size of in_addr."
-            );
+        self.value.clear();
+        self.value.resize(layout.size, 0);
 
-            #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
-            let ips: Vec<net::Ipv6Addr> =
-                unsafe { slice::from_raw_parts(value.as_ptr() as *const libc::in6_addr, count) }
-                    .iter()
-                    .map(|in6_addr| net::Ipv6Addr::from(in6_addr.s6_addr))
-                    .filter(|ip| !ip.is_unspecified())
-                    .collect();
+        let mut jiov = [
+            iovec!(b"jid\0"),
+            iovec!(&jid as *const _, mem::size_of::<i32>()),
+            iovec!(layout.name.as_bytes_with_nul()),
+            iovec!(mut self.value),
+            // Reserved for errmsg.
+            iovec!(),
+            iovec!(),
+        ];
 
-            Ok(Value::Ipv6Addrs(ips))
-        }
+        unsafe { jail_sys::jail_get_buffered(&mut jiov, &mut self.errmsg, flags) }?;
+
+        Ok(layout.ty)
     }
 }
 
+/// Get a jail parameter given the jid and the parameter name, reusing the
+/// memory of a [Buffer].
+///
+/// Apart from decoding the returned [Value], this does not allocate once the
+/// parameter has been read into the same buffer before. The raw bytes stay
+/// available through [Buffer::bytes] until the next read.
+///
+/// # Examples
+/// ```
+/// use jail::param;
+/// # use jail::StoppedJail;
+/// # let jail = StoppedJail::new("/rescue")
+/// #     .name("testjail_getparam_into")
+/// #     .start()
+/// #     .expect("could not start jail");
+/// # let jid = jail.jid;
+///
+/// let mut buf = param::Buffer::new();
+/// for _ in 0..10 {
+///     let children = param::get_into(jid, "children.cur", &mut buf)
+///         .expect("could not get parameter");
+///     assert_eq!(children, param::Value::Int(0));
+/// }
+/// #
+/// # jail.kill().expect("could not stop jail");
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn get_into(jid: i32, name: &str, buf: &mut Buffer) -> Result<Value, JailError> {
+    trace!("get_into(jid={}, name={:?})", jid, name);
+    decode_into(jid, name, buf, JailFlags::empty())
+}
+
+/// Get a jail parameter like [get], but also from a jail that is dying.
+///
+/// Without `JailFlags::DYING`, `jail_get`(2) fails for a removed jail that
+/// the kernel has not freed yet, e.g. while it still has zombie processes.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn get_dying(jid: i32, name: &str) -> Result<Value, JailError> {
+    trace!("get_dying(jid={}, name={:?})", jid, name);
+    decode_into(jid, name, &mut Buffer::new(), JailFlags::DYING)
+}
+
+#[cfg(target_os = "freebsd")]
+fn decode_into(
+    jid: i32,
+    name: &str,
+    buf: &mut Buffer,
+    flags: JailFlags,
+) -> Result<Value, JailError> {
+    let ty = buf.read(jid, name, flags)?;
+
+    if let Some(codec) = codec(name) {
+        return codec.decode(buf.bytes());
+    }
+
+    match ty {
+        Type::Custom => Err(JailError::CodecNotRegistered(name.into())),
+        ty => encoding::decode(ty, buf.bytes()),
+    }
+}
+
+/// Get the `ip4.addr` and `ip6.addr` parameters of a jail with a single
+/// `jail_get`(2) call, using the cached parameter sizes.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn get_ips(jid: i32) -> Result<Vec<net::IpAddr>, JailError> {
+    trace!("get_ips(jid={})", jid);
+    let jail_max_af_ips = jail_max_af_ips()?;
+    let (_, _, ip4size) = info("ip4.addr")?;
+    let (_, _, ip6size) = info("ip6.addr")?;
+
+    let mut ip4: Vec<u8> = vec![0; ip4size * jail_max_af_ips];
+    let mut ip6: Vec<u8> = vec![0; ip6size * jail_max_af_ips];
+
+    let mut jiov: Vec<libc::iovec> = vec![
+        iovec!(b"jid\0" => (&jid as *const _, mem::size_of::<i32>())),
+        iovec!(b"ip4.addr\0" => mut ip4),
+        iovec!(b"ip6.addr\0" => mut ip6),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    unsafe { jail_sys::jail_get(&mut jiov, JailFlags::empty()) }?;
+
+    let mut ips: Vec<net::IpAddr> = vec![];
+    ips.extend(
+        encoding::decode(Type::Ipv4Addrs, &ip4)?
+            .unpack_ipv4()?
+            .into_iter()
+            .map(net::IpAddr::V4),
+    );
+    ips.extend(
+        encoding::decode(Type::Ipv6Addrs, &ip6)?
+            .unpack_ipv6()?
+            .into_iter()
+            .map(net::IpAddr::V6),
+    );
+    Ok(ips)
+}
+
+/// Get several parameters of the first jail with a `jid` greater than
+/// `lastjid` with a single `jail_get`(2) call.
+///
+/// Returns the `jid` of that jail and the values in the order of `names`.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn get_next(lastjid: i32, names: &[&str]) -> Result<(i32, Vec<Value>), JailError> {
+    trace!("get_next(lastjid={}, names={:?})", lastjid, names);
+    let mut layouts: Vec<(CString, Type, Vec<u8>)> = Vec::with_capacity(names.len());
+    for name in names {
+        let (paramtype, _, typesize) = info(name)?;
+        let size = match *name {
+            "ip4.addr" | "ip6.addr" => typesize * jail_max_af_ips()?,
+            _ => typesize,
+        };
+
+        layouts.push((
+            CString::new(*name).map_err(JailError::CStringError)?,
+            ctltype_to_type(name, paramtype)?,
+            vec![0; size],
+        ));
+    }
+
+    let mut jiov: Vec<libc::iovec> =
+        iovec!(b"lastjid\0" => (&lastjid as *const _, mem::size_of::<i32>()));
+    for (name, _, value) in layouts.iter_mut() {
+        jiov.extend(iovec!(name.as_bytes_with_nul() => mut value));
+    }
+
+    let jid = unsafe { jail_sys::jail_get(&mut jiov, JailFlags::empty()) }?;
+
+    let values = names
+        .iter()
+        .zip(layouts)
+        .map(|(name, (_, ty, value))| match (codec(name), ty) {
+            (Some(codec), _) => codec.decode(&value),
+            (None, Type::Custom) => Err(JailError::CodecNotRegistered(name.to_string())),
+            (None, ty) => encoding::decode(ty, &value),
+        })
+        .collect::<Result<Vec<Value>, JailError>>()?;
+
+    Ok((jid, values))
+}
+
 /// Set a jail parameter given the jid, the parameter name and the value.
 ///
 /// # Examples
@@ -762,41 +1443,25 @@ pub fn set(jid: i32, name: &str, value: Value) -> Result<(), JailError> {
 
     let paramname = CString::new(name).expect("Could not convert parameter name to CString");
 
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
-
-    let paramtype: Type = (&value).into();
-    assert_eq!(ctltype, paramtype.into());
-
-    let mut bytes = value.as_bytes()?;
-
-    let mut jiov: Vec<libc::iovec> = vec![
-        iovec!(b"jid\0"),
-        iovec!(&jid as *const _, mem::size_of::<i32>()),
-        iovec!(paramname.as_ptr(), paramname.as_bytes().len() + 1),
-        iovec!(bytes.as_mut_ptr(), bytes.len()),
-        iovec!(b"errmsg\0"),
-        iovec!(errmsg.as_mut_ptr(), errmsg.len()),
-    ];
-
-    let jid = unsafe {
-        libc::jail_set(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::UPDATE.bits(),
-        )
+    let mut bytes = match codec(name) {
+        Some(codec) => codec.encode(&value)?,
+        None => {
+            let paramtype: Type = (&value).into();
+            assert_eq!(ctltype, paramtype.into());
+            value.as_bytes()?
+        }
     };
 
-    let err = unsafe { CStr::from_ptr(errmsg.as_ptr() as *mut libc::c_char) }
-        .to_string_lossy()
-        .to_string();
+    let mut jiov: Vec<libc::iovec> = vec![
+        iovec!(b"jid\0" => (&jid as *const _, mem::size_of::<i32>())),
+        iovec!(paramname.as_bytes_with_nul() => mut bytes),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
 
-    match jid {
-        e if e < 0 => match errmsg[0] {
-            0 => Err(JailError::from_errno()),
-            _ => Err(JailError::JailSetError(err)),
-        },
-        _ => Ok(()),
-    }
+    unsafe { jail_sys::jail_set(&mut jiov, JailFlags::UPDATE) }?;
+    Ok(())
 }
 
 /// Set a jail parameter given the jid, the parameter name and the value.
@@ -820,7 +1485,44 @@ pub fn set(jid: i32, name: &str, value: Value) -> Result<(), JailError> {
 /// ```
 pub fn get_all(jid: i32) -> Result<HashMap<String, Value>, JailError> {
     trace!("get_all(jid={})", jid);
+    let mut buf = Buffer::new();
+    let params: Result<Vec<(String, Value)>, JailError> = names()?
+        .into_iter()
+        .map(|name| get_into(jid, &name, &mut buf).map(|v| (name, v)))
+        .collect();
+
+    Ok(HashMap::from_iter(params?))
+}
 
+/// Get all parameters of a jail like [get_all], skipping parameters that
+/// cannot be read.
+///
+/// The names of the parameters that could not be read are added to `failed`
+/// with the error for each.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn get_all_lossy(
+    jid: i32,
+    failed: &mut Vec<(String, JailError)>,
+) -> Result<HashMap<String, Value>, JailError> {
+    trace!("get_all_lossy(jid={})", jid);
+    let mut buf = Buffer::new();
+    let mut params = HashMap::new();
+
+    for name in names()? {
+        match get_into(jid, &name, &mut buf) {
+            Ok(value) => {
+                params.insert(name, value);
+            }
+            Err(e) => failed.push((name, e)),
+        }
+    }
+
+    Ok(params)
+}
+
+/// The names of the parameters read by [get_all].
+#[cfg(target_os = "freebsd")]
+fn names() -> Result<Vec<String>, JailError> {
     // If we have individual filters on each of these, we'll end up with a
     // very large type_length_limit. We can quickly check names against a vec
     // to avoid that.
@@ -839,7 +1541,7 @@ pub fn get_all(jid: i32) -> Result<HashMap<String, Value>, JailError> {
         "ip6.addr",
     ];
 
-    let params: Result<Vec<(String, Value)>, JailError> = Ctl::new("security.jail.param")
+    Ok(Ctl::new("security.jail.param")
         .map_err(JailError::SysctlError)?
         .into_iter()
         .filter_map(Result::ok)
@@ -855,9 +1557,249 @@ pub fn get_all(jid: i32) -> Result<HashMap<String, Value>, JailError> {
             // Filter out any names in the filtered_names vec.
             && !filtered_names.contains(&name.as_str())
         })
-        // get parameters
-        .map(|name| get(jid, &name).map(|v| (name, v)))
-        .collect();
+        .collect())
+}
 
-    Ok(HashMap::from_iter(params?))
+/// Whether and when a jail parameter can be set.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum Access {
+    /// The parameter is maintained by the kernel, e.g. `dying`.
+    ReadOnly,
+
+    /// The parameter can only be set when the jail is created, e.g.
+    /// `osrelease`. See [JailError::ParameterTunableError].
+    CreateOnly,
+
+    /// The parameter can also be changed on a running jail.
+    ReadWrite,
+}
+
+/// The description of a jail parameter supported by the running kernel.
+///
+/// See [schema].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Schema {
+    /// The name of the parameter, e.g. `allow.raw_sockets`
+    pub name: String,
+
+    /// The type of the parameter
+    #[cfg_attr(
+        feature = "serialize",
+        serde(rename = "type", serialize_with = "serialize_type")
+    )]
+    pub type_: Type,
+
+    /// Whether and when the parameter can be set
+    pub access: Access,
+
+    /// The size of the parameter in bytes, i.e. the maximum length including
+    /// the terminating NUL byte for strings
+    pub size: usize,
+
+    /// The description of the parameter provided by the kernel
+    pub description: String,
+
+    /// The typed accessor of this library for the parameter, if any, e.g.
+    /// `StoppedJail::hostname` or `allow::Allow::RawSockets`
+    pub accessor: Option<String>,
+}
+
+#[cfg(feature = "serialize")]
+fn serialize_type<S: serde::Serializer>(type_: &Type, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:?}", type_).to_lowercase())
+}
+
+/// Parameters with typed accessors on [StoppedJail](../struct.StoppedJail.html).
+///
+/// `allow.*` parameters are mapped through [Allow](../allow/enum.Allow.html).
+#[cfg(target_os = "freebsd")]
+const ACCESSORS: &[(&str, &str)] = &[
+    ("name", "StoppedJail::name"),
+    ("path", "StoppedJail::new"),
+    ("host.hostname", "StoppedJail::hostname"),
+    ("ip4.addr", "StoppedJail::ip"),
+    ("ip6.addr", "StoppedJail::ip"),
+    ("osrelease", "StoppedJail::osrelease"),
+    ("osreldate", "StoppedJail::osreldate"),
+    ("sysvmsg", "StoppedJail::sysvipc"),
+    ("sysvsem", "StoppedJail::sysvipc"),
+    ("sysvshm", "StoppedJail::sysvipc"),
+];
+
+/// Get the typed accessor of this library for the parameter `name`.
+#[cfg(target_os = "freebsd")]
+fn accessor(name: &str) -> Option<String> {
+    if let Some((_, accessor)) = ACCESSORS.iter().find(|(param, _)| *param == name) {
+        return Some(accessor.to_string());
+    }
+
+    name.parse::<crate::allow::Allow>()
+        .ok()
+        .map(|allow| format!("allow::Allow::{:?}", allow))
+}
+
+/// Describe all jail parameters supported by the running kernel, sorted by
+/// name.
+///
+/// This allows front ends to generate forms and completions for parameters.
+/// Parameters of types not supported by this library are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use jail::param::{self, Access, Type};
+///
+/// let schema = param::schema().expect("could not get parameter schema");
+/// let hostname = schema
+///     .iter()
+///     .find(|param| param.name == "host.hostname")
+///     .unwrap();
+///
+/// assert_eq!(hostname.type_, Type::String);
+/// assert_eq!(hostname.access, Access::ReadWrite);
+/// assert_eq!(hostname.accessor, Some("StoppedJail::hostname".into()));
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn schema() -> Result<Vec<Schema>, JailError> {
+    trace!("schema()");
+    let prefix = "security.jail.param.";
+
+    let mut schema = vec![];
+    for ctl in Ctl::new("security.jail.param")
+        .map_err(JailError::SysctlError)?
+        .into_iter()
+        .filter_map(Result::ok)
+    {
+        let name = match ctl.name() {
+            Ok(name) if name.starts_with(prefix) && !name.ends_with('.') => {
+                name[prefix.len()..].to_string()
+            }
+            _ => continue,
+        };
+
+        let (ctl_type, flags, size) = match info(&name) {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("skipping parameter {}: {}", name, e);
+                continue;
+            }
+        };
+
+        let access = if flags.contains(CtlFlags::TUN) {
+            Access::CreateOnly
+        } else if flags.contains(CtlFlags::WR) {
+            Access::ReadWrite
+        } else {
+            Access::ReadOnly
+        };
+
+        schema.push(Schema {
+            type_: match codec(&name) {
+                Some(_) => Type::Custom,
+                None => ctltype_to_type(&name, ctl_type)?,
+            },
+            access,
+            size,
+            description: ctl.description().unwrap_or_default(),
+            accessor: accessor(&name),
+            name,
+        });
+    }
+
+    schema.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(schema)
+}
+
+/// Describe all jail parameters supported by the running kernel as JSON.
+///
+/// See [schema].
+///
+/// # Examples
+///
+/// ```
+/// use jail::param;
+///
+/// let json = param::schema_json().expect("could not get parameter schema");
+/// assert!(json.contains(r#""name": "host.hostname""#));
+/// ```
+#[cfg(all(target_os = "freebsd", feature = "serialize"))]
+pub fn schema_json() -> Result<String, JailError> {
+    trace!("schema_json()");
+    serde_json::to_string_pretty(&schema()?).map_err(|_| JailError::SerializeFailed)
+}
+
+/// Check whether the kernel supports the `meta` and `env` parameters, which
+/// hold arbitrary `key=value` metadata (FreeBSD 15 and later).
+///
+/// The `meta` parameter is only visible on the host, while `env` can also be
+/// read from inside the jail.
+///
+/// # Examples
+///
+/// ```
+/// use jail::param;
+///
+/// if !param::meta_supported() {
+///     println!("jail metadata is kept by this process only");
+/// }
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn meta_supported() -> bool {
+    trace!("meta_supported()");
+    Type::of_param("meta").is_ok()
+}
+
+/// Encode metadata as the value of the `meta` or `env` parameters, one
+/// `key=value` pair per line.
+///
+/// Keys must not be empty or contain `=`, and neither keys nor values may
+/// contain line breaks.
+///
+/// # Examples
+///
+/// ```
+/// use jail::param;
+/// use std::collections::BTreeMap;
+///
+/// let mut meta = BTreeMap::new();
+/// meta.insert("owner".to_string(), "alice".to_string());
+/// meta.insert("tier".to_string(), "web".to_string());
+///
+/// let encoded = param::encode_meta("meta", &meta).unwrap();
+/// assert_eq!(encoded, "owner=alice\ntier=web");
+/// assert_eq!(param::decode_meta(&encoded), meta);
+///
+/// meta.insert("bad=key".to_string(), "".to_string());
+/// assert!(param::encode_meta("meta", &meta).is_err());
+/// ```
+pub fn encode_meta(name: &str, meta: &BTreeMap<String, String>) -> Result<String, JailError> {
+    trace!("encode_meta(name={:?}, meta={:?})", name, meta);
+    meta.iter()
+        .map(|(key, value)| {
+            let line = format!("{}={}", key, value);
+            match key.is_empty() || key.contains('=') || line.contains('\n') {
+                true => Err(JailError::ParameterParseError {
+                    name: name.into(),
+                    value: line,
+                }),
+                false => Ok(line),
+            }
+        })
+        .collect::<Result<Vec<String>, JailError>>()
+        .map(|lines| lines.join("\n"))
+}
+
+/// Decode the value of the `meta` or `env` parameters into key-value pairs.
+///
+/// Lines without `=` are ignored. See [encode_meta].
+pub fn decode_meta(value: &str) -> BTreeMap<String, String> {
+    trace!("decode_meta(value={:?})", value);
+    value
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
 }
@@ -0,0 +1,119 @@
+//! Minimal readers for the `passwd(5)` and `group(5)` databases of a jail
+use crate::fs::host_path;
+use crate::JailError;
+use log::trace;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An entry in `/etc/passwd` or `/etc/group`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub name: String,
+    pub id: u32,
+}
+
+/// The login details of a user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Account {
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+/// Look up the user `name` in `/etc/passwd` below `root`.
+pub(crate) fn account(root: &Path, name: &str) -> Result<Option<Account>, JailError> {
+    trace!("passwd::account(root={:?}, name={:?})", root, name);
+    let contents = fs::read_to_string(host_path(root, Path::new("/etc/passwd"))?)
+        .map_err(JailError::IoError)?;
+    Ok(parse_account(&contents, name))
+}
+
+/// Find the account of `name`, skipping malformed lines.
+fn parse_account(contents: &str, name: &str) -> Option<Account> {
+    contents.lines().find_map(|line| {
+        let fields: Vec<&str> = line.trim().split(':').collect();
+        match fields[..] {
+            [user, _, uid, gid, _, home, _] if user == name => Some(Account {
+                uid: uid.parse().ok()?,
+                gid: gid.parse().ok()?,
+                home: PathBuf::from(home),
+            }),
+            _ => None,
+        }
+    })
+}
+
+/// Read the users from `/etc/passwd` below `root`.
+pub(crate) fn users(root: &Path) -> Result<Vec<Entry>, JailError> {
+    trace!("passwd::users(root={:?})", root);
+    read(root, "/etc/passwd")
+}
+
+/// Read the groups from `/etc/group` below `root`.
+pub(crate) fn groups(root: &Path) -> Result<Vec<Entry>, JailError> {
+    trace!("passwd::groups(root={:?})", root);
+    read(root, "/etc/group")
+}
+
+fn read(root: &Path, database: &str) -> Result<Vec<Entry>, JailError> {
+    let contents =
+        fs::read_to_string(host_path(root, Path::new(database))?).map_err(JailError::IoError)?;
+    Ok(parse(&contents))
+}
+
+/// Parse the name and numeric id of every entry, skipping comments and
+/// malformed lines. Both databases keep the id in the third field.
+fn parse(contents: &str) -> Vec<Entry> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some(Entry {
+                name: name.to_string(),
+                id,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_passwd() {
+        let contents = "# $FreeBSD$\n\
+                        root:*:0:0:Charlie &:/root:/bin/csh\n\
+                        \n\
+                        broken\n\
+                        www:*:80:80:World Wide Web Owner:/nonexistent:/usr/sbin/nologin\n";
+
+        assert_eq!(
+            parse(contents),
+            vec![
+                Entry {
+                    name: "root".into(),
+                    id: 0
+                },
+                Entry {
+                    name: "www".into(),
+                    id: 80
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_account(contents, "www"),
+            Some(Account {
+                uid: 80,
+                gid: 80,
+                home: PathBuf::from("/nonexistent")
+            })
+        );
+        assert_eq!(parse_account(contents, "broken"), None);
+    }
+}
@@ -0,0 +1,377 @@
+//! Pidfiles of processes running in jails
+//!
+//! A [Pidfile] records the pid of a process in a jail, either below the
+//! root of the jail, where the services of the jail expect it, or in a
+//! per-jail directory on the host, out of reach of the jail.
+//!
+//! Since a process may exit and its pid be reused, possibly by a process of
+//! another jail, a recorded pid is only taken to be running if the process
+//! with that pid runs in the jail of the pidfile, see [Pidfile::check].
+//! Pidfiles are locked with `flock`(2) while held by a [PidfileLock], like
+//! `pidfile`(3) does.
+#[cfg(target_os = "freebsd")]
+use crate::{fs, sys, JailError, RunningJail};
+#[cfg(target_os = "freebsd")]
+use log::trace;
+#[cfg(target_os = "freebsd")]
+use std::ffi::CString;
+#[cfg(target_os = "freebsd")]
+use std::fs::File;
+#[cfg(target_os = "freebsd")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(target_os = "freebsd")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "freebsd")]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(target_os = "freebsd")]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+#[cfg(target_os = "freebsd")]
+use std::path::{Component, Path, PathBuf};
+
+/// What a pidfile records, see [Pidfile::check].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PidState {
+    /// There is no pidfile
+    Missing,
+
+    /// The pidfile does not hold a pid
+    Invalid,
+
+    /// The recorded process runs in the jail
+    Running(libc::pid_t),
+
+    /// The recorded process exited, or the pid now belongs to a process
+    /// outside of the jail
+    Stale(libc::pid_t),
+}
+
+/// Parse the contents of a pidfile: a positive decimal pid, optionally
+/// followed by a newline.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+fn parse(contents: &str) -> Option<libc::pid_t> {
+    contents
+        .trim_end_matches('\n')
+        .parse()
+        .ok()
+        .filter(|pid| *pid > 0)
+}
+
+/// Open the directory holding `path` below `root`, one component at a time
+/// without following symbolic links, and get the name of `path` in it.
+#[cfg(target_os = "freebsd")]
+fn parent_beneath(root: &Path, path: &Path) -> io::Result<(File, CString)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} does not name a file below {:?}", path, root),
+        )
+    };
+
+    let mut names = path
+        .strip_prefix(root)
+        .map_err(|_| invalid())?
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => Ok(CString::new(name.as_bytes())?),
+            _ => Err(invalid()),
+        })
+        .collect::<io::Result<Vec<CString>>>()?;
+    let name = names.pop().ok_or_else(invalid)?;
+
+    let mut dir = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_CLOEXEC)
+        .open(root)?;
+    for component in names {
+        let fd = unsafe {
+            libc::openat(
+                dir.as_raw_fd(),
+                component.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        dir = unsafe { File::from_raw_fd(fd) };
+    }
+
+    Ok((dir, name))
+}
+
+/// A pidfile of a process running in a jail.
+///
+/// # Examples
+///
+/// ```
+/// use jail::pidfile::{PidState, Pidfile};
+/// use jail::process::Jailed;
+/// use jail::StoppedJail;
+/// use std::process::Command;
+///
+/// let running = StoppedJail::new("/rescue")
+///     .name("testjail_pidfile")
+///     .start()
+///     .expect("could not start jail");
+/// let run_dir = std::env::temp_dir().join("testjail_pidfile");
+///
+/// let pidfile = Pidfile::in_run_dir(&running, &run_dir, "sleep").expect("no pidfile");
+/// let mut lock = pidfile.lock().expect("could not lock pidfile");
+///
+/// let mut child = Command::new("/sleep")
+///     .arg("10")
+///     .jail(&running)
+///     .spawn()
+///     .expect("could not spawn process");
+/// lock.write(child.id() as i32).expect("could not write pidfile");
+/// assert_eq!(pidfile.check().unwrap(), PidState::Running(child.id() as i32));
+///
+/// child.kill().unwrap();
+/// child.wait().unwrap();
+/// assert_eq!(pidfile.check().unwrap(), PidState::Stale(child.id() as i32));
+///
+/// lock.remove().expect("could not remove pidfile");
+/// assert_eq!(pidfile.check().unwrap(), PidState::Missing);
+/// # running.kill().expect("could not stop jail");
+/// # std::fs::remove_dir_all(run_dir).unwrap();
+/// ```
+#[cfg(target_os = "freebsd")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pidfile {
+    /// The path of the pidfile on the host
+    path: PathBuf,
+
+    /// The root of the jail, if the pidfile is below it and must be opened
+    /// relative to it
+    root: Option<PathBuf>,
+
+    /// The `jid` of the jail the recorded process runs in
+    jid: i32,
+}
+
+#[cfg(target_os = "freebsd")]
+impl Pidfile {
+    /// Get the pidfile at `path` inside `jail`, e.g. `/var/run/sshd.pid`.
+    ///
+    /// `path` is resolved below the root of the jail, see [fs::host_path].
+    /// Since the jail may replace any directory on the way by a symbolic
+    /// link later on, the pidfile is always opened relative to the root of
+    /// the jail, one component at a time, and fails with `ELOOP` or
+    /// `ENOTDIR` instead of following a link.
+    pub fn in_jail(jail: &RunningJail, path: &Path) -> Result<Self, JailError> {
+        trace!("Pidfile::in_jail(jail={:?}, path={:?})", jail, path);
+        let root = jail.path()?;
+        Ok(Pidfile {
+            path: fs::host_path(&root, path)?,
+            root: Some(root),
+            jid: jail.jid,
+        })
+    }
+
+    /// Get the pidfile `<run_dir>/<jail name>/<name>.pid` on the host,
+    /// creating the directory of the jail if needed.
+    pub fn in_run_dir(jail: &RunningJail, run_dir: &Path, name: &str) -> Result<Self, JailError> {
+        trace!(
+            "Pidfile::in_run_dir(jail={:?}, run_dir={:?}, name={:?})",
+            jail,
+            run_dir,
+            name
+        );
+        let dir = run_dir.join(jail.name()?);
+        std::fs::create_dir_all(&dir).map_err(JailError::IoError)?;
+        Ok(Pidfile {
+            path: dir.join(format!("{}.pid", name)),
+            root: None,
+            jid: jail.jid,
+        })
+    }
+
+    /// Get the path of the pidfile on the host.
+    pub fn path(&self) -> &Path {
+        trace!("Pidfile::path({:?})", self);
+        &self.path
+    }
+
+    /// Open the pidfile with `flags`, without following symbolic links.
+    fn open(&self, flags: libc::c_int) -> io::Result<File> {
+        let flags = flags | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = match self.root {
+            Some(ref root) => {
+                let (dir, name) = parent_beneath(root, &self.path)?;
+                unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags, 0o600) }
+            }
+            None => {
+                let path = CString::new(self.path.as_os_str().as_bytes())?;
+                unsafe { libc::open(path.as_ptr(), flags, 0o600) }
+            }
+        };
+
+        match fd {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(unsafe { File::from_raw_fd(fd) }),
+        }
+    }
+
+    /// Read the contents of the pidfile, if there is one.
+    fn contents(&self) -> Result<Option<String>, JailError> {
+        let mut contents = String::new();
+        match self
+            .open(libc::O_RDONLY)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+        {
+            Ok(_) => Ok(Some(contents)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(JailError::IoError(e)),
+        }
+    }
+
+    /// Read the recorded pid, if there is a pidfile holding one.
+    pub fn read(&self) -> Result<Option<libc::pid_t>, JailError> {
+        trace!("Pidfile::read({:?})", self);
+        Ok(self.contents()?.as_deref().and_then(parse))
+    }
+
+    /// Check whether the recorded process still runs in the jail.
+    pub fn check(&self) -> Result<PidState, JailError> {
+        trace!("Pidfile::check({:?})", self);
+        let contents = match self.contents()? {
+            Some(contents) => contents,
+            None => return Ok(PidState::Missing),
+        };
+
+        let pid = match parse(&contents) {
+            Some(pid) => pid,
+            None => return Ok(PidState::Invalid),
+        };
+
+        match sys::process_jid(pid) {
+            Ok(jid) if jid == self.jid => Ok(PidState::Running(pid)),
+            Ok(_) => Ok(PidState::Stale(pid)),
+            Err(JailError::IoError(ref e)) if e.raw_os_error() == Some(libc::ESRCH) => {
+                Ok(PidState::Stale(pid))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Remove the pidfile if it is stale or invalid, returning whether it
+    /// was removed.
+    ///
+    /// A pidfile locked by a [PidfileLock] is never removed.
+    pub fn clean_stale(&self) -> Result<bool, JailError> {
+        trace!("Pidfile::clean_stale({:?})", self);
+        match self.check()? {
+            PidState::Stale(_) | PidState::Invalid => {}
+            PidState::Missing | PidState::Running(_) => return Ok(false),
+        }
+
+        match self.lock() {
+            Ok(lock) => lock.remove().map(|_| true),
+            Err(JailError::PidfileInUse { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create and lock the pidfile.
+    ///
+    /// Fails with [JailError::PidfileInUse] if another [PidfileLock], or
+    /// `pidfile`(3) in another process, holds the lock, or if the recorded
+    /// process still runs in the jail.
+    pub fn lock(&self) -> Result<PidfileLock, JailError> {
+        trace!("Pidfile::lock({:?})", self);
+        let in_use = |pid| JailError::PidfileInUse {
+            path: self.path.clone(),
+            pid,
+        };
+
+        let file = self
+            .open(libc::O_RDWR | libc::O_CREAT)
+            .map_err(JailError::IoError)?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EWOULDBLOCK) => Err(in_use(self.read().ok().flatten())),
+                _ => Err(JailError::IoError(error)),
+            };
+        }
+
+        if let PidState::Running(pid) = self.check()? {
+            return Err(in_use(Some(pid)));
+        }
+
+        file.set_len(0).map_err(JailError::IoError)?;
+        Ok(PidfileLock {
+            pidfile: self.clone(),
+            file,
+        })
+    }
+}
+
+/// A locked pidfile, see [Pidfile::lock].
+///
+/// The lock is released when this is dropped, leaving the pidfile in place,
+/// so that it can be checked with [Pidfile::check] later.
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct PidfileLock {
+    pidfile: Pidfile,
+    file: File,
+}
+
+#[cfg(target_os = "freebsd")]
+impl PidfileLock {
+    /// Get the locked pidfile.
+    pub fn pidfile(&self) -> &Pidfile {
+        trace!("PidfileLock::pidfile({:?})", self);
+        &self.pidfile
+    }
+
+    /// Record `pid`, replacing the previous contents.
+    pub fn write(&mut self, pid: libc::pid_t) -> Result<(), JailError> {
+        trace!("PidfileLock::write({:?}, pid={})", self, pid);
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| writeln!(self.file, "{}", pid))
+            .and_then(|_| self.file.sync_data())
+            .map_err(JailError::IoError)
+    }
+
+    /// Remove the pidfile and release the lock.
+    pub fn remove(self) -> Result<(), JailError> {
+        trace!("PidfileLock::remove({:?})", self);
+        let removed = match self.pidfile.root {
+            Some(ref root) => {
+                parent_beneath(root, &self.pidfile.path).and_then(|(dir, name)| {
+                    match unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), 0) } {
+                        0 => Ok(()),
+                        _ => Err(io::Error::last_os_error()),
+                    }
+                })
+            }
+            None => std::fs::remove_file(&self.pidfile.path),
+        };
+
+        match removed {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(JailError::IoError(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pidfile() {
+        assert_eq!(parse("1234\n"), Some(1234));
+        assert_eq!(parse("1234"), Some(1234));
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("0\n"), None);
+        assert_eq!(parse("-1\n"), None);
+        assert_eq!(parse("12 34\n"), None);
+        assert_eq!(parse("sshd\n"), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Human-readable summaries of jails
+//!
+//! Used by
+//! [StoppedJail::to_pretty_string](../struct.StoppedJail.html#method.to_pretty_string)
+//! and [RunningJail::describe](../struct.RunningJail.html#method.describe),
+//! which print one labelled row per field, like `jls -v`, and resource
+//! limits with humanized amounts, like `rctl -h`.
+use crate::param::Value;
+#[cfg(target_os = "freebsd")]
+use crate::StoppedJail;
+
+/// Format a number of bytes with a binary unit suffix, like
+/// `humanize_number`(3), e.g. `1536` becomes `1.5K`.
+pub(crate) fn bytes(amount: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T", "P", "E"];
+
+    if amount < 1024 {
+        return amount.to_string();
+    }
+
+    let mut scaled = amount as f64;
+    let mut unit = "";
+    for u in UNITS {
+        if scaled < 1024.0 {
+            break;
+        }
+        scaled /= 1024.0;
+        unit = u;
+    }
+
+    if scaled.fract() == 0.0 {
+        format!("{}{}", scaled, unit)
+    } else {
+        format!("{:.1}{}", scaled, unit)
+    }
+}
+
+/// Format a limit as an `rctl`(8) rule without subject, with byte amounts
+/// humanized like `rctl -h`.
+#[cfg(feature = "rctl")]
+pub(crate) fn limit(
+    resource: &rctl::Resource,
+    limit: &rctl::Limit,
+    action: &rctl::Action,
+) -> String {
+    use rctl::Resource::*;
+
+    let (amount, per) = match crate::racct::amount_per(limit) {
+        Ok(amount_per) => amount_per,
+        Err(_) => return format!("{}:{}={}", resource, action, limit),
+    };
+    let amount = match resource {
+        DataSize | StackSize | CoreDumpSize | MemoryUse | MemoryLocked | VMemoryUse | SwapUse
+        | MsgqSize | ShmSize | ReadBps | WriteBps => bytes(amount as u64),
+        _ => amount.to_string(),
+    };
+
+    match per {
+        Some(ref per) => format!("{}:{}={}/{}", resource, action, amount, per),
+        None => format!("{}:{}={}", resource, action, amount),
+    }
+}
+
+/// Format a parameter value the way `jls`(8) prints it.
+pub(crate) fn value(value: &Value) -> String {
+    fn join<T: ToString>(items: &[T]) -> String {
+        items
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    match value {
+        Value::Int(v) => v.to_string(),
+        Value::String(v) => v.clone(),
+        Value::S64(v) => v.to_string(),
+        Value::Uint(v) => v.to_string(),
+        Value::Long(v) => v.to_string(),
+        Value::Ulong(v) => v.to_string(),
+        Value::U64(v) => v.to_string(),
+        Value::U8(v) => v.to_string(),
+        Value::U16(v) => v.to_string(),
+        Value::S8(v) => v.to_string(),
+        Value::S16(v) => v.to_string(),
+        Value::S32(v) => v.to_string(),
+        Value::U32(v) => v.to_string(),
+        Value::Ipv4Addrs(addrs) => join(addrs),
+        Value::Ipv6Addrs(addrs) => join(addrs),
+        Value::Raw(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        Value::Custom(custom) => {
+            let fields: Vec<String> = custom
+                .fields
+                .iter()
+                .map(|(name, v)| format!("{}={}", name, self::value(v)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+/// Rows of labelled values, rendered with the values aligned.
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    rows: Vec<(&'static str, Vec<String>)>,
+}
+
+impl Table {
+    /// Add a row, spanning one line per value. Rows without values are
+    /// left out.
+    pub(crate) fn row<I, S>(&mut self, label: &'static str, values: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        let values: Vec<String> = values.into_iter().map(|v| v.to_string()).collect();
+        if !values.is_empty() {
+            self.rows.push((label, values));
+        }
+    }
+
+    /// Add the fields of a stopped jail.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn jail(&mut self, stopped: &StoppedJail) {
+        self.row("name", &stopped.name);
+        self.row(
+            "path",
+            stopped.path.as_ref().map(|path| path.display().to_string()),
+        );
+        self.row("hostname", &stopped.hostname);
+        self.row("ips", &stopped.ips);
+
+        let mut params: Vec<_> = stopped.params.iter().collect();
+        params.sort_by_key(|(name, _)| *name);
+        self.row(
+            "params",
+            params
+                .into_iter()
+                .map(|(name, v)| format!("{} = {}", name, value(v))),
+        );
+
+        #[cfg(feature = "rctl")]
+        self.row(
+            "limits",
+            stopped
+                .limits
+                .iter()
+                .map(|(resource, l, action)| limit(resource, l, action))
+                .chain(
+                    stopped
+                        .scoped_limits
+                        .iter()
+                        .map(|(scope, (resource, l, action))| {
+                            format!("{}:{}", scope, limit(resource, l, action))
+                        }),
+                ),
+        );
+
+        self.row("disk limit", stopped.disk_limit.map(bytes));
+        self.row(
+            "scheduling",
+            stopped
+                .scheduling
+                .as_ref()
+                .map(|scheduling| format!("{:?}", scheduling)),
+        );
+        self.row(
+            "mounts",
+            stopped.mounts.iter().map(|mount| {
+                let flags = [
+                    (mount.readonly, ", read-only"),
+                    (mount.nosuid, ", nosuid"),
+                    (mount.noexec, ", noexec"),
+                ];
+                format!(
+                    "{} on {} ({}{})",
+                    mount.source.display(),
+                    mount.target.display(),
+                    mount.fstype,
+                    flags
+                        .iter()
+                        .filter(|(set, _)| *set)
+                        .map(|(_, name)| *name)
+                        .collect::<String>()
+                )
+            }),
+        );
+        self.row("provision", &stopped.provision);
+        self.row(
+            "meta",
+            stopped.meta.iter().map(|(k, v)| format!("{} = {}", k, v)),
+        );
+        self.row(
+            "env",
+            stopped.env.iter().map(|(k, v)| format!("{} = {}", k, v)),
+        );
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let width = self
+            .rows
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut lines = vec![];
+        for (label, values) in &self.rows {
+            for (i, v) in values.iter().enumerate() {
+                let label = if i == 0 { *label } else { "" };
+                lines.push(format!("{:width$}  {}", label, v, width = width));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn humanize_bytes() {
+        assert_eq!(bytes(0), "0");
+        assert_eq!(bytes(1023), "1023");
+        assert_eq!(bytes(1024), "1K");
+        assert_eq!(bytes(1536), "1.5K");
+        assert_eq!(bytes(1 << 30), "1G");
+        assert_eq!(bytes(u64::MAX), "16E");
+    }
+
+    #[test]
+    fn aligned_rows() {
+        let mut table = Table::default();
+        table.row("name", Some("www"));
+        table.row("hostname", None::<String>);
+        table.row("ips", vec!["192.0.2.1", "2001:db8::1"]);
+
+        assert_eq!(
+            table.render(),
+            "name  www\nips   192.0.2.1\n      2001:db8::1"
+        );
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(value(&Value::Int(1)), "1");
+        assert_eq!(
+            value(&Value::Ipv4Addrs(vec![
+                "192.0.2.1".parse().unwrap(),
+                "192.0.2.2".parse().unwrap()
+            ])),
+            "192.0.2.1,192.0.2.2"
+        );
+        assert_eq!(value(&Value::Raw(vec![0xde, 0xad])), "dead");
+    }
+}
@@ -0,0 +1,217 @@
+//! What the credentials of this process allow
+//!
+//! Most jail operations need root: `jail_set`(2), `jail_remove`(2) and
+//! `jail_attach`(2) check for `PRIV_JAIL_SET`, `PRIV_JAIL_REMOVE` and
+//! `PRIV_JAIL_ATTACH`, which only the superuser holds, even for jails created
+//! by the same user. Only reading jails with `jail_get`(2) is open to every
+//! user, limited to the jails visible from the jail of the process.
+//!
+//! [check] reports what the current credentials permit, so that tools can
+//! fail early with a clear message, or offer a read-only mode, instead of
+//! running into `EPERM` halfway through an operation.
+use crate::JailError;
+use log::trace;
+use std::fmt;
+
+#[cfg(target_os = "freebsd")]
+use sysctl::{Ctl, CtlValue, Sysctl};
+
+/// An operation on jails needing some privilege.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    /// Reading jails and their parameters with `jail_get`(2)
+    Read,
+
+    /// Creating jails
+    Create,
+
+    /// Changing parameters of running jails
+    Modify,
+
+    /// Removing jails
+    Remove,
+
+    /// Attaching processes to jails, e.g. to run commands in them
+    Attach,
+
+    /// Setting `RCTL` resource limits
+    Limit,
+
+    /// Mounting file systems for jails
+    Mount,
+}
+
+/// All operations, in the order of [Operation].
+pub const OPERATIONS: &[Operation] = &[
+    Operation::Read,
+    Operation::Create,
+    Operation::Modify,
+    Operation::Remove,
+    Operation::Attach,
+    Operation::Limit,
+    Operation::Mount,
+];
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Operation::Read => "reading jails",
+            Operation::Create => "creating jails",
+            Operation::Modify => "changing jail parameters",
+            Operation::Remove => "removing jails",
+            Operation::Attach => "attaching to jails",
+            Operation::Limit => "setting resource limits",
+            Operation::Mount => "mounting file systems",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The credentials of this process and the host settings deciding which
+/// [Operation]s are permitted, as returned by [check].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Privileges {
+    /// The effective user id
+    pub euid: u32,
+
+    /// Whether the process runs inside a jail
+    pub jailed: bool,
+
+    /// Whether resource accounting is enabled (`kern.racct.enable`)
+    pub racct: bool,
+
+    /// Whether the jail of the process may mount file systems
+    /// (`security.jail.mount_allowed`), always true outside of jails
+    pub mount_allowed: bool,
+}
+
+impl Privileges {
+    /// Why `operation` is not permitted, or `None` if it is.
+    fn denied(&self, operation: Operation) -> Option<&'static str> {
+        match operation {
+            Operation::Read => None,
+            _ if self.euid != 0 => Some("requires root"),
+            Operation::Limit if self.jailed => Some("RCTL is not available inside jails"),
+            Operation::Limit if !self.racct => {
+                Some("RACCT is disabled, set kern.racct.enable=1 in /boot/loader.conf")
+            }
+            Operation::Mount if !self.mount_allowed => Some("allow.mount is not set for this jail"),
+            _ => None,
+        }
+    }
+
+    /// Check whether `operation` is permitted.
+    ///
+    /// Inside a jail, creating jails also needs the `children.max`
+    /// parameter of the jail to be set, which cannot be read from inside.
+    pub fn permits(&self, operation: Operation) -> bool {
+        trace!("Privileges::permits({:?}, operation={:?})", self, operation);
+        self.denied(operation).is_none()
+    }
+
+    /// Fail with [JailError::NotPermitted] unless `operation` is permitted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::privileges::{self, Operation};
+    ///
+    /// let privileges = privileges::check();
+    /// privileges
+    ///     .require(Operation::Read)
+    ///     .expect("anyone can read jails");
+    /// ```
+    pub fn require(&self, operation: Operation) -> Result<(), JailError> {
+        trace!("Privileges::require({:?}, operation={:?})", self, operation);
+        match self.denied(operation) {
+            None => Ok(()),
+            Some(reason) => Err(JailError::NotPermitted {
+                operation: operation.to_string(),
+                reason: reason.to_string(),
+            }),
+        }
+    }
+
+    /// Get the permitted operations.
+    pub fn permitted(&self) -> Vec<Operation> {
+        trace!("Privileges::permitted({:?})", self);
+        OPERATIONS
+            .iter()
+            .copied()
+            .filter(|operation| self.permits(*operation))
+            .collect()
+    }
+}
+
+/// Read a boolean sysctl, taking missing nodes as false.
+#[cfg(target_os = "freebsd")]
+fn sysctl_flag(name: &str) -> bool {
+    match Ctl::new(name).and_then(|ctl| ctl.value()) {
+        Ok(CtlValue::Int(i)) => i != 0,
+        Ok(CtlValue::Uint(u)) | Ok(CtlValue::U32(u)) => u != 0,
+        Ok(CtlValue::U8(u)) => u != 0,
+        _ => false,
+    }
+}
+
+/// Check what the credentials of this process permit.
+///
+/// # Examples
+///
+/// ```
+/// use jail::privileges::{self, Operation};
+///
+/// let privileges = privileges::check();
+/// if !privileges.permits(Operation::Create) {
+///     println!("read-only mode: {:?}", privileges.permitted());
+/// }
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn check() -> Privileges {
+    trace!("privileges::check()");
+    let jailed = sysctl_flag("security.jail.jailed");
+    Privileges {
+        euid: unsafe { libc::geteuid() },
+        jailed,
+        racct: sysctl_flag("kern.racct.enable"),
+        mount_allowed: !jailed || sysctl_flag("security.jail.mount_allowed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permitted_operations() {
+        let root = Privileges {
+            euid: 0,
+            jailed: false,
+            racct: true,
+            mount_allowed: true,
+        };
+        assert_eq!(root.permitted(), OPERATIONS);
+
+        let user = Privileges { euid: 1001, ..root };
+        assert_eq!(user.permitted(), vec![Operation::Read]);
+        assert!(matches!(
+            user.require(Operation::Attach),
+            Err(JailError::NotPermitted { .. })
+        ));
+
+        let jailed = Privileges {
+            jailed: true,
+            mount_allowed: false,
+            ..root
+        };
+        assert!(!jailed.permits(Operation::Limit));
+        assert!(!jailed.permits(Operation::Mount));
+        assert!(jailed.permits(Operation::Create));
+
+        let no_racct = Privileges {
+            racct: false,
+            ..root
+        };
+        assert!(!no_racct.permits(Operation::Limit));
+    }
+}
@@ -1,14 +1,47 @@
 //! Jail-Specific extensions to the `std::process` module
-use crate::{JailError, RunningJail};
-use log::trace;
+#[cfg(feature = "audit")]
+use crate::audit::{self, Operation};
+#[cfg(feature = "journal")]
+use crate::journal;
+use crate::{sys, JailError, RunningJail};
+use log::{trace, warn};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(target_os = "freebsd")]
+use std::collections::BTreeMap;
+#[cfg(target_os = "freebsd")]
+use std::fmt;
+#[cfg(target_os = "freebsd")]
+use std::fs::File;
+#[cfg(target_os = "freebsd")]
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+#[cfg(target_os = "freebsd")]
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+#[cfg(target_os = "freebsd")]
+use std::sync::{Condvar, Mutex};
 
 /// Extension to the `std::process::Command` builder to run the command in a
 /// jail.
 ///
 /// Adds a `before_exec` hook to the `std::process::Command` builder that calls
-/// the `jail_attach`(2) syscall.
+/// the `jail_attach`(2) syscall, and applies the scheduling class of the jail,
+/// if any (see [RunningJail::set_scheduling]).
+///
+/// Where the isolation of a jail is not needed, [Jailed::chroot] only changes
+/// the root directory, so that the same code can run commands either way.
+/// In both cases, the environment is passed on unchanged, the working
+/// directory is the new root, and options applied by `std` before the hooks
+/// run, such as `CommandExt::uid` and `Command::current_dir`, take effect on
+/// the host.
 ///
 /// # Examples
 ///
@@ -35,6 +68,59 @@ pub trait Jailed {
     /// to calling `jail_attach` in the child process. Failure in the
     /// `jail_attach` call will cause the spawn to fail.
     fn jail(&mut self, jail: &RunningJail) -> &mut process::Command;
+
+    /// Sets the child process to be executed with its root directory changed
+    /// to `path`, without entering a jail. This translates to calling
+    /// `chroot`(2) and `chdir("/")` in the child process. Failure in either
+    /// call will cause the spawn to fail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::process::Command;
+    /// use jail::process::Jailed;
+    /// use std::path::Path;
+    ///
+    /// let output = Command::new("/hostname")
+    ///              .chroot(Path::new("/rescue"))
+    ///              .output()
+    ///              .expect("Failed to execute command");
+    ///
+    /// assert!(output.status.success());
+    /// ```
+    fn chroot(&mut self, path: &Path) -> &mut process::Command;
+
+    /// Sets the child process to be executed within a jail, like
+    /// [Jailed::jail], and to be killed once `timeout` has passed after it
+    /// was spawned.
+    ///
+    /// The returned [Deadline] spawns the command and starts a watchdog
+    /// thread enforcing the timeout, which can also remove the whole jail,
+    /// see [Deadline::kill_jail].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::process::Command;
+    /// use jail::process::Jailed;
+    /// use std::time::Duration;
+    /// # let jail = jail::StoppedJail::new("/rescue")
+    /// #     .name("testjail_jail_timeout")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///     .arg("10")
+    ///     .jail_timeout(&jail, Duration::from_millis(100))
+    ///     .spawn()
+    ///     .expect("Failed to execute command");
+    ///
+    /// let status = child.wait().expect("Could not wait for child.");
+    /// assert!(!status.success());
+    /// assert!(child.timed_out());
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    fn jail_timeout(&mut self, jail: &RunningJail, timeout: Duration) -> Deadline<'_>;
 }
 
 #[cfg(target_os = "freebsd")]
@@ -42,16 +128,1743 @@ impl Jailed for process::Command {
     fn jail(&mut self, jail: &RunningJail) -> &mut process::Command {
         trace!("process::Command::jail({:?}, jail={:?})", self, jail);
         let jail = *jail;
+        // Look up the scheduling here, since locks must not be taken after
+        // forking.
+        let scheduling = jail.scheduling();
+
+        // The attach itself happens in the forked child, where logging is
+        // not safe, so it is audited here instead.
+        #[cfg(feature = "audit")]
+        audit::emit::<()>(
+            Operation::Attach,
+            jail.jid,
+            jail.name().ok().as_deref(),
+            self.get_program().to_str(),
+            &Ok(()),
+        );
+
+        #[cfg(feature = "journal")]
+        journal::record::<(), _>(
+            journal::Operation::Exec,
+            jail.jid,
+            jail.name().ok().as_deref(),
+            &Ok(()),
+            |entry| {
+                entry.command = std::iter::once(self.get_program())
+                    .chain(self.get_args())
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect();
+            },
+        );
+
         unsafe {
             self.pre_exec(move || {
                 trace!("pre_exec handler: attaching");
-                jail.attach().map_err(|err| match err {
+                sys::jail_attach(jail.jid).map_err(|err| match err {
                     JailError::JailAttachError(e) => e,
                     _ => panic!("jail.attach() failed with unexpected error"),
-                })
+                })?;
+
+                match scheduling {
+                    Some(scheduling) => scheduling.apply(0),
+                    None => Ok(()),
+                }
             });
         }
 
         self
     }
+
+    fn chroot(&mut self, path: &Path) -> &mut process::Command {
+        trace!("process::Command::chroot({:?}, path={:?})", self, path);
+        // Allocate before forking; an invalid path fails the spawn.
+        let path = CString::new(path.as_os_str().as_bytes());
+
+        unsafe {
+            self.pre_exec(move || {
+                let path = path
+                    .as_ref()
+                    .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?;
+
+                if libc::chroot(path.as_ptr()) != 0 || libc::chdir(b"/\0".as_ptr() as *const _) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        self
+    }
+
+    fn jail_timeout(&mut self, jail: &RunningJail, timeout: Duration) -> Deadline<'_> {
+        trace!(
+            "process::Command::jail_timeout({:?}, jail={:?}, timeout={:?})",
+            self,
+            jail,
+            timeout
+        );
+        Deadline {
+            command: self.jail(jail),
+            jail: *jail,
+            timeout,
+            kill_jail: false,
+        }
+    }
+}
+
+/// A command to be run in a jail with a time limit, created by
+/// [Jailed::jail_timeout].
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct Deadline<'a> {
+    command: &'a mut process::Command,
+    jail: RunningJail,
+    timeout: Duration,
+    kill_jail: bool,
+}
+
+#[cfg(target_os = "freebsd")]
+impl Deadline<'_> {
+    /// Set whether the whole jail is killed when the timeout expires,
+    /// instead of only the command.
+    pub fn kill_jail(mut self, kill_jail: bool) -> Self {
+        trace!("Deadline::kill_jail({:?}, kill_jail={})", self, kill_jail);
+        self.kill_jail = kill_jail;
+        self
+    }
+
+    /// Spawn the command and the watchdog thread enforcing the timeout.
+    pub fn spawn(self) -> io::Result<TimedChild> {
+        trace!("Deadline::spawn({:?})", self);
+        let child = self.command.spawn()?;
+        let pid = child.id() as libc::pid_t;
+        let (jail, timeout, kill_jail) = (self.jail, self.timeout, self.kill_jail);
+        let deadline = Instant::now() + timeout;
+
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let watchdog = thread::spawn(move || {
+            // Dropping the sender without cancelling leaves the deadline in
+            // place.
+            if let Ok(()) = cancelled.recv_timeout(timeout) {
+                return false;
+            }
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+
+            warn!(
+                "killing process {} in jail {} after {:?}",
+                pid, jail.jid, timeout
+            );
+            // The child is not reaped before the watchdog is cancelled, so
+            // the pid cannot have been reused.
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            if kill_jail {
+                if let Err(e) = jail.kill() {
+                    warn!("could not kill jail {}: {}", jail.jid, e);
+                }
+            }
+            true
+        });
+
+        Ok(TimedChild {
+            child,
+            watchdog: Some((cancel, watchdog)),
+            timed_out: false,
+        })
+    }
+
+    /// Spawn the command, wait for it and collect its output, like
+    /// `std::process::Command::output`.
+    ///
+    /// A command that runs out of time is killed with `SIGKILL`, which
+    /// shows in the exit status.
+    pub fn output(self) -> io::Result<process::Output> {
+        trace!("Deadline::output({:?})", self);
+        self.command
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+        self.spawn()?.wait_with_output()
+    }
+}
+
+/// A child spawned with a time limit, see [Jailed::jail_timeout].
+///
+/// Dropping it leaves the child running until the timeout expires.
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct TimedChild {
+    child: process::Child,
+    watchdog: Option<(mpsc::Sender<()>, thread::JoinHandle<bool>)>,
+    timed_out: bool,
+}
+
+#[cfg(target_os = "freebsd")]
+impl TimedChild {
+    /// The process id of the child.
+    pub fn id(&self) -> u32 {
+        trace!("TimedChild::id({:?})", self);
+        self.child.id()
+    }
+
+    /// The child, e.g. to access its standard input or output.
+    ///
+    /// Waiting for the child through it instead of [TimedChild::wait] lets
+    /// the watchdog kill an unrelated process reusing its pid.
+    pub fn child(&mut self) -> &mut process::Child {
+        trace!("TimedChild::child({:?})", self);
+        &mut self.child
+    }
+
+    /// Whether the child was killed because it ran out of time.
+    pub fn timed_out(&self) -> bool {
+        trace!("TimedChild::timed_out({:?})", self);
+        self.timed_out
+    }
+
+    /// Wait for the child to exit, then stop the watchdog.
+    pub fn wait(&mut self) -> io::Result<process::ExitStatus> {
+        trace!("TimedChild::wait({:?})", self);
+        if let Some((cancel, watchdog)) = self.watchdog.take() {
+            // Wait without reaping, so that the pid stays valid until the
+            // watchdog is stopped.
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            loop {
+                let result = unsafe {
+                    libc::waitid(
+                        libc::P_PID,
+                        self.child.id() as libc::id_t,
+                        &mut info,
+                        libc::WEXITED | libc::WNOWAIT,
+                    )
+                };
+                match result {
+                    0 => break,
+                    _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                    _ => return Err(io::Error::last_os_error()),
+                }
+            }
+
+            cancel.send(()).ok();
+            self.timed_out = watchdog.join().unwrap_or(false);
+        }
+
+        self.child.wait()
+    }
+
+    /// Wait for the child to exit and collect its standard output and
+    /// standard error, if they are piped.
+    pub fn wait_with_output(mut self) -> io::Result<process::Output> {
+        trace!("TimedChild::wait_with_output({:?})", self);
+        let mut stdout = self.child.stdout.take();
+        let mut stderr = self.child.stderr.take();
+
+        let (stdout, stderr) = thread::scope(|scope| {
+            let stderr = scope.spawn(move || -> io::Result<Vec<u8>> {
+                let mut buf = vec![];
+                if let Some(ref mut stderr) = stderr {
+                    stderr.read_to_end(&mut buf)?;
+                }
+                Ok(buf)
+            });
+
+            let mut out = vec![];
+            let stdout = match stdout {
+                Some(ref mut stdout) => stdout.read_to_end(&mut out).map(|_| out),
+                None => Ok(out),
+            };
+            (stdout, stderr.join().expect("stderr reader panicked"))
+        });
+
+        Ok(process::Output {
+            status: self.wait()?,
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    }
+}
+
+/// Why a command run inside a jail using
+/// [RunningJail::exec](../struct.RunningJail.html#method.exec) failed.
+///
+/// Failures to start the command are told apart by the error returned by
+/// `execve`(2), and failures of the dynamic linker by the messages of
+/// `rtld`(1) on standard error.
+#[derive(thiserror::Error, Debug)]
+pub enum ExecError {
+    /// The process could not enter the jail, e.g. because the jail no longer
+    /// exists or the caller is not root.
+    #[error("could not attach to the jail: {0}")]
+    AttachFailed(io::Error),
+
+    /// The program, or the interpreter of a script, does not exist inside the
+    /// jail.
+    #[error("{0:?} does not exist inside the jail")]
+    NotFound(PathBuf),
+
+    /// The program is not executable by the user inside the jail.
+    #[error("{0:?} is not executable inside the jail")]
+    PermissionDenied(PathBuf),
+
+    /// The program is not in a format the kernel can execute.
+    #[error("{0:?} has an unknown executable format")]
+    InvalidExecutable(PathBuf),
+
+    /// The dynamic linker failed, e.g. because a shared library is missing
+    /// in a thin jail.
+    #[error("dynamic linker failed: {message}")]
+    DynamicLinker {
+        /// The missing shared library, if that was the cause
+        library: Option<String>,
+
+        /// The message of the dynamic linker
+        message: String,
+    },
+
+    /// The command ran and exited unsuccessfully.
+    #[error("command failed with {status}: {stderr}")]
+    Failed {
+        /// The exit status of the command
+        status: process::ExitStatus,
+
+        /// The standard output of the command
+        stdout: Vec<u8>,
+
+        /// The standard error of the command
+        stderr: String,
+    },
+
+    /// Another error occurred while running the command.
+    #[error("could not run the command: {0}")]
+    Io(io::Error),
+}
+
+impl ExecError {
+    /// Classify an error spawning `program`.
+    ///
+    /// `jail_attach`(2) fails with `EPERM` or `EINVAL`, which `execve`(2)
+    /// does not return for a missing or non-executable program.
+    fn spawn(error: io::Error, program: PathBuf) -> ExecError {
+        match error.raw_os_error() {
+            Some(libc::EPERM) | Some(libc::EINVAL) => ExecError::AttachFailed(error),
+            Some(libc::ENOENT) | Some(libc::ENOTDIR) => ExecError::NotFound(program),
+            Some(libc::EACCES) => ExecError::PermissionDenied(program),
+            Some(libc::ENOEXEC) => ExecError::InvalidExecutable(program),
+            _ => ExecError::Io(error),
+        }
+    }
+
+    /// Classify the output of a command, passing successful output through.
+    fn output(output: process::Output) -> Result<process::Output, ExecError> {
+        if output.status.success() {
+            return Ok(output);
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        if let Some(line) = stderr.lines().find(|line| line.starts_with("ld-elf")) {
+            let library = line
+                .split("Shared object \"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .map(String::from);
+
+            return Err(ExecError::DynamicLinker {
+                library,
+                message: line.to_string(),
+            });
+        }
+
+        Err(ExecError::Failed {
+            status: output.status,
+            stdout: output.stdout,
+            stderr,
+        })
+    }
+}
+
+/// Run `command` inside `jail`, capturing its output.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn exec(
+    jail: &RunningJail,
+    command: &mut process::Command,
+) -> Result<process::Output, ExecError> {
+    trace!("process::exec(jail={:?}, command={:?})", jail, command);
+    if !sys::jail_exists(jail.jid) {
+        return Err(ExecError::AttachFailed(io::Error::from_raw_os_error(
+            libc::EINVAL,
+        )));
+    }
+
+    let program = PathBuf::from(command.get_program());
+    let output = command
+        .jail(jail)
+        .output()
+        .map_err(|e| ExecError::spawn(e, program))?;
+
+    ExecError::output(output)
+}
+
+/// What to do with output beyond the limit of a [CaptureLimit].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the output beyond the limit silently.
+    Discard,
+
+    /// Drop the output beyond the limit and append a marker line telling
+    /// how many bytes were dropped, e.g. `[4096 bytes truncated]`.
+    #[default]
+    Mark,
+}
+
+/// A limit on the output captured from each of standard output and standard
+/// error, see [RunningJail::exec_capped].
+///
+/// The output beyond the limit is still read, so that the command does not
+/// block writing, but not kept. The default limit is 1 MiB, marking
+/// truncated output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureLimit {
+    /// The number of bytes kept of each stream
+    pub max_bytes: usize,
+
+    /// What to do with the output beyond `max_bytes`
+    pub overflow: Overflow,
+}
+
+impl Default for CaptureLimit {
+    fn default() -> Self {
+        CaptureLimit {
+            max_bytes: 1 << 20,
+            overflow: Overflow::Mark,
+        }
+    }
+}
+
+impl CaptureLimit {
+    /// Keep up to `max_bytes` of each stream, marking truncated output.
+    pub fn new(max_bytes: usize) -> Self {
+        trace!("CaptureLimit::new(max_bytes={})", max_bytes);
+        CaptureLimit {
+            max_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Set what to do with the output beyond the limit.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        trace!(
+            "CaptureLimit::overflow({:?}, overflow={:?})",
+            self,
+            overflow
+        );
+        self.overflow = overflow;
+        self
+    }
+
+    /// Read `reader` to the end, keeping at most `max_bytes`.
+    fn capture<R: Read>(&self, mut reader: R) -> io::Result<Vec<u8>> {
+        let mut output = vec![];
+        let mut dropped = 0;
+        let mut buf = [0u8; 8192];
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let kept = n.min(self.max_bytes - output.len());
+            output.extend_from_slice(&buf[..kept]);
+            dropped += n - kept;
+        }
+
+        if dropped > 0 && self.overflow == Overflow::Mark {
+            if !output.ends_with(b"\n") && !output.is_empty() {
+                output.push(b'\n');
+            }
+            output.extend_from_slice(format!("[{} bytes truncated]\n", dropped).as_bytes());
+        }
+
+        Ok(output)
+    }
+}
+
+/// Run `command` inside `jail`, capturing its output up to `limit`.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn exec_capped(
+    jail: &RunningJail,
+    command: &mut process::Command,
+    limit: CaptureLimit,
+) -> Result<process::Output, ExecError> {
+    trace!(
+        "process::exec_capped(jail={:?}, command={:?}, limit={:?})",
+        jail,
+        command,
+        limit
+    );
+    if !sys::jail_exists(jail.jid) {
+        return Err(ExecError::AttachFailed(io::Error::from_raw_os_error(
+            libc::EINVAL,
+        )));
+    }
+
+    let program = PathBuf::from(command.get_program());
+    let mut child = command
+        .jail(jail)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| ExecError::spawn(e, program))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let (stdout, stderr) = thread::scope(|scope| {
+        let stderr = scope.spawn(|| limit.capture(stderr));
+        let stdout = limit.capture(stdout);
+        (
+            stdout,
+            stderr.join().expect("stderr capture thread panicked"),
+        )
+    });
+
+    let status = child.wait().map_err(ExecError::Io)?;
+    ExecError::output(process::Output {
+        status,
+        stdout: stdout.map_err(ExecError::Io)?,
+        stderr: stderr.map_err(ExecError::Io)?,
+    })
+}
+
+/// Run the commands of the `exec.*` parameter `hook` with `/bin/sh -c`, like
+/// `jail`(8) does: inside `jail` if given, on the host otherwise.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn run_hook(
+    exec: &BTreeMap<String, Vec<String>>,
+    hook: &str,
+    jail: Option<&RunningJail>,
+) -> Result<(), JailError> {
+    trace!(
+        "process::run_hook(exec={:?}, hook={:?}, jail={:?})",
+        exec,
+        hook,
+        jail
+    );
+    for command in exec.get(hook).into_iter().flatten() {
+        let mut sh = process::Command::new("/bin/sh");
+        sh.arg("-c").arg(command);
+
+        let failed = |message: String| JailError::ExecHookFailed {
+            hook: hook.to_string(),
+            command: command.clone(),
+            message,
+        };
+
+        match jail {
+            Some(jail) => {
+                exec_capped(jail, &mut sh, CaptureLimit::default())
+                    .map_err(|e| failed(e.to_string()))?;
+            }
+            None => {
+                let output = sh.output().map_err(|e| failed(e.to_string()))?;
+                if !output.status.success() {
+                    return Err(failed(format!(
+                        "{}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A command running inside a jail.
+///
+/// Created by [RunningJail::spawn_task]. The output of the command is read by
+/// background threads and delivered line by line through channels. The child
+/// process is reaped automatically, even if the `JailTask` is dropped before
+/// the command has terminated.
+///
+/// # Examples
+///
+/// ```
+/// use std::process::Command;
+/// use std::time::Duration;
+/// # let jail = jail::StoppedJail::new("/rescue")
+/// #     .name("testjail_task")
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let mut task = jail
+///     .spawn_task(Command::new("/echo").arg("hello"))
+///     .expect("could not spawn task");
+///
+/// let status = task
+///     .wait_timeout(Duration::from_secs(5))
+///     .expect("could not wait for task")
+///     .expect("task did not finish in time");
+///
+/// assert!(status.success());
+/// assert_eq!(task.stdout().recv().unwrap(), "hello");
+/// # jail.kill().expect("could not stop jail");
+/// ```
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct JailTask {
+    child: Option<process::Child>,
+    status: Option<process::ExitStatus>,
+    stdout: mpsc::Receiver<String>,
+    stderr: mpsc::Receiver<String>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl JailTask {
+    /// Spawn the command inside the jail, capturing stdout and stderr.
+    pub(crate) fn spawn(
+        jail: &RunningJail,
+        command: &mut process::Command,
+    ) -> Result<JailTask, JailError> {
+        trace!("JailTask::spawn(jail={:?}, command={:?})", jail, command);
+        let mut child = command
+            .jail(jail)
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn()
+            .map_err(JailError::IoError)?;
+
+        let stdout = Self::stream_lines(child.stdout.take());
+        let stderr = Self::stream_lines(child.stderr.take());
+
+        Ok(JailTask {
+            child: Some(child),
+            status: None,
+            stdout,
+            stderr,
+        })
+    }
+
+    fn stream_lines<R: Read + Send + 'static>(reader: Option<R>) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+
+        if let Some(reader) = reader {
+            thread::spawn(move || {
+                let mut reader = BufReader::new(reader);
+                let mut line = vec![];
+                loop {
+                    line.clear();
+                    match reader.read_until(b'\n', &mut line) {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => {}
+                    }
+
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                    }
+
+                    let line = String::from_utf8_lossy(&line).into_owned();
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        receiver
+    }
+
+    /// Return the OS-assigned process identifier of the command.
+    pub fn id(&self) -> Option<u32> {
+        self.child.as_ref().map(process::Child::id)
+    }
+
+    /// The lines written by the command to its standard output.
+    ///
+    /// The channel is disconnected once the command closes its standard
+    /// output.
+    pub fn stdout(&self) -> &mpsc::Receiver<String> {
+        &self.stdout
+    }
+
+    /// The lines written by the command to its standard error.
+    ///
+    /// The channel is disconnected once the command closes its standard
+    /// error.
+    pub fn stderr(&self) -> &mpsc::Receiver<String> {
+        &self.stderr
+    }
+
+    /// Return the exit status if the command has terminated, without
+    /// blocking.
+    pub fn try_wait(&mut self) -> io::Result<Option<process::ExitStatus>> {
+        trace!("JailTask::try_wait({:?})", self);
+        if self.status.is_none() {
+            if let Some(ref mut child) = self.child {
+                self.status = child.try_wait()?;
+            }
+        }
+
+        Ok(self.status)
+    }
+
+    /// Wait for the command to terminate and return its exit status.
+    pub fn wait(&mut self) -> io::Result<process::ExitStatus> {
+        trace!("JailTask::wait({:?})", self);
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+
+        let status = match self.child {
+            Some(ref mut child) => child.wait()?,
+            None => unreachable!("JailTask without child and status"),
+        };
+
+        self.status = Some(status);
+        Ok(status)
+    }
+
+    /// Wait for the command to terminate for at most `timeout`.
+    ///
+    /// Returns `None` if the command is still running after the timeout has
+    /// elapsed.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> io::Result<Option<process::ExitStatus>> {
+        trace!("JailTask::wait_timeout({:?}, timeout={:?})", self, timeout);
+        let deadline = Instant::now() + timeout;
+        let mut interval = Duration::from_millis(1);
+
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(Some(status));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+
+            thread::sleep(interval.min(deadline - now));
+            interval = (interval * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    /// Kill the command and reap it.
+    pub fn kill(&mut self) -> io::Result<process::ExitStatus> {
+        trace!("JailTask::kill({:?})", self);
+        if let Some(status) = self.try_wait()? {
+            return Ok(status);
+        }
+
+        if let Some(ref mut child) = self.child {
+            child.kill()?;
+        }
+
+        self.wait()
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Drop for JailTask {
+    fn drop(&mut self) {
+        if self.status.is_some() {
+            return;
+        }
+
+        // Reap the child in the background to avoid leaving a zombie.
+        if let Some(mut child) = self.child.take() {
+            thread::spawn(move || child.wait());
+        }
+    }
+}
+
+/// A handle to the thread watching the processes of a jail, as returned by
+/// [RunningJail::watch_processes].
+///
+/// The thread is stopped when the handle is dropped, or when the jail is
+/// removed.
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct ProcessWatch {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl ProcessWatch {
+    pub(crate) fn spawn<F>(jail: RunningJail, interval: Duration, mut on_empty: F) -> Self
+    where
+        F: FnMut(&RunningJail) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut attached = 0;
+            loop {
+                match jail.attached_process_count() {
+                    Ok(0) if attached > 0 => {
+                        attached = 0;
+                        on_empty(&jail);
+                    }
+                    Ok(count) => attached = count,
+                    Err(e) => warn!("could not count processes of jail {}: {}", jail.jid, e),
+                }
+
+                if !RunningJail::exists(jail.jid) {
+                    break;
+                }
+
+                match stopped.recv_timeout(interval) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            }
+        });
+
+        ProcessWatch {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the watch thread and wait for it to finish.
+    pub fn stop(mut self) {
+        trace!("ProcessWatch::stop({:?})", self);
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        // Dropping the sender wakes up the thread.
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("process watch callback panicked");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Drop for ProcessWatch {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// The write end of the pipe signalled on `SIGCHLD`, see [Reaper].
+#[cfg(target_os = "freebsd")]
+static SIGCHLD_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// The `SIGCHLD` handler installed before the one of the [Reaper], which is
+/// called after it.
+#[cfg(target_os = "freebsd")]
+static PREVIOUS_HANDLER: AtomicUsize = AtomicUsize::new(libc::SIG_DFL);
+
+/// Whether [PREVIOUS_HANDLER] takes `siginfo_t`.
+#[cfg(target_os = "freebsd")]
+static PREVIOUS_SIGINFO: AtomicBool = AtomicBool::new(false);
+
+/// Counts the `SIGCHLD`s received, notifying waiting reapers.
+#[cfg(target_os = "freebsd")]
+static SIGCHLD_COUNT: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// Whether the `SIGCHLD` handler is installed.
+#[cfg(target_os = "freebsd")]
+static SIGCHLD_INSTALLED: Mutex<bool> = Mutex::new(false);
+
+/// Wake up the reapers by writing to the `SIGCHLD` pipe. Only
+/// async-signal-safe functions may be called here.
+#[cfg(target_os = "freebsd")]
+extern "C" fn on_sigchld(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    unsafe {
+        let errno = *libc::__error();
+        let fd = SIGCHLD_PIPE.load(Ordering::Relaxed);
+        if fd >= 0 {
+            // If the pipe is full, a wakeup is pending already.
+            libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+        *libc::__error() = errno;
+
+        let previous = PREVIOUS_HANDLER.load(Ordering::Relaxed);
+        if previous == libc::SIG_DFL || previous == libc::SIG_IGN {
+            return;
+        }
+
+        if PREVIOUS_SIGINFO.load(Ordering::Relaxed) {
+            let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                std::mem::transmute(previous);
+            handler(signal, info, context);
+        } else {
+            let handler: extern "C" fn(libc::c_int) = std::mem::transmute(previous);
+            handler(signal);
+        }
+    }
+}
+
+/// Install the `SIGCHLD` handler and the thread turning the bytes written
+/// by it into notifications, unless done already.
+#[cfg(target_os = "freebsd")]
+fn install_sigchld_handler() -> Result<(), JailError> {
+    let mut installed = SIGCHLD_INSTALLED
+        .lock()
+        .expect("SIGCHLD handler lock poisoned");
+    if *installed {
+        return Ok(());
+    }
+
+    let mut fds: [libc::c_int; 2] = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(JailError::from_errno());
+    }
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+    if unsafe { libc::fcntl(write_fd, libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
+        let error = JailError::from_errno();
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+        return Err(error);
+    }
+
+    let mut pipe = unsafe { File::from_raw_fd(read_fd) };
+    thread::Builder::new()
+        .name("jail-reaper".into())
+        .spawn(move || {
+            let mut buf = [0u8; 64];
+            loop {
+                match pipe.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        let (count, changed) = &SIGCHLD_COUNT;
+                        *count.lock().expect("SIGCHLD count poisoned") += 1;
+                        changed.notify_all();
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        warn!("could not read SIGCHLD pipe: {}", e);
+                        break;
+                    }
+                }
+            }
+        })
+        .map_err(|e| {
+            unsafe { libc::close(write_fd) };
+            JailError::IoError(e)
+        })?;
+    SIGCHLD_PIPE.store(write_fd, Ordering::Relaxed);
+
+    unsafe {
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGCHLD, std::ptr::null(), &mut previous) != 0 {
+            return Err(JailError::from_errno());
+        }
+        PREVIOUS_SIGINFO.store(previous.sa_flags & libc::SA_SIGINFO != 0, Ordering::Relaxed);
+        PREVIOUS_HANDLER.store(previous.sa_sigaction, Ordering::Relaxed);
+
+        let mut action: libc::sigaction = std::mem::zeroed();
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+            on_sigchld;
+        action.sa_sigaction = handler as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_NOCLDSTOP;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) != 0 {
+            return Err(JailError::from_errno());
+        }
+    }
+
+    *installed = true;
+    Ok(())
+}
+
+/// A jailed child that exited, as collected by a [Reaper].
+#[cfg(target_os = "freebsd")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Exit {
+    /// The process id the child had
+    pub pid: u32,
+
+    /// The `jid` of the jail the child ran in
+    pub jid: i32,
+
+    /// How the child exited
+    pub status: process::ExitStatus,
+}
+
+#[cfg(target_os = "freebsd")]
+type ExitCallback = Box<dyn FnMut(&Exit) + Send + 'static>;
+
+/// Collects the exit statuses of jailed children, so that long-running
+/// managers do not accumulate zombies.
+///
+/// Children are handed to the reaper with [Reaper::spawn] or
+/// [Reaper::track], and collected with [Reaper::poll] or [Reaper::wait],
+/// which also run the callbacks registered with [Reaper::on_exit].
+///
+/// A `SIGCHLD` handler, installed once per process, only wakes up waiting
+/// reapers through a pipe. Children are collected by their pid only, so the
+/// children of other code are never reaped. A `SIGCHLD` handler installed
+/// before the first reaper is created is called after the one of the
+/// reaper.
+///
+/// Children still running when the reaper is dropped are collected in the
+/// background.
+///
+/// # Examples
+///
+/// ```
+/// use jail::process::Reaper;
+/// use jail::StoppedJail;
+/// use std::process::Command;
+/// use std::time::Duration;
+///
+/// let running = StoppedJail::new("/rescue")
+///     .name("testjail_reaper")
+///     .start()
+///     .expect("could not start jail");
+///
+/// let reaper = Reaper::new().expect("could not create reaper");
+/// reaper.on_exit(|exit| println!("{} exited in jail {}", exit.pid, exit.jid));
+///
+/// let pid = reaper
+///     .spawn(&running, &mut Command::new("/sleep").arg("1"))
+///     .expect("could not spawn process");
+///
+/// let exits = reaper.wait(Duration::from_secs(5));
+/// assert_eq!(exits.len(), 1);
+/// assert_eq!(exits[0].pid, pid);
+/// assert_eq!(exits[0].jid, running.jid);
+/// assert!(exits[0].status.success());
+/// # running.kill().expect("could not stop jail");
+/// ```
+#[cfg(target_os = "freebsd")]
+pub struct Reaper {
+    children: Mutex<Vec<(i32, process::Child)>>,
+    callbacks: Mutex<Vec<ExitCallback>>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl fmt::Debug for Reaper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reaper")
+            .field("children", &self.children)
+            .finish()
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Reaper {
+    /// Create a reaper, installing the `SIGCHLD` handler if this is the
+    /// first one.
+    pub fn new() -> Result<Self, JailError> {
+        trace!("Reaper::new()");
+        install_sigchld_handler()?;
+        Ok(Reaper {
+            children: Mutex::new(vec![]),
+            callbacks: Mutex::new(vec![]),
+        })
+    }
+
+    /// Spawn `command` in `jail`, see [Jailed::jail], and track the child,
+    /// returning its pid.
+    pub fn spawn(
+        &self,
+        jail: &RunningJail,
+        command: &mut process::Command,
+    ) -> Result<u32, JailError> {
+        trace!(
+            "Reaper::spawn({:?}, jail={:?}, command={:?})",
+            self,
+            jail,
+            command
+        );
+        let child = command.jail(jail).spawn().map_err(JailError::IoError)?;
+        let pid = child.id();
+        self.track(jail, child);
+        Ok(pid)
+    }
+
+    /// Track a child running in `jail`. The reaper takes over waiting for
+    /// it.
+    pub fn track(&self, jail: &RunningJail, child: process::Child) {
+        trace!(
+            "Reaper::track({:?}, jail={:?}, child={})",
+            self,
+            jail,
+            child.id()
+        );
+        self.children
+            .lock()
+            .expect("reaper poisoned")
+            .push((jail.jid, child));
+    }
+
+    /// Register a callback run for each child collected by [Reaper::poll]
+    /// or [Reaper::wait], on the thread collecting it.
+    ///
+    /// The callback must not call into the reaper.
+    pub fn on_exit<F>(&self, callback: F)
+    where
+        F: FnMut(&Exit) + Send + 'static,
+    {
+        trace!("Reaper::on_exit({:?})", self);
+        self.callbacks
+            .lock()
+            .expect("reaper poisoned")
+            .push(Box::new(callback));
+    }
+
+    /// The number of tracked children that have not been collected yet.
+    pub fn pending(&self) -> usize {
+        trace!("Reaper::pending({:?})", self);
+        self.children.lock().expect("reaper poisoned").len()
+    }
+
+    /// Collect the tracked children that have exited, without blocking.
+    pub fn poll(&self) -> Vec<Exit> {
+        trace!("Reaper::poll({:?})", self);
+        let mut exits = vec![];
+        self.children
+            .lock()
+            .expect("reaper poisoned")
+            .retain_mut(|(jid, child)| match child.try_wait() {
+                Ok(None) => true,
+                Ok(Some(status)) => {
+                    exits.push(Exit {
+                        pid: child.id(),
+                        jid: *jid,
+                        status,
+                    });
+                    false
+                }
+                Err(e) => {
+                    warn!("could not wait for process {}: {}", child.id(), e);
+                    false
+                }
+            });
+
+        if !exits.is_empty() {
+            let mut callbacks = self.callbacks.lock().expect("reaper poisoned");
+            for exit in &exits {
+                for callback in callbacks.iter_mut() {
+                    callback(exit);
+                }
+            }
+        }
+
+        exits
+    }
+
+    /// Block until at least one tracked child has exited or `timeout`
+    /// expires, and collect the children that have exited.
+    ///
+    /// Returns right away if no children are tracked.
+    pub fn wait(&self, timeout: Duration) -> Vec<Exit> {
+        trace!("Reaper::wait({:?}, timeout={:?})", self, timeout);
+        let deadline = Instant::now() + timeout;
+        let (count, changed) = &SIGCHLD_COUNT;
+
+        loop {
+            // Read the count before polling, so that a child exiting in
+            // between is not missed.
+            let seen = *count.lock().expect("SIGCHLD count poisoned");
+            let exits = self.poll();
+            if !exits.is_empty() || self.pending() == 0 {
+                return exits;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return exits;
+            }
+
+            let guard = count.lock().expect("SIGCHLD count poisoned");
+            drop(
+                changed
+                    .wait_timeout_while(guard, remaining, |count| *count == seen)
+                    .expect("SIGCHLD count poisoned"),
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        let children = match self.children.get_mut() {
+            Ok(children) => std::mem::take(children),
+            Err(_) => return,
+        };
+
+        for (_, mut child) in children {
+            thread::spawn(move || child.wait());
+        }
+    }
+}
+
+/// Options for [daemonize_in].
+#[cfg(target_os = "freebsd")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DaemonOptions {
+    pidfile: Option<PathBuf>,
+    working_dir: Option<PathBuf>,
+    keep_fds: Vec<RawFd>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl DaemonOptions {
+    /// Create the default options: no pidfile, `/` as the working
+    /// directory, and no file descriptors kept open.
+    pub fn new() -> Self {
+        trace!("DaemonOptions::new()");
+        DaemonOptions::default()
+    }
+
+    /// Write the pid of the daemon to `path`, an absolute path inside the
+    /// jail.
+    pub fn pidfile<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        trace!("DaemonOptions::pidfile({:?}, path={:?})", self, path);
+        self.pidfile = Some(path);
+        self
+    }
+
+    /// Change the working directory of the daemon to `path`, an absolute
+    /// path inside the jail, instead of `/`.
+    pub fn working_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        trace!("DaemonOptions::working_dir({:?}, path={:?})", self, path);
+        self.working_dir = Some(path);
+        self
+    }
+
+    /// Keep the file descriptor `fd` open in the daemon. All other file
+    /// descriptors above standard error are closed.
+    pub fn keep_fd(mut self, fd: RawFd) -> Self {
+        trace!("DaemonOptions::keep_fd({:?}, fd={})", self, fd);
+        self.keep_fds.push(fd);
+        self
+    }
+}
+
+/// The process [daemonize_in] returned in.
+#[cfg(target_os = "freebsd")]
+#[must_use]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Daemonized {
+    /// The calling process, with the pid of the daemon
+    Parent { pid: u32 },
+
+    /// The daemon, running in the jail
+    Daemon,
+}
+
+/// The steps of [daemonize_in] in the forked processes, as reported to
+/// the parent. Step 0 is success.
+#[cfg(target_os = "freebsd")]
+const DAEMON_STEPS: &[&str] = &[
+    "",
+    "jail_attach",
+    "setsid",
+    "fork",
+    "chdir",
+    "writing the pidfile",
+    "redirecting standard I/O",
+];
+
+/// Report `step` and `value`, the pid or an `errno`, to the parent. Only
+/// async-signal-safe functions may be called here.
+#[cfg(target_os = "freebsd")]
+fn daemon_report(fd: RawFd, step: i32, value: i32) {
+    let mut message = [0u8; 8];
+    message[..4].copy_from_slice(&step.to_ne_bytes());
+    message[4..].copy_from_slice(&value.to_ne_bytes());
+    unsafe { libc::write(fd, message.as_ptr() as *const libc::c_void, message.len()) };
+}
+
+/// Report the failure of `step` with the current `errno` to the parent and
+/// exit.
+#[cfg(target_os = "freebsd")]
+fn daemon_fail(fd: RawFd, step: i32) -> ! {
+    let errno = unsafe { *libc::__error() };
+    daemon_report(fd, step, errno);
+    unsafe { libc::_exit(1) }
+}
+
+/// Write `pid` and a newline to the file at `path`, without allocating.
+#[cfg(target_os = "freebsd")]
+fn write_pidfile(path: &CString, pid: libc::pid_t) -> bool {
+    let mut buf = [0u8; 12];
+    let mut start = buf.len() - 1;
+    buf[start] = b'\n';
+    let mut pid = pid as u32;
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (pid % 10) as u8;
+        pid /= 10;
+        if pid == 0 {
+            break;
+        }
+    }
+
+    unsafe {
+        let fd = libc::open(
+            path.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC,
+            0o644,
+        );
+        if fd < 0 {
+            return false;
+        }
+        let len = buf.len() - start;
+        let written = libc::write(fd, buf[start..].as_ptr() as *const libc::c_void, len);
+        libc::close(fd);
+        written == len as isize
+    }
+}
+
+/// Run the rest of the calling process as a daemon in `jail`.
+///
+/// This forks, attaches the child to the jail and starts a new session
+/// there, then forks again, so that the daemon is not a session leader
+/// and cannot acquire a controlling terminal. The daemon changes its
+/// working directory, writes its pid to the pidfile, connects standard
+/// input and output to `/dev/null` of the host and closes all other file
+/// descriptors, see [DaemonOptions].
+///
+/// Like `fork`(2), this returns twice: in the calling process, once the
+/// daemon is set up, and in the daemon. If any step fails, the forked
+/// processes exit and the error is returned in the calling process
+/// instead, as [JailError::DaemonizeFailed].
+///
+/// Only the thread calling this function is carried over to the daemon,
+/// so it should be called before any other threads are started, or the
+/// daemon should only `exec` another program.
+///
+/// # Examples
+///
+/// ```
+/// use jail::process::{self, DaemonOptions, Daemonized};
+/// use jail::StoppedJail;
+/// use std::os::unix::process::CommandExt;
+///
+/// let running = StoppedJail::new("/rescue")
+///     .name("testjail_daemonize")
+///     .start()
+///     .expect("could not start jail");
+///
+/// match process::daemonize_in(&running, DaemonOptions::new()).expect("could not daemonize") {
+///     Daemonized::Daemon => {
+///         let error = std::process::Command::new("/sleep").arg("10").exec();
+///         panic!("could not run sleep: {}", error);
+///     }
+///     Daemonized::Parent { pid } => assert!(pid > 0),
+/// }
+/// # running.kill().expect("could not stop jail");
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn daemonize_in(jail: &RunningJail, options: DaemonOptions) -> Result<Daemonized, JailError> {
+    trace!("daemonize_in(jail={:?}, options={:?})", jail, options);
+
+    // Everything the forked processes need is allocated up front.
+    let to_cstring = |path: &Path| -> Result<CString, JailError> {
+        if !path.is_absolute() {
+            return Err(JailError::DaemonizeFailed {
+                step: "setup".into(),
+                source: io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{:?} is not an absolute path", path),
+                ),
+            });
+        }
+        CString::new(path.as_os_str().as_bytes()).map_err(JailError::CStringError)
+    };
+    let pidfile = options.pidfile.as_deref().map(to_cstring).transpose()?;
+    let working_dir = to_cstring(options.working_dir.as_deref().unwrap_or(Path::new("/")))?;
+
+    let dev_null = File::open("/dev/null").map_err(JailError::IoError)?;
+    let mut fds: [libc::c_int; 2] = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(JailError::from_errno());
+    }
+    let (mut report, report_fd) = (unsafe { File::from_raw_fd(fds[0]) }, fds[1]);
+
+    let mut keep_fds = options.keep_fds.clone();
+    keep_fds.push(report_fd);
+    let highest_fd = keep_fds.iter().copied().max().unwrap_or(2).max(2);
+
+    match unsafe { libc::fork() } {
+        -1 => {
+            let error = JailError::from_errno();
+            unsafe { libc::close(report_fd) };
+            Err(error)
+        }
+        0 => {
+            drop(report);
+            unsafe {
+                if libc::jail_attach(jail.jid) != 0 {
+                    daemon_fail(report_fd, 1);
+                }
+                if libc::setsid() == -1 {
+                    daemon_fail(report_fd, 2);
+                }
+                match libc::fork() {
+                    -1 => daemon_fail(report_fd, 3),
+                    0 => {}
+                    _ => libc::_exit(0),
+                }
+
+                if libc::chdir(working_dir.as_ptr()) != 0 {
+                    daemon_fail(report_fd, 4);
+                }
+                let pid = libc::getpid();
+                if let Some(ref pidfile) = pidfile {
+                    if !write_pidfile(pidfile, pid) {
+                        daemon_fail(report_fd, 5);
+                    }
+                }
+
+                let null_fd = dev_null.as_raw_fd();
+                for fd in 0..=2 {
+                    if libc::dup2(null_fd, fd) == -1 {
+                        daemon_fail(report_fd, 6);
+                    }
+                }
+                for fd in 3..=highest_fd {
+                    if !keep_fds.contains(&fd) {
+                        libc::close(fd);
+                    }
+                }
+                libc::closefrom(highest_fd + 1);
+                std::mem::forget(dev_null);
+
+                daemon_report(report_fd, 0, pid);
+                libc::close(report_fd);
+            }
+            Ok(Daemonized::Daemon)
+        }
+        child => {
+            unsafe { libc::close(report_fd) };
+            drop(dev_null);
+
+            let mut message = Vec::with_capacity(8);
+            let read = report.read_to_end(&mut message);
+
+            let mut status = 0;
+            while unsafe { libc::waitpid(child, &mut status, 0) } == -1 {
+                if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    break;
+                }
+            }
+            read.map_err(JailError::IoError)?;
+
+            if message.len() != 8 {
+                return Err(JailError::DaemonizeFailed {
+                    step: "setup".into(),
+                    source: io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the daemon exited without reporting",
+                    ),
+                });
+            }
+
+            let step = i32::from_ne_bytes([message[0], message[1], message[2], message[3]]);
+            let value = i32::from_ne_bytes([message[4], message[5], message[6], message[7]]);
+            match DAEMON_STEPS.get(step as usize) {
+                Some(_) if step == 0 => Ok(Daemonized::Parent { pid: value as u32 }),
+                Some(step) => Err(JailError::DaemonizeFailed {
+                    step: (*step).into(),
+                    source: io::Error::from_raw_os_error(value),
+                }),
+                None => Err(JailError::DaemonizeFailed {
+                    step: "setup".into(),
+                    source: io::Error::new(io::ErrorKind::InvalidData, "invalid report"),
+                }),
+            }
+        }
+    }
+}
+
+/// The state of a process, as shown in the `STAT` column of `ps`(1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub enum ProcessState {
+    /// Being created
+    Idle,
+    /// Runnable
+    Running,
+    /// Sleeping for less than 20 seconds
+    Sleeping,
+    /// Stopped by a signal or a debugger
+    Stopped,
+    /// Exited, but not yet reaped by its parent
+    Zombie,
+    /// Waiting for an interrupt
+    Waiting,
+    /// Blocked on a lock
+    Locked,
+    /// A state unknown to this library
+    Unknown,
+}
+
+impl ProcessState {
+    /// Decode the `ki_stat` field of a `kinfo_proc`.
+    #[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+    fn from_stat(stat: libc::c_char) -> Self {
+        match stat {
+            1 => ProcessState::Idle,
+            2 => ProcessState::Running,
+            3 => ProcessState::Sleeping,
+            4 => ProcessState::Stopped,
+            5 => ProcessState::Zombie,
+            6 => ProcessState::Waiting,
+            7 => ProcessState::Locked,
+            _ => ProcessState::Unknown,
+        }
+    }
+}
+
+/// A process running in a jail and its children, as returned by
+/// [RunningJail::process_tree].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct ProcessNode {
+    /// The process ID
+    pub pid: libc::pid_t,
+
+    /// The process ID of the parent
+    pub ppid: libc::pid_t,
+
+    /// The effective user ID
+    pub uid: libc::uid_t,
+
+    /// The name of the executable
+    pub command: String,
+
+    /// The command line, which is empty for kernel processes and processes
+    /// that exited while the tree was read
+    pub args: Vec<String>,
+
+    /// The scheduling state
+    pub state: ProcessState,
+
+    /// The CPU time used so far
+    pub cpu_time: Duration,
+
+    /// The decaying average of the CPU usage, in percent of one CPU
+    pub cpu_percent: f64,
+
+    /// The resident set size in bytes
+    pub rss: u64,
+
+    /// The virtual size in bytes
+    pub vsize: u64,
+
+    /// When the process was started
+    pub started: SystemTime,
+
+    /// The children of the process in the same jail, ordered by `pid`
+    pub children: Vec<ProcessNode>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl ProcessNode {
+    /// Fixed-point scale of `ki_pctcpu`.
+    const FSCALE: f64 = (1 << 11) as f64;
+
+    pub(crate) fn from_kinfo(process: &libc::kinfo_proc, page_size: u64) -> Self {
+        let command: Vec<u8> = process
+            .ki_comm
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as u8)
+            .collect();
+
+        ProcessNode {
+            pid: process.ki_pid,
+            ppid: process.ki_ppid,
+            uid: process.ki_uid,
+            command: String::from_utf8_lossy(&command).into_owned(),
+            args: sys::process_args(process.ki_pid).unwrap_or_default(),
+            state: ProcessState::from_stat(process.ki_stat),
+            cpu_time: Duration::from_micros(process.ki_runtime),
+            cpu_percent: f64::from(process.ki_pctcpu) * 100.0 / Self::FSCALE,
+            rss: process.ki_rssize as u64 * page_size,
+            vsize: process.ki_size as u64,
+            started: SystemTime::UNIX_EPOCH
+                + Duration::new(
+                    process.ki_start.tv_sec as u64,
+                    process.ki_start.tv_usec as u32 * 1000,
+                ),
+            children: vec![],
+        }
+    }
+}
+
+/// Arrange processes by parent. Processes whose parent is not among them are
+/// returned as roots, ordered by `pid` like their children.
+pub(crate) fn build_tree(mut processes: Vec<ProcessNode>) -> Vec<ProcessNode> {
+    processes.sort_by_key(|p| p.pid);
+    let pids: HashSet<libc::pid_t> = processes.iter().map(|p| p.pid).collect();
+
+    let mut roots = vec![];
+    let mut children: HashMap<libc::pid_t, Vec<ProcessNode>> = HashMap::new();
+    for process in processes {
+        if process.ppid != process.pid && pids.contains(&process.ppid) {
+            children.entry(process.ppid).or_default().push(process);
+        } else {
+            roots.push(process);
+        }
+    }
+
+    fn adopt(node: &mut ProcessNode, children: &mut HashMap<libc::pid_t, Vec<ProcessNode>>) {
+        if let Some(mut own) = children.remove(&node.pid) {
+            for child in &mut own {
+                adopt(child, children);
+            }
+            node.children = own;
+        }
+    }
+
+    for root in &mut roots {
+        adopt(root, &mut children);
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn failed(stderr: &str) -> process::Output {
+        process::Output {
+            status: process::ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn classify_errors() {
+        let program = PathBuf::from("/usr/local/bin/nginx");
+        let spawn = |errno| ExecError::spawn(io::Error::from_raw_os_error(errno), program.clone());
+
+        assert!(matches!(spawn(libc::EPERM), ExecError::AttachFailed(_)));
+        assert!(matches!(spawn(libc::ENOENT), ExecError::NotFound(ref p) if *p == program));
+        assert!(matches!(
+            spawn(libc::EACCES),
+            ExecError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            spawn(libc::ENOEXEC),
+            ExecError::InvalidExecutable(_)
+        ));
+        assert!(matches!(spawn(libc::ENOMEM), ExecError::Io(_)));
+
+        let error = ExecError::output(failed(
+            "ld-elf.so.1: Shared object \"libpcre.so.1\" not found, required by \"nginx\"\n",
+        ));
+        assert!(matches!(
+            error,
+            Err(ExecError::DynamicLinker { library: Some(ref l), .. }) if l == "libpcre.so.1"
+        ));
+
+        let error = ExecError::output(failed("nginx: [emerg] invalid configuration\n"));
+        assert!(
+            matches!(error, Err(ExecError::Failed { ref stderr, .. }) if stderr.contains("emerg"))
+        );
+    }
+
+    fn node(pid: libc::pid_t, ppid: libc::pid_t) -> ProcessNode {
+        ProcessNode {
+            pid,
+            ppid,
+            uid: 0,
+            command: "sh".into(),
+            args: vec![],
+            state: ProcessState::Sleeping,
+            cpu_time: Duration::default(),
+            cpu_percent: 0.0,
+            rss: 0,
+            vsize: 0,
+            started: SystemTime::UNIX_EPOCH,
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn process_tree() {
+        // 10 and 40 were started from outside the jail, 30 is 20's child.
+        let tree = build_tree(vec![
+            node(30, 20),
+            node(40, 1),
+            node(20, 10),
+            node(25, 10),
+            node(10, 1),
+        ]);
+
+        let pids = |nodes: &[ProcessNode]| nodes.iter().map(|n| n.pid).collect::<Vec<_>>();
+        assert_eq!(pids(&tree), vec![10, 40]);
+        assert_eq!(pids(&tree[0].children), vec![20, 25]);
+        assert_eq!(pids(&tree[0].children[0].children), vec![30]);
+        assert!(tree[1].children.is_empty());
+
+        assert!(build_tree(vec![]).is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "freebsd")]
+    fn reap_children() {
+        let reaper = Reaper::new().unwrap();
+        let (tx, rx) = mpsc::channel();
+        reaper.on_exit(move |exit| tx.send(exit.pid).unwrap());
+
+        let host = RunningJail::from_jid_unchecked(0);
+        let child = process::Command::new("/bin/sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        reaper.track(&host, child);
+        assert_eq!(reaper.pending(), 1);
+
+        let exits = reaper.wait(Duration::from_secs(10));
+        assert_eq!(exits.len(), 1);
+        assert_eq!((exits[0].pid, exits[0].jid), (pid, 0));
+        assert_eq!(exits[0].status.code(), Some(3));
+        assert_eq!(rx.try_recv(), Ok(pid));
+        assert_eq!(reaper.pending(), 0);
+        assert!(reaper.wait(Duration::from_secs(10)).is_empty());
+    }
+
+    #[test]
+    fn capped_capture() {
+        let output: &[u8] = b"hello world\n";
+        let limit = CaptureLimit::new(5);
+        assert_eq!(
+            limit.capture(output).unwrap(),
+            b"hello\n[7 bytes truncated]\n"
+        );
+        assert_eq!(
+            limit.overflow(Overflow::Discard).capture(output).unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            CaptureLimit::new(12).capture(output).unwrap(),
+            output.to_vec()
+        );
+
+        let large = vec![b'x'; 100_000];
+        assert_eq!(
+            CaptureLimit::new(0).capture(&large[..]).unwrap(),
+            b"[100000 bytes truncated]\n"
+        );
+    }
 }
@@ -0,0 +1,840 @@
+//! Provisioning steps applied to the root of a jail before it is started
+//!
+//! Steps are declared on a [StoppedJail](../struct.StoppedJail.html) and run
+//! in order by [StoppedJail::start](../struct.StoppedJail.html#method.start)
+//! before the jail is created. File systems mounted by a step are unmounted
+//! again when the jail is killed using
+//! [RunningJail::kill](../struct.RunningJail.html#method.kill).
+//!
+//! Steps can also be run against a jail root directly as a [ProvisionPlan],
+//! which rolls back all changes if a step fails.
+use crate::{fs, mount, passwd, JailError};
+use log::{trace, warn};
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// The directory containing the log socket of `syslogd`(8).
+const LOG_SOCKET_DIR: &str = "/var/run";
+
+/// The resolver configuration file, see `resolv.conf`(5).
+pub(crate) const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// The maximum number of name servers used by the resolver.
+const MAXNS: usize = 3;
+
+/// The time zone database of the host.
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// The local time zone, see `tzfile`(5).
+const LOCALTIME: &str = "/etc/localtime";
+
+/// The name of the local time zone, as recorded by `tzsetup`(8).
+const ZONEINFO_NAME: &str = "/var/db/zoneinfo";
+
+/// The system configuration file, see `rc.conf`(5).
+const RC_CONF: &str = "/etc/rc.conf";
+
+/// A provisioning step.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum Step {
+    /// Forward the log messages of the jail to the host.
+    SyslogForward(SyslogForward),
+
+    /// Write the resolver configuration of the jail.
+    ResolvConf(ResolvConf),
+
+    /// Set the local time zone of the jail.
+    Timezone(Timezone),
+
+    /// Enable an `rc.d` service by setting `<service>_enable="YES"` in
+    /// `/etc/rc.conf`.
+    EnableService(String),
+
+    /// Add an SSH public key to the `~/.ssh/authorized_keys` of a user, who
+    /// must exist in the jail root.
+    AuthorizeKey { user: String, key: String },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::SyslogForward(_) => write!(f, "syslog_forward"),
+            Step::ResolvConf(_) => write!(f, "resolv_conf"),
+            Step::Timezone(_) => write!(f, "timezone"),
+            Step::EnableService(_) => write!(f, "enable_service"),
+            Step::AuthorizeKey { .. } => write!(f, "authorize_key"),
+        }
+    }
+}
+
+impl Step {
+    /// Whether the step mounts file systems below the jail root.
+    pub(crate) fn mounts(&self) -> bool {
+        match self {
+            Step::SyslogForward(forward) => forward.host_socket,
+            _ => false,
+        }
+    }
+
+    /// Get the paths inside the jail root of all files the step may modify.
+    #[cfg(target_os = "freebsd")]
+    fn files(&self, root: &Path) -> Result<Vec<PathBuf>, JailError> {
+        Ok(match self {
+            Step::SyslogForward(forward) => match forward.remote {
+                Some(_) => vec![PathBuf::from("/etc/syslog.conf")],
+                None => vec![],
+            },
+            Step::ResolvConf(_) => vec![PathBuf::from(RESOLV_CONF)],
+            Step::Timezone(_) => vec![PathBuf::from(LOCALTIME), PathBuf::from(ZONEINFO_NAME)],
+            Step::EnableService(_) => vec![PathBuf::from(RC_CONF)],
+            Step::AuthorizeKey { user, .. } => {
+                let account = passwd::account(root, user)?
+                    .ok_or_else(|| self.error(format!("no such user {:?}", user)))?;
+                vec![account.home.join(".ssh/authorized_keys")]
+            }
+        })
+    }
+
+    /// Apply the step to the jail root at `root`, recording the host paths
+    /// of all mounted file systems in `mounts`.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn apply(&self, root: &Path, mounts: &mut Vec<PathBuf>) -> Result<(), JailError> {
+        trace!("Step::apply({:?}, root={:?})", self, root);
+        match self {
+            Step::SyslogForward(forward) => forward.apply(root, mounts),
+            Step::ResolvConf(resolv_conf) => resolv_conf.apply(root),
+            Step::Timezone(timezone) => timezone.apply(root),
+            Step::EnableService(service) => self.enable_service(root, service),
+            Step::AuthorizeKey { user, key } => self.authorize_key(root, user, key),
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn enable_service(&self, root: &Path, service: &str) -> Result<(), JailError> {
+        if service.is_empty()
+            || !service
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(self.error(format!("invalid service name {:?}", service)));
+        }
+
+        let contents = read_optional(root, Path::new(RC_CONF))?.unwrap_or_default();
+        let variable = format!("{}_enable", service);
+        let contents = rc_conf_set(&String::from_utf8_lossy(&contents), &variable, "YES");
+        fs::write_file(root, Path::new(RC_CONF), contents.as_bytes(), 0o644)
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn authorize_key(&self, root: &Path, user: &str, key: &str) -> Result<(), JailError> {
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_control) {
+            return Err(self.error("invalid public key"));
+        }
+
+        let account = passwd::account(root, user)?
+            .ok_or_else(|| self.error(format!("no such user {:?}", user)))?;
+        let directory = account.home.join(".ssh");
+        let file = directory.join("authorized_keys");
+
+        let host_directory = fs::host_path(root, &directory)?;
+        if !host_directory.is_dir() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&host_directory)
+                .map_err(JailError::IoError)?;
+            std::os::unix::fs::lchown(&host_directory, Some(account.uid), Some(account.gid))
+                .map_err(JailError::IoError)?;
+        }
+
+        let mut contents =
+            String::from_utf8_lossy(&read_optional(root, &file)?.unwrap_or_default()).into_owned();
+        if contents.lines().any(|line| line.trim() == key) {
+            return Ok(());
+        }
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push_str(key);
+        contents.push('\n');
+
+        fs::write_file(root, &file, contents.as_bytes(), 0o600)?;
+        std::os::unix::fs::lchown(
+            fs::host_path(root, &file)?,
+            Some(account.uid),
+            Some(account.gid),
+        )
+        .map_err(JailError::IoError)
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> JailError {
+        JailError::ProvisionError {
+            step: self.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Forwarding of the log messages of a jail to the host.
+///
+/// There are two ways to get log messages out of a jail:
+///
+/// * Run `syslogd`(8) in the jail and let it forward messages to the host
+///   over UDP. [SyslogForward::to] writes a matching `/etc/syslog.conf` into
+///   the jail root, replacing the existing one. The `syslogd` on the host has
+///   to accept messages from the jail's address, e.g. using `-a`, and the
+///   jail needs an IP address that can reach the host. Keep the default
+///   `syslogd_flags="-s"` in the jail: `-ss` disables forwarding altogether.
+///
+/// * Share the log socket of the host with the jail, so that messages are
+///   logged by the host's `syslogd` directly. [SyslogForward::host_socket]
+///   mounts `/var/run/log` of the host at `/var/run/log` in the jail using
+///   `nullfs`(5), which requires FreeBSD 14 or later. No `syslogd` may run in
+///   the jail, since it would try to replace the socket. Note that messages
+///   are indistinguishable from those of host processes, and processes in the
+///   jail can forge any message.
+///
+/// # Examples
+///
+/// ```
+/// use jail::provision::SyslogForward;
+///
+/// let forward = SyslogForward::to("192.0.2.1").selector("*.notice");
+/// assert_eq!(forward.remote, Some("192.0.2.1".to_string()));
+/// assert!(!forward.host_socket);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct SyslogForward {
+    /// The host, optionally followed by `:port`, that `syslogd` in the jail
+    /// forwards messages to, or `None` to leave `/etc/syslog.conf` as it is
+    pub remote: Option<String>,
+
+    /// The `syslog.conf`(5) selector of the forwarded messages
+    pub selector: String,
+
+    /// Whether to mount the log socket of the host into the jail
+    pub host_socket: bool,
+}
+
+impl SyslogForward {
+    /// Forward all messages from `syslogd` in the jail to `remote`.
+    pub fn to<S: Into<String> + fmt::Debug>(remote: S) -> Self {
+        trace!("SyslogForward::to(remote={:?})", remote);
+        SyslogForward {
+            remote: Some(remote.into()),
+            selector: "*.*".into(),
+            host_socket: false,
+        }
+    }
+
+    /// Share the log socket of the host with the jail.
+    pub fn host_socket() -> Self {
+        trace!("SyslogForward::host_socket()");
+        SyslogForward {
+            remote: None,
+            selector: "*.*".into(),
+            host_socket: true,
+        }
+    }
+
+    /// Only forward messages matching `selector`, e.g. `*.notice`.
+    pub fn selector<S: Into<String> + fmt::Debug>(mut self, selector: S) -> Self {
+        trace!(
+            "SyslogForward::selector({:?}, selector={:?})",
+            self,
+            selector
+        );
+        self.selector = selector.into();
+        self
+    }
+
+    /// Also mount the log socket of the host into the jail.
+    pub fn bind_host_socket(mut self, bind: bool) -> Self {
+        trace!("SyslogForward::bind_host_socket({:?}, bind={})", self, bind);
+        self.host_socket = bind;
+        self
+    }
+
+    /// Render the `syslog.conf`(5) forwarding to `remote`.
+    fn config(&self, remote: &str) -> Result<String, String> {
+        if remote.is_empty() || remote.contains(|c: char| c.is_whitespace() || c == '#') {
+            return Err(format!("invalid remote host {:?}", remote));
+        }
+
+        if self.selector.is_empty() || self.selector.contains(char::is_control) {
+            return Err(format!("invalid selector {:?}", self.selector));
+        }
+
+        Ok(format!(
+            "# Written by libjail-rs: forward log messages to the host.\n{}\t@{}\n",
+            self.selector, remote
+        ))
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn apply(&self, root: &Path, mounts: &mut Vec<PathBuf>) -> Result<(), JailError> {
+        let step = Step::SyslogForward(self.clone());
+
+        if let Some(ref remote) = self.remote {
+            let config = self.config(remote).map_err(|e| step.error(e))?;
+            fs::write_file(
+                root,
+                Path::new("/etc/syslog.conf"),
+                config.as_bytes(),
+                0o644,
+            )?;
+        }
+
+        if self.host_socket {
+            let directory = fs::host_path(root, Path::new(LOG_SOCKET_DIR))?;
+            std::fs::create_dir_all(&directory).map_err(JailError::IoError)?;
+            let target = directory.join("log");
+
+            // nullfs needs an existing mount point of the same kind.
+            match std::fs::symlink_metadata(&target) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    std::fs::remove_file(&target).map_err(JailError::IoError)?;
+                    std::fs::File::create(&target).map_err(JailError::IoError)?;
+                }
+                Ok(metadata) if metadata.is_dir() => {
+                    return Err(step.error(format!("{:?} is a directory", target)));
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    std::fs::File::create(&target).map_err(JailError::IoError)?;
+                }
+                Err(e) => return Err(JailError::IoError(e)),
+            }
+
+            mount::nmount(
+                "nullfs",
+                &Path::new(LOG_SOCKET_DIR).join("log"),
+                &target,
+                0,
+                &[],
+            )?;
+            mounts.push(target);
+        }
+
+        Ok(())
+    }
+}
+
+/// The resolver configuration of a jail, written to `/etc/resolv.conf` in
+/// the jail root on start.
+///
+/// To keep the configuration of a running jail in sync with the host, call
+/// [RunningJail::sync_resolv_conf](../struct.RunningJail.html#method.sync_resolv_conf)
+/// whenever the configuration of the host changes, e.g. from a
+/// `resolvconf.conf`(5) hook.
+///
+/// # Examples
+///
+/// ```
+/// use jail::provision::ResolvConf;
+///
+/// let resolv_conf = ResolvConf {
+///     nameservers: vec!["192.0.2.53".parse().unwrap()],
+///     search: vec!["example.com".into()],
+/// };
+/// let built = ResolvConf::default()
+///     .nameserver("192.0.2.53".parse().unwrap())
+///     .search("example.com");
+///
+/// assert_eq!(resolv_conf, built);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct ResolvConf {
+    /// The name servers to query, in order. The resolver uses at most three.
+    pub nameservers: Vec<IpAddr>,
+
+    /// The domains to search for names without dots
+    pub search: Vec<String>,
+}
+
+impl ResolvConf {
+    /// Add a name server.
+    pub fn nameserver(mut self, nameserver: IpAddr) -> Self {
+        trace!(
+            "ResolvConf::nameserver({:?}, nameserver={})",
+            self,
+            nameserver
+        );
+        self.nameservers.push(nameserver);
+        self
+    }
+
+    /// Add a search domain.
+    pub fn search<S: Into<String> + fmt::Debug>(mut self, domain: S) -> Self {
+        trace!("ResolvConf::search({:?}, domain={:?})", self, domain);
+        self.search.push(domain.into());
+        self
+    }
+
+    /// Read the name servers and search domains of the host.
+    ///
+    /// Other options in the `/etc/resolv.conf` of the host are ignored.
+    pub fn from_host() -> Result<Self, JailError> {
+        trace!("ResolvConf::from_host()");
+        let contents = std::fs::read_to_string(RESOLV_CONF).map_err(JailError::IoError)?;
+        Ok(ResolvConf::parse(&contents))
+    }
+
+    /// Parse the name servers and search domains of a `resolv.conf`(5).
+    fn parse(contents: &str) -> Self {
+        let mut resolv_conf = ResolvConf::default();
+        for line in contents.lines() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    if let Some(Ok(address)) = words.next().map(str::parse) {
+                        resolv_conf.nameservers.push(address);
+                    }
+                }
+                // The last search or domain line wins.
+                Some("search") | Some("domain") => {
+                    resolv_conf.search = words.map(String::from).collect();
+                }
+                _ => {}
+            }
+        }
+
+        resolv_conf
+    }
+
+    /// Render the `resolv.conf`(5).
+    fn config(&self) -> Result<String, String> {
+        if let Some(domain) = self.search.iter().find(|domain| {
+            domain.is_empty()
+                || domain.contains(|c: char| c.is_whitespace() || c == '#' || c == ';')
+        }) {
+            return Err(format!("invalid search domain {:?}", domain));
+        }
+
+        if self.nameservers.len() > MAXNS {
+            warn!(
+                "only the first {} of {} name servers are used",
+                MAXNS,
+                self.nameservers.len()
+            );
+        }
+
+        let mut config = String::from("# Written by libjail-rs.\n");
+        if !self.search.is_empty() {
+            config.push_str(&format!("search {}\n", self.search.join(" ")));
+        }
+        for nameserver in &self.nameservers {
+            config.push_str(&format!("nameserver {}\n", nameserver));
+        }
+
+        Ok(config)
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn apply(&self, root: &Path) -> Result<(), JailError> {
+        let config = self
+            .config()
+            .map_err(|e| Step::ResolvConf(self.clone()).error(e))?;
+        fs::write_file(root, Path::new(RESOLV_CONF), config.as_bytes(), 0o644)
+    }
+}
+
+/// The local time zone of a jail, copied to `/etc/localtime` in the jail
+/// root on start.
+///
+/// Without `/etc/localtime`, processes in the jail use UTC, which often
+/// looks like clock skew between the jail and the host.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum Timezone {
+    /// A time zone of the database of the host, e.g. `Europe/Berlin`
+    Named(String),
+
+    /// The local time zone of the host
+    Host,
+}
+
+impl Timezone {
+    /// Get the path of the time zone file of `name` on the host.
+    fn zoneinfo(name: &str) -> Result<PathBuf, String> {
+        let relative = Path::new(name);
+        let valid = !name.is_empty()
+            && relative
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)));
+
+        match valid {
+            true => Ok(Path::new(ZONEINFO_DIR).join(relative)),
+            false => Err(format!("invalid time zone {:?}", name)),
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn apply(&self, root: &Path) -> Result<(), JailError> {
+        let step = Step::Timezone(self.clone());
+        let (source, name) = match self {
+            Timezone::Named(name) => (
+                Timezone::zoneinfo(name).map_err(|e| step.error(e))?,
+                Some(format!("{}\n", name)),
+            ),
+            Timezone::Host => (
+                PathBuf::from(LOCALTIME),
+                std::fs::read_to_string(ZONEINFO_NAME).ok(),
+            ),
+        };
+
+        let contents = std::fs::read(&source)
+            .map_err(|e| step.error(format!("could not read {:?}: {}", source, e)))?;
+        fs::write_file(root, Path::new(LOCALTIME), &contents, 0o444)?;
+
+        match name {
+            Some(name) => fs::write_file(root, Path::new(ZONEINFO_NAME), name.as_bytes(), 0o644),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Read the file at `path` inside the jail `root`, if it exists.
+#[cfg(target_os = "freebsd")]
+fn read_optional(root: &Path, path: &Path) -> Result<Option<Vec<u8>>, JailError> {
+    match std::fs::read(fs::host_path(root, path)?) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(JailError::IoError(e)),
+    }
+}
+
+/// Set `variable` to `value` in the `rc.conf`(5) `contents`, replacing any
+/// existing assignments.
+fn rc_conf_set(contents: &str, variable: &str, value: &str) -> String {
+    let assignment = format!("{}=\"{}\"", variable, value);
+    let mut replaced = false;
+    let mut lines: Vec<&str> = contents
+        .lines()
+        .filter_map(|line| {
+            let assigns = line
+                .trim_start()
+                .strip_prefix(variable)
+                .is_some_and(|rest| rest.starts_with('='));
+            match (assigns, replaced) {
+                (false, _) => Some(line),
+                (true, false) => {
+                    replaced = true;
+                    Some(assignment.as_str())
+                }
+                (true, true) => None,
+            }
+        })
+        .collect();
+
+    if !replaced {
+        lines.push(&assignment);
+    }
+
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    contents
+}
+
+/// The original state of a file modified by a [ProvisionPlan].
+#[derive(Clone, Debug)]
+enum Original {
+    Missing,
+    File { contents: Vec<u8>, mode: u32 },
+    Symlink(PathBuf),
+}
+
+/// A file inside a jail root, as it was before a [ProvisionPlan] ran.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    path: PathBuf,
+    original: Original,
+}
+
+#[cfg(target_os = "freebsd")]
+impl Snapshot {
+    fn take(root: &Path, path: &Path) -> Result<Snapshot, JailError> {
+        let host = fs::Side::Jail(root).resolve(path)?;
+        let original = match std::fs::symlink_metadata(&host) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Original::Missing,
+            Err(e) => return Err(JailError::IoError(e)),
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                Original::Symlink(std::fs::read_link(&host).map_err(JailError::IoError)?)
+            }
+            Ok(metadata) => Original::File {
+                contents: std::fs::read(&host).map_err(JailError::IoError)?,
+                mode: metadata.mode() & 0o7777,
+            },
+        };
+
+        Ok(Snapshot {
+            path: path.to_path_buf(),
+            original,
+        })
+    }
+
+    fn restore(&self, root: &Path) -> Result<(), JailError> {
+        let host = fs::Side::Jail(root).resolve(&self.path)?;
+        let remove = || match std::fs::remove_file(&host) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(JailError::IoError(e)),
+            _ => Ok(()),
+        };
+
+        match self.original {
+            Original::Missing => remove(),
+            Original::File { ref contents, mode } => {
+                fs::write_file(root, &self.path, contents, mode)
+            }
+            Original::Symlink(ref target) => {
+                remove()?;
+                std::os::unix::fs::symlink(target, &host).map_err(JailError::IoError)
+            }
+        }
+    }
+}
+
+/// The outcome of a step of a [ProvisionPlan].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum Outcome {
+    /// The step was applied.
+    Applied,
+
+    /// The step was applied, but rolled back since a later step failed.
+    RolledBack,
+
+    /// The step failed with the given message.
+    Failed(String),
+
+    /// The step was not run, since an earlier step failed.
+    Skipped,
+}
+
+/// The outcome of running a [ProvisionPlan], step by step.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct PlanReport {
+    /// The name and outcome of every step, in order
+    pub steps: Vec<(String, Outcome)>,
+
+    /// The host paths of all file systems mounted by the steps
+    pub mounts: Vec<PathBuf>,
+}
+
+impl PlanReport {
+    /// Whether all steps were applied.
+    pub fn is_ok(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|(_, outcome)| *outcome == Outcome::Applied)
+    }
+
+    /// Turn a failed report into [JailError::ProvisionError] for the failed
+    /// step.
+    pub fn into_result(self) -> Result<PlanReport, JailError> {
+        let failed = self.steps.iter().find_map(|(step, outcome)| match outcome {
+            Outcome::Failed(message) => Some((step.clone(), message.clone())),
+            _ => None,
+        });
+
+        match failed {
+            Some((step, message)) => Err(JailError::ProvisionError { step, message }),
+            None => Ok(self),
+        }
+    }
+}
+
+/// A sequence of provisioning steps, run against a jail root as a whole.
+///
+/// Before a step runs, the files it may modify are saved. If a step fails,
+/// all saved files are restored, all file systems mounted by earlier steps
+/// are unmounted, and the remaining steps are skipped. Directories created
+/// by the steps are left in place.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::provision::{Outcome, ProvisionPlan, Step};
+/// use std::path::Path;
+///
+/// let report = ProvisionPlan::new()
+///     .step(Step::EnableService("sshd".into()))
+///     .step(Step::AuthorizeKey {
+///         user: "root".into(),
+///         key: "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIExample admin@example.com".into(),
+///     })
+///     .run(Path::new("/jails/www"));
+///
+/// for (step, outcome) in &report.steps {
+///     println!("{}: {:?}", step, outcome);
+/// }
+/// assert!(report.is_ok());
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct ProvisionPlan {
+    /// The steps, in order
+    pub steps: Vec<Step>,
+}
+
+impl From<Vec<Step>> for ProvisionPlan {
+    fn from(steps: Vec<Step>) -> Self {
+        ProvisionPlan { steps }
+    }
+}
+
+impl ProvisionPlan {
+    /// Create an empty plan.
+    pub fn new() -> Self {
+        trace!("ProvisionPlan::new()");
+        ProvisionPlan::default()
+    }
+
+    /// Add a step to the plan.
+    pub fn step(mut self, step: Step) -> Self {
+        trace!("ProvisionPlan::step({:?}, step={:?})", self, step);
+        self.steps.push(step);
+        self
+    }
+
+    /// Run all steps against the jail root at `root`.
+    #[cfg(target_os = "freebsd")]
+    pub fn run(&self, root: &Path) -> PlanReport {
+        trace!("ProvisionPlan::run({:?}, root={:?})", self, root);
+        let mut report = PlanReport::default();
+        let mut snapshots: Vec<Snapshot> = vec![];
+
+        for step in &self.steps {
+            if !report.is_ok() {
+                report.steps.push((step.to_string(), Outcome::Skipped));
+                continue;
+            }
+
+            let result = step.files(root).and_then(|files| {
+                for file in files {
+                    if !snapshots.iter().any(|snapshot| snapshot.path == file) {
+                        snapshots.push(Snapshot::take(root, &file)?);
+                    }
+                }
+                step.apply(root, &mut report.mounts)
+            });
+
+            let error = match result {
+                Ok(()) => {
+                    report.steps.push((step.to_string(), Outcome::Applied));
+                    continue;
+                }
+                Err(JailError::ProvisionError { message, .. }) => message,
+                Err(e) => e.to_string(),
+            };
+
+            warn!("provisioning step '{}' failed: {}", step, error);
+            for (_, outcome) in report.steps.iter_mut() {
+                *outcome = Outcome::RolledBack;
+            }
+            report
+                .steps
+                .push((step.to_string(), Outcome::Failed(error)));
+
+            if let Err(e) = mount::unmount_all(&report.mounts) {
+                warn!("could not unmount file systems: {}", e);
+            }
+            report.mounts.clear();
+
+            for snapshot in snapshots.iter().rev() {
+                if let Err(e) = snapshot.restore(root) {
+                    warn!("could not restore {:?}: {}", snapshot.path, e);
+                }
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_config() {
+        let forward = SyslogForward::to("192.0.2.1:5140").selector("*.notice");
+        assert_eq!(
+            forward.config("192.0.2.1:5140").unwrap(),
+            "# Written by libjail-rs: forward log messages to the host.\n*.notice\t@192.0.2.1:5140\n"
+        );
+
+        assert!(forward.config("192.0.2.1\n*.*\t/etc/passwd").is_err());
+        assert!(forward.clone().selector("").config("192.0.2.1").is_err());
+    }
+
+    #[test]
+    fn resolv_conf() {
+        let resolv_conf = ResolvConf::parse(
+            "# Generated by resolvconf\n\
+             domain example.org\n\
+             search example.com example.net\n\
+             nameserver 192.0.2.53\n\
+             nameserver 2001:db8::53\n\
+             options edns0\n",
+        );
+        assert_eq!(
+            resolv_conf,
+            ResolvConf::default()
+                .nameserver("192.0.2.53".parse().unwrap())
+                .nameserver("2001:db8::53".parse().unwrap())
+                .search("example.com")
+                .search("example.net")
+        );
+
+        assert_eq!(
+            resolv_conf.config().unwrap(),
+            "# Written by libjail-rs.\n\
+             search example.com example.net\n\
+             nameserver 192.0.2.53\n\
+             nameserver 2001:db8::53\n"
+        );
+
+        assert!(resolv_conf
+            .search("evil\nnameserver 0.0.0.0")
+            .config()
+            .is_err());
+    }
+
+    #[test]
+    fn timezone_path() {
+        assert_eq!(
+            Timezone::zoneinfo("Europe/Berlin").unwrap(),
+            Path::new("/usr/share/zoneinfo/Europe/Berlin")
+        );
+        assert!(Timezone::zoneinfo("../../../etc/master.passwd").is_err());
+        assert!(Timezone::zoneinfo("/etc/localtime").is_err());
+        assert!(Timezone::zoneinfo("").is_err());
+    }
+
+    #[test]
+    fn set_rc_conf() {
+        assert_eq!(
+            rc_conf_set("", "sshd_enable", "YES"),
+            "sshd_enable=\"YES\"\n"
+        );
+        assert_eq!(
+            rc_conf_set(
+                "hostname=\"www\"\nsshd_enable=\"NO\"\nsshd_enable_x=1\n  sshd_enable=NO\n",
+                "sshd_enable",
+                "YES"
+            ),
+            "hostname=\"www\"\nsshd_enable=\"YES\"\nsshd_enable_x=1\n"
+        );
+    }
+}
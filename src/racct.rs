@@ -0,0 +1,375 @@
+//! Threshold alerts on `RACCT` resource usage, and cleanup of `RCTL` rules
+//!
+//! Instead of polling [RunningJail::racct_statistics] in every application,
+//! register thresholds and callbacks on [Alerts] and let a single monitor
+//! thread evaluate them for all running jails.
+use crate::{sys, JailError, RunningJail};
+use log::{trace, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// The value a resource is compared against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// An absolute amount, in the unit of the resource.
+    Amount(usize),
+
+    /// A percentage of the `RCTL` limit set on the jail for the resource.
+    /// Jails without such a limit never trigger the alert.
+    PercentOfLimit(f64),
+}
+
+/// A threshold that was exceeded, passed to the alert callback.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    /// The jail exceeding the threshold
+    pub jail: RunningJail,
+
+    /// The resource that exceeded the threshold
+    pub resource: rctl::Resource,
+
+    /// The current usage of the resource
+    pub usage: usize,
+
+    /// The threshold as an amount, in the unit of the resource
+    pub threshold: usize,
+}
+
+type Callback = Box<dyn Fn(&Alert) + Send + 'static>;
+
+struct Rule {
+    resource: rctl::Resource,
+    threshold: Threshold,
+    callback: Callback,
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rule")
+            .field("resource", &self.resource)
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+/// A set of resource usage thresholds and the callbacks to invoke when they
+/// are exceeded.
+///
+/// Alerts are edge-triggered: a callback is invoked when a jail first exceeds
+/// the threshold, and again only after the usage has dropped below it in
+/// between.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::racct::{Alerts, Threshold};
+/// use std::time::Duration;
+///
+/// let monitor = Alerts::new()
+///     .interval(Duration::from_secs(10))
+///     .on(rctl::Resource::MemoryUse, Threshold::PercentOfLimit(90.0), |alert| {
+///         println!(
+///             "jail {} uses {} of {} bytes",
+///             alert.jail.jid, alert.usage, alert.threshold
+///         );
+///     })
+///     .spawn();
+///
+/// // ...
+///
+/// monitor.stop();
+/// ```
+#[derive(Debug)]
+pub struct Alerts {
+    rules: Vec<Rule>,
+    interval: Duration,
+    triggered: HashSet<(i32, usize)>,
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Alerts {
+            rules: vec![],
+            interval: Duration::from_secs(5),
+            triggered: HashSet::new(),
+        }
+    }
+}
+
+impl Alerts {
+    /// Create an empty set of alerts, sampled every five seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the interval in which the monitor thread samples resource usage.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        trace!("Alerts::interval({:?}, interval={:?})", self, interval);
+        self.interval = interval;
+        self
+    }
+
+    /// Invoke `callback` whenever a jail exceeds `threshold` for `resource`.
+    pub fn on<F>(mut self, resource: rctl::Resource, threshold: Threshold, callback: F) -> Self
+    where
+        F: Fn(&Alert) + Send + 'static,
+    {
+        trace!(
+            "Alerts::on({:?}, resource={:?}, threshold={:?})",
+            self,
+            resource,
+            threshold
+        );
+        self.rules.push(Rule {
+            resource,
+            threshold,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Sample the resource usage of all running jails once, invoking the
+    /// callbacks of all newly exceeded thresholds.
+    ///
+    /// Jails that cannot be sampled, e.g. because they have no name, are
+    /// skipped.
+    pub fn check(&mut self) -> Result<(), JailError> {
+        trace!("Alerts::check({:?})", self);
+        if !rctl::State::check().is_enabled() {
+            return Err(JailError::NotSupported(
+                "RACCT/RCTL is not enabled in the kernel".into(),
+            ));
+        }
+
+        let mut triggered = HashSet::new();
+
+        for jail in RunningJail::all() {
+            let name = match jail.name() {
+                Ok(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+
+            let usage = match jail.racct_statistics() {
+                Ok(usage) => usage,
+                Err(e) => {
+                    warn!("could not get RACCT statistics of jail {}: {}", jail.jid, e);
+                    continue;
+                }
+            };
+
+            let limits = limits(&name);
+
+            for (index, rule) in self.rules.iter().enumerate() {
+                let threshold = match rule.threshold {
+                    Threshold::Amount(amount) => amount,
+                    Threshold::PercentOfLimit(percent) => match limits.get(&rule.resource) {
+                        Some(limit) => (*limit as f64 * percent / 100.0) as usize,
+                        None => continue,
+                    },
+                };
+
+                let value = match usage.get(&rule.resource) {
+                    Some(value) => *value,
+                    None => continue,
+                };
+
+                if value <= threshold {
+                    continue;
+                }
+
+                triggered.insert((jail.jid, index));
+                if !self.triggered.contains(&(jail.jid, index)) {
+                    (rule.callback)(&Alert {
+                        jail,
+                        resource: rule.resource,
+                        usage: value,
+                        threshold,
+                    });
+                }
+            }
+        }
+
+        self.triggered = triggered;
+        Ok(())
+    }
+
+    /// Evaluate the alerts in a background thread until the returned
+    /// [Monitor] is stopped or dropped.
+    pub fn spawn(mut self) -> Monitor {
+        trace!("Alerts::spawn({:?})", self);
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || loop {
+            if let Err(e) = self.check() {
+                warn!("could not check RACCT alerts: {}", e);
+            }
+
+            match stopped.recv_timeout(self.interval) {
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                _ => break,
+            }
+        });
+
+        Monitor {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+/// A handle to the thread evaluating [Alerts].
+///
+/// The thread is stopped when the handle is dropped.
+#[derive(Debug)]
+pub struct Monitor {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Stop the monitor thread and wait for it to finish.
+    pub fn stop(mut self) {
+        trace!("Monitor::stop({:?})", self);
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        // Dropping the sender wakes up the thread.
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                warn!("RACCT alert callback panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Get the smallest per-jail `RCTL` limit of each resource.
+pub(crate) fn limits(name: &str) -> HashMap<rctl::Resource, usize> {
+    let filter: rctl::Filter = rctl::Subject::jail_name(name).into();
+    let mut limits = HashMap::new();
+
+    let rules = match filter.rules() {
+        Ok(rules) => rules,
+        Err(_) => return limits,
+    };
+
+    for rule in &rules {
+        let amount = match amount_per(&rule.limit) {
+            Ok((amount, None)) | Ok((amount, Some(rctl::SubjectType::Jail))) => amount,
+            _ => continue,
+        };
+
+        let limit = limits.entry(rule.resource).or_insert(amount);
+        *limit = (*limit).min(amount);
+    }
+
+    limits
+}
+
+/// Remove all `RCTL` rules of jails that do not exist.
+///
+/// Rules of a jail are removed when it is killed using
+/// [RunningJail::kill]. If the killing process crashes in between, the rules
+/// stay behind, and would apply to the next jail of the same name. This
+/// removes them, and returns the removed rules.
+///
+/// Note that this also removes rules set up in advance for jails that have
+/// not been started yet, e.g. from `rctl.conf`(5).
+///
+/// Does nothing if `RCTL` is not enabled.
+///
+/// # Examples
+///
+/// ```
+/// # if !rctl::State::check().is_enabled() {
+/// #     return;
+/// # }
+/// let stale = rctl::Rule {
+///     subject: rctl::Subject::jail_name("testjail_rctl_gc"),
+///     resource: rctl::Resource::MaxProcesses,
+///     limit: rctl::Limit::amount(100),
+///     action: rctl::Action::Deny,
+/// };
+/// stale.apply().unwrap();
+///
+/// let removed = jail::rctl_gc().expect("could not remove stale rules");
+/// assert!(removed.contains(&stale));
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn rctl_gc() -> Result<Vec<rctl::Rule>, JailError> {
+    trace!("rctl_gc()");
+    if !rctl::State::check().is_enabled() {
+        return Ok(vec![]);
+    }
+
+    let mut stale: HashMap<String, Vec<rctl::Rule>> = HashMap::new();
+    let filter = rctl::Filter::new().subject_type(&rctl::SubjectType::Jail);
+    let rules = filter.rules().map_err(JailError::RctlError)?;
+    for rule in &rules {
+        let name = match rule.subject {
+            rctl::Subject::Jail(ref jail) => jail.0.clone(),
+            _ => continue,
+        };
+
+        if sys::jail_getid_by_name(&name).is_err() {
+            stale.entry(name).or_default().push(rule);
+        }
+    }
+
+    let mut removed = vec![];
+    for (name, rules) in stale {
+        warn!("removing {} stale RCTL rules of jail {}", rules.len(), name);
+        let filter: rctl::Filter = rctl::Subject::jail_name(name).into();
+        filter.remove_rules().map_err(JailError::RctlError)?;
+        removed.extend(rules);
+    }
+
+    Ok(removed)
+}
+
+/// Get the amount of a limit, and the subject type it is accounted per.
+///
+/// `rctl::Limit` does not expose either, so they are read back from its
+/// unhumanized string form, e.g. `1073741824/jail`.
+pub(crate) fn amount_per(
+    limit: &rctl::Limit,
+) -> Result<(usize, Option<rctl::SubjectType>), JailError> {
+    let limit = String::from(limit);
+    let (amount, per) = match limit.split_once('/') {
+        Some((amount, per)) => (amount, Some(per)),
+        None => (limit.as_str(), None),
+    };
+
+    let parsed = amount
+        .parse()
+        .map_err(|_| rctl::ParseError::InvalidLimitLiteral(amount.into()))
+        .and_then(|amount| Ok((amount, per.map(str::parse).transpose()?)));
+    parsed.map_err(|e| JailError::RctlError(rctl::Error::ParseError(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts() {
+        assert_eq!(
+            amount_per(&rctl::Limit::amount(1 << 30)).unwrap(),
+            (1 << 30, None)
+        );
+        assert_eq!(
+            amount_per(&rctl::Limit::amount_per(100, rctl::SubjectType::Process)).unwrap(),
+            (100, Some(rctl::SubjectType::Process))
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Process-local state of running jails
+//!
+//! Some settings, like the scheduling priority of future processes, the
+//! file systems mounted for a jail or its metadata on older kernels, are not
+//! known to the kernel. They are
+//! kept here, keyed by `jid`, from the time a jail is started (or configured)
+//! until it is killed through this library.
+//!
+//! Addresses handed out by an [IpPool](../net/struct.IpPool.html) are kept
+//! here as well, from their allocation until the jail using them is created,
+//! and so is the [Namespace](../struct.Namespace.html) of the jails managed
+//! by this process. Loopback aliases added for jails are reference counted
+//! here, so that they are only removed with the last jail using them.
+use crate::devfs::DevfsConfig;
+use crate::mount::UnmountPolicy;
+use crate::name::Namespace;
+use crate::sched::Scheduling;
+use crate::JailError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+/// The state kept for a single jail.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct JailState {
+    pub scheduling: Option<Scheduling>,
+
+    /// Host paths of the file systems mounted for the jail, in mount order,
+    /// and how they are unmounted
+    pub mounts: Vec<PathBuf>,
+    pub unmount_policy: UnmountPolicy,
+
+    /// Metadata of the jail, on kernels without the `meta` parameter
+    pub meta: BTreeMap<String, String>,
+
+    /// Scoped resource limits applied for the jail, removed when it is
+    /// killed
+    #[cfg(feature = "rctl")]
+    pub scoped_limits: Vec<crate::limits::ScopedLimit>,
+
+    /// The `exec.*` parameters of the jail, and whether its stop hooks are
+    /// run when it is killed
+    pub exec: BTreeMap<String, Vec<String>>,
+    pub exec_hooks: bool,
+
+    /// The `devfs` mounted for the jail
+    pub devfs: Option<DevfsConfig>,
+
+    /// The loopback aliases held for the jail, released when it is killed
+    pub loopback_aliases: Vec<IpAddr>,
+}
+
+fn registry() -> &'static Mutex<HashMap<i32, JailState>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<i32, JailState>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Get a copy of the state of the jail `jid`.
+pub(crate) fn get(jid: i32) -> JailState {
+    registry()
+        .lock()
+        .expect("jail registry poisoned")
+        .get(&jid)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Modify the state of the jail `jid`.
+pub(crate) fn update<F: FnOnce(&mut JailState)>(jid: i32, f: F) {
+    f(registry()
+        .lock()
+        .expect("jail registry poisoned")
+        .entry(jid)
+        .or_default())
+}
+
+/// Forget the state of the jail `jid`, e.g. after it has been removed.
+pub(crate) fn remove(jid: i32) {
+    registry()
+        .lock()
+        .expect("jail registry poisoned")
+        .remove(&jid);
+}
+
+fn namespace_slot() -> &'static Mutex<Option<Namespace>> {
+    static NAMESPACE: OnceLock<Mutex<Option<Namespace>>> = OnceLock::new();
+    NAMESPACE.get_or_init(Default::default)
+}
+
+/// Get the namespace of the jails managed by this process.
+pub(crate) fn namespace() -> Option<Namespace> {
+    namespace_slot().lock().expect("namespace poisoned").clone()
+}
+
+/// Set the namespace of the jails managed by this process.
+pub(crate) fn set_namespace(namespace: Option<Namespace>) {
+    *namespace_slot().lock().expect("namespace poisoned") = namespace;
+}
+
+fn reserved() -> &'static Mutex<HashSet<IpAddr>> {
+    static RESERVED: OnceLock<Mutex<HashSet<IpAddr>>> = OnceLock::new();
+    RESERVED.get_or_init(Default::default)
+}
+
+/// Reserve the first of the `candidates` that is not reserved yet.
+pub(crate) fn reserve_ip<I: IntoIterator<Item = IpAddr>>(candidates: I) -> Option<IpAddr> {
+    let mut reserved = reserved().lock().expect("address reservations poisoned");
+    let ip = candidates.into_iter().find(|ip| !reserved.contains(ip))?;
+    reserved.insert(ip);
+    Some(ip)
+}
+
+/// Release reserved addresses, e.g. once a jail using them was created.
+pub(crate) fn release_ips(ips: &[IpAddr]) {
+    let mut reserved = reserved().lock().expect("address reservations poisoned");
+    for ip in ips {
+        reserved.remove(ip);
+    }
+}
+
+fn aliases() -> &'static Mutex<HashMap<IpAddr, usize>> {
+    static ALIASES: OnceLock<Mutex<HashMap<IpAddr, usize>>> = OnceLock::new();
+    ALIASES.get_or_init(Default::default)
+}
+
+/// Take a reference on the loopback alias `ip`, calling `add` to add it if
+/// no jail holds it yet. `add` returns whether it added the alias; if not,
+/// e.g. since the host has the address already, no reference is taken.
+/// Returns whether a reference was taken.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+pub(crate) fn acquire_alias<F>(ip: IpAddr, add: F) -> Result<bool, JailError>
+where
+    F: FnOnce() -> Result<bool, JailError>,
+{
+    let mut aliases = aliases().lock().expect("loopback aliases poisoned");
+    if let Some(count) = aliases.get_mut(&ip) {
+        *count += 1;
+        return Ok(true);
+    }
+
+    let added = add()?;
+    if added {
+        aliases.insert(ip, 1);
+    }
+    Ok(added)
+}
+
+/// Release a reference on the loopback alias `ip`, calling `remove` to
+/// remove it once no jail holds it anymore.
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+pub(crate) fn release_alias<F>(ip: IpAddr, remove: F) -> Result<(), JailError>
+where
+    F: FnOnce() -> Result<(), JailError>,
+{
+    let mut aliases = aliases().lock().expect("loopback aliases poisoned");
+    match aliases.get_mut(&ip) {
+        Some(count) if *count > 1 => {
+            *count -= 1;
+            Ok(())
+        }
+        Some(_) => {
+            aliases.remove(&ip);
+            remove()
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alias_reference_counting() {
+        let ip: IpAddr = "127.77.0.1".parse().unwrap();
+        let added = std::cell::Cell::new(0);
+        let removed = std::cell::Cell::new(0);
+        let add = || {
+            added.set(added.get() + 1);
+            Ok(true)
+        };
+        let remove = || {
+            removed.set(removed.get() + 1);
+            Ok(())
+        };
+
+        assert!(acquire_alias(ip, add).unwrap());
+        assert!(acquire_alias(ip, add).unwrap());
+        assert_eq!(added.get(), 1);
+
+        release_alias(ip, remove).unwrap();
+        assert_eq!(removed.get(), 0);
+        release_alias(ip, remove).unwrap();
+        assert_eq!(removed.get(), 1);
+        release_alias(ip, remove).unwrap();
+        assert_eq!(removed.get(), 1);
+
+        // Addresses the host already has are not held.
+        let configured: IpAddr = "127.77.0.2".parse().unwrap();
+        assert!(!acquire_alias(configured, || Ok(false)).unwrap());
+        release_alias(configured, remove).unwrap();
+        assert_eq!(removed.get(), 1);
+    }
+}
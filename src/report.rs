@@ -0,0 +1,231 @@
+//! Resource usage reports across all jails
+//!
+//! [fleet] samples the `RACCT` usage and `RCTL` limits of all running jails
+//! into comparable, serializable rows.
+use crate::{racct, JailError, RunningJail};
+use log::{trace, warn};
+use std::cmp::Ordering;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// The usage of a resource by a jail.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct ResourceUsage {
+    /// The resource
+    pub resource: rctl::Resource,
+
+    /// The current usage, in the unit of the resource
+    pub usage: usize,
+
+    /// The smallest per-jail `RCTL` limit of the resource, if any
+    pub limit: Option<usize>,
+
+    /// The usage as a fraction of the limit, if there is a non-zero limit
+    pub utilization: Option<f64>,
+}
+
+/// The resource usage of a jail.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct JailUsage {
+    /// The `jid` of the jail
+    pub jid: i32,
+
+    /// The name of the jail
+    pub name: String,
+
+    /// The usage of all resources accounted for the jail, sorted by
+    /// resource name
+    pub resources: Vec<ResourceUsage>,
+}
+
+impl JailUsage {
+    /// Get the usage of a resource.
+    pub fn get(&self, resource: rctl::Resource) -> Option<&ResourceUsage> {
+        self.resources
+            .iter()
+            .find(|usage| usage.resource == resource)
+    }
+
+    /// Get the highest utilization of any limited resource.
+    pub fn max_utilization(&self) -> Option<f64> {
+        self.resources
+            .iter()
+            .filter_map(|usage| usage.utilization)
+            .fold(None, |max, u| Some(max.map_or(u, |max: f64| max.max(u))))
+    }
+}
+
+/// The order of a report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Ascending by `jid`
+    Jid,
+
+    /// Ascending by name
+    Name,
+
+    /// Descending by the usage of a resource
+    Usage(rctl::Resource),
+
+    /// Descending by the utilization of a resource
+    Utilization(rctl::Resource),
+
+    /// Descending by the highest utilization of any resource
+    MaxUtilization,
+}
+
+/// Sort a report. Jails lacking the metric are sorted last, by `jid`.
+pub fn sort(report: &mut [JailUsage], by: SortBy) {
+    trace!("report::sort(by={:?})", by);
+
+    fn descending<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    report.sort_by(|a, b| {
+        let order = match by {
+            SortBy::Jid => Ordering::Equal,
+            SortBy::Name => a.name.cmp(&b.name),
+            SortBy::Usage(resource) => descending(
+                a.get(resource).map(|u| u.usage),
+                b.get(resource).map(|u| u.usage),
+            ),
+            SortBy::Utilization(resource) => descending(
+                a.get(resource).and_then(|u| u.utilization),
+                b.get(resource).and_then(|u| u.utilization),
+            ),
+            SortBy::MaxUtilization => descending(a.max_utilization(), b.max_utilization()),
+        };
+
+        order.then(a.jid.cmp(&b.jid))
+    });
+}
+
+/// Sample the resource usage of a jail.
+fn usage(jail: &RunningJail) -> Result<JailUsage, JailError> {
+    let name = jail.name()?;
+    let limits = racct::limits(&name);
+
+    let mut resources: Vec<ResourceUsage> = jail
+        .racct_statistics()?
+        .into_iter()
+        .map(|(resource, usage)| {
+            let limit = limits.get(&resource).copied();
+            ResourceUsage {
+                resource,
+                usage,
+                limit,
+                utilization: limit
+                    .filter(|limit| *limit > 0)
+                    .map(|limit| usage as f64 / limit as f64),
+            }
+        })
+        .collect();
+    resources.sort_by_key(|usage| usage.resource.to_string());
+
+    Ok(JailUsage {
+        jid: jail.jid,
+        name,
+        resources,
+    })
+}
+
+/// Sample the resource usage and limits of all running jails, sorted as
+/// given.
+///
+/// Jails that cannot be sampled, e.g. because they have no name, are
+/// skipped.
+///
+/// # Examples
+///
+/// ```
+/// use jail::report::{self, SortBy};
+/// # use jail::StoppedJail;
+/// # if !rctl::State::check().is_enabled() {
+/// #     return;
+/// # }
+/// # let running = StoppedJail::new("/rescue")
+/// #     .name("testjail_fleet")
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let fleet = report::fleet(SortBy::Usage(rctl::Resource::MemoryUse))
+///     .expect("could not sample jails");
+///
+/// for jail in fleet {
+///     let memory = jail.get(rctl::Resource::MemoryUse);
+///     println!("{} {:?}", jail.name, memory.map(|m| m.usage));
+/// }
+/// # running.kill().unwrap();
+/// ```
+pub fn fleet(by: SortBy) -> Result<Vec<JailUsage>, JailError> {
+    trace!("report::fleet(by={:?})", by);
+    if !rctl::State::check().is_enabled() {
+        return Err(JailError::NotSupported(
+            "RACCT/RCTL is not enabled in the kernel".into(),
+        ));
+    }
+
+    let mut report: Vec<JailUsage> = RunningJail::all()
+        .filter_map(|jail| match usage(&jail) {
+            Ok(usage) => Some(usage),
+            Err(e) => {
+                warn!("could not sample jail {}: {}", jail.jid, e);
+                None
+            }
+        })
+        .collect();
+
+    sort(&mut report, by);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jail(jid: i32, memory: usize, limit: Option<usize>) -> JailUsage {
+        JailUsage {
+            jid,
+            name: format!("jail{}", 10 - jid),
+            resources: vec![ResourceUsage {
+                resource: rctl::Resource::MemoryUse,
+                usage: memory,
+                limit,
+                utilization: limit.map(|limit| memory as f64 / limit as f64),
+            }],
+        }
+    }
+
+    #[test]
+    fn sort_report() {
+        let mut report = vec![
+            jail(1, 100, None),
+            jail(2, 300, Some(1000)),
+            jail(3, 200, Some(250)),
+        ];
+        let jids = |report: &[JailUsage]| report.iter().map(|j| j.jid).collect::<Vec<_>>();
+
+        sort(&mut report, SortBy::Usage(rctl::Resource::MemoryUse));
+        assert_eq!(jids(&report), vec![2, 3, 1]);
+
+        sort(&mut report, SortBy::MaxUtilization);
+        assert_eq!(jids(&report), vec![3, 2, 1]);
+
+        sort(&mut report, SortBy::Utilization(rctl::Resource::CpuTime));
+        assert_eq!(jids(&report), vec![1, 2, 3]);
+
+        sort(&mut report, SortBy::Name);
+        assert_eq!(jids(&report), vec![3, 2, 1]);
+
+        assert_eq!(report[0].max_utilization(), Some(0.8));
+    }
+}
@@ -1,10 +1,30 @@
-use crate::{param, sys, JailError, StoppedJail};
-use log::trace;
-use std::collections::HashMap;
+#[cfg(feature = "audit")]
+use crate::audit::{self, Operation};
+use crate::fs::{self, CopyOptions, Side, Tail};
+#[cfg(feature = "journal")]
+use crate::journal;
+use crate::mount;
+use crate::name;
+use crate::net::loopback;
+use crate::oscompat::{self, Feature};
+use crate::pretty::Table;
+use crate::process::{run_hook, CaptureLimit, ExecError, JailTask, ProcessNode, ProcessWatch};
+use crate::provision;
+use crate::registry;
+use crate::sched::Scheduling;
+use crate::service::Service;
+use crate::sysctls;
+#[cfg(feature = "zfs")]
+use crate::zfs;
+use crate::{param, sys, JailError, JailView, StopPolicy, StoppedJail};
+use log::{trace, warn};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
-use std::io::{Error, ErrorKind};
 use std::net;
 use std::path;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// Represents a running jail.
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -17,10 +37,34 @@ pub struct RunningJail {
 /// Represent a running jail.
 #[cfg(target_os = "freebsd")]
 impl RunningJail {
+    /// Test if a jail with the given `jid` exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_exists")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// # let jid = jail.jid;
+    ///
+    /// assert!(RunningJail::exists(jid));
+    /// # jail.kill().expect("could not stop jail");
+    /// assert!(!RunningJail::exists(jid));
+    /// ```
+    pub fn exists(jid: i32) -> bool {
+        trace!("RunningJail::exists({})", jid);
+        sys::jail_exists(jid)
+    }
+
     /// Create a [RunningJail](struct.RunningJail.html) instance given a `jid`.
     ///
     /// Returns an [Option] containing a [RunningJail] if a Jail with the given
-    /// `jid` exists, or None.
+    /// `jid` exists, or None. The jail is never returned directly; use
+    /// `RunningJail::try_from(jid)` to get a [Result] failing with
+    /// [JailError::NoSuchJail] instead.
     ///
     /// # Examples
     ///
@@ -47,7 +91,7 @@ impl RunningJail {
     /// ```
     pub fn from_jid(jid: i32) -> Option<RunningJail> {
         trace!("RunningJail::from_jid({})", jid);
-        match sys::jail_exists(jid) {
+        match RunningJail::exists(jid) {
             true => Some(Self::from_jid_unchecked(jid)),
             false => None,
         }
@@ -77,14 +121,55 @@ impl RunningJail {
         RunningJail { jid }
     }
 
+    /// Get the jail a process runs in, or `None` if it runs on the host.
+    ///
+    /// Fails if no process with the given `pid` exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// use jail::process::Jailed;
+    /// use std::process::Command;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_for_pid")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///     .arg("10")
+    ///     .jail(&jail)
+    ///     .spawn()
+    ///     .expect("could not start process");
+    ///
+    /// let owner = RunningJail::for_pid(child.id() as i32).expect("no such process");
+    /// assert_eq!(owner, Some(jail));
+    ///
+    /// let host = RunningJail::for_pid(std::process::id() as i32).unwrap();
+    /// assert_eq!(host, None);
+    /// # child.kill().unwrap();
+    /// # child.wait().unwrap();
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    pub fn for_pid(pid: libc::pid_t) -> Result<Option<RunningJail>, JailError> {
+        trace!("RunningJail::for_pid({})", pid);
+        match sys::process_jid(pid)? {
+            0 => Ok(None),
+            jid => Ok(Some(RunningJail::from_jid_unchecked(jid))),
+        }
+    }
+
     /// Create a [RunningJail](struct.RunningJail.html) given the jail `name`.
     ///
     /// The `jid` will be internally resolved using
-    /// [jail_getid](fn.jail_getid.html).
+    /// [jail_getid](fn.jail_getid.html), which returns numeric names as `jid`s
+    /// without checking that the jail exists.
     ///
     /// # Examples
     ///
     /// ```
+    /// # #![allow(deprecated)]
     /// use jail::RunningJail;
     /// # use jail::StoppedJail;
     /// # let jail = StoppedJail::new("/rescue")
@@ -97,9 +182,45 @@ impl RunningJail {
     /// #
     /// # running.kill();
     /// ```
+    #[deprecated(
+        since = "0.2.1",
+        note = "use `from_name_strict` to look up names, or `str::parse` to accept names and jids"
+    )]
     pub fn from_name(name: &str) -> Result<RunningJail, JailError> {
         trace!("RunningJail::from_name({})", name);
-        sys::jail_getid(name).map(RunningJail::from_jid_unchecked)
+        sys::jail_getid(&name::qualify(name)).map(RunningJail::from_jid_unchecked)
+    }
+
+    /// Create a [RunningJail](struct.RunningJail.html) given the jail `name`,
+    /// failing if no jail has this name.
+    ///
+    /// The name is looked up in the namespace of this process, if any, see
+    /// [set_namespace](fn.set_namespace.html).
+    ///
+    /// Unlike parsing a [RunningJail] from a string, numeric names are looked
+    /// up as names and never taken as `jid`s.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_from_name_strict")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let running = RunningJail::from_name_strict("testjail_from_name_strict")
+    ///     .expect("Could not get testjail");
+    /// assert_eq!(running, jail);
+    ///
+    /// assert!(RunningJail::from_name_strict(&running.jid.to_string()).is_err());
+    /// #
+    /// # running.kill();
+    /// ```
+    pub fn from_name_strict(name: &str) -> Result<RunningJail, JailError> {
+        trace!("RunningJail::from_name_strict({})", name);
+        sys::jail_getid_by_name(&name::qualify(name)).map(RunningJail::from_jid_unchecked)
     }
 
     /// Return the jail's `name`.
@@ -208,6 +329,86 @@ impl RunningJail {
         Ok(ips)
     }
 
+    /// Get the IP addresses, reading both address families with a single
+    /// system call.
+    ///
+    /// Returns the same addresses as [RunningJail::ips], but avoids the
+    /// redundant `sysctl`(3) lookups of reading each parameter on its own.
+    /// Use it when polling the addresses of many jails.
+    ///
+    /// # Examples
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # use std::net::IpAddr;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_ips_fast")
+    /// #     .ip("127.0.1.3".parse().unwrap())
+    /// #     .ip("fe80::3".parse().unwrap())
+    /// #     .start()
+    /// #     .expect("Could not start jail");
+    /// let ips = running.ips_fast()
+    ///     .expect("could not get ip addresses");
+    /// assert_eq!(ips, running.ips().unwrap());
+    /// # running.kill();
+    /// ```
+    pub fn ips_fast(&self) -> Result<Vec<net::IpAddr>, JailError> {
+        trace!("RunningJail::ips_fast({:?})", self);
+        param::get_ips(self.jid)
+    }
+
+    /// Whether IPv4 source address selection is enabled, see
+    /// [StoppedJail::ip4_saddrsel].
+    ///
+    /// # Examples
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_ip4_saddrsel")
+    /// #     .ip("127.0.1.4".parse().unwrap())
+    /// #     .ip("127.0.1.5".parse().unwrap())
+    /// #     .start()
+    /// #     .expect("Could not start jail");
+    /// assert!(running.ip4_saddrsel().unwrap());
+    ///
+    /// // Always send from 127.0.1.4 from now on.
+    /// running.set_ip4_saddrsel(false).unwrap();
+    /// assert!(!running.ip4_saddrsel().unwrap());
+    /// # running.kill();
+    /// ```
+    pub fn ip4_saddrsel(&self) -> Result<bool, JailError> {
+        trace!("RunningJail::ip4_saddrsel({:?})", self);
+        Ok(self.param("ip4.saddrsel")?.unpack_i64()? != 0)
+    }
+
+    /// Enable or disable IPv4 source address selection, see
+    /// [StoppedJail::ip4_saddrsel].
+    pub fn set_ip4_saddrsel(&self, enabled: bool) -> Result<(), JailError> {
+        trace!(
+            "RunningJail::set_ip4_saddrsel({:?}, enabled={})",
+            self,
+            enabled
+        );
+        self.param_set("ip4.saddrsel", param::Value::Int(enabled.into()))
+    }
+
+    /// Whether IPv6 source address selection is enabled, see
+    /// [StoppedJail::ip6_saddrsel].
+    pub fn ip6_saddrsel(&self) -> Result<bool, JailError> {
+        trace!("RunningJail::ip6_saddrsel({:?})", self);
+        Ok(self.param("ip6.saddrsel")?.unpack_i64()? != 0)
+    }
+
+    /// Enable or disable IPv6 source address selection, see
+    /// [StoppedJail::ip6_saddrsel].
+    pub fn set_ip6_saddrsel(&self, enabled: bool) -> Result<(), JailError> {
+        trace!(
+            "RunningJail::set_ip6_saddrsel({:?}, enabled={})",
+            self,
+            enabled
+        );
+        self.param_set("ip6.saddrsel", param::Value::Int(enabled.into()))
+    }
+
     /// Return a jail parameter.
     ///
     /// # Examples
@@ -276,7 +477,92 @@ impl RunningJail {
             name,
             value
         );
-        param::set(self.jid, name, value)
+        #[cfg(feature = "journal")]
+        let journaled = value.clone();
+        let result = param::set(self.jid, name, value);
+
+        #[cfg(feature = "audit")]
+        audit::emit(
+            Operation::ParamSet,
+            self.jid,
+            self.name().ok().as_deref(),
+            Some(name),
+            &result,
+        );
+
+        #[cfg(feature = "journal")]
+        journal::record(
+            journal::Operation::ParamSet,
+            self.jid,
+            self.name().ok().as_deref(),
+            &result,
+            |entry| {
+                entry.params.insert(name.into(), journaled);
+            },
+        );
+
+        result
+    }
+
+    /// Read a sysctl inside the jail, e.g. a per-jail `net.inet` node of a
+    /// VNET jail.
+    ///
+    /// The sysctl is read by a forked child process attached to the jail,
+    /// and typed as the kernel reports it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::{param, StoppedJail};
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_sysctl")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// let somaxconn = running.sysctl("kern.ipc.somaxconn")
+    ///     .expect("could not read sysctl");
+    /// println!("somaxconn: {:?}", somaxconn);
+    /// #
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn sysctl(&self, name: &str) -> Result<param::Value, JailError> {
+        trace!("RunningJail::sysctl({:?}, name={:?})", self, name);
+        sysctls::get(self, name)
+    }
+
+    /// Set a sysctl inside the jail, returning its previous value.
+    ///
+    /// Only the sysctls in
+    /// [sysctls::WRITABLE](sysctls/constant.WRITABLE.html) can be set, and
+    /// the `net` ones only in jails with their own network stack. The value
+    /// must have the type the kernel reports for the node.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::{param, StoppedJail};
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_set_sysctl")
+    /// #     .param("vnet", param::Value::Int(1))
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// running.set_sysctl("net.inet.ip.forwarding", param::Value::Int(1))
+    ///     .expect("could not set sysctl");
+    ///
+    /// assert_eq!(
+    ///     running.sysctl("net.inet.ip.forwarding").unwrap(),
+    ///     param::Value::Int(1)
+    /// );
+    /// #
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn set_sysctl(&self, name: &str, value: param::Value) -> Result<param::Value, JailError> {
+        trace!(
+            "RunningJail::set_sysctl({:?}, name={:?}, value={:?})",
+            self,
+            name,
+            value
+        );
+        sysctls::set(self, name, value)
     }
 
     /// Kill a running jail, consuming it.
@@ -294,24 +580,131 @@ impl RunningJail {
     /// ```
     pub fn kill(self) -> Result<(), JailError> {
         trace!("RunningJail::kill({:?})", self);
-        let name = self.name()?;
-        sys::jail_remove(self.jid)?;
+        let report = self.kill_with_report()?;
+        match report.still_mounted.is_empty() {
+            true => Ok(()),
+            false => Err(JailError::StillMounted {
+                jid: report.jid,
+                paths: report.still_mounted,
+            }),
+        }
+    }
 
-        // Tear down RCTL rules
-        {
-            if name.is_empty() {
-                return Ok(());
+    /// Kill a running jail, consuming it, and report what was cleaned up.
+    ///
+    /// See [RunningJail::kill]. Unlike it, this succeeds if file systems of
+    /// the jail stay mounted despite its
+    /// [UnmountPolicy](mount/struct.UnmountPolicy.html), and reports them in
+    /// [KillReport::still_mounted]. A jail that has already been removed but
+    /// is still dying is cleaned up as well, and reported as
+    /// [KillReport::was_dying].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_kill_with_report")
+    /// #     .start().unwrap();
+    /// let report = running.kill_with_report().expect("could not kill jail");
+    ///
+    /// assert_eq!(report.name, "testjail_kill_with_report");
+    /// assert!(!report.was_dying);
+    /// assert_eq!(report.processes_killed, 0);
+    /// ```
+    pub fn kill_with_report(self) -> Result<KillReport, JailError> {
+        trace!("RunningJail::kill_with_report({:?})", self);
+        let name = param::get_dying(self.jid, "name")?.unpack_string()?;
+        let was_dying = matches!(
+            param::get_dying(self.jid, "dying"),
+            Ok(param::Value::Int(dying)) if dying != 0
+        );
+
+        let state = registry::get(self.jid);
+        if state.exec_hooks && !was_dying {
+            for (hook, jail) in [("exec.prestop", None), ("exec.stop", Some(&self))] {
+                if let Err(e) = run_hook(&state.exec, hook, jail) {
+                    warn!("jail {}: {}", self.jid, e);
+                }
+            }
+        }
+
+        let processes_killed = sys::jail_pids(self.jid).map(|pids| pids.len()).unwrap_or(0);
+
+        // A dying jail has already been removed.
+        let result = match sys::jail_remove(self.jid) {
+            Err(_) if was_dying => Ok(()),
+            result => result,
+        };
+
+        #[cfg(feature = "audit")]
+        audit::emit(Operation::Kill, self.jid, Some(&name), None, &result);
+
+        #[cfg(feature = "journal")]
+        journal::record(
+            journal::Operation::Kill,
+            self.jid,
+            Some(&name),
+            &result,
+            |_| {},
+        );
+
+        result?;
+        registry::remove(self.jid);
+        let still_mounted = mount::teardown(&state.mounts, &state.unmount_policy);
+        if let Err(e) = loopback::remove_aliases(&state.loopback_aliases) {
+            warn!(
+                "jail {}: could not remove loopback aliases: {}",
+                self.jid, e
+            );
+        }
+
+        if state.exec_hooks {
+            if let Err(e) = run_hook(&state.exec, "exec.poststop", None) {
+                warn!("jail {}: {}", self.jid, e);
             }
+        }
 
-            let filter: rctl::Filter = rctl::Subject::jail_name(name).into();
+        // Tear down RCTL rules
+        #[allow(unused_mut)]
+        let mut rctl_rules_removed = None;
+        #[cfg(feature = "rctl")]
+        if !name.is_empty() {
+            let filter: rctl::Filter = rctl::Subject::jail_name(name.clone()).into();
+            let rules = filter.rules().map(|rules| (&rules).into_iter().count());
             match filter.remove_rules() {
-                Ok(_) => Ok(()),
-                Err(rctl::Error::InvalidKernelState(_)) => Ok(()),
-                Err(e) => Err(JailError::RctlError(e)),
+                Ok(_) => rctl_rules_removed = Some(rules.unwrap_or(0)),
+                Err(rctl::Error::InvalidKernelState(_)) => {}
+                Err(e) => return Err(JailError::RctlError(e)),
             }
-        }?;
+        }
+
+        #[cfg(feature = "rctl")]
+        for (scope, (resource, limit, action)) in &state.scoped_limits {
+            let rule = rctl::Rule {
+                subject: scope.subject(),
+                resource: *resource,
+                limit: limit.clone(),
+                action: *action,
+            };
 
-        Ok(())
+            rule.remove().map_err(JailError::RctlError)?;
+            rctl_rules_removed = Some(rctl_rules_removed.unwrap_or(0) + 1);
+        }
+
+        Ok(KillReport {
+            jid: self.jid,
+            name,
+            was_dying,
+            processes_killed,
+            rctl_rules_removed,
+            unmounted: state
+                .mounts
+                .into_iter()
+                .filter(|target| !still_mounted.contains(target))
+                .collect(),
+            still_mounted,
+        })
     }
 
     /// Create a StoppedJail from a RunningJail, while not consuming the
@@ -341,38 +734,212 @@ impl RunningJail {
     /// ```
     pub fn save(self: &RunningJail) -> Result<StoppedJail, JailError> {
         trace!("RunningJail::save({:?})", self);
+        self.capture(true).map(|(stopped, _)| stopped)
+    }
+
+    /// Create a StoppedJail from a RunningJail like [RunningJail::save],
+    /// leaving out what cannot be read instead of failing.
+    ///
+    /// Returns the configuration, and the parameters, addresses, metadata
+    /// and limits that could not be captured. This lets backups succeed for
+    /// degraded jails, e.g. nested jails where some parameters cannot be
+    /// read. Only failing to read the path of the jail is an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_save_lossy")
+    /// #     .start()
+    /// #     .unwrap();
+    /// let (stopped, uncaptured) = running
+    ///     .save_lossy()
+    ///     .expect("could not read jail path");
+    ///
+    /// for u in &uncaptured {
+    ///     eprintln!("not saved: {}: {}", u.item, u.error);
+    /// }
+    /// assert_eq!(stopped.name, Some("testjail_save_lossy".into()));
+    /// # running.kill().unwrap();
+    /// ```
+    pub fn save_lossy(self: &RunningJail) -> Result<(StoppedJail, Vec<Uncaptured>), JailError> {
+        trace!("RunningJail::save_lossy({:?})", self);
+        self.capture(false)
+    }
+
+    /// Read the configuration of the jail. Unless `strict`, errors are
+    /// collected instead of returned.
+    fn capture(&self, strict: bool) -> Result<(StoppedJail, Vec<Uncaptured>), JailError> {
         let mut stopped = StoppedJail::new(self.path()?);
+        let mut uncaptured = vec![];
+        let mut omit = |item: &str, error: JailError| {
+            if strict {
+                return Err(error);
+            }
+            uncaptured.push(Uncaptured {
+                item: item.to_string(),
+                error,
+            });
+            Ok(())
+        };
+
+        // The name and hostname are optional, so failing to read them is
+        // not an error even if `strict`.
+        match self.name() {
+            Ok(name) => stopped.name = Some(name),
+            Err(e) => {
+                let _ = omit("name", e);
+            }
+        }
+        match self.hostname() {
+            Ok(hostname) => stopped.hostname = Some(hostname),
+            Err(e) => {
+                let _ = omit("host.hostname", e);
+            }
+        }
+
+        match self.ips() {
+            Ok(ips) => stopped.ips = ips,
+            Err(e) => omit("ip4.addr, ip6.addr", e)?,
+        }
+
+        if strict {
+            stopped.params = self.params()?;
+        } else {
+            let mut failed = vec![];
+            stopped.params = param::get_all_lossy(self.jid, &mut failed)?;
+            for (name, e) in failed {
+                omit(&name, e)?;
+            }
+        }
 
-        stopped.name = self.name().ok();
-        stopped.hostname = self.hostname().ok();
-        stopped.ips = self.ips()?;
-        stopped.params = self.params()?;
+        stopped.disk_limit = self.disk_usage().ok().and_then(|usage| usage.limit);
+        stopped.scheduling = self.scheduling();
+        match self.meta() {
+            Ok(meta) => stopped.meta = meta,
+            Err(e) => omit("meta", e)?,
+        }
+        stopped.params.remove("meta");
+        if let Some(param::Value::String(env)) = stopped.params.remove("env") {
+            stopped.env = param::decode_meta(&env);
+        }
 
         // Save RCTL rules
+        #[cfg(feature = "rctl")]
         if rctl::State::check().is_enabled() {
             let name = self.name();
 
             if let Ok(name) = name {
                 let filter: rctl::Filter = rctl::Subject::jail_name(name).into();
-                for rctl::Rule {
-                    subject: _,
-                    resource,
-                    limit,
-                    action,
-                } in filter.rules().map_err(JailError::RctlError)?.into_iter()
-                {
-                    stopped.limits.push((resource, limit, action));
+                match filter.rules() {
+                    Ok(rules) => {
+                        for rctl::Rule {
+                            subject: _,
+                            resource,
+                            limit,
+                            action,
+                        } in rules.into_iter()
+                        {
+                            stopped.limits.push((resource, limit, action));
+                        }
+                    }
+                    Err(e) => omit("limits", JailError::RctlError(e))?,
                 }
             }
         }
 
+        #[cfg(feature = "rctl")]
+        {
+            stopped.scoped_limits = registry::get(self.jid).scoped_limits;
+        }
+
+        let state = registry::get(self.jid);
+        stopped.exec = state.exec;
+        stopped.exec_hooks = state.exec_hooks;
+        stopped.devfs = state.devfs;
+        stopped.unmount_policy = state.unmount_policy;
+        stopped.loopback_aliases = !state.loopback_aliases.is_empty();
+
         // Special-Case VNET. Non-VNET jails have the "vnet" parameter set to
         // "inherit" (2).
         if stopped.params.get("vnet") == Some(&param::Value::Int(2)) {
             stopped.params.remove("vnet");
         }
 
-        Ok(stopped)
+        Ok((stopped, uncaptured))
+    }
+
+    /// Summarize the jail in aligned, human-readable lines, like `jls -v`
+    /// followed by the `RCTL` rules of the jail as listed by `rctl -h`.
+    ///
+    /// The configuration is read with [RunningJail::save_lossy], so that
+    /// degraded jails can still be described. Fields that could not be read
+    /// are listed as `unreadable`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_describe")
+    /// #     .start()
+    /// #     .unwrap();
+    /// let description = running.describe().expect("could not describe jail");
+    /// println!("{}", description);
+    ///
+    /// let row = |label: &str| {
+    ///     description
+    ///         .lines()
+    ///         .find(|line| line.starts_with(label))
+    ///         .map(|line| line[label.len()..].trim())
+    /// };
+    /// assert_eq!(row("jid"), Some(running.jid.to_string().as_str()));
+    /// assert_eq!(row("state"), Some("ACTIVE"));
+    /// assert_eq!(row("name"), Some("testjail_describe"));
+    /// # running.kill().unwrap();
+    /// ```
+    pub fn describe(&self) -> Result<String, JailError> {
+        trace!("RunningJail::describe({:?})", self);
+        let dying = matches!(self.param("dying"), Ok(param::Value::Int(dying)) if dying != 0);
+        let (stopped, uncaptured) = self.save_lossy()?;
+
+        let mut table = Table::default();
+        table.row("jid", Some(self.jid));
+        table.row("state", Some(if dying { "DYING" } else { "ACTIVE" }));
+        table.jail(&stopped);
+        table.row(
+            "unreadable",
+            uncaptured
+                .iter()
+                .map(|u| format!("{}: {}", u.item, u.error)),
+        );
+        Ok(table.render())
+    }
+
+    /// Take a snapshot of the state of the jail.
+    ///
+    /// The returned [JailView] is immutable and can be shared between
+    /// threads, e.g. to render the jail without reading its state again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_view_method")
+    /// #     .hostname("view.example.com")
+    /// #     .start()
+    /// #     .unwrap();
+    /// let view = running.view().expect("could not read jail");
+    /// assert_eq!(view.jid, running.jid);
+    /// assert_eq!(view.hostname, "view.example.com");
+    /// assert!(!view.dying);
+    /// # running.kill().unwrap();
+    /// ```
+    pub fn view(&self) -> Result<JailView, JailError> {
+        trace!("RunningJail::view({:?})", self);
+        JailView::of(self)
     }
 
     /// Stop a jail, keeping its configuration in a StoppedJail.
@@ -397,106 +964,1152 @@ impl RunningJail {
     /// ```
     pub fn stop(self: RunningJail) -> Result<StoppedJail, JailError> {
         trace!("RunningJail::stop({:?})", self);
+        self.stop_with(&StopPolicy::immediate())
+    }
+
+    /// Stop a jail like [RunningJail::stop], terminating its processes
+    /// according to `policy`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::StopPolicy;
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_stop_with")
+    /// #     .start()
+    /// #     .unwrap();
+    /// let stopped = running
+    ///     .stop_with(&StopPolicy::default())
+    ///     .expect("failed to stop jail");
+    ///
+    /// assert_eq!(stopped.name, Some("testjail_stop_with".into()));
+    /// ```
+    pub fn stop_with(self: RunningJail, policy: &StopPolicy) -> Result<StoppedJail, JailError> {
+        trace!("RunningJail::stop_with({:?}, policy={:?})", self, policy);
         let stopped = self.save()?;
-        self.kill()?;
+        self.shutdown(policy)?;
 
         Ok(stopped)
     }
 
-    /// Restart a jail by stopping it and starting it again
+    /// Terminate the processes of a jail according to `policy`, then remove
+    /// it like [RunningJail::kill_with_report].
     ///
-    /// This is a wrapper around `RunningJail::stop` and `StoppedJail::start`
+    /// If processes are still running after the grace period and the policy
+    /// does not escalate, this fails with [JailError::StopTimeout] and the
+    /// jail keeps running.
     ///
     /// # Examples
     ///
     /// ```
+    /// use jail::{JailError, Signal, StopPolicy};
+    /// use jail::process::Jailed;
+    /// use std::process::Command;
+    /// use std::time::Duration;
     /// # use jail::StoppedJail;
     /// # let running = StoppedJail::new("/rescue")
-    /// #     .name("testjail_restart")
+    /// #     .name("testjail_shutdown")
     /// #     .start()
     /// #     .unwrap();
     ///
-    /// let old_jid = running.jid;
-    /// let running = running.restart()
+    /// // sh ignores SIGHUP here, so it outlives the grace period.
+    /// let mut child = Command::new("/sh")
+    ///     .args(&["-c", "trap '' HUP; sleep 30"])
+    ///     .jail(&running)
+    ///     .spawn()
+    ///     .unwrap();
+    /// # std::thread::sleep(Duration::from_millis(200));
+    ///
+    /// let gentle = StopPolicy::default()
+    ///     .signal(Signal::Hup)
+    ///     .grace(Duration::from_millis(500))
+    ///     .escalate(false);
+    /// assert!(matches!(
+    ///     running.shutdown(&gentle),
+    ///     Err(JailError::StopTimeout { .. })
+    /// ));
+    ///
+    /// let report = running.shutdown(&gentle.escalate(true)).unwrap();
+    /// assert!(report.processes_killed > 0);
+    /// # child.wait().unwrap();
+    /// ```
+    pub fn shutdown(self, policy: &StopPolicy) -> Result<KillReport, JailError> {
+        trace!("RunningJail::shutdown({:?}, policy={:?})", self, policy);
+        let remaining = policy.terminate(self.jid)?;
+        if remaining > 0 && !policy.escalate {
+            return Err(JailError::StopTimeout {
+                jid: self.jid,
+                remaining,
+            });
+        }
+
+        self.kill_with_report()
+    }
+
+    /// Restart a jail by stopping it and starting it again
+    ///
+    /// This is a wrapper around `RunningJail::stop` and `StoppedJail::start`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_restart")
+    /// #     .start()
+    /// #     .unwrap();
+    ///
+    /// let old_jid = running.jid;
+    /// let running = running.restart()
     ///     .expect("failed to restart jail");
     /// assert!(running.jid != old_jid);
     ///
     /// # running.kill();
     /// ```
-    pub fn restart(self: RunningJail) -> Result<RunningJail, JailError> {
-        trace!("RunningJail::restart({:?})", self);
-        let stopped = self.stop()?;
-        stopped.start()
+    pub fn restart(self: RunningJail) -> Result<RunningJail, JailError> {
+        trace!("RunningJail::restart({:?})", self);
+        let stopped = self.stop()?;
+        stopped.start()
+    }
+
+    /// Replace a jail by a new one, starting the new jail before killing the
+    /// old one.
+    ///
+    /// This is a wrapper around [RunningJail::replace_with] without
+    /// temporary addresses or readiness check.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_replace")
+    /// #     .start()
+    /// #     .unwrap();
+    /// let new = StoppedJail::new("/rescue")
+    ///     .name("testjail_replace")
+    ///     .hostname("v2.example.com");
+    ///
+    /// let running = running.replace(new).expect("failed to replace jail");
+    /// assert_eq!(running.name().unwrap(), "testjail_replace");
+    /// assert_eq!(running.hostname().unwrap(), "v2.example.com");
+    /// # running.kill();
+    /// ```
+    pub fn replace(self: RunningJail, new: StoppedJail) -> Result<RunningJail, JailError> {
+        trace!("RunningJail::replace({:?}, new={:?})", self, new);
+        self.replace_with(new, vec![], |_| Ok(()))
+    }
+
+    /// Replace a jail by a new one, starting the new jail before killing the
+    /// old one.
+    ///
+    /// Unlike [RunningJail::restart], this keeps the downtime to renaming
+    /// the jail and moving its addresses:
+    ///
+    /// 1. `new` is started under a temporary name, with `temporary_ips`
+    ///    instead of its own addresses and without its resource limits.
+    /// 2. `ready` is called with the new jail. If it fails, the new jail is
+    ///    killed and the old one is left running.
+    /// 3. The old jail is killed.
+    /// 4. The new jail is renamed, gets its own addresses and its resource
+    ///    limits are applied.
+    ///
+    /// If `new` has no name, the name of the old jail is used. If the last
+    /// step fails, the new jail keeps running under its temporary name.
+    /// The temporary addresses should be of the same families as the final
+    /// ones. Jails with mounts can only be replaced by jails without mounts
+    /// and vice versa.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::process::Jailed;
+    /// use jail::JailError;
+    /// use std::process::Command;
+    ///
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_replace_with")
+    /// #     .ip("127.0.1.10".parse().unwrap())
+    /// #     .start()
+    /// #     .unwrap();
+    /// let new = StoppedJail::new("/rescue").ip("127.0.1.10".parse().unwrap());
+    ///
+    /// let running = running
+    ///     .replace_with(new, vec!["127.0.1.11".parse().unwrap()], |new| {
+    ///         let status = Command::new("/rescue/true")
+    ///             .jail(new)
+    ///             .status()
+    ///             .map_err(JailError::IoError)?;
+    ///         match status.success() {
+    ///             true => Ok(()),
+    ///             false => Err(JailError::NotSupported("not ready".into())),
+    ///         }
+    ///     })
+    ///     .expect("failed to replace jail");
+    ///
+    /// assert_eq!(running.name().unwrap(), "testjail_replace_with");
+    /// assert_eq!(running.ips().unwrap(), vec!["127.0.1.10".parse::<std::net::IpAddr>().unwrap()]);
+    /// # running.kill();
+    /// ```
+    pub fn replace_with<F>(
+        self: RunningJail,
+        new: StoppedJail,
+        temporary_ips: Vec<net::IpAddr>,
+        ready: F,
+    ) -> Result<RunningJail, JailError>
+    where
+        F: FnOnce(&RunningJail) -> Result<(), JailError>,
+    {
+        trace!(
+            "RunningJail::replace_with({:?}, new={:?}, temporary_ips={:?})",
+            self,
+            new,
+            temporary_ips
+        );
+        let name = match new.name {
+            Some(ref name) => name.clone(),
+            None => self.name()?,
+        };
+
+        // Unmounting by path would remove the mounts of the new jail.
+        let mounts = !new.mounts.is_empty()
+            || new.devfs.is_some()
+            || new.provision.iter().any(|step| step.mounts());
+        if mounts && !registry::get(self.jid).mounts.is_empty() {
+            return Err(JailError::NotSupported(
+                "replacing a jail with mounts by one with mounts".into(),
+            ));
+        }
+
+        let mut temporary = new.clone();
+        temporary.name = Some(format!("{}_replacement_{}", name, self.jid));
+        temporary.ips = temporary_ips.clone();
+        #[cfg(feature = "rctl")]
+        {
+            temporary.limits = vec![];
+            temporary.scoped_limits = vec![];
+        }
+
+        let replacement = temporary.start()?;
+        if let Err(e) = ready(&replacement) {
+            replacement.kill()?;
+            return Err(e);
+        }
+
+        self.kill()?;
+
+        replacement.param_set("name", param::Value::String(name.clone()))?;
+
+        // Move the addresses of each family that was or is to be used.
+        let ipv4: Vec<net::Ipv4Addr> = new
+            .ips
+            .iter()
+            .filter_map(|ip| match ip {
+                net::IpAddr::V4(ip) => Some(*ip),
+                _ => None,
+            })
+            .collect();
+        let ipv6: Vec<net::Ipv6Addr> = new
+            .ips
+            .iter()
+            .filter_map(|ip| match ip {
+                net::IpAddr::V6(ip) => Some(*ip),
+                _ => None,
+            })
+            .collect();
+
+        if !ipv4.is_empty() || temporary_ips.iter().any(net::IpAddr::is_ipv4) {
+            replacement.param_set("ip4.addr", param::Value::Ipv4Addrs(ipv4))?;
+        }
+        if !ipv6.is_empty() || temporary_ips.iter().any(net::IpAddr::is_ipv6) {
+            replacement.param_set("ip6.addr", param::Value::Ipv6Addrs(ipv6))?;
+        }
+
+        #[cfg(feature = "rctl")]
+        if !new.limits.is_empty() {
+            new.apply_limits(&name)?;
+        }
+
+        #[cfg(feature = "rctl")]
+        new.apply_scoped_limits(replacement.jid)?;
+
+        Ok(replacement)
+    }
+
+    /// Returns an Iterator over all running jails on this host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// # use jail::StoppedJail;
+    /// # let mut running_jails: Vec<RunningJail> = (1..5)
+    /// #     .map(|i| {
+    /// #         StoppedJail::new("/rescue")
+    /// #             .name(format!("testjail_iterate_{}", i))
+    /// #             .start()
+    /// #             .expect("failed to start jail")
+    /// #     })
+    /// #     .collect();
+    ///
+    /// for running in RunningJail::all() {
+    ///     println!("jail: {}", running.name().unwrap());
+    /// }
+    /// #
+    /// # for to_kill in running_jails.drain(..) {
+    /// #     to_kill.kill().expect("failed to kill jail");
+    /// # }
+    /// ```
+    pub fn all() -> RunningJails {
+        trace!("RunningJail::all()");
+        RunningJails::default()
+    }
+
+    /// Get the `RCTL` / `RACCT` usage statistics for this jail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use jail::{StoppedJail, JailError};
+    /// #
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_racct")
+    /// #     .start()
+    /// #     .expect("Could not start jail");
+    /// match running.racct_statistics() {
+    ///     Ok(stats) => println!("{:#?}", stats),
+    ///     Err(e) => println!("Error: {}", e),
+    /// };
+    /// #
+    /// # running.kill();
+    /// ```
+    #[cfg(feature = "rctl")]
+    pub fn racct_statistics(&self) -> Result<HashMap<rctl::Resource, usize>, JailError> {
+        trace!("RunningJail::racct_statistics({:?})", self);
+        // First let's try to get the RACCT statistics in the happy path
+        rctl::Subject::jail_name(self.name()?)
+            .usage()
+            .map_err(JailError::RctlError)
+    }
+
+    /// Get the disk space used by the jail root and its limit.
+    ///
+    /// The jail root must be the mountpoint of a ZFS dataset, and the `zfs`
+    /// feature must be enabled. See [StoppedJail::disk_limit].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/jails/www")
+    /// #     .name("testjail_disk_usage")
+    /// #     .disk_limit(1024 * 1024 * 1024)
+    /// #     .start()
+    /// #     .unwrap();
+    /// let usage = running.disk_usage().expect("could not get disk usage");
+    /// assert_eq!(usage.limit, Some(1024 * 1024 * 1024));
+    /// println!("{} bytes used", usage.used);
+    /// #
+    /// # running.kill();
+    /// ```
+    pub fn disk_usage(&self) -> Result<DiskUsage, JailError> {
+        trace!("RunningJail::disk_usage({:?})", self);
+        #[cfg(feature = "zfs")]
+        {
+            let (used, limit) = zfs::Dataset::at(&self.path()?)?.usage()?;
+            Ok(DiskUsage { used, limit })
+        }
+
+        #[cfg(not(feature = "zfs"))]
+        Err(JailError::NotSupported(
+            "disk usage requires the `zfs` feature".into(),
+        ))
+    }
+
+    /// Get the traffic counters of the network interfaces of a VNET jail.
+    ///
+    /// The interfaces are listed from inside the jail by a forked child
+    /// process attached to it. Jails sharing the network stack of the host
+    /// fail with [JailError::NotSupported], since their traffic cannot be
+    /// told apart.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::{param, StoppedJail};
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_network_stats")
+    /// #     .param("vnet", param::Value::Int(1))
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// let stats = running.network_stats().expect("could not get statistics");
+    ///
+    /// let lo0 = stats.iter().find(|i| i.name == "lo0").unwrap();
+    /// println!("lo0: {} bytes received", lo0.rx_bytes);
+    /// #
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn network_stats(&self) -> Result<Vec<InterfaceStats>, JailError> {
+        trace!("RunningJail::network_stats({:?})", self);
+        if self.param("vnet")? != param::Value::Int(1) {
+            return Err(JailError::NotSupported(format!(
+                "jail {} does not have its own network stack",
+                self.jid
+            )));
+        }
+
+        let output = sys::attach_in_fork(self.jid, || {
+            sys::interface_counters().map(String::into_bytes)
+        })?;
+
+        let invalid = |line: &str| {
+            JailError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected interface statistics {:?}", line),
+            ))
+        };
+
+        String::from_utf8_lossy(&output)
+            .lines()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(' ').collect();
+                let counters: Vec<u64> = fields[1..]
+                    .iter()
+                    .map(|field| field.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid(line))?;
+
+                match counters[..] {
+                    [rx_bytes, tx_bytes, rx_packets, tx_packets, rx_errors, tx_errors] => {
+                        Ok(InterfaceStats {
+                            name: fields[0].to_string(),
+                            rx_bytes,
+                            tx_bytes,
+                            rx_packets,
+                            tx_packets,
+                            rx_errors,
+                            tx_errors,
+                        })
+                    }
+                    _ => Err(invalid(line)),
+                }
+            })
+            .collect()
+    }
+
+    /// Get a snapshot of the processes running in the jail, arranged by
+    /// parent like `ps auxd` run inside the jail.
+    ///
+    /// Processes whose parent is outside the jail, e.g. commands run with
+    /// `jexec`(8), are the roots of the returned trees. Processes in child
+    /// jails are not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::process::Jailed;
+    /// use std::process::Command;
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_process_tree")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///     .arg("10")
+    ///     .jail(&running)
+    ///     .spawn()
+    ///     .expect("could not spawn process");
+    ///
+    /// let tree = running.process_tree().expect("could not list processes");
+    /// assert_eq!(tree.len(), 1);
+    /// assert_eq!(tree[0].pid, child.id() as i32);
+    /// assert_eq!(tree[0].args, vec!["/sleep", "10"]);
+    /// # child.kill().unwrap();
+    /// # child.wait().unwrap();
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn process_tree(&self) -> Result<Vec<ProcessNode>, JailError> {
+        trace!("RunningJail::process_tree({:?})", self);
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+
+        let processes = sys::processes()?
+            .iter()
+            .filter(|p| p.ki_jid == self.jid)
+            .map(|p| ProcessNode::from_kinfo(p, page_size))
+            .collect();
+
+        Ok(crate::process::build_tree(processes))
+    }
+
+    /// Count the processes attached to the jail.
+    ///
+    /// A jail without the `persist` flag (see [RunningJail::defer_cleanup])
+    /// is removed by the kernel once this drops to zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::process::Jailed;
+    /// use jail::StoppedJail;
+    /// use std::process::Command;
+    ///
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_attached_process_count")
+    ///     .start()
+    ///     .expect("could not start jail");
+    /// assert_eq!(running.attached_process_count().unwrap(), 0);
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///     .arg("10")
+    ///     .jail(&running)
+    ///     .spawn()
+    ///     .expect("could not spawn process");
+    /// assert_eq!(running.attached_process_count().unwrap(), 1);
+    /// # child.kill().unwrap();
+    /// # child.wait().unwrap();
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn attached_process_count(&self) -> Result<usize, JailError> {
+        trace!("RunningJail::attached_process_count({:?})", self);
+        Ok(sys::jail_pids(self.jid)?.len())
+    }
+
+    /// Stop all processes in the jail with `SIGSTOP`, e.g. to take a
+    /// consistent snapshot of its file systems.
+    ///
+    /// Processes forked while the jail is being frozen are stopped as well.
+    /// Processes attached later, e.g. with [RunningJail::exec], are not.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::process::Jailed;
+    /// use jail::StoppedJail;
+    /// use std::process::Command;
+    ///
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_freeze")
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///     .arg("10")
+    ///     .jail(&running)
+    ///     .spawn()
+    ///     .expect("could not spawn process");
+    ///
+    /// running.freeze().expect("could not freeze jail");
+    /// let tree = running.process_tree().unwrap();
+    /// assert_eq!(tree[0].state, jail::process::ProcessState::Stopped);
+    ///
+    /// running.thaw().expect("could not thaw jail");
+    /// # child.kill().unwrap();
+    /// # child.wait().unwrap();
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn freeze(&self) -> Result<(), JailError> {
+        trace!("RunningJail::freeze({:?})", self);
+        let mut stopped = vec![];
+        loop {
+            let pids: Vec<libc::pid_t> = sys::jail_pids(self.jid)?
+                .into_iter()
+                .filter(|pid| !stopped.contains(pid))
+                .collect();
+            if pids.is_empty() {
+                return Ok(());
+            }
+
+            signal(&pids, libc::SIGSTOP)?;
+            stopped.extend(pids);
+        }
+    }
+
+    /// Continue all processes in the jail with `SIGCONT`, after
+    /// [RunningJail::freeze].
+    pub fn thaw(&self) -> Result<(), JailError> {
+        trace!("RunningJail::thaw({:?})", self);
+        signal(&sys::jail_pids(self.jid)?, libc::SIGCONT)
+    }
+
+    /// Watch the processes attached to the jail in a background thread,
+    /// calling `on_empty` whenever their count drops to zero.
+    ///
+    /// Supervisors relying on [RunningJail::defer_cleanup] use this to log
+    /// or alert when the kernel reaps the jail. The count is polled every
+    /// `interval`, so the jail may already be removed when `on_empty` runs.
+    /// Watching stops when the returned [ProcessWatch] is stopped or
+    /// dropped, or when the jail is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::process::Jailed;
+    /// use jail::StoppedJail;
+    /// use std::process::Command;
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_watch_processes")
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///     .arg("1")
+    ///     .jail(&running)
+    ///     .spawn()
+    ///     .expect("could not spawn process");
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let watch = running.watch_processes(Duration::from_millis(100), move |jail| {
+    ///     tx.send(jail.jid).unwrap();
+    /// });
+    /// running.defer_cleanup().expect("could not defer cleanup");
+    ///
+    /// child.wait().expect("could not wait for child");
+    /// assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(running.jid));
+    /// watch.stop();
+    /// ```
+    pub fn watch_processes<F>(&self, interval: Duration, on_empty: F) -> ProcessWatch
+    where
+        F: FnMut(&RunningJail) + Send + 'static,
+    {
+        trace!(
+            "RunningJail::watch_processes({:?}, interval={:?})",
+            self,
+            interval
+        );
+        ProcessWatch::spawn(*self, interval, on_empty)
+    }
+
+    /// Get the metadata of the jail, see [StoppedJail::meta].
+    ///
+    /// On kernels without the `meta` parameter, the metadata is only known
+    /// for jails started or annotated by this process.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_running_meta")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// running.set_meta("owner", "bob").expect("could not set metadata");
+    ///
+    /// let meta = running.meta().expect("could not get metadata");
+    /// assert_eq!(meta.get("owner").map(String::as_str), Some("bob"));
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn meta(&self) -> Result<BTreeMap<String, String>, JailError> {
+        trace!("RunningJail::meta({:?})", self);
+        match param::meta_supported() {
+            true => Ok(param::decode_meta(&self.param("meta")?.unpack_string()?)),
+            false => Ok(registry::get(self.jid).meta),
+        }
+    }
+
+    /// Set a metadata entry of the jail, see [RunningJail::meta].
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<(), JailError> {
+        trace!(
+            "RunningJail::set_meta({:?}, key={:?}, value={:?})",
+            self,
+            key,
+            value
+        );
+        let mut meta = self.meta()?;
+        meta.insert(key.into(), value.into());
+        let encoded = param::encode_meta("meta", &meta)?;
+
+        match param::meta_supported() {
+            true => self.param_set("meta", param::Value::String(encoded)),
+            false => {
+                registry::update(self.jid, |state| state.meta = meta);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the metadata of the jail that is also visible inside the jail, see
+    /// [StoppedJail::env].
+    ///
+    /// Fails with [JailError::UnsupportedOnRelease] on releases before
+    /// FreeBSD 15.
+    pub fn env(&self) -> Result<BTreeMap<String, String>, JailError> {
+        trace!("RunningJail::env({:?})", self);
+        oscompat::require(Feature::Metadata)?;
+        if !param::meta_supported() {
+            return Err(JailError::NotSupported(
+                "the env parameter requires FreeBSD 15 or later".into(),
+            ));
+        }
+
+        Ok(param::decode_meta(&self.param("env")?.unpack_string()?))
+    }
+
+    /// Get the scheduling class and priority applied to new processes in the
+    /// jail, if any.
+    ///
+    /// This is only known for jails started or configured by this process.
+    pub fn scheduling(&self) -> Option<Scheduling> {
+        trace!("RunningJail::scheduling({:?})", self);
+        registry::get(self.jid).scheduling
+    }
+
+    /// Set the scheduling class and priority of all processes in the jail.
+    ///
+    /// The setting is applied to all processes currently running in the jail,
+    /// and to all processes spawned in it using
+    /// [Jailed::jail](process/trait.Jailed.html#tymethod.jail) from now on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::sched::Scheduling;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_set_scheduling")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// running.set_scheduling(Scheduling::Nice(10))
+    ///     .expect("could not set scheduling");
+    /// assert_eq!(running.scheduling(), Some(Scheduling::Nice(10)));
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn set_scheduling(&self, scheduling: Scheduling) -> Result<(), JailError> {
+        trace!(
+            "RunningJail::set_scheduling({:?}, scheduling={:?})",
+            self,
+            scheduling
+        );
+        registry::update(self.jid, |state| state.scheduling = Some(scheduling));
+        scheduling
+            .apply_all(&sys::jail_pids(self.jid)?)
+            .map_err(JailError::IoError)
+    }
+
+    /// Jail the current process into the given jail.
+    pub fn attach(&self) -> Result<(), JailError> {
+        trace!("RunningJail::attach({:?})", self);
+        let result = sys::jail_attach(self.jid);
+
+        #[cfg(feature = "audit")]
+        audit::emit(
+            Operation::Attach,
+            self.jid,
+            self.name().ok().as_deref(),
+            None,
+            &result,
+        );
+
+        #[cfg(feature = "journal")]
+        journal::record(
+            journal::Operation::Attach,
+            self.jid,
+            self.name().ok().as_deref(),
+            &result,
+            |_| {},
+        );
+
+        result
+    }
+
+    /// Spawn a command inside the jail as a [JailTask].
+    ///
+    /// The standard output and standard error of the command are captured
+    /// and streamed line by line through the channels of the returned task.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::process::Command;
+    /// use std::time::Duration;
+    /// # let jail = jail::StoppedJail::new("/rescue")
+    /// #     .name("testjail_spawn_task")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let mut task = jail
+    ///     .spawn_task(Command::new("/sleep").arg("10"))
+    ///     .expect("could not spawn task");
+    ///
+    /// let finished = task
+    ///     .wait_timeout(Duration::from_millis(100))
+    ///     .expect("could not wait for task");
+    /// assert!(finished.is_none());
+    ///
+    /// task.kill().expect("could not kill task");
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    pub fn spawn_task(&self, command: &mut process::Command) -> Result<JailTask, JailError> {
+        trace!("RunningJail::spawn_task({:?}, command={:?})", self, command);
+        JailTask::spawn(self, command)
+    }
+
+    /// Translate a path inside the jail into a path on the host.
+    ///
+    /// Symbolic links are resolved relative to the jail root, so the returned
+    /// path is always below it. See [fs::host_path] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # use std::path::PathBuf;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_host_path")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// let host = running.host_path("/etc/rc.conf")
+    ///     .expect("could not translate path");
+    /// assert_eq!(host, PathBuf::from("/rescue/etc/rc.conf"));
+    /// # running.kill();
+    /// ```
+    pub fn host_path<P: AsRef<path::Path>>(&self, path: P) -> Result<path::PathBuf, JailError> {
+        trace!(
+            "RunningJail::host_path({:?}, path={:?})",
+            self,
+            path.as_ref()
+        );
+        fs::host_path(&self.path()?, path.as_ref())
+    }
+
+    /// Translate a path on the host into the path seen inside the jail.
+    ///
+    /// Returns `None` if the path is outside of the jail root. See
+    /// [fs::jail_path] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # use std::path::PathBuf;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_jail_path")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// let inside = running.jail_path("/rescue/sh")
+    ///     .expect("could not translate path");
+    /// assert_eq!(inside, Some(PathBuf::from("/sh")));
+    ///
+    /// let outside = running.jail_path("/etc/passwd")
+    ///     .expect("could not translate path");
+    /// assert_eq!(outside, None);
+    /// # running.kill();
+    /// ```
+    pub fn jail_path<P: AsRef<path::Path>>(
+        &self,
+        host_path: P,
+    ) -> Result<Option<path::PathBuf>, JailError> {
+        trace!(
+            "RunningJail::jail_path({:?}, host_path={:?})",
+            self,
+            host_path.as_ref()
+        );
+        Ok(fs::jail_path(&self.path()?, host_path.as_ref()))
+    }
+
+    /// Run a command inside the jail and capture its output, telling apart
+    /// the common reasons for failure.
+    ///
+    /// Unlike running the command using [Jailed](process/trait.Jailed.html),
+    /// an unsuccessful exit status is an error, and errors are classified as
+    /// [ExecError], e.g. distinguishing a program missing inside the jail from
+    /// a shared library missing in a thin jail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::process::ExecError;
+    /// use std::process::Command;
+    /// # let running = jail::StoppedJail::new("/rescue")
+    /// #     .name("testjail_exec")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let output = running.exec(&mut Command::new("/echo").arg("hello")).unwrap();
+    /// assert_eq!(output.stdout, b"hello\n");
+    ///
+    /// let missing = running.exec(&mut Command::new("/usr/local/bin/nginx"));
+    /// assert!(matches!(missing, Err(ExecError::NotFound(_))));
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn exec(&self, command: &mut process::Command) -> Result<process::Output, ExecError> {
+        trace!("RunningJail::exec({:?}, command={:?})", self, command);
+        crate::process::exec(self, command)
+    }
+
+    /// Run a command inside the jail like [RunningJail::exec], but keep at
+    /// most [CaptureLimit::max_bytes] of each of its standard output and
+    /// standard error.
+    ///
+    /// This keeps a misbehaving command from exhausting the memory of the
+    /// caller with unbounded output. Standard input is closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::process::{CaptureLimit, Overflow};
+    /// use std::process::Command;
+    /// # let running = jail::StoppedJail::new("/rescue")
+    /// #     .name("testjail_exec_capped")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let output = running
+    ///     .exec_capped(
+    ///         Command::new("/echo").arg("hello world"),
+    ///         CaptureLimit::new(5),
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(output.stdout, b"hello\n[7 bytes truncated]\n");
+    ///
+    /// let output = running
+    ///     .exec_capped(
+    ///         Command::new("/echo").arg("hello world"),
+    ///         CaptureLimit::new(5).overflow(Overflow::Discard),
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(output.stdout, b"hello");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn exec_capped(
+        &self,
+        command: &mut process::Command,
+        limit: CaptureLimit,
+    ) -> Result<process::Output, ExecError> {
+        trace!(
+            "RunningJail::exec_capped({:?}, command={:?}, limit={:?})",
+            self,
+            command,
+            limit
+        );
+        crate::process::exec_capped(self, command, limit)
+    }
+
+    /// Get an `rc.d` service of the jail, to start, stop or query it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::service::Status;
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_service")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let sshd = running.service("sshd");
+    /// assert_eq!(sshd.name(), "sshd");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn service(&self, name: &str) -> Service {
+        trace!("RunningJail::service({:?}, name={:?})", self, name);
+        Service::new(*self, name)
+    }
+
+    /// Replace `/etc/resolv.conf` in the jail root with that of the host.
+    ///
+    /// Call this whenever the resolver configuration of the host changes to
+    /// keep the jail in sync, see [ResolvConf](provision/struct.ResolvConf.html).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/jails/www")
+    /// #     .name("testjail_sync_resolv_conf")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// running
+    ///     .sync_resolv_conf()
+    ///     .expect("could not update resolv.conf");
+    /// # running.kill();
+    /// ```
+    pub fn sync_resolv_conf(&self) -> Result<(), JailError> {
+        trace!("RunningJail::sync_resolv_conf({:?})", self);
+        let resolv_conf = path::Path::new(provision::RESOLV_CONF);
+        let contents = std::fs::read(resolv_conf).map_err(JailError::IoError)?;
+        fs::write_file(&self.path()?, resolv_conf, &contents, 0o644)
+    }
+
+    /// Follow a file inside the jail, yielding lines as they are appended.
+    ///
+    /// `path` is resolved relative to the root of the jail, the same way a
+    /// process inside the jail would resolve it. In particular, symbolic links
+    /// cannot be used to escape the jail root. Rotated and truncated files
+    /// are followed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/")
+    /// #     .name("testjail_tail_file")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// for line in running.tail_file("/var/log/messages").unwrap() {
+    ///     println!("{}", line.unwrap());
+    /// }
+    /// # running.kill();
+    /// ```
+    pub fn tail_file<P: AsRef<path::Path>>(&self, path: P) -> Result<Tail, JailError> {
+        trace!(
+            "RunningJail::tail_file({:?}, path={:?})",
+            self,
+            path.as_ref()
+        );
+        Tail::follow(&self.path()?, path.as_ref())
+    }
+
+    /// Copy a file or directory from the host into the jail.
+    ///
+    /// Permissions are preserved and the copies are owned by the calling
+    /// user. `jail_dst` is resolved relative to the jail root, and symbolic
+    /// links are copied as links rather than followed, so files outside of
+    /// the jail can neither be read nor overwritten. If `jail_dst` is an
+    /// existing directory, `host_src` is copied into it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # let running = StoppedJail::new("/rescue")
+    /// #     .name("testjail_copy_in")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    /// # let tmp = std::env::temp_dir().join("testjail_copy_in");
+    /// # std::fs::write(&tmp, "hello").unwrap();
+    /// running.copy_in(&tmp, "/tmp/hello").expect("could not copy file");
+    /// # std::fs::remove_file(running.host_path("/tmp/hello").unwrap()).unwrap();
+    /// # running.kill();
+    /// ```
+    pub fn copy_in<S, D>(&self, host_src: S, jail_dst: D) -> Result<(), JailError>
+    where
+        S: AsRef<path::Path>,
+        D: AsRef<path::Path>,
+    {
+        trace!(
+            "RunningJail::copy_in({:?}, host_src={:?}, jail_dst={:?})",
+            self,
+            host_src.as_ref(),
+            jail_dst.as_ref()
+        );
+        self.copy_in_with(host_src, jail_dst, &CopyOptions::default())
     }
 
-    /// Returns an Iterator over all running jails on this host.
+    /// Copy a file or directory from the host into the jail with the given
+    /// options.
     ///
     /// # Examples
     ///
-    /// ```
-    /// use jail::RunningJail;
+    /// Translate owners by name using the `passwd` and `group` databases of
+    /// the host and the jail:
+    ///
+    /// ```no_run
     /// # use jail::StoppedJail;
-    /// # let mut running_jails: Vec<RunningJail> = (1..5)
-    /// #     .map(|i| {
-    /// #         StoppedJail::new("/rescue")
-    /// #             .name(format!("testjail_iterate_{}", i))
-    /// #             .start()
-    /// #             .expect("failed to start jail")
-    /// #     })
-    /// #     .collect();
+    /// use jail::fs::{CopyOptions, Ownership};
+    /// # let running = StoppedJail::new("/")
+    /// #     .name("testjail_copy_in_with")
+    /// #     .start()
+    /// #     .expect("could not start jail");
     ///
-    /// for running in RunningJail::all() {
-    ///     println!("jail: {}", running.name().unwrap());
-    /// }
-    /// #
-    /// # for to_kill in running_jails.drain(..) {
-    /// #     to_kill.kill().expect("failed to kill jail");
-    /// # }
+    /// let options = CopyOptions::new().ownership(Ownership::Remap);
+    /// running.copy_in_with("/usr/local/www/site", "/usr/local/www", &options)
+    ///     .expect("could not copy site");
+    /// # running.kill();
     /// ```
-    pub fn all() -> RunningJails {
-        trace!("RunningJail::all()");
-        RunningJails::default()
+    pub fn copy_in_with<S, D>(
+        &self,
+        host_src: S,
+        jail_dst: D,
+        options: &CopyOptions,
+    ) -> Result<(), JailError>
+    where
+        S: AsRef<path::Path>,
+        D: AsRef<path::Path>,
+    {
+        trace!(
+            "RunningJail::copy_in_with({:?}, host_src={:?}, jail_dst={:?}, options={:?})",
+            self,
+            host_src.as_ref(),
+            jail_dst.as_ref(),
+            options
+        );
+        let root = self.path()?;
+        fs::copy(
+            Side::Host,
+            host_src.as_ref(),
+            Side::Jail(&root),
+            jail_dst.as_ref(),
+            options,
+        )
     }
 
-    /// Get the `RCTL` / `RACCT` usage statistics for this jail.
+    /// Copy a file or directory from the jail to the host.
     ///
-    /// # Example
+    /// This is the counterpart of [RunningJail::copy_in]: `jail_src` is
+    /// resolved relative to the jail root and symbolic links inside the jail
+    /// are copied as links rather than followed.
+    ///
+    /// # Examples
     ///
     /// ```
-    /// # use jail::{StoppedJail, JailError};
-    /// #
+    /// # use jail::StoppedJail;
     /// # let running = StoppedJail::new("/rescue")
-    /// #     .name("testjail_racct")
+    /// #     .name("testjail_copy_out")
     /// #     .start()
-    /// #     .expect("Could not start jail");
-    /// match running.racct_statistics() {
-    ///     Ok(stats) => println!("{:#?}", stats),
-    ///     Err(e) => println!("Error: {}", e),
-    /// };
-    /// #
+    /// #     .expect("could not start jail");
+    /// let dst = std::env::temp_dir().join("testjail_copy_out_sh");
+    /// running.copy_out("/sh", &dst).expect("could not copy file");
+    /// # std::fs::remove_file(&dst).unwrap();
     /// # running.kill();
     /// ```
-    pub fn racct_statistics(&self) -> Result<HashMap<rctl::Resource, usize>, JailError> {
-        trace!("RunningJail::racct_statistics({:?})", self);
-        // First let's try to get the RACCT statistics in the happy path
-        rctl::Subject::jail_name(self.name()?)
-            .usage()
-            .map_err(JailError::RctlError)
+    pub fn copy_out<S, D>(&self, jail_src: S, host_dst: D) -> Result<(), JailError>
+    where
+        S: AsRef<path::Path>,
+        D: AsRef<path::Path>,
+    {
+        trace!(
+            "RunningJail::copy_out({:?}, jail_src={:?}, host_dst={:?})",
+            self,
+            jail_src.as_ref(),
+            host_dst.as_ref()
+        );
+        self.copy_out_with(jail_src, host_dst, &CopyOptions::default())
     }
 
-    /// Jail the current process into the given jail.
-    pub fn attach(&self) -> Result<(), JailError> {
-        trace!("RunningJail::attach({:?})", self);
-        let ret = unsafe { libc::jail_attach(self.jid) };
-        match ret {
-            0 => Ok(()),
-            -1 => Err(Error::last_os_error()),
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                "invalid return value from jail_attach",
-            )),
-        }
-        .map_err(JailError::JailAttachError)
+    /// Copy a file or directory from the jail to the host with the given
+    /// options.
+    pub fn copy_out_with<S, D>(
+        &self,
+        jail_src: S,
+        host_dst: D,
+        options: &CopyOptions,
+    ) -> Result<(), JailError>
+    where
+        S: AsRef<path::Path>,
+        D: AsRef<path::Path>,
+    {
+        trace!(
+            "RunningJail::copy_out_with({:?}, jail_src={:?}, host_dst={:?}, options={:?})",
+            self,
+            jail_src.as_ref(),
+            host_dst.as_ref(),
+            options
+        );
+        let root = self.path()?;
+        fs::copy(
+            Side::Jail(&root),
+            jail_src.as_ref(),
+            Side::Host,
+            host_dst.as_ref(),
+            options,
+        )
     }
 
     /// Clear the `persist` flag on the Jail.
@@ -510,7 +2123,9 @@ impl RunningJail {
     /// Jails started with [StoppedJail::start] start with this flag set, since
     /// they would otherwise be immediately cleaned up again by the kernel.
     /// This method clears the persist flag and therefore delegates cleanup to
-    /// the kernel once all jailed processes have terminated.
+    /// the kernel once all jailed processes have terminated. If no process
+    /// runs in the jail, it is removed right away; see
+    /// [RunningJail::defer_cleanup_checked] to guard against that.
     ///
     /// # Example
     ///
@@ -539,6 +2154,261 @@ impl RunningJail {
         trace!("RunningJail::defer_cleanup({:?})", self);
         sys::jail_clearpersist(self.jid)
     }
+
+    /// Clear the `persist` flag on the Jail, but only if a process runs
+    /// inside it.
+    ///
+    /// Unlike [RunningJail::defer_cleanup], which removes a jail without
+    /// processes right away, this fails with
+    /// [JailError::NoJailedProcesses] and leaves the jail running.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::process::Command;
+    /// use jail::process::Jailed;
+    /// use jail::JailError;
+    ///
+    /// let jail = jail::StoppedJail::new("/rescue")
+    ///      .name("testjail_defer_cleanup_checked")
+    ///      .start()
+    ///      .expect("could not start jail");
+    ///
+    /// match jail.defer_cleanup_checked() {
+    ///     Err(JailError::NoJailedProcesses(jid)) => assert_eq!(jid, jail.jid),
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///              .arg("3")
+    ///              .jail(&jail)
+    ///              .spawn()
+    ///              .expect("Failed to execute command");
+    ///
+    /// jail.defer_cleanup_checked().expect("could not defer cleanup");
+    ///
+    /// child.wait().expect("Could not wait for child.");
+    ///
+    /// jail.kill().expect_err("Jail should be dead by now.");
+    /// ```
+    pub fn defer_cleanup_checked(&self) -> Result<(), JailError> {
+        trace!("RunningJail::defer_cleanup_checked({:?})", self);
+        if sys::jail_pids(self.jid)?.is_empty() {
+            return Err(JailError::NoJailedProcesses(self.jid));
+        }
+
+        sys::jail_clearpersist(self.jid)
+    }
+
+    /// Clear the `persist` flag on the Jail once `child` is known to run
+    /// inside it, so that the jail is removed when `child` and any other
+    /// jailed processes have terminated.
+    ///
+    /// Fails with [JailError::ProcessNotJailed] if `child` does not run in
+    /// the jail, e.g. because it has already exited.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::process::Command;
+    /// use jail::process::Jailed;
+    ///
+    /// let jail = jail::StoppedJail::new("/rescue")
+    ///      .name("testjail_defer_cleanup_after")
+    ///      .start()
+    ///      .expect("could not start jail");
+    ///
+    /// let mut child = Command::new("/sleep")
+    ///              .arg("3")
+    ///              .jail(&jail)
+    ///              .spawn()
+    ///              .expect("Failed to execute command");
+    ///
+    /// jail.defer_cleanup_after(&child).expect("could not defer cleanup");
+    ///
+    /// child.wait().expect("Could not wait for child.");
+    ///
+    /// jail.kill().expect_err("Jail should be dead by now.");
+    /// ```
+    pub fn defer_cleanup_after(&self, child: &process::Child) -> Result<(), JailError> {
+        trace!(
+            "RunningJail::defer_cleanup_after({:?}, child={})",
+            self,
+            child.id()
+        );
+        let pid = child.id();
+        if !sys::jail_pids(self.jid)?
+            .into_iter()
+            .any(|p| p as u32 == pid)
+        {
+            return Err(JailError::ProcessNotJailed { pid, jid: self.jid });
+        }
+
+        sys::jail_clearpersist(self.jid)
+    }
+}
+
+/// Send `signal` to the processes `pids`, ignoring those that have exited.
+#[cfg(target_os = "freebsd")]
+fn signal(pids: &[libc::pid_t], signal: libc::c_int) -> Result<(), JailError> {
+    for pid in pids {
+        if unsafe { libc::kill(*pid, signal) } != 0 {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::ESRCH) {
+                return Err(JailError::IoError(error));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What was cleaned up when killing a jail, as returned by
+/// [RunningJail::kill_with_report].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KillReport {
+    /// The `jid` of the killed jail
+    pub jid: i32,
+
+    /// The name of the killed jail
+    pub name: String,
+
+    /// Whether the jail was already dying, i.e. removed but still holding
+    /// resources
+    pub was_dying: bool,
+
+    /// The number of processes running in the jail, not counting child
+    /// jails, when it was killed
+    pub processes_killed: usize,
+
+    /// The number of `RCTL` rules removed, or `None` if `RCTL` is not
+    /// enabled, the jail has neither a name nor scoped limits, or the `rctl`
+    /// feature is disabled
+    pub rctl_rules_removed: Option<usize>,
+
+    /// The host paths of the file systems unmounted
+    pub unmounted: Vec<path::PathBuf>,
+
+    /// The host paths of the file systems left mounted, since they were
+    /// still busy, see [UnmountPolicy](mount/struct.UnmountPolicy.html)
+    pub still_mounted: Vec<path::PathBuf>,
+}
+
+/// A part of a jail configuration that [RunningJail::save_lossy] could not
+/// capture.
+#[derive(Debug)]
+pub struct Uncaptured {
+    /// The name of the parameter, or `limits` for the `RCTL` rules
+    pub item: String,
+
+    /// Why it could not be read
+    pub error: JailError,
+}
+
+/// Traffic counters of a network interface, as returned by
+/// [RunningJail::network_stats].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct InterfaceStats {
+    /// The name of the interface inside the jail
+    pub name: String,
+
+    /// The number of bytes received
+    pub rx_bytes: u64,
+
+    /// The number of bytes sent
+    pub tx_bytes: u64,
+
+    /// The number of packets received
+    pub rx_packets: u64,
+
+    /// The number of packets sent
+    pub tx_packets: u64,
+
+    /// The number of input errors
+    pub rx_errors: u64,
+
+    /// The number of output errors
+    pub tx_errors: u64,
+}
+
+/// Disk space used by a jail root, as returned by [RunningJail::disk_usage].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// The number of bytes used
+    pub used: u64,
+
+    /// The quota in bytes, if any
+    pub limit: Option<u64>,
+}
+
+/// Parse a jail name or `jid`.
+///
+/// Strings that parse as an `i32` are taken as `jid`s, without a system call
+/// and without checking that the jail exists, like `jail_getid`(3). All other
+/// strings are looked up as names. Use
+/// [RunningJail::from_name_strict](struct.RunningJail.html#method.from_name_strict)
+/// to only accept names.
+///
+/// # Examples
+///
+/// ```
+/// use jail::RunningJail;
+/// # use jail::StoppedJail;
+/// # let jail = StoppedJail::new("/rescue")
+/// #     .name("testjail_from_str")
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let by_name: RunningJail = "testjail_from_str".parse().unwrap();
+/// let by_jid: RunningJail = by_name.jid.to_string().parse().unwrap();
+/// assert_eq!(by_name, by_jid);
+///
+/// // Numeric strings are not checked.
+/// let missing: RunningJail = "424242".parse().unwrap();
+/// assert_eq!(missing.jid, 424242);
+/// # jail.kill().unwrap();
+/// ```
+#[cfg(target_os = "freebsd")]
+impl FromStr for RunningJail {
+    type Err = JailError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        trace!("RunningJail::from_str({})", name);
+        sys::jail_getid(name).map(RunningJail::from_jid_unchecked)
+    }
+}
+
+/// Create a [RunningJail] given a `jid`, failing with
+/// [JailError::NoSuchJail] if no jail with this `jid` exists.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{JailError, RunningJail};
+/// use std::convert::TryFrom;
+/// # use jail::StoppedJail;
+/// # let jail = StoppedJail::new("/rescue")
+/// #     .name("testjail_try_from_jid")
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let running = RunningJail::try_from(jail.jid).expect("jail does not exist");
+/// # running.kill().expect("could not stop jail");
+///
+/// assert!(matches!(
+///     RunningJail::try_from(99999),
+///     Err(JailError::NoSuchJail(99999))
+/// ));
+/// ```
+#[cfg(target_os = "freebsd")]
+impl TryFrom<i32> for RunningJail {
+    type Error = JailError;
+
+    fn try_from(jid: i32) -> Result<RunningJail, Self::Error> {
+        trace!("RunningJail::try_from({})", jid);
+        RunningJail::from_jid(jid).ok_or(JailError::NoSuchJail(jid))
+    }
 }
 
 impl TryFrom<StoppedJail> for RunningJail {
@@ -573,6 +2443,183 @@ impl RunningJails {
         trace!("RunningJails::new()");
         RunningJails::default()
     }
+
+    /// Collect the remaining jails whose parameters `names` satisfy
+    /// `matches`.
+    ///
+    /// Each jail is read with a single `jail_get`(2) call, which also returns
+    /// the next `jid`.
+    fn find<F>(self, names: &[&str], mut matches: F) -> Result<Vec<RunningJail>, JailError>
+    where
+        F: FnMut(&[param::Value]) -> bool,
+    {
+        let mut found = vec![];
+        let mut lastjid = self.lastjid;
+
+        loop {
+            let (jid, values) = match param::get_next(lastjid, names) {
+                Ok(next) => next,
+                // Jails may disappear while iterating, so only fail if there
+                // are jails left.
+                Err(e) => match sys::jail_nextjid(lastjid) {
+                    Ok(_) => return Err(e),
+                    Err(_) => return Ok(found),
+                },
+            };
+
+            if matches(&values) {
+                found.push(RunningJail { jid });
+            }
+            lastjid = jid;
+        }
+    }
+
+    /// Find the jails that have the address `ip` assigned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_find_by_ip")
+    /// #     .ip("127.0.1.42".parse().unwrap())
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let owners = RunningJail::all()
+    ///     .find_by_ip("127.0.1.42".parse().unwrap())
+    ///     .expect("could not search jails");
+    /// assert_eq!(owners, vec![jail]);
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    pub fn find_by_ip(self, ip: net::IpAddr) -> Result<Vec<RunningJail>, JailError> {
+        trace!("RunningJails::find_by_ip({:?}, ip={})", self, ip);
+        match ip {
+            net::IpAddr::V4(ip) => self.find(
+                &["ip4.addr"],
+                |values| matches!(&values[0], param::Value::Ipv4Addrs(ips) if ips.contains(&ip)),
+            ),
+            net::IpAddr::V6(ip) => self.find(
+                &["ip6.addr"],
+                |values| matches!(&values[0], param::Value::Ipv6Addrs(ips) if ips.contains(&ip)),
+            ),
+        }
+    }
+
+    /// Find the jails whose root is `prefix` or below it.
+    ///
+    /// Paths are compared by component, so `/jails/www` is below `/jails`,
+    /// but not below `/jail`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_find_by_path")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let rescued = RunningJail::all()
+    ///     .find_by_path("/rescue")
+    ///     .expect("could not search jails");
+    /// assert!(rescued.contains(&jail));
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    pub fn find_by_path<P: AsRef<path::Path>>(
+        self,
+        prefix: P,
+    ) -> Result<Vec<RunningJail>, JailError> {
+        let prefix = prefix.as_ref();
+        trace!(
+            "RunningJails::find_by_path({:?}, prefix={:?})",
+            self,
+            prefix
+        );
+        self.find(&["path"], |values| {
+            matches!(&values[0], param::Value::String(path) if path::Path::new(path).starts_with(prefix))
+        })
+    }
+
+    /// Find the jails in the namespace of this process, or all jails if it
+    /// has none, see [set_namespace](fn.set_namespace.html).
+    pub fn find_in_namespace(self) -> Result<Vec<RunningJail>, JailError> {
+        trace!("RunningJails::find_in_namespace({:?})", self);
+        let namespace = name::namespace();
+        self.find(&["name"], |values| match (&namespace, &values[0]) {
+            (None, _) => true,
+            (Some(namespace), param::Value::String(name)) => namespace.local(name).is_some(),
+            (Some(_), _) => false,
+        })
+    }
+
+    /// Find the jails whose hostname matches a glob `pattern`, where `*`
+    /// matches any sequence of characters and `?` matches a single
+    /// character.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::RunningJail;
+    /// # use jail::StoppedJail;
+    /// # let jail = StoppedJail::new("/rescue")
+    /// #     .name("testjail_find_by_hostname")
+    /// #     .hostname("www42.find-by-hostname.example.org")
+    /// #     .start()
+    /// #     .expect("could not start jail");
+    ///
+    /// let web = RunningJail::all()
+    ///     .find_by_hostname("www??.find-by-hostname.*")
+    ///     .expect("could not search jails");
+    /// assert_eq!(web, vec![jail]);
+    /// # jail.kill().expect("could not stop jail");
+    /// ```
+    pub fn find_by_hostname(self, pattern: &str) -> Result<Vec<RunningJail>, JailError> {
+        trace!(
+            "RunningJails::find_by_hostname({:?}, pattern={:?})",
+            self,
+            pattern
+        );
+        self.find(&["host.hostname"], |values| {
+            matches!(&values[0], param::Value::String(hostname) if glob_match(pattern, hostname))
+        })
+    }
+}
+
+/// Match `text` against a glob `pattern` supporting `*` and `?`.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Position of the last `*` in the pattern and the text position it was
+    // tried at, to backtrack to on a mismatch.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 #[cfg(target_os = "freebsd")]
@@ -591,3 +2638,22 @@ impl Iterator for RunningJails {
         Some(RunningJail { jid })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("www.example.org", "www.example.org"));
+        assert!(glob_match("*.example.org", "www.example.org"));
+        assert!(glob_match("www??.*", "www42.example.org"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+
+        assert!(!glob_match("www??.*", "www4.example.org"));
+        assert!(!glob_match("*.example.org", "www.example.com"));
+        assert!(!glob_match("www", "www.example.org"));
+        assert!(!glob_match("?", ""));
+    }
+}
@@ -0,0 +1,71 @@
+//! Scheduling priority of jailed processes
+use log::trace;
+use std::io;
+
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+
+/// The scheduling class and priority of the processes in a jail.
+///
+/// See `setpriority`(2) and `rtprio`(2).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub enum Scheduling {
+    /// The normal time sharing class with the given nice value, from -20
+    /// (highest priority) to 20 (lowest priority).
+    Nice(i32),
+
+    /// The realtime class with the given priority, from 0 (highest priority)
+    /// to 31 (lowest priority).
+    Realtime(u16),
+
+    /// The idle class with the given priority, from 0 (highest priority) to
+    /// 31 (lowest priority). Processes in this class only run when no other
+    /// process is runnable.
+    Idle(u16),
+}
+
+impl Scheduling {
+    /// Apply the scheduling class and priority to the process `pid`, or to
+    /// the calling process if `pid` is 0.
+    ///
+    /// Only async-signal-safe functions are called, so this may be used in
+    /// a `pre_exec` hook.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn apply(&self, pid: libc::pid_t) -> io::Result<()> {
+        let (type_, prio) = match *self {
+            Scheduling::Nice(_) => (libc::RTP_PRIO_NORMAL, 0),
+            Scheduling::Realtime(prio) => (libc::RTP_PRIO_REALTIME, prio),
+            Scheduling::Idle(prio) => (libc::RTP_PRIO_IDLE, prio),
+        };
+
+        let mut rtp = libc::rtprio { type_, prio };
+        if unsafe { libc::rtprio(libc::RTP_SET, pid, &mut rtp) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if let Scheduling::Nice(nice) = *self {
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, pid, nice) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the scheduling class and priority to the processes in `pids`.
+    ///
+    /// Processes that have exited in the meantime are ignored.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn apply_all(&self, pids: &[libc::pid_t]) -> io::Result<()> {
+        trace!("Scheduling::apply_all({:?}, pids={:?})", self, pids);
+        for pid in pids {
+            match self.apply(*pid) {
+                Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+                result => result?,
+            }
+        }
+
+        Ok(())
+    }
+}
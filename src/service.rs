@@ -0,0 +1,230 @@
+//! Control of `rc.d` services inside running jails
+//!
+//! [Service] runs `service`(8) inside a jail and turns its exit code and
+//! output into typed results. Get one using
+//! [RunningJail::service](../struct.RunningJail.html#method.service).
+use crate::{JailError, RunningJail};
+use log::trace;
+use std::fmt;
+
+#[cfg(target_os = "freebsd")]
+use crate::process::Jailed;
+#[cfg(target_os = "freebsd")]
+use std::process::Command;
+
+/// The path of `service`(8) inside the jail.
+const SERVICE: &str = "/usr/sbin/service";
+
+/// The status of a service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The service is running, with the given `pid` if reported.
+    Running(Option<u32>),
+
+    /// The service is not running.
+    Stopped,
+
+    /// The service is not enabled in `rc.conf`(5), so its status cannot be
+    /// queried without [Service::one].
+    NotEnabled,
+}
+
+/// An `rc.d` service inside a running jail.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::service::Status;
+/// # use jail::StoppedJail;
+/// # let running = StoppedJail::new("/jails/www")
+/// #     .name("testjail_service")
+/// #     .start()
+/// #     .expect("could not start jail");
+///
+/// let nginx = running.service("nginx");
+/// if nginx.status().expect("could not query nginx") == Status::Stopped {
+///     nginx.start().expect("could not start nginx");
+/// }
+/// # running.kill();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    jail: RunningJail,
+    name: String,
+    one: bool,
+}
+
+impl Service {
+    /// Get the service `name` of `jail`.
+    pub(crate) fn new(jail: RunningJail, name: &str) -> Service {
+        trace!("Service::new(jail={:?}, name={:?})", jail, name);
+        Service {
+            jail,
+            name: name.to_string(),
+            one: false,
+        }
+    }
+
+    /// Run the commands even if the service is not enabled in `rc.conf`(5),
+    /// using the `one` prefix (e.g. `onestart`).
+    pub fn one(mut self, one: bool) -> Self {
+        trace!("Service::one({:?}, one={})", self, one);
+        self.one = one;
+        self
+    }
+
+    /// The name of the service.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn command(&self, command: &str) -> String {
+        match self.one {
+            true => format!("one{}", command),
+            false => command.to_string(),
+        }
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> JailError {
+        JailError::ServiceError {
+            service: self.name.clone(),
+            message: message.into(),
+        }
+    }
+
+    /// Run `service <name> <command>` in the jail, returning its exit code
+    /// and its combined output.
+    #[cfg(target_os = "freebsd")]
+    fn run(&self, command: &str) -> Result<(Option<i32>, String), JailError> {
+        if self.name.is_empty() || self.name.contains(|c: char| c == '/' || c.is_whitespace()) {
+            return Err(self.error("invalid service name"));
+        }
+
+        let command = self.command(command);
+        trace!("Service::run({:?}, command={:?})", self, command);
+        let output = Command::new(SERVICE)
+            .arg(&self.name)
+            .arg(&command)
+            .jail(&self.jail)
+            .output()
+            .map_err(JailError::IoError)?;
+
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok((output.status.code(), text.trim().to_string()))
+    }
+
+    /// Run a command that either succeeds or fails.
+    #[cfg(target_os = "freebsd")]
+    fn control(&self, command: &str) -> Result<(), JailError> {
+        match self.run(command)? {
+            (Some(0), _) => Ok(()),
+            (_, output) => Err(self.error(format!("{} failed: {}", command, output))),
+        }
+    }
+
+    /// Start the service.
+    #[cfg(target_os = "freebsd")]
+    pub fn start(&self) -> Result<(), JailError> {
+        trace!("Service::start({:?})", self);
+        self.control("start")
+    }
+
+    /// Stop the service.
+    #[cfg(target_os = "freebsd")]
+    pub fn stop(&self) -> Result<(), JailError> {
+        trace!("Service::stop({:?})", self);
+        self.control("stop")
+    }
+
+    /// Restart the service.
+    #[cfg(target_os = "freebsd")]
+    pub fn restart(&self) -> Result<(), JailError> {
+        trace!("Service::restart({:?})", self);
+        self.control("restart")
+    }
+
+    /// Reload the configuration of the service, if it supports it.
+    #[cfg(target_os = "freebsd")]
+    pub fn reload(&self) -> Result<(), JailError> {
+        trace!("Service::reload({:?})", self);
+        self.control("reload")
+    }
+
+    /// Query the status of the service.
+    #[cfg(target_os = "freebsd")]
+    pub fn status(&self) -> Result<Status, JailError> {
+        trace!("Service::status({:?})", self);
+        let (code, output) = self.run("status")?;
+        parse_status(&self.name, code, &output).ok_or_else(|| self.error(output))
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in jail {}", self.name, self.jail.jid)
+    }
+}
+
+/// Interpret the exit code and output of `service <name> status`.
+///
+/// Returns `None` if the output is not recognized, e.g. because the service
+/// does not exist.
+fn parse_status(name: &str, code: Option<i32>, output: &str) -> Option<Status> {
+    if output.contains(&format!("Set {}_enable to YES", name)) {
+        return Some(Status::NotEnabled);
+    }
+
+    match code {
+        Some(0) => {
+            let pid = output
+                .split("pid")
+                .nth(1)
+                .and_then(|rest| {
+                    rest.split(|c: char| !c.is_ascii_digit())
+                        .find(|s| !s.is_empty())
+                })
+                .and_then(|pid| pid.parse().ok());
+            Some(Status::Running(pid))
+        }
+        Some(_) if output.contains("is not running") => Some(Status::Stopped),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status() {
+        assert_eq!(
+            parse_status("nginx", Some(0), "nginx is running as pid 1234."),
+            Some(Status::Running(Some(1234)))
+        );
+        assert_eq!(
+            parse_status("sshd", Some(0), "sshd is running as pid 89 90."),
+            Some(Status::Running(Some(89)))
+        );
+        assert_eq!(
+            parse_status("nginx", Some(1), "nginx is not running."),
+            Some(Status::Stopped)
+        );
+        assert_eq!(
+            parse_status(
+                "nginx",
+                Some(1),
+                "Cannot 'status' nginx. Set nginx_enable to YES in /etc/rc.conf or use 'onestatus' instead of 'status'."
+            ),
+            Some(Status::NotEnabled)
+        );
+        assert_eq!(
+            parse_status(
+                "nginx",
+                Some(1),
+                "nginx does not exist in /etc/rc.d or the local startup\ndirectories (/usr/local/etc/rc.d)"
+            ),
+            None
+        );
+    }
+}
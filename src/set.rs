@@ -0,0 +1,277 @@
+//! Sets of jails with start dependencies
+use crate::{JailError, RunningJail, StopPolicy, StoppedJail};
+use log::{trace, warn};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A set of named jails, some of which depend on others.
+///
+/// Like the `depend` parameter of `jail.conf`(5), a jail is only started
+/// after all jails it depends on are running, and stopped before them.
+/// Jails that do not depend on each other are started and stopped in
+/// parallel.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{JailSet, StoppedJail};
+///
+/// let set = JailSet::new()
+///     .jail(StoppedJail::new("/rescue").name("testjailset_db"))
+///     .jail(StoppedJail::new("/rescue").name("testjailset_cache"))
+///     .jail(StoppedJail::new("/rescue").name("testjailset_web"))
+///     .depend("testjailset_web", "testjailset_db")
+///     .depend("testjailset_web", "testjailset_cache");
+///
+/// assert_eq!(
+///     set.stages().unwrap(),
+///     vec![
+///         vec!["testjailset_cache".to_string(), "testjailset_db".to_string()],
+///         vec!["testjailset_web".to_string()],
+///     ]
+/// );
+///
+/// let running = set.start_all().expect("could not start jails");
+/// assert_eq!(running.len(), 3);
+/// set.stop_all().expect("could not stop jails");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct JailSet {
+    jails: BTreeMap<String, StoppedJail>,
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl JailSet {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        trace!("JailSet::new()");
+        JailSet::default()
+    }
+
+    /// Add a jail to the set, replacing any jail of the same name.
+    ///
+    /// The jail must have a name, see [StoppedJail::name].
+    pub fn jail(mut self, stopped: StoppedJail) -> Self {
+        trace!("JailSet::jail({:?}, stopped={:?})", self, stopped);
+        let name = stopped.name.clone().unwrap_or_default();
+        self.jails.insert(name, stopped);
+        self
+    }
+
+    /// Declare that `jail` depends on `dependency`.
+    pub fn depend<S: Into<String>, T: Into<String>>(mut self, jail: S, dependency: T) -> Self {
+        let jail = jail.into();
+        let dependency = dependency.into();
+        trace!(
+            "JailSet::depend({:?}, jail={:?}, dependency={:?})",
+            self,
+            jail,
+            dependency
+        );
+        self.dependencies
+            .entry(jail)
+            .or_default()
+            .insert(dependency);
+        self
+    }
+
+    /// Group the jails into stages, ordered by their dependencies.
+    ///
+    /// All jails of a stage only depend on jails of earlier stages. Within a
+    /// stage, jails are sorted by name.
+    pub fn stages(&self) -> Result<Vec<Vec<String>>, JailError> {
+        trace!("JailSet::stages({:?})", self);
+        if self.jails.contains_key("") {
+            return Err(JailError::DependencyError(
+                "all jails in a set must have a name".into(),
+            ));
+        }
+
+        for (jail, dependencies) in &self.dependencies {
+            for name in std::iter::once(jail).chain(dependencies) {
+                if !self.jails.contains_key(name) {
+                    return Err(JailError::DependencyError(format!(
+                        "unknown jail '{}'",
+                        name
+                    )));
+                }
+            }
+        }
+
+        let mut remaining: BTreeSet<&String> = self.jails.keys().collect();
+        let mut stages = vec![];
+        while !remaining.is_empty() {
+            let stage: Vec<&String> = remaining
+                .iter()
+                .filter(|jail| {
+                    self.dependencies
+                        .get(**jail)
+                        .map(|dependencies| dependencies.iter().all(|d| !remaining.contains(d)))
+                        .unwrap_or(true)
+                })
+                .copied()
+                .collect();
+
+            if stage.is_empty() {
+                let cycle: Vec<&str> = remaining.iter().map(|s| s.as_str()).collect();
+                return Err(JailError::DependencyError(format!(
+                    "dependency cycle between {}",
+                    cycle.join(", ")
+                )));
+            }
+
+            for jail in &stage {
+                remaining.remove(*jail);
+            }
+
+            stages.push(stage.into_iter().cloned().collect());
+        }
+
+        Ok(stages)
+    }
+
+    /// Start all jails, stage by stage, starting the jails of each stage in
+    /// parallel.
+    ///
+    /// The dependencies of each jail are recorded with [StoppedJail::depend],
+    /// so that [shutdown_all](fn.shutdown_all.html) stops the jails in the
+    /// right order.
+    ///
+    /// The running jails are returned in the order they were started. If a
+    /// jail fails to start, all jails started so far are killed in reverse
+    /// order, and the error is returned.
+    pub fn start_all(&self) -> Result<Vec<RunningJail>, JailError> {
+        trace!("JailSet::start_all({:?})", self);
+        let mut started: Vec<RunningJail> = vec![];
+
+        for stage in self.stages()? {
+            let results: Vec<Result<RunningJail, JailError>> = std::thread::scope(|scope| {
+                let handles: Vec<_> = stage
+                    .iter()
+                    .map(|name| {
+                        let stopped = self
+                            .dependencies
+                            .get(name)
+                            .into_iter()
+                            .flatten()
+                            .fold(self.jails[name].clone(), |stopped, dependency| {
+                                stopped.depend(dependency.as_str())
+                            });
+                        scope.spawn(move || stopped.start())
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("jail start thread panicked"))
+                    .collect()
+            });
+
+            let mut error = None;
+            for (name, result) in stage.iter().zip(results) {
+                match result {
+                    Ok(running) => started.push(running),
+                    Err(e) => {
+                        warn!("could not start jail {}: {}", name, e);
+                        error = error.or(Some(e));
+                    }
+                }
+            }
+
+            if let Some(e) = error {
+                for running in started.into_iter().rev() {
+                    if let Err(e) = running.kill() {
+                        warn!("could not kill jail {}: {}", running.jid, e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(started)
+    }
+
+    /// Stop all running jails of the set, stage by stage in reverse order,
+    /// killing the jails of each stage in parallel.
+    ///
+    /// Jails of the set that are not running are skipped. All jails are
+    /// stopped even if some fail; the first error is returned.
+    pub fn stop_all(&self) -> Result<(), JailError> {
+        trace!("JailSet::stop_all({:?})", self);
+        self.stop_all_with(&StopPolicy::immediate())
+    }
+
+    /// Stop all running jails of the set like [JailSet::stop_all],
+    /// terminating their processes according to `policy`.
+    pub fn stop_all_with(&self, policy: &StopPolicy) -> Result<(), JailError> {
+        trace!("JailSet::stop_all_with({:?}, policy={:?})", self, policy);
+        let mut result = Ok(());
+
+        for stage in self.stages()?.into_iter().rev() {
+            let running: Vec<RunningJail> = stage
+                .iter()
+                .filter_map(|name| RunningJail::from_name_strict(name).ok())
+                .collect();
+
+            let results: Vec<Result<(), JailError>> = std::thread::scope(|scope| {
+                let handles: Vec<_> = running
+                    .into_iter()
+                    .map(|running| scope.spawn(move || running.shutdown(policy).map(|_| ())))
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("jail kill thread panicked"))
+                    .collect()
+            });
+
+            for r in results {
+                result = result.and(r);
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> JailSet {
+        names.iter().fold(JailSet::new(), |set, name| {
+            set.jail(StoppedJail::new("/").name(*name))
+        })
+    }
+
+    #[test]
+    fn order_stages() {
+        let set = set(&["db", "web", "cache", "proxy", "log"])
+            .depend("web", "db")
+            .depend("web", "cache")
+            .depend("proxy", "web")
+            .depend("cache", "log");
+
+        assert_eq!(
+            set.stages().unwrap(),
+            vec![
+                vec!["db".to_string(), "log".to_string()],
+                vec!["cache".to_string()],
+                vec!["web".to_string()],
+                vec!["proxy".to_string()],
+            ]
+        );
+
+        assert!(matches!(
+            set.clone().depend("db", "proxy").stages(),
+            Err(JailError::DependencyError(_))
+        ));
+        assert!(matches!(
+            set.clone().depend("web", "mail").stages(),
+            Err(JailError::DependencyError(_))
+        ));
+        assert!(matches!(
+            set.jail(StoppedJail::new("/")).stages(),
+            Err(JailError::DependencyError(_))
+        ));
+    }
+}
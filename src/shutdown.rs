@@ -0,0 +1,322 @@
+//! Stopping all jails of the host, like `rc.d/jail` at system shutdown
+use crate::{JailError, KillReport, Namespace, RunningJail, StopPolicy};
+use log::{trace, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+/// The metadata key recording the names of the jails a jail depends on,
+/// see [StoppedJail::depend](struct.StoppedJail.html#method.depend).
+pub(crate) const DEPEND: &str = "depend";
+
+/// How to stop all jails with [shutdown_all].
+///
+/// Each jail is stopped according to [ShutdownPolicy::stop], but the
+/// grace period is cut short to what is left of the overall
+/// [ShutdownPolicy::timeout], after which the processes are killed even if
+/// the policy does not escalate. Jails stopped once the timeout is used up
+/// are killed right away.
+///
+/// The default gives each jail the default [StopPolicy] and all jails 90
+/// seconds, like `rcshutdown_timeout` in `rc.conf`(5).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShutdownPolicy {
+    /// How to stop each jail
+    pub stop: StopPolicy,
+
+    /// How long stopping all jails may take
+    pub timeout: Duration,
+}
+
+impl Default for ShutdownPolicy {
+    fn default() -> Self {
+        ShutdownPolicy {
+            stop: StopPolicy::default(),
+            timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+impl ShutdownPolicy {
+    /// Set how each jail is stopped.
+    pub fn stop(mut self, stop: StopPolicy) -> Self {
+        trace!("ShutdownPolicy::stop({:?}, stop={:?})", self, stop);
+        self.stop = stop;
+        self
+    }
+
+    /// Set how long stopping all jails may take.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        trace!("ShutdownPolicy::timeout({:?}, timeout={:?})", self, timeout);
+        self.timeout = timeout;
+        self
+    }
+
+    /// The policy for a jail stopped `elapsed` after the shutdown started,
+    /// and whether it was cut short by the overall timeout.
+    fn budget(&self, elapsed: Duration) -> (StopPolicy, bool) {
+        let remaining = self.timeout.saturating_sub(elapsed);
+        if remaining.is_zero() {
+            return (StopPolicy::immediate(), true);
+        }
+
+        match self.stop.grace > remaining {
+            true => (self.stop.grace(remaining).escalate(true), true),
+            false => (self.stop, false),
+        }
+    }
+}
+
+/// The outcome of stopping a single jail, see [ShutdownReport].
+#[derive(Debug)]
+pub struct JailShutdown {
+    /// The `jid` the jail had
+    pub jid: i32,
+
+    /// The name the jail had
+    pub name: String,
+
+    /// The stage the jail was stopped in, counting from zero
+    pub stage: usize,
+
+    /// Whether the grace period of the jail was cut short by the overall
+    /// timeout
+    pub forced: bool,
+
+    /// How long stopping the jail took
+    pub elapsed: Duration,
+
+    /// What was cleaned up, or why the jail could not be stopped
+    pub result: Result<KillReport, JailError>,
+}
+
+/// What [shutdown_all] did, in the order the jails were stopped.
+#[derive(Debug)]
+pub struct ShutdownReport {
+    /// The jails that were stopped or failed to stop
+    pub jails: Vec<JailShutdown>,
+
+    /// How long stopping all jails took
+    pub elapsed: Duration,
+}
+
+impl ShutdownReport {
+    /// Whether all jails were stopped.
+    pub fn is_success(&self) -> bool {
+        trace!("ShutdownReport::is_success({:?})", self);
+        self.jails.iter().all(|jail| jail.result.is_ok())
+    }
+
+    /// The jails that could not be stopped.
+    pub fn failed(&self) -> impl Iterator<Item = &JailShutdown> {
+        trace!("ShutdownReport::failed({:?})", self);
+        self.jails.iter().filter(|jail| jail.result.is_err())
+    }
+}
+
+/// Group jails into stages to stop them in, given the jails each depends on.
+///
+/// A jail is stopped before the jails it depends on. Jails in a dependency
+/// cycle are stopped together in the last stage.
+fn stages(dependencies: &BTreeMap<i32, BTreeSet<i32>>) -> Vec<Vec<i32>> {
+    let mut remaining: BTreeSet<i32> = dependencies.keys().copied().collect();
+    let mut stages = vec![];
+
+    while !remaining.is_empty() {
+        let mut stage: Vec<i32> = remaining
+            .iter()
+            .filter(|jid| {
+                !remaining
+                    .iter()
+                    .any(|other| dependencies[other].contains(jid))
+            })
+            .copied()
+            .collect();
+
+        if stage.is_empty() {
+            warn!("dependency cycle between jails {:?}", remaining);
+            stage = remaining.iter().copied().collect();
+        }
+
+        for jid in &stage {
+            remaining.remove(jid);
+        }
+        stages.push(stage);
+    }
+
+    stages
+}
+
+/// Get the jids of the jails the jail `name` depends on: those named in its
+/// `depend` metadata, and its parent.
+///
+/// Like the names of the jails themselves, the names in `depend` are
+/// qualified with the `namespace` of this process.
+fn depends_on(
+    name: &str,
+    depend: &str,
+    namespace: Option<&Namespace>,
+    jids: &BTreeMap<&str, i32>,
+) -> BTreeSet<i32> {
+    let parent = name.rsplit_once('.').map(|(parent, _)| parent.to_string());
+    depend
+        .split(',')
+        .map(str::trim)
+        .filter(|dependency| !dependency.is_empty())
+        .map(|dependency| match namespace {
+            Some(namespace) => namespace.qualify(dependency),
+            None => dependency.to_string(),
+        })
+        .chain(parent)
+        .filter_map(|name| jids.get(name.as_str()).copied())
+        .collect()
+}
+
+/// Stop all running jails, as `rc.d/jail` does at system shutdown.
+///
+/// A jail is stopped before the jails it depends on, as recorded with
+/// [StoppedJail::depend](struct.StoppedJail.html#method.depend) or by
+/// [JailSet::start_all](struct.JailSet.html#method.start_all), and child
+/// jails before their parent. Jails that do not depend on each other are
+/// stopped in parallel, each according to the [ShutdownPolicy].
+///
+/// Jails that fail to stop are reported rather than aborting the shutdown.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::ShutdownPolicy;
+/// use std::time::Duration;
+///
+/// let report = jail::shutdown_all(&ShutdownPolicy::default().timeout(Duration::from_secs(30)));
+///
+/// for jail in report.failed() {
+///     eprintln!("could not stop jail {}: {:?}", jail.name, jail.result);
+/// }
+/// ```
+pub fn shutdown_all(policy: &ShutdownPolicy) -> ShutdownReport {
+    trace!("shutdown_all(policy={:?})", policy);
+    let start = Instant::now();
+
+    let mut names = BTreeMap::new();
+    for running in RunningJail::all() {
+        // The jail may have been removed since it was listed.
+        if let Ok(name) = running.name() {
+            names.insert(running.jid, name);
+        }
+    }
+    let jids: BTreeMap<&str, i32> = names
+        .iter()
+        .map(|(jid, name)| (name.as_str(), *jid))
+        .collect();
+
+    let namespace = crate::namespace();
+    let mut dependencies: BTreeMap<i32, BTreeSet<i32>> = BTreeMap::new();
+    for (jid, name) in &names {
+        let depend = RunningJail::from_jid_unchecked(*jid)
+            .meta()
+            .ok()
+            .and_then(|mut meta| meta.remove(DEPEND))
+            .unwrap_or_default();
+
+        dependencies.insert(*jid, depends_on(name, &depend, namespace.as_ref(), &jids));
+    }
+
+    let mut jails = vec![];
+    for (index, stage) in stages(&dependencies).into_iter().enumerate() {
+        let results: Vec<JailShutdown> = std::thread::scope(|scope| {
+            let handles: Vec<_> = stage
+                .iter()
+                .map(|jid| {
+                    let name = names[jid].clone();
+                    let (stop, forced) = policy.budget(start.elapsed());
+                    let running = RunningJail::from_jid_unchecked(*jid);
+
+                    scope.spawn(move || {
+                        let stopping = Instant::now();
+                        let result = running.shutdown(&stop);
+                        if let Err(ref e) = result {
+                            warn!("could not stop jail {}: {}", name, e);
+                        }
+
+                        JailShutdown {
+                            jid: running.jid,
+                            name,
+                            stage: index,
+                            forced,
+                            elapsed: stopping.elapsed(),
+                            result,
+                        }
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("jail stop thread panicked"))
+                .collect()
+        });
+
+        jails.extend(results);
+    }
+
+    ShutdownReport {
+        jails,
+        elapsed: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies(edges: &[(i32, &[i32])]) -> BTreeMap<i32, BTreeSet<i32>> {
+        edges
+            .iter()
+            .map(|(jid, deps)| (*jid, deps.iter().copied().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn shutdown_stages() {
+        // 3 depends on 1 and 2, 4 depends on 3.
+        let deps = dependencies(&[(1, &[]), (2, &[]), (3, &[1, 2]), (4, &[3]), (5, &[])]);
+        assert_eq!(stages(&deps), vec![vec![4, 5], vec![3], vec![1, 2]]);
+
+        let cycle = dependencies(&[(1, &[2]), (2, &[1]), (3, &[1])]);
+        assert_eq!(stages(&cycle), vec![vec![3], vec![1, 2]]);
+    }
+
+    #[test]
+    fn shutdown_depends_on() {
+        let jids = BTreeMap::from([("app-db", 1), ("app-cache", 2), ("app-web", 3)]);
+        let namespace = Namespace::new("app-").unwrap();
+
+        assert_eq!(
+            depends_on("app-web", "db, app-cache", Some(&namespace), &jids),
+            BTreeSet::from([1, 2])
+        );
+        assert_eq!(
+            depends_on("app-web.child", "", Some(&namespace), &jids),
+            BTreeSet::from([3])
+        );
+        assert!(depends_on("app-web", "db", None, &jids).is_empty());
+    }
+
+    #[test]
+    fn shutdown_budget() {
+        let policy = ShutdownPolicy::default()
+            .stop(StopPolicy::default().grace(Duration::from_secs(10)))
+            .timeout(Duration::from_secs(30));
+
+        assert_eq!(policy.budget(Duration::from_secs(5)), (policy.stop, false));
+
+        let (stop, forced) = policy.budget(Duration::from_secs(25));
+        assert_eq!(stop.grace, Duration::from_secs(5));
+        assert!(forced);
+
+        assert_eq!(
+            policy.budget(Duration::from_secs(30)),
+            (StopPolicy::immediate(), true)
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Termination semantics shared by all ways of stopping a jail
+use crate::JailError;
+use log::trace;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(target_os = "freebsd")]
+use crate::sys;
+#[cfg(target_os = "freebsd")]
+use std::io;
+#[cfg(target_os = "freebsd")]
+use std::thread;
+#[cfg(target_os = "freebsd")]
+use std::time::Instant;
+
+/// A signal sent to the processes of a jail to ask them to exit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub enum Signal {
+    Hup,
+    Int,
+    Quit,
+    Kill,
+    Term,
+    Usr1,
+    Usr2,
+}
+
+impl Signal {
+    /// The signal number.
+    pub fn raw(self) -> libc::c_int {
+        match self {
+            Signal::Hup => libc::SIGHUP,
+            Signal::Int => libc::SIGINT,
+            Signal::Quit => libc::SIGQUIT,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Term => libc::SIGTERM,
+            Signal::Usr1 => libc::SIGUSR1,
+            Signal::Usr2 => libc::SIGUSR2,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Signal::Hup => "HUP",
+            Signal::Int => "INT",
+            Signal::Quit => "QUIT",
+            Signal::Kill => "KILL",
+            Signal::Term => "TERM",
+            Signal::Usr1 => "USR1",
+            Signal::Usr2 => "USR2",
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+/// Parse a signal name like `kill`(1), with or without the `SIG` prefix.
+///
+/// # Examples
+///
+/// ```
+/// use jail::Signal;
+///
+/// assert_eq!("TERM".parse::<Signal>().unwrap(), Signal::Term);
+/// assert_eq!("sigkill".parse::<Signal>().unwrap(), Signal::Kill);
+/// assert!("SIGWINCH".parse::<Signal>().is_err());
+/// ```
+impl FromStr for Signal {
+    type Err = JailError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+
+        [
+            Signal::Hup,
+            Signal::Int,
+            Signal::Quit,
+            Signal::Kill,
+            Signal::Term,
+            Signal::Usr1,
+            Signal::Usr2,
+        ]
+        .iter()
+        .copied()
+        .find(|signal| signal.name() == name)
+        .ok_or_else(|| JailError::NotSupported(format!("unknown signal {:?}", s)))
+    }
+}
+
+/// How the processes of a jail are terminated when it is stopped.
+///
+/// The processes are sent [StopPolicy::signal], and given
+/// [StopPolicy::grace] to exit. Processes still running afterwards are
+/// killed if [StopPolicy::escalate] is set, otherwise stopping fails with
+/// [JailError::StopTimeout] and the jail keeps running.
+///
+/// The default mirrors `jail`(8): `SIGTERM`, 10 seconds of grace (its
+/// `stop.timeout`), then `SIGKILL`.
+///
+/// # Examples
+///
+/// ```
+/// use jail::{Signal, StopPolicy, StoppedJail};
+/// use std::time::Duration;
+///
+/// let running = StoppedJail::new("/rescue")
+///     .name("testjail_stop_policy")
+///     .start()
+///     .expect("could not start jail");
+///
+/// let policy = StopPolicy::default()
+///     .signal(Signal::Int)
+///     .grace(Duration::from_secs(2));
+/// running.shutdown(&policy).expect("could not stop jail");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct StopPolicy {
+    /// How long the processes are given to exit
+    pub grace: Duration,
+
+    /// The signal asking the processes to exit
+    pub signal: Signal,
+
+    /// Whether processes still running after the grace period are killed
+    pub escalate: bool,
+}
+
+impl Default for StopPolicy {
+    fn default() -> Self {
+        StopPolicy {
+            grace: Duration::from_secs(10),
+            signal: Signal::Term,
+            escalate: true,
+        }
+    }
+}
+
+impl StopPolicy {
+    /// Kill all processes right away, like
+    /// [RunningJail::kill](struct.RunningJail.html#method.kill).
+    pub fn immediate() -> Self {
+        trace!("StopPolicy::immediate()");
+        StopPolicy {
+            grace: Duration::from_secs(0),
+            signal: Signal::Kill,
+            escalate: true,
+        }
+    }
+
+    /// Set how long the processes are given to exit.
+    pub fn grace(mut self, grace: Duration) -> Self {
+        trace!("StopPolicy::grace({:?}, grace={:?})", self, grace);
+        self.grace = grace;
+        self
+    }
+
+    /// Set the signal asking the processes to exit.
+    pub fn signal(mut self, signal: Signal) -> Self {
+        trace!("StopPolicy::signal({:?}, signal={})", self, signal);
+        self.signal = signal;
+        self
+    }
+
+    /// Set whether processes still running after the grace period are
+    /// killed.
+    pub fn escalate(mut self, escalate: bool) -> Self {
+        trace!("StopPolicy::escalate({:?}, escalate={})", self, escalate);
+        self.escalate = escalate;
+        self
+    }
+
+    /// Signal the processes of the jail with the given `jid` and wait for
+    /// them to exit, returning how many are still running.
+    ///
+    /// Processes of child jails are left to the removal of the jail.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn terminate(&self, jid: i32) -> Result<usize, JailError> {
+        trace!("StopPolicy::terminate({:?}, jid={})", self, jid);
+        if self.signal == Signal::Kill && self.escalate {
+            return Ok(sys::jail_pids(jid)?.len());
+        }
+
+        for pid in sys::jail_pids(jid)? {
+            if unsafe { libc::kill(pid, self.signal.raw()) } != 0 {
+                let error = io::Error::last_os_error();
+                // The process may have exited in the meantime.
+                if error.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(JailError::IoError(error));
+                }
+            }
+        }
+
+        let start = Instant::now();
+        loop {
+            let remaining = sys::jail_pids(jid)?.len();
+            if remaining == 0 || start.elapsed() >= self.grace {
+                return Ok(remaining);
+            }
+
+            thread::sleep(
+                Duration::from_millis(100).min(self.grace.saturating_sub(start.elapsed())),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_names() {
+        for signal in &[Signal::Hup, Signal::Term, Signal::Usr2] {
+            assert_eq!(signal.to_string().parse::<Signal>().unwrap(), *signal);
+        }
+        assert_eq!("hup".parse::<Signal>().unwrap(), Signal::Hup);
+        assert!("".parse::<Signal>().is_err());
+        assert!("SIG".parse::<Signal>().is_err());
+    }
+}
@@ -1,6 +1,27 @@
+use crate::allow::Allow;
+#[cfg(feature = "audit")]
+use crate::audit::{self, Operation};
+use crate::defaults::Profile;
+use crate::devfs::{self, DevfsConfig};
+use crate::hostname;
+#[cfg(feature = "journal")]
+use crate::journal;
+use crate::mount::{self, Mount, UnmountPolicy};
+use crate::name::{self, JailName};
+use crate::net::loopback;
+use crate::oscompat::{self, Feature};
+use crate::osrelease;
+use crate::pretty::Table;
+use crate::process;
+use crate::provision::{ProvisionPlan, ResolvConf, Step, SyslogForward, Timezone};
+use crate::registry;
+use crate::sched::Scheduling;
+use crate::vars::Variables;
+#[cfg(feature = "zfs")]
+use crate::zfs;
 use crate::{param, sys, JailError, RunningJail};
-use log::trace;
-use std::collections::HashMap;
+use log::{trace, warn};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
 use std::net;
@@ -30,7 +51,63 @@ pub struct StoppedJail {
     pub ips: Vec<net::IpAddr>,
 
     /// A list of resource limits
+    #[cfg(feature = "rctl")]
     pub limits: Vec<(rctl::Resource, rctl::Limit, rctl::Action)>,
+
+    /// Resource limits scoped to users or login classes, see
+    /// [StoppedJail::scoped_limit]
+    #[cfg(feature = "rctl")]
+    pub scoped_limits: Vec<crate::limits::ScopedLimit>,
+
+    /// The maximum disk space in bytes available to the jail root
+    pub disk_limit: Option<u64>,
+
+    /// The scheduling class and priority of processes in the jail
+    pub scheduling: Option<Scheduling>,
+
+    /// Provisioning steps applied to the jail root before start
+    pub provision: Vec<Step>,
+
+    /// File systems mounted below the jail root on start
+    pub mounts: Vec<Mount>,
+
+    /// The `devfs` mounted below the jail root on start, see
+    /// [StoppedJail::devfs]
+    pub devfs: Option<DevfsConfig>,
+
+    /// How the file systems of the jail are unmounted when it is killed
+    pub unmount_policy: UnmountPolicy,
+
+    /// Whether to grant the `allow.mount.*` permissions matching the mounts
+    /// of the jail, see [StoppedJail::auto_allow_mount]
+    pub auto_allow_mount: bool,
+
+    /// Whether to remove stale `RCTL` rules before start, see
+    /// [rctl_gc](fn.rctl_gc.html)
+    #[cfg(feature = "rctl")]
+    pub rctl_gc: bool,
+
+    /// Whether to check the addresses of the jail before start, see
+    /// [StoppedJail::check_ips]
+    pub check_ips: bool,
+
+    /// Whether to add the loopback addresses of the jail as aliases of
+    /// `lo0`, see [StoppedJail::loopback_aliases]
+    pub loopback_aliases: bool,
+
+    /// Metadata only visible on the host, see [StoppedJail::meta]
+    pub meta: BTreeMap<String, String>,
+
+    /// Metadata also visible inside the jail, see [StoppedJail::env]
+    pub env: BTreeMap<String, String>,
+
+    /// Commands of `exec.*` parameters by their full name, e.g.
+    /// `exec.start`, see [StoppedJail::exec]
+    pub exec: BTreeMap<String, Vec<String>>,
+
+    /// Whether to run the `exec.*` commands on start and kill, see
+    /// [StoppedJail::exec_hooks]
+    pub exec_hooks: bool,
 }
 
 #[cfg(target_os = "freebsd")]
@@ -43,7 +120,25 @@ impl Default for StoppedJail {
             hostname: None,
             params: HashMap::new(),
             ips: vec![],
+            #[cfg(feature = "rctl")]
             limits: vec![],
+            #[cfg(feature = "rctl")]
+            scoped_limits: vec![],
+            disk_limit: None,
+            scheduling: None,
+            provision: vec![],
+            mounts: vec![],
+            devfs: None,
+            unmount_policy: UnmountPolicy::default(),
+            auto_allow_mount: true,
+            #[cfg(feature = "rctl")]
+            rctl_gc: false,
+            check_ips: false,
+            loopback_aliases: false,
+            meta: BTreeMap::new(),
+            env: BTreeMap::new(),
+            exec: BTreeMap::new(),
+            exec_hooks: false,
         }
     }
 }
@@ -91,18 +186,562 @@ impl StoppedJail {
     /// let running = stopped.start().unwrap();
     /// # running.kill();
     /// ```
-    pub fn start(self) -> Result<RunningJail, JailError> {
+    pub fn start(mut self) -> Result<RunningJail, JailError> {
         trace!("StoppedJail::start({:?})", self);
+        self.name = self.name.map(|name| name::qualify(&name));
+        let (path, jid) = self.check_start()?;
+
+        self.check_osrelease();
+        self.apply_disk_limit(&path)?;
+        let mounts = self.prepare_root(&path)?;
+
+        if self.exec_hooks {
+            if let Err(e) = process::run_hook(&self.exec, "exec.prestart", None) {
+                mount::unmount_all(&mounts).ok();
+                return Err(e);
+            }
+        }
+
+        let (ret, meta_in_kernel) = match self.create(&path, jid) {
+            Ok(created) => created,
+            Err(e) => {
+                mount::unmount_all(&mounts).ok();
+                return Err(e);
+            }
+        };
+
+        self.set_up(ret, mounts, meta_in_kernel)
+    }
+
+    /// Create the jail, but do not set it up yet.
+    ///
+    /// The returned [CreatedJail] exists in the kernel, but the jail root
+    /// is not provisioned, nothing is mounted, no resource limits are
+    /// applied and no [StoppedJail::exec_hooks] have run. This allows to
+    /// run custom steps, e.g. configuring the network of the jail, before
+    /// [CreatedJail::finish] does all that.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::{param, StoppedJail};
+    ///
+    /// let created = StoppedJail::new("/rescue")
+    ///     .name("testjail_start_paused")
+    ///     .start_paused()
+    ///     .expect("could not create jail");
+    ///
+    /// created
+    ///     .jail()
+    ///     .param_set("securelevel", param::Value::Int(1))
+    ///     .expect("could not set parameter");
+    ///
+    /// let running = created.finish().expect("could not set up jail");
+    /// assert_eq!(running.param("securelevel").unwrap(), param::Value::Int(1));
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn start_paused(mut self) -> Result<CreatedJail, JailError> {
+        trace!("StoppedJail::start_paused({:?})", self);
+        self.name = self.name.map(|name| name::qualify(&name));
+        let (path, jid) = self.check_start()?;
+
+        self.check_osrelease();
+        let (jail, meta_in_kernel) = self.create(&path, jid)?;
+
+        Ok(CreatedJail {
+            jail,
+            stopped: self,
+            meta_in_kernel,
+        })
+    }
+
+    /// Check that the jail can be started, returning its path and the
+    /// `jid` it asks for.
+    fn check_start(&self) -> Result<(path::PathBuf, Option<i32>), JailError> {
         let path = match self.path {
             None => return Err(JailError::PathNotGiven),
             Some(ref p) => p.clone(),
         };
 
         // If we don't have a name, we can't have RCTL rules...
+        #[cfg(feature = "rctl")]
         if self.name.is_none() && !self.limits.is_empty() {
             return Err(JailError::UnnamedButLimited);
         }
 
+        #[cfg(feature = "rctl")]
+        if self.rctl_gc {
+            crate::rctl_gc()?;
+        }
+
+        let jid = match self.params.get("jid") {
+            Some(param::Value::Int(jid)) if *jid != 0 => Some(*jid),
+            _ => None,
+        };
+
+        self.check_name(jid)?;
+
+        if let Some(ref name) = self.name {
+            if sys::jail_getid_by_name(name).is_ok() {
+                return Err(JailError::NameInUse(name.clone()));
+            }
+        }
+
+        if let Some(jid) = jid {
+            if sys::jail_exists(jid) {
+                return Err(JailError::JidInUse(jid));
+            }
+        }
+
+        self.require_features()?;
+        self.verify_ips()?;
+        self.mounts.iter().try_for_each(Mount::validate)?;
+
+        match self.devfs {
+            Some(ref devfs) => devfs.check(&self.params)?,
+            None => {
+                if let Some(reason) = devfs::unused_ruleset(&self.params) {
+                    warn!("jail {:?}: {}", self.name, reason);
+                }
+            }
+        }
+
+        Ok((path, jid))
+    }
+
+    /// Create the jail in the kernel, returning it and whether its metadata
+    /// is kept in the `meta` parameter.
+    fn create(
+        &self,
+        path: &path::Path,
+        jid: Option<i32>,
+    ) -> Result<(RunningJail, bool), JailError> {
+        let meta_params = self.meta_params()?;
+        let meta_in_kernel = meta_params.contains_key("meta");
+
+        let mut params = self.kernel_params();
+        params.extend(meta_params);
+
+        let aliases = loopback::add_aliases(&self.alias_ips())?;
+
+        #[cfg(feature = "journal")]
+        let journaled = journal::create_params(path, &params);
+        let ret = sys::jail_create(path, params).map_err(|e| match (jid, e) {
+            // The kernel also refuses jids still held by dying jails.
+            (Some(jid), JailError::JailSetError(msg))
+                if msg
+                    .strip_prefix("jail ")
+                    .and_then(|rest| rest.strip_suffix(" already exists"))
+                    .and_then(|rest| rest.parse().ok())
+                    == Some(jid) =>
+            {
+                JailError::JidInUse(jid)
+            }
+            (_, e) => e,
+        });
+
+        #[cfg(feature = "audit")]
+        audit::emit(
+            Operation::Create,
+            *ret.as_ref().unwrap_or(&-1),
+            self.name.as_deref(),
+            None,
+            &ret,
+        );
+
+        #[cfg(feature = "journal")]
+        journal::record(
+            journal::Operation::Create,
+            *ret.as_ref().unwrap_or(&-1),
+            self.name.as_deref(),
+            &ret,
+            |entry| entry.params = journaled,
+        );
+
+        let ret = match ret {
+            Ok(jid) => RunningJail::from_jid_unchecked(jid),
+            Err(e) => {
+                loopback::remove_aliases(&aliases).ok();
+                return Err(e);
+            }
+        };
+        registry::release_ips(&self.ips);
+
+        if !aliases.is_empty() {
+            registry::update(ret.jid, |state| state.loopback_aliases = aliases);
+        }
+
+        Ok((ret, meta_in_kernel))
+    }
+
+    /// Set up a created jail with its root prepared and `mounts` mounted:
+    /// apply the resource limits, remember the state kept by this process
+    /// and run the hooks.
+    fn set_up(
+        &self,
+        ret: RunningJail,
+        mounts: Vec<path::PathBuf>,
+        meta_in_kernel: bool,
+    ) -> Result<RunningJail, JailError> {
+        if !mounts.is_empty() {
+            registry::update(ret.jid, |state| {
+                state.mounts = mounts;
+                state.unmount_policy = self.unmount_policy;
+            });
+        }
+
+        // Set resource limits
+        #[cfg(feature = "rctl")]
+        if !self.limits.is_empty() {
+            let name = self.name.as_ref().expect(
+                "Unreachable: Should have thrown \
+                 JailError::UnnamedButLimited",
+            );
+            self.apply_limits(name)?;
+        }
+
+        #[cfg(feature = "rctl")]
+        self.apply_scoped_limits(ret.jid)?;
+
+        if let Some(scheduling) = self.scheduling {
+            registry::update(ret.jid, |state| state.scheduling = Some(scheduling));
+        }
+
+        if !meta_in_kernel && !self.meta.is_empty() {
+            registry::update(ret.jid, |state| state.meta = self.meta.clone());
+        }
+
+        if self.devfs.is_some() {
+            registry::update(ret.jid, |state| state.devfs = self.devfs.clone());
+        }
+
+        if self.exec_hooks {
+            registry::update(ret.jid, |state| {
+                state.exec = self.exec.clone();
+                state.exec_hooks = true;
+            });
+
+            // Like jail(8), remove the jail again if it fails to come up.
+            let started = process::run_hook(&self.exec, "exec.created", None)
+                .and_then(|_| process::run_hook(&self.exec, "exec.start", Some(&ret)))
+                .and_then(|_| process::run_hook(&self.exec, "exec.poststart", None));
+            if let Err(e) = started {
+                ret.kill().ok();
+                return Err(e);
+            }
+        } else if !self.exec.is_empty() {
+            registry::update(ret.jid, |state| state.exec = self.exec.clone());
+        }
+
+        Ok(ret)
+    }
+
+    /// Start `n` jails from this definition.
+    ///
+    /// The jails are named after `name_template`, in which `{}` is replaced
+    /// by the index of the jail. If the template does not contain `{}`, the
+    /// index is appended. The parameters are only encoded once, which makes
+    /// this considerably faster than calling [StoppedJail::start] in a loop.
+    ///
+    /// If any of the jails fails to start, all jails started so far are
+    /// killed again. Definitions with IP addresses are rejected, since the
+    /// jails would all share them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::StoppedJail;
+    ///
+    /// let jails = StoppedJail::new("/rescue")
+    ///     .start_many(10, "testjail_start_many_{}")
+    ///     .expect("could not start jails");
+    ///
+    /// assert_eq!(jails.len(), 10);
+    /// assert_eq!(jails[3].name().unwrap(), "testjail_start_many_3");
+    /// #
+    /// # for jail in jails {
+    /// #     jail.kill().expect("could not stop jail");
+    /// # }
+    /// ```
+    pub fn start_many(&self, n: usize, name_template: &str) -> Result<Vec<RunningJail>, JailError> {
+        trace!(
+            "StoppedJail::start_many({:?}, n={}, name_template={:?})",
+            self,
+            n,
+            name_template
+        );
+        self.start_many_parallel(n, name_template, 1)
+    }
+
+    /// Start `n` jails from this definition, using up to `threads` threads.
+    ///
+    /// This behaves like [StoppedJail::start_many], but splits the work
+    /// between several threads. Note that the kernel serializes jail creation
+    /// internally, so the speedup is limited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::StoppedJail;
+    ///
+    /// let jails = StoppedJail::new("/rescue")
+    ///     .start_many_parallel(10, "testjail_start_many_parallel_{}", 4)
+    ///     .expect("could not start jails");
+    ///
+    /// assert_eq!(jails.len(), 10);
+    /// #
+    /// # for jail in jails {
+    /// #     jail.kill().expect("could not stop jail");
+    /// # }
+    /// ```
+    pub fn start_many_parallel(
+        &self,
+        n: usize,
+        name_template: &str,
+        threads: usize,
+    ) -> Result<Vec<RunningJail>, JailError> {
+        trace!(
+            "StoppedJail::start_many_parallel({:?}, n={}, name_template={:?}, threads={})",
+            self,
+            n,
+            name_template,
+            threads
+        );
+        let path = match self.path {
+            None => return Err(JailError::PathNotGiven),
+            Some(ref p) => p.clone(),
+        };
+
+        let names: Vec<String> = (0..n)
+            .map(|i| match name_template.contains("{}") {
+                true => name_template.replace("{}", &i.to_string()),
+                false => format!("{}{}", name_template, i),
+            })
+            .map(|name| name::qualify(&name))
+            .collect();
+
+        for name in &names {
+            JailName::new(name.as_str())?;
+            if sys::jail_getid_by_name(name).is_ok() {
+                return Err(JailError::NameInUse(name.clone()));
+            }
+        }
+
+        // The jails would all get the same addresses.
+        if let Some(ip) = self.ips.first() {
+            return Err(JailError::NotSupported(format!(
+                "the address {} is not supported when starting many jails",
+                ip
+            )));
+        }
+
+        self.require_features()?;
+        self.verify_ips()?;
+        self.check_osrelease();
+
+        // Mounts cannot be shared between the jails, since killing any of
+        // them would unmount them.
+        if let Some(mount) = self.mounts.first() {
+            return Err(JailError::NotSupported(format!(
+                "mounting {:?} is not supported when starting many jails",
+                mount.target
+            )));
+        }
+
+        if self.devfs.is_some() {
+            return Err(JailError::NotSupported(
+                "mounting devfs is not supported when starting many jails".into(),
+            ));
+        }
+
+        if let Some(ip) = self.alias_ips().first() {
+            return Err(JailError::NotSupported(format!(
+                "adding the loopback alias {} is not supported when starting many jails",
+                ip
+            )));
+        }
+
+        if let Some(step) = self.provision.iter().find(|step| step.mounts()) {
+            return Err(JailError::NotSupported(format!(
+                "provisioning step '{}' mounts file systems, which is not supported when starting many jails",
+                step
+            )));
+        }
+        if self.params.contains_key("jid") {
+            return Err(JailError::NotSupported(
+                "a fixed jid is not supported when starting many jails".into(),
+            ));
+        }
+        if self.exec_hooks && !self.exec.is_empty() {
+            return Err(JailError::NotSupported(
+                "exec hooks are not supported when starting many jails".into(),
+            ));
+        }
+
+        let meta_params = self.meta_params()?;
+        let meta_in_kernel = meta_params.contains_key("meta");
+
+        self.apply_disk_limit(&path)?;
+        let mounts = self.prepare_root(&path)?;
+
+        let mut params = self.kernel_params();
+        params.extend(meta_params);
+        params.remove("name");
+
+        let chunk_size = n.div_ceil(threads.max(1)).max(1);
+
+        let (path, params) = (&path, &params);
+        let results: Vec<Result<Vec<i32>, JailError>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = names
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || sys::jail_create_many(path, params, chunk)))
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("jail creation thread panicked"))
+                .collect()
+        });
+
+        let mut jails = vec![];
+        let mut error = None;
+        #[cfg(feature = "audit")]
+        for (result, chunk) in results.iter().zip(names.chunks(chunk_size)) {
+            match result {
+                Ok(jids) => {
+                    for (jid, name) in jids.iter().zip(chunk) {
+                        audit::emit(Operation::Create, *jid, Some(name), None, result);
+                    }
+                }
+                Err(_) => audit::emit(Operation::Create, -1, None, None, result),
+            }
+        }
+
+        #[cfg(feature = "journal")]
+        {
+            let journaled = journal::create_params(path, params);
+            for (result, chunk) in results.iter().zip(names.chunks(chunk_size)) {
+                match result {
+                    Ok(jids) => {
+                        for (jid, name) in jids.iter().zip(chunk) {
+                            journal::record(
+                                journal::Operation::Create,
+                                *jid,
+                                Some(name),
+                                result,
+                                |entry| {
+                                    entry.params = journaled.clone();
+                                    entry
+                                        .params
+                                        .insert("name".into(), param::Value::String(name.clone()));
+                                },
+                            );
+                        }
+                    }
+                    Err(_) => {
+                        journal::record(journal::Operation::Create, -1, None, result, |entry| {
+                            entry.params = journaled.clone()
+                        })
+                    }
+                }
+            }
+        }
+
+        for result in results {
+            match result {
+                Ok(jids) => jails.extend(jids.into_iter().map(RunningJail::from_jid_unchecked)),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+
+        #[cfg(feature = "rctl")]
+        if error.is_none() && !self.limits.is_empty() {
+            error = names
+                .iter()
+                .map(|name| self.apply_limits(name))
+                .find_map(Result::err);
+        }
+
+        #[cfg(feature = "rctl")]
+        if error.is_none() {
+            error = jails
+                .iter()
+                .map(|jail| self.apply_scoped_limits(jail.jid))
+                .find_map(Result::err);
+        }
+
+        if let Some(e) = error {
+            for jail in jails {
+                jail.kill().ok();
+            }
+            mount::unmount_all(&mounts).ok();
+            return Err(e);
+        }
+
+        if let Some(scheduling) = self.scheduling {
+            for jail in &jails {
+                registry::update(jail.jid, |state| state.scheduling = Some(scheduling));
+            }
+        }
+
+        if !meta_in_kernel && !self.meta.is_empty() {
+            for jail in &jails {
+                registry::update(jail.jid, |state| state.meta = self.meta.clone());
+            }
+        }
+
+        Ok(jails)
+    }
+
+    /// Check that the host release supports all features used by the jail.
+    fn require_features(&self) -> Result<(), JailError> {
+        oscompat::require_params(&self.params)?;
+
+        if self.provision.iter().any(Step::mounts) {
+            oscompat::require(Feature::NullfsFileMounts)?;
+        }
+
+        if !self.env.is_empty() {
+            oscompat::require(Feature::Metadata)?;
+        }
+
+        for mount in &self.mounts {
+            mount.require_support()?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the `meta` and `env` parameters to pass to the kernel when creating
+    /// the jail.
+    ///
+    /// On kernels without these parameters, the metadata is kept in the
+    /// registry instead, while environment metadata is not supported.
+    fn meta_params(&self) -> Result<HashMap<String, param::Value>, JailError> {
+        let mut params = HashMap::new();
+        let meta = param::encode_meta("meta", &self.meta)?;
+        let env = param::encode_meta("env", &self.env)?;
+
+        if !param::meta_supported() {
+            return match self.env.is_empty() {
+                true => Ok(params),
+                false => Err(JailError::NotSupported(
+                    "the env parameter requires FreeBSD 15 or later".into(),
+                )),
+            };
+        }
+
+        for (name, value) in [("meta", meta), ("env", env)] {
+            if !value.is_empty() {
+                params.insert(name.to_string(), param::Value::String(value));
+            }
+        }
+
+        Ok(params)
+    }
+
+    /// Get the parameters to pass to the kernel when creating the jail.
+    fn kernel_params(&self) -> HashMap<String, param::Value> {
         let mut params = self.params.clone();
 
         let ipv4_addresses: Vec<_> = self
@@ -147,69 +786,356 @@ impl StoppedJail {
             );
         }
 
-        let ret = sys::jail_create(&path, params).map(RunningJail::from_jid_unchecked)?;
+        if self.auto_allow_mount {
+            self.allow_mount_params(&mut params);
+        }
+
+        params
+    }
 
-        // Set resource limits
-        if !self.limits.is_empty() {
-            let subject = rctl::Subject::jail_name(self.name.expect(
-                "Unreachable: Should have thrown \
-                 JailError::UnnamedButLimited",
-            ));
-            for (resource, limit, action) in self.limits {
-                let rule = rctl::Rule {
-                    subject: subject.clone(),
-                    resource,
-                    limit,
-                    action,
-                };
-
-                rule.apply().map_err(JailError::RctlError)?;
+    /// Grant the `allow.mount` permissions for the file systems mounted for
+    /// the jail, keeping the values of parameters set explicitly.
+    fn allow_mount_params(&self, params: &mut HashMap<String, param::Value>) {
+        let fstypes = self
+            .mounts
+            .iter()
+            .map(|mount| mount.fstype.as_str())
+            .chain(self.devfs.as_ref().map(|_| "devfs"));
+
+        let allow = mount::allow_params(fstypes);
+        if allow.is_empty() {
+            return;
+        }
+
+        for allow in allow {
+            if !allow.is_supported() {
+                warn!(
+                    "jail {:?}: not setting {}, which the running kernel does not support",
+                    self.name, allow
+                );
+                continue;
             }
+            params
+                .entry(allow.name().into())
+                .or_insert(param::Value::Int(1));
         }
 
-        Ok(ret)
+        // Jails can only mount file systems with enforce_statfs below 2.
+        match params.get("enforce_statfs") {
+            None => {
+                params.insert("enforce_statfs".into(), param::Value::Int(1));
+            }
+            Some(param::Value::Int(2)) => warn!(
+                "jail {:?}: enforce_statfs is 2, so the jail cannot mount file systems itself",
+                self.name
+            ),
+            Some(_) => {}
+        }
     }
 
-    /// Set the jail name
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # use jail::StoppedJail;
-    /// #
-    /// let mut stopped = StoppedJail::new("/rescue")
-    ///     .name("test_stopped_name");
-    ///
-    /// assert_eq!(stopped.name, Some("test_stopped_name".to_string()));
-    /// ```
-    pub fn name<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
-        trace!("StoppedJail::start({:?}, name={:?})", self, name);
-        self.name = Some(name.into());
-        self
+    /// Apply the resource limits to the jail with the given name.
+    #[cfg(feature = "rctl")]
+    pub(crate) fn apply_limits(&self, name: &str) -> Result<(), JailError> {
+        let subject = rctl::Subject::jail_name(name);
+        for (resource, limit, action) in &self.limits {
+            let rule = rctl::Rule {
+                subject: subject.clone(),
+                resource: *resource,
+                limit: limit.clone(),
+                action: *action,
+            };
+
+            rule.apply().map_err(JailError::RctlError)?;
+        }
+
+        Ok(())
     }
 
-    /// Set the jail name
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # use jail::StoppedJail;
-    /// #
-    /// let mut stopped = StoppedJail::new("/rescue")
-    /// #   .name("test_stopped_hostname")
-    ///     .hostname("example.com");
-    ///
-    /// assert_eq!(stopped.hostname, Some("example.com".to_string()));
-    /// ```
-    pub fn hostname<S: Into<String> + fmt::Debug>(mut self, hostname: S) -> Self {
-        trace!("StoppedJail::hostname({:?}, hostname={:?})", self, hostname);
-        self.hostname = Some(hostname.into());
-        self
+    /// Apply the scoped resource limits, remembering them so that they are
+    /// removed when the jail `jid` is killed.
+    #[cfg(feature = "rctl")]
+    pub(crate) fn apply_scoped_limits(&self, jid: i32) -> Result<(), JailError> {
+        for scoped in &self.scoped_limits {
+            let (scope, (resource, limit, action)) = scoped;
+            let rule = rctl::Rule {
+                subject: scope.subject(),
+                resource: *resource,
+                limit: limit.clone(),
+                action: *action,
+            };
+
+            rule.apply().map_err(JailError::RctlError)?;
+            registry::update(jid, |state| state.scoped_limits.push(scoped.clone()));
+        }
+
+        Ok(())
     }
 
-    /// Set a jail parameter
-    ///
-    /// # Examples
+    /// Fail early on names the kernel would reject on start.
+    fn check_name(&self, jid: Option<i32>) -> Result<(), JailError> {
+        match self.name {
+            // A numeric name is accepted if it is the jid of the jail.
+            Some(ref name) if jid.is_some_and(|jid| *name == jid.to_string()) => Ok(()),
+            Some(ref name) => JailName::new(name.as_str()).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the addresses of the jail if asked to, unless it has its own
+    /// network stack.
+    fn verify_ips(&self) -> Result<(), JailError> {
+        if !self.check_ips || self.params.get("vnet") == Some(&param::Value::Int(1)) {
+            return Ok(());
+        }
+
+        // Loopback aliases are only added once the checks have passed.
+        let aliases = self.alias_ips();
+        let ips: Vec<net::IpAddr> = self
+            .ips
+            .iter()
+            .filter(|ip| !aliases.contains(ip))
+            .copied()
+            .collect();
+        crate::net::verify_ips(&ips)
+    }
+
+    /// Get the addresses to add as aliases of `lo0` on start, see
+    /// [StoppedJail::loopback_aliases].
+    fn alias_ips(&self) -> Vec<net::IpAddr> {
+        if !self.loopback_aliases || self.params.get("vnet") == Some(&param::Value::Int(1)) {
+            return vec![];
+        }
+
+        self.ips
+            .iter()
+            .copied()
+            .filter(loopback::is_loopback_alias)
+            .collect()
+    }
+
+    /// Warn if the `osrelease` and `osreldate` seen inside the jail disagree.
+    ///
+    /// If only one of them is set, the other one is inherited from the host.
+    fn check_osrelease(&self) {
+        let release = match self.params.get("osrelease") {
+            Some(param::Value::String(release)) => Some(release.clone()),
+            _ => None,
+        };
+
+        let date = match self.params.get("osreldate") {
+            Some(param::Value::Int(date)) => Some(*date),
+            _ => None,
+        };
+
+        let (release, date) = match (release, date) {
+            (None, None) => return,
+            (Some(release), Some(date)) => (release, date),
+            (release, date) => match osrelease::host() {
+                Some((host_release, host_date)) => {
+                    (release.unwrap_or(host_release), date.unwrap_or(host_date))
+                }
+                None => return,
+            },
+        };
+
+        if let Err(mismatch) = osrelease::check(&release, date) {
+            warn!(
+                "jail {:?} has inconsistent osrelease and osreldate: {}",
+                self.name, mismatch
+            );
+        }
+    }
+
+    /// Apply the provisioning steps to the jail root and mount the declared
+    /// file systems, returning the host paths of all mountpoints.
+    ///
+    /// If anything fails, everything mounted before is unmounted. Files
+    /// modified by the provisioning steps are only restored if a step fails.
+    fn prepare_root(&self, path: &path::Path) -> Result<Vec<path::PathBuf>, JailError> {
+        let mut mounts = ProvisionPlan::from(self.provision.clone())
+            .run(path)
+            .into_result()?
+            .mounts;
+
+        let result = self
+            .mounts
+            .iter()
+            .try_for_each(|m| m.mount(path).map(|target| mounts.push(target)))
+            .and_then(|_| match self.devfs {
+                Some(ref devfs) => devfs.mount(path).map(|target| mounts.push(target)),
+                None => Ok(()),
+            });
+
+        if let Err(e) = result {
+            mount::unmount_all(&mounts).ok();
+            return Err(e);
+        }
+
+        Ok(mounts)
+    }
+
+    /// Set the quota on the ZFS dataset backing the jail root.
+    fn apply_disk_limit(&self, path: &path::Path) -> Result<(), JailError> {
+        match self.disk_limit {
+            None => Ok(()),
+            #[cfg(feature = "zfs")]
+            Some(bytes) => zfs::Dataset::at(path)?.set_quota(Some(bytes)),
+            #[cfg(not(feature = "zfs"))]
+            Some(_) => Err(JailError::NotSupported(format!(
+                "disk limit on {:?} requires the `zfs` feature",
+                path
+            ))),
+        }
+    }
+
+    /// Request a specific `jid` for the jail, instead of letting the kernel
+    /// pick the next free one.
+    ///
+    /// This is useful when the identity of a jail is encoded in firewall
+    /// rules by `jid`. Starting the jail fails with [JailError::JidInUse] if
+    /// a running or dying jail already has the `jid`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::{JailError, StoppedJail};
+    ///
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_stopped_jid")
+    ///     .jid(4242)
+    ///     .start()
+    ///     .expect("could not start jail");
+    /// assert_eq!(running.jid, 4242);
+    ///
+    /// let collision = StoppedJail::new("/rescue")
+    ///     .name("testjail_stopped_jid_collision")
+    ///     .jid(4242)
+    ///     .start();
+    /// assert!(matches!(collision, Err(JailError::JidInUse(4242))));
+    /// #
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn jid(mut self, jid: i32) -> Self {
+        trace!("StoppedJail::jid({:?}, jid={})", self, jid);
+        self.params.insert("jid".into(), param::Value::Int(jid));
+        self
+    }
+
+    /// Set the jail name
+    ///
+    /// The name is validated on start, failing with
+    /// [JailError::InvalidName](enum.JailError.html#variant.InvalidName) if
+    /// the kernel would reject it. Pass a [JailName](struct.JailName.html) to
+    /// validate it up front.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// #
+    /// let mut stopped = StoppedJail::new("/rescue")
+    ///     .name("test_stopped_name");
+    ///
+    /// assert_eq!(stopped.name, Some("test_stopped_name".to_string()));
+    /// ```
+    ///
+    /// ```
+    /// use jail::{JailError, StoppedJail};
+    ///
+    /// let dotted = StoppedJail::new("/rescue")
+    ///     .name("www.example.org")
+    ///     .start();
+    /// assert!(matches!(dotted, Err(JailError::InvalidName { .. })));
+    /// ```
+    pub fn name<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
+        trace!("StoppedJail::start({:?}, name={:?})", self, name);
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the jail name
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// #
+    /// let mut stopped = StoppedJail::new("/rescue")
+    /// #   .name("test_stopped_hostname")
+    ///     .hostname("example.com");
+    ///
+    /// assert_eq!(stopped.hostname, Some("example.com".to_string()));
+    /// ```
+    pub fn hostname<S: Into<String> + fmt::Debug>(mut self, hostname: S) -> Self {
+        trace!("StoppedJail::hostname({:?}, hostname={:?})", self, hostname);
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Derive the hostname from the jail name as `<name>.<domain>`.
+    ///
+    /// The name must be set before. Fails if the jail has no name, or the
+    /// resulting hostname is not valid according to RFC 1123, see
+    /// [hostname::validate](hostname/fn.validate.html). Chain
+    /// [StoppedJail::domainname] to set `host.domainname` as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::{JailError, StoppedJail};
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("www")
+    ///     .hostname_from_name("example.org")
+    ///     .expect("invalid hostname")
+    ///     .domainname("example.org");
+    ///
+    /// assert_eq!(stopped.hostname, Some("www.example.org".to_string()));
+    ///
+    /// let underscored = StoppedJail::new("/rescue")
+    ///     .name("my_www")
+    ///     .hostname_from_name("example.org");
+    /// assert!(matches!(underscored, Err(JailError::InvalidHostname { .. })));
+    /// ```
+    pub fn hostname_from_name(mut self, domain: &str) -> Result<Self, JailError> {
+        trace!(
+            "StoppedJail::hostname_from_name({:?}, domain={:?})",
+            self,
+            domain
+        );
+        let name = self
+            .name
+            .as_ref()
+            .ok_or_else(|| JailError::InvalidHostname {
+                hostname: domain.to_string(),
+                reason: "the jail has no name to derive the hostname from".into(),
+            })?;
+
+        self.hostname = Some(hostname::fqdn(name, domain)?);
+        Ok(self)
+    }
+
+    /// Set the NIS domain name of the jail, `host.domainname`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::{param, StoppedJail};
+    ///
+    /// let stopped = StoppedJail::new("/rescue").domainname("example.org");
+    /// assert_eq!(
+    ///     stopped.params.get("host.domainname"),
+    ///     Some(&param::Value::String("example.org".into()))
+    /// );
+    /// ```
+    pub fn domainname<S: Into<String> + fmt::Debug>(self, domain: S) -> Self {
+        trace!("StoppedJail::domainname({:?}, domain={:?})", self, domain);
+        self.param("host.domainname", param::Value::String(domain.into()))
+    }
+
+    /// Set a jail parameter
+    ///
+    /// # Examples
     ///
     /// ```
     /// # use jail::StoppedJail;
@@ -230,6 +1156,218 @@ impl StoppedJail {
         self
     }
 
+    /// Grant the jail a permission.
+    ///
+    /// This sets the corresponding `allow.*` parameter. Starting the jail
+    /// fails if the running kernel does not support it, see
+    /// [StoppedJail::allow_if_supported] to skip it instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::allow::Allow;
+    /// use jail::param;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .allow(Allow::RawSockets);
+    ///
+    /// assert_eq!(stopped.params["allow.raw_sockets"], param::Value::Int(1));
+    /// ```
+    pub fn allow(mut self, allow: Allow) -> Self {
+        trace!("StoppedJail::allow({:?}, allow={:?})", self, allow);
+        self.params
+            .insert(allow.name().into(), param::Value::Int(1));
+        self
+    }
+
+    /// Grant the jail a permission if the running kernel supports it.
+    ///
+    /// Unsupported permissions are skipped with a warning, so that a jail
+    /// definition degrades predictably on older hosts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::allow::Allow;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .allow_if_supported(Allow::UnprivilegedProcDebug);
+    ///
+    /// assert_eq!(
+    ///     stopped.params.contains_key("allow.unprivileged_proc_debug"),
+    ///     Allow::UnprivilegedProcDebug.is_supported()
+    /// );
+    /// ```
+    pub fn allow_if_supported(self, allow: Allow) -> Self {
+        trace!(
+            "StoppedJail::allow_if_supported({:?}, allow={:?})",
+            self,
+            allow
+        );
+        if !allow.is_supported() {
+            warn!("{} is not supported by the running kernel, skipping", allow);
+            return self;
+        }
+
+        self.allow(allow)
+    }
+
+    /// Set the operating system release reported inside the jail.
+    ///
+    /// The release should be of the form `X.Y-BRANCH`, e.g. `12.4-RELEASE`,
+    /// and agree with [StoppedJail::osreldate]. Otherwise, `pkg`(8) and other
+    /// tools inside the jail may derive the wrong ABI. A warning is logged if
+    /// the format is invalid, and when the jail is started if the two values
+    /// (or the value and the one inherited from the host) diverge.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::param;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .osrelease("12.4-RELEASE")
+    ///     .osreldate(1204000);
+    ///
+    /// assert_eq!(
+    ///     stopped.params["osrelease"],
+    ///     param::Value::String("12.4-RELEASE".into())
+    /// );
+    /// assert_eq!(stopped.params["osreldate"], param::Value::Int(1204000));
+    /// ```
+    pub fn osrelease<S: Into<String> + fmt::Debug>(self, osrelease: S) -> Self {
+        trace!(
+            "StoppedJail::osrelease({:?}, osrelease={:?})",
+            self,
+            osrelease
+        );
+        let osrelease = osrelease.into();
+        if osrelease::parse(&osrelease).is_none() {
+            warn!("osrelease {:?} is not of the form X.Y-BRANCH", osrelease);
+        }
+
+        self.param("osrelease", param::Value::String(osrelease))
+    }
+
+    /// Set the `__FreeBSD_version` reported inside the jail.
+    ///
+    /// The value encodes the version as `XXYYZZZ`, e.g. `1204000` for 12.4.
+    /// See [StoppedJail::osrelease].
+    pub fn osreldate(self, osreldate: i32) -> Self {
+        trace!(
+            "StoppedJail::osreldate({:?}, osreldate={})",
+            self,
+            osreldate
+        );
+        if osrelease::version(osreldate).is_none() {
+            warn!("osreldate {} is invalid", osreldate);
+        }
+
+        self.param("osreldate", param::Value::Int(osreldate))
+    }
+
+    /// Set the access of the jail to System V IPC primitives.
+    ///
+    /// See [param::SysVIpc] for controlling message queues, semaphores and
+    /// shared memory individually.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::param::{SysVIpc, SysVIpcMode};
+    ///
+    /// // Give the jail its own SysV IPC namespace
+    /// let isolated = StoppedJail::new("/rescue")
+    ///     .sysvipc(SysVIpcMode::New);
+    ///
+    /// // Share only memory with the host
+    /// let shared = StoppedJail::new("/rescue")
+    ///     .sysvipc(SysVIpc::all(SysVIpcMode::Disable).shm(SysVIpcMode::Inherit));
+    /// ```
+    pub fn sysvipc<I: Into<param::SysVIpc> + fmt::Debug>(mut self, ipc: I) -> Self {
+        trace!("StoppedJail::sysvipc({:?}, ipc={:?})", self, ipc);
+        for name in &["sysvmsg", "sysvsem", "sysvshm", "allow.sysvipc"] {
+            self.params.remove(*name);
+        }
+        self.params.extend(ipc.into().params());
+        self
+    }
+
+    /// Enable or disable IPv4 source address selection, the `ip4.saddrsel`
+    /// parameter.
+    ///
+    /// Source address selection is enabled by default: sockets of the jail
+    /// that are not bound to an address send from the address of the jail
+    /// the kernel considers closest to the destination, like on the host.
+    ///
+    /// When disabled, they always send from the primary address of the jail,
+    /// which is the first IPv4 address added with [StoppedJail::ip]. The
+    /// order of the addresses is kept, so the primary address can be chosen
+    /// by adding it first, e.g. to send all outgoing traffic from an address
+    /// that firewalls or remote services expect.
+    ///
+    /// Requires FreeBSD 9.0 or later, see
+    /// [Feature::IpSaddrSel](oscompat/enum.Feature.html#variant.IpSaddrSel).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::param;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .ip("192.0.2.10".parse().unwrap())
+    ///     .ip("192.0.2.11".parse().unwrap())
+    ///     .ip4_saddrsel(false);
+    ///
+    /// // Outgoing connections are made from 192.0.2.10.
+    /// assert_eq!(stopped.params["ip4.saddrsel"], param::Value::Int(0));
+    /// ```
+    pub fn ip4_saddrsel(self, enabled: bool) -> Self {
+        trace!("StoppedJail::ip4_saddrsel({:?}, enabled={})", self, enabled);
+        self.param("ip4.saddrsel", param::Value::Int(enabled.into()))
+    }
+
+    /// Enable or disable IPv6 source address selection, the `ip6.saddrsel`
+    /// parameter.
+    ///
+    /// When disabled, sockets of the jail that are not bound to an address
+    /// always send from the first IPv6 address added with [StoppedJail::ip].
+    /// See [StoppedJail::ip4_saddrsel].
+    pub fn ip6_saddrsel(self, enabled: bool) -> Self {
+        trace!("StoppedJail::ip6_saddrsel({:?}, enabled={})", self, enabled);
+        self.param("ip6.saddrsel", param::Value::Int(enabled.into()))
+    }
+
+    /// Seed the jail parameters from a [Profile].
+    ///
+    /// Parameters of the profile overwrite previously set parameters of the
+    /// same name, so the profile should be applied before customizing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::defaults::Profile;
+    /// use jail::param;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .profile(Profile::BuildJail)
+    ///     .param("allow.mount.tmpfs", param::Value::Int(0));
+    ///
+    /// assert_eq!(stopped.params["allow.mount.nullfs"], param::Value::Int(1));
+    /// assert_eq!(stopped.params["allow.mount.tmpfs"], param::Value::Int(0));
+    /// ```
+    pub fn profile(mut self, profile: Profile) -> Self {
+        trace!("StoppedJail::profile({:?}, profile={:?})", self, profile);
+        self.params.extend(profile.params());
+        self
+    }
+
     /// Set a resource limit
     ///
     /// # Examples
@@ -244,6 +1382,8 @@ impl StoppedJail {
     ///     rctl::Limit::amount_per(100 * 1024 * 1024, rctl::SubjectType::Process),
     ///     rctl::Action::Deny,
     /// );
+    /// ```
+    #[cfg(feature = "rctl")]
     pub fn limit(
         mut self,
         resource: rctl::Resource,
@@ -261,21 +1401,949 @@ impl StoppedJail {
         self
     }
 
-    /// Add an IP Address
+    /// Set a resource limit on the processes of a user or login class, e.g.
+    /// to share the resources of a jail between its tenants.
+    ///
+    /// The limit is applied when the jail is started and removed when it is
+    /// killed. As `RCTL` matches users and login classes on the whole host,
+    /// it also applies to their processes outside of the jail, see
+    /// [Scope](limits/enum.Scope.html).
     ///
     /// # Examples
     ///
     /// ```
-    /// # use jail::StoppedJail;
-    /// # use std::net::IpAddr;
-    /// #
-    /// let mut stopped = StoppedJail::new("rescue")
-    ///     .ip("127.0.1.1".parse().expect("could not parse 127.0.1.1"))
-    ///     .ip("fe80::2".parse().expect("could not parse ::1"));
+    /// use jail::limits::Scope;
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("shared")
+    ///     .scoped_limit(
+    ///         Scope::LoginClass("tenant_a".into()),
+    ///         rctl::Resource::MaxProcesses,
+    ///         rctl::Limit::amount(50),
+    ///         rctl::Action::Deny,
+    ///     )
+    ///     .scoped_limit(
+    ///         Scope::User(1002),
+    ///         rctl::Resource::MemoryUse,
+    ///         rctl::Limit::amount(1 << 30),
+    ///         rctl::Action::Deny,
+    ///     );
+    ///
+    /// assert_eq!(
+    ///     stopped.rctl_rules().unwrap(),
+    ///     vec![
+    ///         "loginclass:tenant_a:maxproc:deny=50",
+    ///         "user:1002:memoryuse:deny=1073741824",
+    ///     ]
+    /// );
     /// ```
-    pub fn ip(mut self, ip: net::IpAddr) -> Self {
-        trace!("StoppedJail::ip({:?}, ip={:?})", self, ip);
-        self.ips.push(ip);
+    #[cfg(feature = "rctl")]
+    pub fn scoped_limit(
+        mut self,
+        scope: crate::limits::Scope,
+        resource: rctl::Resource,
+        limit: rctl::Limit,
+        action: rctl::Action,
+    ) -> Self {
+        trace!(
+            "StoppedJail::scoped_limit({:?}, scope={:?}, resource={:?}, limit={:?}, action={:?})",
+            self,
+            scope,
+            resource,
+            limit,
+            action
+        );
+        self.scoped_limits.push((scope, (resource, limit, action)));
         self
     }
+
+    /// Summarize the configuration in aligned, human-readable lines, with one
+    /// labelled row per field like `jls -v`, and limits humanized like
+    /// `rctl -h`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("www")
+    ///     .ip("192.0.2.1".parse().unwrap())
+    ///     .ip("2001:db8::1".parse().unwrap())
+    ///     .limit(
+    ///         rctl::Resource::MemoryUse,
+    ///         rctl::Limit::amount(1 << 30),
+    ///         rctl::Action::Deny,
+    ///     );
+    ///
+    /// let pretty = stopped.to_pretty_string();
+    /// assert_eq!(
+    ///     pretty.lines().collect::<Vec<_>>(),
+    ///     vec![
+    ///         "name    www",
+    ///         "path    /rescue",
+    ///         "ips     192.0.2.1",
+    ///         "        2001:db8::1",
+    ///         "limits  memoryuse:deny=1G",
+    ///     ]
+    /// );
+    /// ```
+    pub fn to_pretty_string(&self) -> String {
+        trace!("StoppedJail::to_pretty_string({:?})", self);
+        let mut table = Table::default();
+        table.jail(self);
+        table.render()
+    }
+
+    /// The resource limits, including the scoped limits, as `rctl.conf`(5)
+    /// rules, see [limits](limits/index.html).
+    ///
+    /// Fails with [JailError::UnnamedButLimited] if the jail has limits but
+    /// no name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("www")
+    ///     .limit(
+    ///         rctl::Resource::MaxProcesses,
+    ///         rctl::Limit::amount(100),
+    ///         rctl::Action::Deny,
+    ///     );
+    ///
+    /// assert_eq!(stopped.rctl_rules().unwrap(), vec!["jail:www:maxproc:deny=100"]);
+    /// ```
+    #[cfg(feature = "rctl")]
+    pub fn rctl_rules(&self) -> Result<Vec<String>, JailError> {
+        trace!("StoppedJail::rctl_rules({:?})", self);
+        let mut rules = vec![];
+        if !self.limits.is_empty() {
+            let name = self.name.as_ref().ok_or(JailError::UnnamedButLimited)?;
+            rules.extend(
+                self.limits
+                    .iter()
+                    .map(|limit| crate::limits::format_rule(name, limit)),
+            );
+        }
+
+        rules.extend(
+            self.scoped_limits
+                .iter()
+                .map(crate::limits::format_scoped_rule),
+        );
+        Ok(rules)
+    }
+
+    /// Remove the stale `RCTL` rules of all jails that do not exist before
+    /// starting the jail, see [rctl_gc](fn.rctl_gc.html).
+    ///
+    /// This makes sure that no rules left behind by a previous jail of the
+    /// same name apply to the jail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("testjail_rctl_gc")
+    ///     .rctl_gc(true);
+    ///
+    /// assert!(stopped.rctl_gc);
+    /// ```
+    #[cfg(feature = "rctl")]
+    pub fn rctl_gc(mut self, enabled: bool) -> Self {
+        trace!("StoppedJail::rctl_gc({:?}, enabled={})", self, enabled);
+        self.rctl_gc = enabled;
+        self
+    }
+
+    /// Check before start that each address of the jail is configured on a
+    /// host interface and not used by another jail, see
+    /// [net::verify_ips](net/fn.verify_ips.html).
+    ///
+    /// This turns the kernel's `EADDRNOTAVAIL` into an error naming the
+    /// missing address. Jails with their own network stack are not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::{JailError, StoppedJail};
+    ///
+    /// let result = StoppedJail::new("/rescue")
+    ///     .name("testjail_check_ips")
+    ///     .ip("192.0.2.254".parse().unwrap())
+    ///     .check_ips(true)
+    ///     .start();
+    ///
+    /// assert!(matches!(result, Err(JailError::IpNotConfigured(_))));
+    /// ```
+    pub fn check_ips(mut self, enabled: bool) -> Self {
+        trace!("StoppedJail::check_ips({:?}, enabled={})", self, enabled);
+        self.check_ips = enabled;
+        self
+    }
+
+    /// Set whether to add the loopback addresses of the jail as aliases of
+    /// `lo0` on start, and remove them again when it is killed. Jails with
+    /// their own network stack are not affected.
+    ///
+    /// Addresses in `127.0.0.0/8` other than `127.0.0.1` are added, unless
+    /// already configured on the host. An alias shared by several jails
+    /// started by this process is removed with the last of them. Aliases
+    /// are not checked by [StoppedJail::check_ips].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_loopback_aliases")
+    ///     .ip("127.0.1.1".parse().unwrap())
+    ///     .loopback_aliases(true)
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// // The alias is configured on the host now.
+    /// assert!(std::net::TcpListener::bind("127.0.1.1:0").is_ok());
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn loopback_aliases(mut self, enabled: bool) -> Self {
+        trace!(
+            "StoppedJail::loopback_aliases({:?}, enabled={})",
+            self,
+            enabled
+        );
+        self.loopback_aliases = enabled;
+        self
+    }
+
+    /// Add a command to an `exec.*` parameter of `jail`(8), e.g. `exec.start`.
+    ///
+    /// The kernel knows nothing about these parameters, so the commands are
+    /// only carried along, e.g. when converting a definition from or to
+    /// `jail.conf`(5), unless [StoppedJail::exec_hooks] is enabled. The
+    /// `exec.` prefix may be left out, and commands are appended to the ones
+    /// already present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .exec("start", "/bin/sh /etc/rc")
+    ///     .exec("exec.stop", "/bin/sh /etc/rc.shutdown");
+    ///
+    /// assert_eq!(stopped.exec["exec.start"], vec!["/bin/sh /etc/rc"]);
+    /// assert_eq!(stopped.exec["exec.stop"], vec!["/bin/sh /etc/rc.shutdown"]);
+    /// ```
+    pub fn exec<N, C>(mut self, name: N, command: C) -> Self
+    where
+        N: Into<String> + fmt::Debug,
+        C: Into<String> + fmt::Debug,
+    {
+        trace!(
+            "StoppedJail::exec({:?}, name={:?}, command={:?})",
+            self,
+            name,
+            command
+        );
+        let mut name = name.into();
+        if !name.starts_with("exec.") {
+            name.insert_str(0, "exec.");
+        }
+        self.exec.entry(name).or_default().push(command.into());
+        self
+    }
+
+    /// Run the `exec.*` commands of the jail like `jail`(8) does.
+    ///
+    /// [StoppedJail::start] runs `exec.prestart`, `exec.created` and
+    /// `exec.poststart` on the host and `exec.start` inside the jail, each
+    /// with `/bin/sh -c`. If any of them fails, the jail is removed again and
+    /// [JailError::ExecHookFailed] is returned. [RunningJail::kill] runs
+    /// `exec.prestop`, `exec.stop` inside the jail and `exec.poststop`, but
+    /// only logs failures. Other `exec.*` parameters, e.g. `exec.clean` or
+    /// `exec.jail_user`, are not interpreted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_exec_hooks")
+    ///     .exec("prestart", "true")
+    ///     .exec_hooks(true)
+    ///     .start()
+    ///     .expect("could not start jail");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn exec_hooks(mut self, enabled: bool) -> Self {
+        trace!("StoppedJail::exec_hooks({:?}, enabled={})", self, enabled);
+        self.exec_hooks = enabled;
+        self
+    }
+
+    /// Expand variables like `${name}` in the definition, as `jail`(8) does
+    /// for `jail.conf`(5).
+    ///
+    /// Variables are expanded in the path, the host name, string parameters,
+    /// [StoppedJail::exec] commands and the paths of [StoppedJail::mount]s.
+    /// The parameters of the jail are available as variables (see
+    /// [Variables::of_jail](vars/struct.Variables.html#method.of_jail)), and
+    /// `variables` can add to or override them. Fails with
+    /// [JailError::VariableError] if a variable is not defined or refers to
+    /// itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::vars::Variables;
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::default()
+    ///     .name("www")
+    ///     .hostname("${name}.$domain")
+    ///     .exec("start", "echo ${host.hostname} > /etc/myname")
+    ///     .interpolate(&Variables::new().set("domain", "example.com"))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(stopped.hostname, Some("www.example.com".into()));
+    /// assert_eq!(
+    ///     stopped.exec["exec.start"],
+    ///     vec!["echo www.example.com > /etc/myname"]
+    /// );
+    /// ```
+    pub fn interpolate(mut self, variables: &Variables) -> Result<Self, JailError> {
+        trace!(
+            "StoppedJail::interpolate({:?}, variables={:?})",
+            self,
+            variables
+        );
+        let vars = variables
+            .iter()
+            .fold(Variables::of_jail(&self), |vars, (name, value)| {
+                vars.set(name, value)
+            });
+        let expand_path = |path: &path::Path| {
+            vars.expand(&path.to_string_lossy())
+                .map(path::PathBuf::from)
+        };
+
+        if let Some(ref path) = self.path {
+            self.path = Some(expand_path(path)?);
+        }
+        if let Some(ref hostname) = self.hostname {
+            self.hostname = Some(vars.expand(hostname)?);
+        }
+
+        for value in self.params.values_mut() {
+            if let param::Value::String(ref mut s) = value {
+                *s = vars.expand(s)?;
+            }
+        }
+
+        for command in self.exec.values_mut().flatten() {
+            *command = vars.expand(command)?;
+        }
+
+        for mount in &mut self.mounts {
+            mount.source = expand_path(&mount.source)?;
+            mount.target = expand_path(&mount.target)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Attach metadata to the jail, e.g. to identify its owner.
+    ///
+    /// The metadata is stored in the `meta` parameter on FreeBSD 15 and
+    /// later, where it is visible to other tools like `jls`(8). On older
+    /// kernels, it is only known to this process, see [RunningJail::meta].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_meta")
+    ///     .meta("owner", "alice")
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// let meta = running.meta().expect("could not get metadata");
+    /// assert_eq!(meta["owner"], "alice");
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn meta<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String> + fmt::Debug,
+        V: Into<String> + fmt::Debug,
+    {
+        trace!(
+            "StoppedJail::meta({:?}, key={:?}, value={:?})",
+            self,
+            key,
+            value
+        );
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    /// Record that the jail depends on the jail `name`, like the `depend`
+    /// parameter of `jail`(8).
+    ///
+    /// The names are kept in the `depend` metadata key, separated by commas
+    /// (see [StoppedJail::meta]), and are not checked on start; use a
+    /// [JailSet](struct.JailSet.html) to start jails in dependency order.
+    /// [shutdown_all](fn.shutdown_all.html) stops a jail before the jails
+    /// it depends on, qualifying the names with the
+    /// [namespace](fn.namespace.html) of the process like the jail name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("web")
+    ///     .depend("db")
+    ///     .depend("cache");
+    ///
+    /// assert_eq!(stopped.meta["depend"], "db,cache");
+    /// ```
+    pub fn depend<S: Into<String> + fmt::Debug>(mut self, name: S) -> Self {
+        trace!("StoppedJail::depend({:?}, name={:?})", self, name);
+        let name = name.into();
+        let depend = self.meta.entry(crate::shutdown::DEPEND.into()).or_default();
+        if !depend.split(',').any(|dependency| dependency == name) {
+            if !depend.is_empty() {
+                depend.push(',');
+            }
+            depend.push_str(&name);
+        }
+        self
+    }
+
+    /// Attach metadata to the jail that can also be read from inside the
+    /// jail.
+    ///
+    /// The metadata is stored in the `env` parameter, which requires FreeBSD
+    /// 15 or later. Starting the jail fails with
+    /// [JailError::UnsupportedOnRelease] on older releases.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let stopped = StoppedJail::new("/rescue").env("DEPLOYMENT", "blue");
+    ///
+    /// assert_eq!(stopped.env["DEPLOYMENT"], "blue");
+    /// ```
+    pub fn env<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String> + fmt::Debug,
+        V: Into<String> + fmt::Debug,
+    {
+        trace!(
+            "StoppedJail::env({:?}, key={:?}, value={:?})",
+            self,
+            key,
+            value
+        );
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Limit the disk space available to the jail.
+    ///
+    /// RCTL cannot limit disk space, so the limit is enforced as a ZFS
+    /// `quota` on the dataset mounted at the jail root when the jail is
+    /// started. This requires the `zfs` feature; without it, or if the jail
+    /// root is not the mountpoint of a ZFS dataset, starting the jail fails
+    /// with [JailError::NotSupported]. UFS quotas apply to users and groups
+    /// rather than directories and are therefore not used.
+    ///
+    /// The quota stays on the dataset after the jail is stopped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// let stopped = StoppedJail::new("/jails/www")
+    ///     .disk_limit(10 * 1024 * 1024 * 1024);
+    ///
+    /// assert_eq!(stopped.disk_limit, Some(10 * 1024 * 1024 * 1024));
+    /// ```
+    pub fn disk_limit(mut self, bytes: u64) -> Self {
+        trace!("StoppedJail::disk_limit({:?}, bytes={})", self, bytes);
+        self.disk_limit = Some(bytes);
+        self
+    }
+
+    /// Set the nice value of processes in the jail.
+    ///
+    /// This is a shorthand for [StoppedJail::scheduling] with
+    /// [Scheduling::Nice]. Values range from -20 (highest priority) to 20
+    /// (lowest priority).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::sched::Scheduling;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .priority(15);
+    ///
+    /// assert_eq!(stopped.scheduling, Some(Scheduling::Nice(15)));
+    /// ```
+    pub fn priority(self, nice: i32) -> Self {
+        trace!("StoppedJail::priority({:?}, nice={})", self, nice);
+        self.scheduling(Scheduling::Nice(nice))
+    }
+
+    /// Set the scheduling class and priority of processes in the jail.
+    ///
+    /// The kernel does not keep a scheduling class per jail. Instead, it is
+    /// applied to every process spawned in the jail using
+    /// [Jailed::jail](process/trait.Jailed.html#tymethod.jail), and inherited
+    /// by their children. See [RunningJail::set_scheduling] to change it for
+    /// a running jail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::process::Jailed;
+    /// use jail::sched::Scheduling;
+    /// use std::process::Command;
+    ///
+    /// let running = StoppedJail::new("/rescue")
+    ///     .name("testjail_scheduling")
+    ///     .scheduling(Scheduling::Idle(10))
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// let output = Command::new("/sh")
+    ///     .arg("-c")
+    ///     .arg("echo idle")
+    ///     .jail(&running)
+    ///     .output()
+    ///     .expect("could not run command");
+    /// assert!(output.status.success());
+    /// # running.kill().expect("could not stop jail");
+    /// ```
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        trace!(
+            "StoppedJail::scheduling({:?}, scheduling={:?})",
+            self,
+            scheduling
+        );
+        self.scheduling = Some(scheduling);
+        self
+    }
+
+    /// Mount a file system below the jail root when the jail is started.
+    ///
+    /// The file system is unmounted again when the jail is killed using
+    /// [RunningJail::kill]. Starting the jail fails if the mount does not
+    /// pass [Mount::validate].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::mount::Mount;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .mount(Mount::nullfs("/usr/ports", "/usr/ports").readonly(true));
+    ///
+    /// assert_eq!(stopped.mounts.len(), 1);
+    /// ```
+    pub fn mount(mut self, mount: Mount) -> Self {
+        trace!("StoppedJail::mount({:?}, mount={:?})", self, mount);
+        self.mounts.push(mount);
+        self
+    }
+
+    /// Set how the file systems of the jail are unmounted when it is
+    /// killed, e.g. to force unmounting file systems still in use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::mount::{Mount, UnmountPolicy};
+    /// use jail::StoppedJail;
+    /// use std::time::Duration;
+    /// # let root = std::env::temp_dir().join("testjail_unmount_policy");
+    /// # std::fs::create_dir_all(&root).unwrap();
+    ///
+    /// let running = StoppedJail::new(&root)
+    ///     .name("testjail_unmount_policy")
+    ///     .mount(Mount::new("tmpfs", "tmpfs", "/tmp"))
+    ///     .unmount_policy(UnmountPolicy::default().grace(Duration::from_millis(100)))
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// // Keep the tmpfs busy.
+    /// let cwd = std::env::current_dir().unwrap();
+    /// std::env::set_current_dir(root.join("tmp")).unwrap();
+    ///
+    /// let report = running.kill_with_report().expect("could not kill jail");
+    /// assert_eq!(report.still_mounted, vec![root.join("tmp")]);
+    /// # std::env::set_current_dir(cwd).unwrap();
+    /// # std::process::Command::new("umount").arg(root.join("tmp")).status().unwrap();
+    /// # std::fs::remove_dir_all(&root).unwrap();
+    /// ```
+    pub fn unmount_policy(mut self, policy: UnmountPolicy) -> Self {
+        trace!(
+            "StoppedJail::unmount_policy({:?}, policy={:?})",
+            self,
+            policy
+        );
+        self.unmount_policy = policy;
+        self
+    }
+
+    /// Set whether to grant the jail the `allow.mount.*` permissions
+    /// matching its mounts on start. Enabled by default.
+    ///
+    /// For each file system type mounted for the jail with a matching
+    /// `allow.mount.<fstype>` parameter, including `devfs` from
+    /// [StoppedJail::devfs], start sets that parameter and `allow.mount`,
+    /// so that the jail can remount or unmount them itself, e.g. from its
+    /// `/etc/fstab`. `enforce_statfs` is set to 1 unless set explicitly, as
+    /// the kernel only lets jails mount file systems below 2. Parameters set
+    /// explicitly, e.g. using [StoppedJail::param], keep their values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::mount::Mount;
+    /// use jail::param::Value;
+    /// use jail::StoppedJail;
+    /// # let root = std::env::temp_dir().join("testjail_auto_allow_mount");
+    /// # std::fs::create_dir_all(&root).unwrap();
+    ///
+    /// let running = StoppedJail::new(&root)
+    ///     .name("testjail_auto_allow_mount")
+    ///     .mount(Mount::new("tmpfs", "tmpfs", "/tmp"))
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// let params = running.params().expect("could not get parameters");
+    /// assert_eq!(params["allow.mount"], Value::Int(1));
+    /// assert_eq!(params["allow.mount.tmpfs"], Value::Int(1));
+    /// # running.kill().expect("could not stop jail");
+    /// # std::fs::remove_dir_all(&root).unwrap();
+    /// ```
+    pub fn auto_allow_mount(mut self, enabled: bool) -> Self {
+        trace!(
+            "StoppedJail::auto_allow_mount({:?}, enabled={})",
+            self,
+            enabled
+        );
+        self.auto_allow_mount = enabled;
+        self
+    }
+
+    /// Mount `devfs` below the jail root on start, and set the
+    /// `devfs_ruleset` parameter to the ruleset applied to it.
+    ///
+    /// This is what makes `devfs_ruleset` take effect, unless the jail may
+    /// mount `devfs` itself. Starting the jail fails if the ruleset is not
+    /// defined on the host, or if `devfs_ruleset` is changed to another
+    /// ruleset afterwards. `devfs` is unmounted again when the jail is
+    /// killed using [RunningJail::kill].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::devfs::DevfsConfig;
+    /// use jail::StoppedJail;
+    /// # let root = std::env::temp_dir().join("testjail_devfs");
+    /// # std::fs::create_dir_all(&root).unwrap();
+    ///
+    /// let running = StoppedJail::new(&root)
+    ///     .name("testjail_devfs")
+    ///     .devfs(DevfsConfig::new())
+    ///     .start()
+    ///     .expect("could not start jail");
+    ///
+    /// assert!(root.join("dev/null").exists());
+    /// assert!(!root.join("dev/mem").exists());
+    /// running.kill().expect("could not stop jail");
+    /// # std::fs::remove_dir_all(&root).unwrap();
+    /// ```
+    pub fn devfs(mut self, devfs: DevfsConfig) -> Self {
+        trace!("StoppedJail::devfs({:?}, devfs={:?})", self, devfs);
+        self.params.insert(
+            "devfs_ruleset".into(),
+            param::Value::Int(i32::from(devfs.ruleset)),
+        );
+        self.devfs = Some(devfs);
+        self
+    }
+
+    /// Forward the log messages of the jail to the host.
+    ///
+    /// This adds a provisioning step which, when the jail is started, writes
+    /// `/etc/syslog.conf` in the jail root and/or mounts the log socket of
+    /// the host into the jail. See [SyslogForward] for the caveats of both.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::provision::{Step, SyslogForward};
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .ip("192.0.2.2".parse().unwrap())
+    ///     .syslog_forward(SyslogForward::to("192.0.2.1"));
+    ///
+    /// assert_eq!(
+    ///     stopped.provision,
+    ///     vec![Step::SyslogForward(SyslogForward::to("192.0.2.1"))]
+    /// );
+    /// ```
+    pub fn syslog_forward(mut self, forward: SyslogForward) -> Self {
+        trace!(
+            "StoppedJail::syslog_forward({:?}, forward={:?})",
+            self,
+            forward
+        );
+        self.provision.push(Step::SyslogForward(forward));
+        self
+    }
+
+    /// Set the name servers and search domains of the jail.
+    ///
+    /// This adds a provisioning step which, when the jail is started, writes
+    /// `/etc/resolv.conf` in the jail root, replacing the existing one. Use
+    /// [ResolvConf::from_host] to use the configuration of the host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::provision::{ResolvConf, Step};
+    ///
+    /// let resolv_conf = ResolvConf {
+    ///     nameservers: vec!["192.0.2.53".parse().unwrap()],
+    ///     search: vec!["example.com".into()],
+    /// };
+    /// let stopped = StoppedJail::new("/rescue").resolv_conf(resolv_conf.clone());
+    ///
+    /// assert_eq!(stopped.provision, vec![Step::ResolvConf(resolv_conf)]);
+    /// ```
+    pub fn resolv_conf(mut self, resolv_conf: ResolvConf) -> Self {
+        trace!(
+            "StoppedJail::resolv_conf({:?}, resolv_conf={:?})",
+            self,
+            resolv_conf
+        );
+        self.provision.push(Step::ResolvConf(resolv_conf));
+        self
+    }
+
+    /// Set the local time zone of the jail, e.g. `Europe/Berlin`.
+    ///
+    /// This adds a provisioning step which, when the jail is started, copies
+    /// the time zone file from the database of the host to `/etc/localtime`
+    /// in the jail root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::provision::{Step, Timezone};
+    ///
+    /// let stopped = StoppedJail::new("/rescue").timezone("Europe/Berlin");
+    ///
+    /// assert_eq!(
+    ///     stopped.provision,
+    ///     vec![Step::Timezone(Timezone::Named("Europe/Berlin".into()))]
+    /// );
+    /// ```
+    pub fn timezone<S: Into<String> + fmt::Debug>(mut self, timezone: S) -> Self {
+        trace!("StoppedJail::timezone({:?}, timezone={:?})", self, timezone);
+        self.provision
+            .push(Step::Timezone(Timezone::Named(timezone.into())));
+        self
+    }
+
+    /// Use the local time zone of the host in the jail.
+    ///
+    /// This adds a provisioning step which, when the jail is started, copies
+    /// `/etc/localtime` of the host into the jail root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::provision::{Step, Timezone};
+    ///
+    /// let stopped = StoppedJail::new("/rescue").timezone_from_host();
+    ///
+    /// assert_eq!(stopped.provision, vec![Step::Timezone(Timezone::Host)]);
+    /// ```
+    pub fn timezone_from_host(mut self) -> Self {
+        trace!("StoppedJail::timezone_from_host({:?})", self);
+        self.provision.push(Step::Timezone(Timezone::Host));
+        self
+    }
+
+    /// Enable an `rc.d` service in the jail, e.g. `sshd`.
+    ///
+    /// This adds a provisioning step which, when the jail is started, sets
+    /// `<service>_enable="YES"` in `/etc/rc.conf` in the jail root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::provision::Step;
+    ///
+    /// let stopped = StoppedJail::new("/rescue").enable_service("sshd");
+    ///
+    /// assert_eq!(stopped.provision, vec![Step::EnableService("sshd".into())]);
+    /// ```
+    pub fn enable_service<S: Into<String> + fmt::Debug>(mut self, service: S) -> Self {
+        trace!(
+            "StoppedJail::enable_service({:?}, service={:?})",
+            self,
+            service
+        );
+        self.provision.push(Step::EnableService(service.into()));
+        self
+    }
+
+    /// Authorize an SSH public key to log in as `user` in the jail.
+    ///
+    /// This adds a provisioning step which, when the jail is started, adds
+    /// `key` to `~/.ssh/authorized_keys` of `user` in the jail root, unless
+    /// it is already present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// use jail::provision::Step;
+    ///
+    /// let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIExample admin@example.com";
+    /// let stopped = StoppedJail::new("/rescue").authorize_key("root", key);
+    ///
+    /// assert_eq!(
+    ///     stopped.provision,
+    ///     vec![Step::AuthorizeKey {
+    ///         user: "root".into(),
+    ///         key: key.into()
+    ///     }]
+    /// );
+    /// ```
+    pub fn authorize_key<U, K>(mut self, user: U, key: K) -> Self
+    where
+        U: Into<String> + fmt::Debug,
+        K: Into<String> + fmt::Debug,
+    {
+        trace!(
+            "StoppedJail::authorize_key({:?}, user={:?}, key={:?})",
+            self,
+            user,
+            key
+        );
+        self.provision.push(Step::AuthorizeKey {
+            user: user.into(),
+            key: key.into(),
+        });
+        self
+    }
+
+    /// Add an IP Address
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use jail::StoppedJail;
+    /// # use std::net::IpAddr;
+    /// #
+    /// let mut stopped = StoppedJail::new("rescue")
+    ///     .ip("127.0.1.1".parse().expect("could not parse 127.0.1.1"))
+    ///     .ip("fe80::2".parse().expect("could not parse ::1"));
+    /// ```
+    pub fn ip(mut self, ip: net::IpAddr) -> Self {
+        trace!("StoppedJail::ip({:?}, ip={:?})", self, ip);
+        self.ips.push(ip);
+        self
+    }
+}
+
+/// A jail created by [StoppedJail::start_paused], which is not set up yet.
+///
+/// The jail is persistent, so it stays in the kernel if the `CreatedJail`
+/// is dropped without calling [CreatedJail::finish] or
+/// [CreatedJail::abort].
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+#[must_use = "the jail is not set up until finish is called"]
+pub struct CreatedJail {
+    jail: RunningJail,
+    stopped: StoppedJail,
+    meta_in_kernel: bool,
+}
+
+#[cfg(target_os = "freebsd")]
+impl CreatedJail {
+    /// The created jail, e.g. to set parameters or run commands in it before
+    /// it is set up.
+    pub fn jail(&self) -> RunningJail {
+        trace!("CreatedJail::jail({:?})", self);
+        self.jail
+    }
+
+    /// Set up the jail like [StoppedJail::start] does after creating it.
+    ///
+    /// The jail root is provisioned, file systems are mounted, resource
+    /// limits are applied and the [StoppedJail::exec_hooks] are run,
+    /// including `exec.prestart`. If any of this fails, the jail is removed
+    /// again.
+    pub fn finish(self) -> Result<RunningJail, JailError> {
+        trace!("CreatedJail::finish({:?})", self);
+        let CreatedJail {
+            jail,
+            stopped,
+            meta_in_kernel,
+        } = self;
+        let path = stopped
+            .path
+            .clone()
+            .expect("checked by StoppedJail::start_paused");
+
+        let prepared = stopped.apply_disk_limit(&path).and_then(|_| {
+            let mounts = stopped.prepare_root(&path)?;
+            if stopped.exec_hooks {
+                if let Err(e) = process::run_hook(&stopped.exec, "exec.prestart", None) {
+                    mount::unmount_all(&mounts).ok();
+                    return Err(e);
+                }
+            }
+            Ok(mounts)
+        });
+
+        let result = prepared.and_then(|mounts| stopped.set_up(jail, mounts, meta_in_kernel));
+        if result.is_err() && RunningJail::exists(jail.jid) {
+            jail.kill().ok();
+        }
+        result
+    }
+
+    /// Remove the jail again without setting it up.
+    pub fn abort(self) -> Result<(), JailError> {
+        trace!("CreatedJail::abort({:?})", self);
+        self.jail.kill()
+    }
 }
@@ -1,275 +1,709 @@
+use crate::oscompat::{self, Backend};
 use crate::{param, JailError};
-use bitflags::bitflags;
 use log::trace;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::io::Read;
 use std::mem;
+use std::net;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::FromRawFd;
 use std::path;
 use std::ptr;
 use std::str;
 
-macro_rules! iovec {
-    ($key:expr => ($value:expr, $size:expr)) => {
-        vec![iovec!($key), iovec!($value, $size)]
-    };
-    ($key:expr => ()) => {
-        vec![iovec!($key), iovec!()]
-    };
-    ($key:expr => $value:expr) => {
-        vec![iovec!($key), iovec!($value)]
-    };
-    ($key:expr => mut $value:expr) => {
-        vec![iovec!($key), iovec!(mut $value)]
-    };
-    ($value:expr, $size:expr) => {
-        libc::iovec {
-            iov_base: $value as *mut libc::c_void,
-            iov_len: $size,
-        }
-    };
-    ($name:expr) => {
-        iovec!($name.as_ptr(), $name.len())
-    };
-    (mut $name:expr) => {
-        iovec!($name.as_mut_ptr(), $name.len())
-    };
-    () => {
-        iovec!(ptr::null::<libc::c_void>(), 0)
-    };
-}
+/// Create a jail with a specific path
+#[cfg(target_os = "freebsd")]
+pub fn jail_create(
+    path: &path::Path,
+    params: HashMap<String, param::Value>,
+) -> Result<i32, JailError> {
+    trace!("jail_create(path={:?}, params={:?})", path, params);
+    if oscompat::backend() == Backend::Legacy {
+        return jail_create_legacy(path, &params, None);
+    }
 
-bitflags! {
-    pub struct JailFlags : i32 {
-        /// Create the Jail if it doesn't exist
-        const CREATE = 0x01;
+    creator(path, &params)?
+        .create(None)
+        .map_err(JailError::from)
+}
 
-        /// Update parameters of existing Jail
-        const UPDATE = 0x02;
+/// Create a jail for each of the given names, sharing the same path and
+/// parameters.
+///
+/// The parameters are only encoded once. If creating any of the jails fails,
+/// all jails created so far are removed again.
+#[cfg(target_os = "freebsd")]
+pub fn jail_create_many(
+    path: &path::Path,
+    params: &HashMap<String, param::Value>,
+    names: &[String],
+) -> Result<Vec<i32>, JailError> {
+    trace!(
+        "jail_create_many(path={:?}, params={:?}, names={:?})",
+        path,
+        params,
+        names
+    );
+    if oscompat::backend() == Backend::Legacy {
+        let mut jids = Vec::with_capacity(names.len());
+        for name in names {
+            match jail_create_legacy(path, params, Some(name)) {
+                Ok(jid) => jids.push(jid),
+                Err(e) => {
+                    for jid in jids {
+                        jail_remove(jid).ok();
+                    }
+                    return Err(e);
+                }
+            }
+        }
 
-        /// Attach to Jail upon creation
-        const ATTACH = 0x04;
+        return Ok(jids);
+    }
 
-        /// Allow getting a dying jail
-        const DYING = 0x08;
+    let mut creator = creator(path, params)?;
+    let mut jids = Vec::with_capacity(names.len());
+
+    for name in names {
+        match creator.create(Some(name)) {
+            Ok(jid) => jids.push(jid),
+            Err(e) => {
+                for jid in jids {
+                    jail_remove(jid).ok();
+                }
+                return Err(e.into());
+            }
+        }
     }
+
+    Ok(jids)
 }
 
-/// Create a jail with a specific path
+/// Encode the `jail_set` arguments for creating jails.
 #[cfg(target_os = "freebsd")]
-pub fn jail_create(
+fn creator(
     path: &path::Path,
-    params: HashMap<String, param::Value>,
-) -> Result<i32, JailError> {
-    trace!("jail_create(path={:?}, params={:?})", path, params);
-
-    // Note: we keep an owned copy of the raw parameter representations
-    // around that we only drop after the unsafe jail_set call.
-    // Dropping it earlier would cause a dangling pointer.
-    let raw_params: Vec<(Vec<u8>, Vec<u8>)> = params
+    params: &HashMap<String, param::Value>,
+) -> Result<jail_sys::JailCreator, JailError> {
+    let raw_params: Vec<(CString, Vec<u8>)> = params
         .iter()
         .map(|(key, value)| {
             Ok((
-                CString::new(key.clone())
-                    .map_err(JailError::CStringError)?
-                    .into_bytes_with_nul(),
+                CString::new(key.clone()).map_err(JailError::CStringError)?,
                 value.clone().as_bytes()?,
             ))
         })
         .collect::<Result<_, JailError>>()?;
 
-    let mut jiov: Vec<libc::iovec> = raw_params
-        .iter()
-        .flat_map(|(ref key, ref value)| iovec!(key => value))
-        .collect();
-
-    let pathstr = path
-        .to_str()
-        .ok_or(JailError::SerializeFailed)
-        .map(CString::new)?
-        .map_err(JailError::CStringError)?
-        .into_bytes_with_nul();
-
-    // Set persist and errmsg
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
-    jiov.append(
-        &mut vec![
-            iovec!(b"path\0" => pathstr),
-            iovec!(b"errmsg\0" => mut errmsg),
-            iovec!(b"persist\0" => ()),
-        ]
-        .into_iter()
-        .flatten()
-        .collect(),
+    let path = path.to_str().ok_or(JailError::SerializeFailed)?;
+    jail_sys::JailCreator::new(path, raw_params).map_err(JailError::from)
+}
+
+/// Create a persistent jail with the original `jail`(2) system call.
+///
+/// `jail`(2) attaches the calling process to the new jail, so it is called
+/// in a forked child, which holds the jail until the parent made it
+/// persistent. `name` overrides the `name` parameter.
+#[cfg(target_os = "freebsd")]
+fn jail_create_legacy(
+    path: &path::Path,
+    params: &HashMap<String, param::Value>,
+    name: Option<&str>,
+) -> Result<i32, JailError> {
+    trace!(
+        "jail_create_legacy(path={:?}, params={:?}, name={:?})",
+        path,
+        params,
+        name
     );
+    let mut jailname = None;
+    let mut hostname = CString::default();
+    let mut ip4: Vec<libc::in_addr> = vec![];
+    let mut ip6: Vec<libc::in6_addr> = vec![];
+    for (key, value) in params {
+        match (key.as_str(), value) {
+            ("name", param::Value::String(s)) => {
+                jailname = Some(CString::new(s.as_str()).map_err(JailError::CStringError)?)
+            }
+            ("host.hostname", param::Value::String(s)) => {
+                hostname = CString::new(s.as_str()).map_err(JailError::CStringError)?
+            }
+            ("ip4.addr", param::Value::Ipv4Addrs(addrs)) => {
+                ip4 = addrs
+                    .iter()
+                    .map(|addr| libc::in_addr {
+                        s_addr: u32::from(*addr).to_be(),
+                    })
+                    .collect()
+            }
+            ("ip6.addr", param::Value::Ipv6Addrs(addrs)) => {
+                ip6 = addrs
+                    .iter()
+                    .map(|addr| libc::in6_addr {
+                        s6_addr: addr.octets(),
+                    })
+                    .collect()
+            }
+            // Jails created by the library are always persistent.
+            ("persist", _) => {}
+            (key, value) => {
+                return Err(JailError::NotSupported(format!(
+                    "parameter {}={:?} with the legacy jail(2) backend",
+                    key, value
+                )))
+            }
+        }
+    }
 
-    let jid = unsafe {
-        libc::jail_set(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::CREATE.bits,
-        )
+    if let Some(name) = name {
+        jailname = Some(CString::new(name).map_err(JailError::CStringError)?);
+    }
+
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(JailError::CStringError)?;
+    let mut jail = libc::jail {
+        version: 2,
+        path: path.as_ptr() as *mut libc::c_char,
+        hostname: hostname.as_ptr() as *mut libc::c_char,
+        jailname: jailname
+            .as_ref()
+            .map_or(ptr::null_mut(), |name| name.as_ptr() as *mut libc::c_char),
+        ip4s: ip4.len() as libc::c_uint,
+        ip6s: ip6.len() as libc::c_uint,
+        ip4: ip4.as_mut_ptr(),
+        ip6: ip6.as_mut_ptr(),
     };
 
-    let err = unsafe { CStr::from_ptr(errmsg.as_ptr() as *mut libc::c_char) }
-        .to_string_lossy()
-        .to_string();
+    // The child reports the jid, or the negated errno, on `result`, and holds
+    // the jail until `release` is closed.
+    let mut result: [libc::c_int; 2] = [0; 2];
+    let mut release: [libc::c_int; 2] = [0; 2];
+    unsafe {
+        if libc::pipe2(result.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+            return Err(JailError::from_errno());
+        }
+        if libc::pipe2(release.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+            let error = JailError::from_errno();
+            libc::close(result[0]);
+            libc::close(result[1]);
+            return Err(error);
+        }
+    }
 
-    match jid {
-        e if e < 0 => match errmsg[0] {
-            0 => Err(JailError::from_errno()),
-            _ => Err(JailError::JailSetError(err)),
+    match unsafe { libc::fork() } {
+        -1 => {
+            let error = JailError::from_errno();
+            for fd in result.iter().chain(release.iter()) {
+                unsafe { libc::close(*fd) };
+            }
+            Err(error)
+        }
+        0 => unsafe {
+            libc::close(result[0]);
+            libc::close(release[1]);
+
+            let jid = match libc::jail(&mut jail) {
+                -1 => -*libc::__error(),
+                jid => jid,
+            };
+            libc::write(
+                result[1],
+                &jid as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>(),
+            );
+
+            let mut byte = 0u8;
+            libc::read(release[0], &mut byte as *mut u8 as *mut libc::c_void, 1);
+            libc::_exit(0)
         },
-        _ => Ok(jid),
+        pid => {
+            let (mut result, release) = unsafe {
+                libc::close(result[1]);
+                libc::close(release[0]);
+                (File::from_raw_fd(result[0]), File::from_raw_fd(release[1]))
+            };
+
+            let mut buf = [0u8; mem::size_of::<libc::c_int>()];
+            let read = result.read_exact(&mut buf);
+            let jid = libc::c_int::from_ne_bytes(buf);
+            let persisted = match (read, jid) {
+                (Err(e), _) => Err(JailError::IoError(e)),
+                (Ok(_), jid) if jid < 0 => Err(JailError::LegacyJailError(
+                    io::Error::from_raw_os_error(-jid),
+                )),
+                (Ok(_), jid) => jail_sys::jail_setpersist(jid)
+                    .map(|_| jid)
+                    .map_err(JailError::from),
+            };
+
+            drop(release);
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+                return Err(JailError::from_errno());
+            }
+
+            persisted
+        }
     }
 }
 
 /// Test if a jail exists. Returns
+#[cfg(target_os = "freebsd")]
 pub fn jail_exists(jid: i32) -> bool {
     trace!("jail_exists({})", jid);
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
-    let mut jiov: Vec<libc::iovec> = vec![
-        iovec!(b"jid\0" => (&jid as *const _, mem::size_of::<i32>())),
-        iovec!(b"errmsg\0" => mut errmsg),
-    ]
-    .into_iter()
-    .flatten()
-    .collect();
-
-    let retjid = unsafe {
-        libc::jail_get(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::empty().bits,
-        )
-    };
-
-    jid == retjid
+    jail_sys::jail_exists(jid)
 }
 
 /// Clear the persist flag
 #[cfg(target_os = "freebsd")]
 pub fn jail_clearpersist(jid: i32) -> Result<(), JailError> {
     trace!("jail_clearpersist({})", jid);
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
-    let mut jiov: Vec<libc::iovec> = vec![
-        iovec!(b"jid\0" => (&jid as *const _, mem::size_of::<i32>())),
-        iovec!(b"errmsg\0" => mut errmsg),
-        iovec!(b"nopersist\0" => ()),
-    ]
-    .into_iter()
-    .flatten()
-    .collect();
-
-    let jid = unsafe {
-        libc::jail_set(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::UPDATE.bits,
-        )
-    };
-
-    let err = unsafe { CStr::from_ptr(errmsg.as_ptr() as *mut libc::c_char) }
-        .to_string_lossy()
-        .to_string();
-
-    match jid {
-        e if e < 0 => match errmsg[0] {
-            0 => Err(JailError::from_errno()),
-            _ => Err(JailError::JailSetError(err)),
-        },
-        _ => Ok(()),
-    }
+    jail_sys::jail_clearpersist(jid).map_err(JailError::from)
 }
 
-/// Get the `jid` of a jail given the name.
+/// Get the `jid` of a jail given the name or the `jid`.
 ///
 /// This function attempts to parse the name into an `i32` first, which is
 /// returned if successful.
 #[cfg(target_os = "freebsd")]
 pub fn jail_getid(name: &str) -> Result<i32, JailError> {
     trace!("jail_getid(name={:?})", name);
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
+    jail_sys::jail_getid(name).map_err(JailError::from)
+}
+
+/// Get the `jid` of a jail given the name, without treating numeric names as
+/// `jid`s.
+#[cfg(target_os = "freebsd")]
+pub fn jail_getid_by_name(name: &str) -> Result<i32, JailError> {
+    trace!("jail_getid_by_name(name={:?})", name);
+    jail_sys::jail_getid_by_name(name).map_err(JailError::from)
+}
 
-    if let Ok(jid) = name.parse::<i32>() {
-        return Ok(jid);
+/// Get the next `jid` given the last `jid`.
+#[cfg(target_os = "freebsd")]
+pub fn jail_nextjid(lastjid: i32) -> Result<i32, JailError> {
+    trace!("jail_nextjid(lastjid={})", lastjid);
+    jail_sys::jail_nextjid(lastjid).map_err(JailError::from)
+}
+
+/// Remove a jail with the given `jid`.
+///
+/// This will kill all processes belonging to the jail, and remove any children
+/// of that jail.
+#[cfg(target_os = "freebsd")]
+pub fn jail_remove(jid: i32) -> Result<(), JailError> {
+    trace!("jail_remove(jid={})", jid);
+    match jail_sys::jail_remove(jid) {
+        Err(jail_sys::Error::UnexpectedReturn(_)) => Err(JailError::JailRemoveFailed),
+        ret => ret.map_err(JailError::from),
+    }
+}
+
+/// Attach the calling process to the jail with the given `jid`.
+#[cfg(target_os = "freebsd")]
+pub fn jail_attach(jid: i32) -> Result<(), JailError> {
+    trace!("jail_attach(jid={})", jid);
+    jail_sys::jail_attach(jid)
+        .map_err(|e| match e {
+            jail_sys::Error::Errno(errno) => io::Error::from_raw_os_error(errno),
+            e => io::Error::other(e.to_string()),
+        })
+        .map_err(JailError::JailAttachError)
+}
+
+/// Get information about all processes on the system.
+///
+/// This reads the `kern.proc.proc` sysctl, which returns one `kinfo_proc`
+/// structure per process.
+#[cfg(target_os = "freebsd")]
+pub fn processes() -> Result<Vec<libc::kinfo_proc>, JailError> {
+    trace!("processes()");
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PROC];
+    let entry_size = mem::size_of::<libc::kinfo_proc>();
+
+    loop {
+        let mut size: libc::size_t = 0;
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as u32,
+                ptr::null_mut(),
+                &mut size,
+                ptr::null(),
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(JailError::from_errno());
+        }
+
+        // Leave room for processes created in the meantime.
+        let capacity = size / entry_size + 16;
+        let mut processes: Vec<libc::kinfo_proc> = Vec::with_capacity(capacity);
+        size = capacity * entry_size;
+
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as u32,
+                processes.as_mut_ptr() as *mut libc::c_void,
+                &mut size,
+                ptr::null(),
+                0,
+            )
+        };
+
+        if ret == 0 {
+            unsafe { processes.set_len(size / entry_size) };
+            return Ok(processes);
+        }
+
+        // More processes were created than we left room for, try again.
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ENOMEM) {
+            return Err(JailError::IoError(error));
+        }
+    }
+}
+
+/// Get the `pid`s of all processes running in the jail with the given `jid`.
+///
+/// Processes in child jails are not included.
+#[cfg(target_os = "freebsd")]
+pub fn jail_pids(jid: i32) -> Result<Vec<libc::pid_t>, JailError> {
+    trace!("jail_pids(jid={})", jid);
+    Ok(processes()?
+        .iter()
+        .filter(|p| p.ki_jid == jid)
+        .map(|p| p.ki_pid)
+        .collect())
+}
+
+/// Get the `jid` of the jail a process runs in, or 0 for the host.
+///
+/// This reads the `kern.proc.pid.<pid>` sysctl, failing with `ESRCH` if no
+/// process with the given `pid` exists.
+#[cfg(target_os = "freebsd")]
+pub fn process_jid(pid: libc::pid_t) -> Result<i32, JailError> {
+    trace!("process_jid(pid={})", pid);
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PID, pid];
+    let mut process = mem::MaybeUninit::<libc::kinfo_proc>::uninit();
+    let mut size: libc::size_t = mem::size_of::<libc::kinfo_proc>();
+
+    let ret = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as u32,
+            process.as_mut_ptr() as *mut libc::c_void,
+            &mut size,
+            ptr::null(),
+            0,
+        )
     };
+    if ret != 0 {
+        return Err(JailError::from_errno());
+    }
 
-    let name = CString::new(name).unwrap().into_bytes_with_nul();
+    // Zombies may be reaped between the checks of the kernel, leaving
+    // nothing to return.
+    if size != mem::size_of::<libc::kinfo_proc>() {
+        return Err(JailError::IoError(io::Error::from_raw_os_error(
+            libc::ESRCH,
+        )));
+    }
 
-    let mut jiov: Vec<libc::iovec> =
-        vec![iovec!(b"name\0" => name), iovec!(b"errmsg\0" => mut errmsg)]
-            .into_iter()
-            .flatten()
-            .collect();
+    Ok(unsafe { process.assume_init() }.ki_jid)
+}
 
-    let jid = unsafe {
-        libc::jail_get(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::empty().bits,
+/// Get the command line of a process.
+///
+/// This reads the `kern.proc.args.<pid>` sysctl, which is empty for kernel
+/// processes.
+#[cfg(target_os = "freebsd")]
+pub fn process_args(pid: libc::pid_t) -> Result<Vec<String>, JailError> {
+    trace!("process_args(pid={})", pid);
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_ARGS, pid];
+
+    let mut size: libc::size_t = 0;
+    let ret = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as u32,
+            ptr::null_mut(),
+            &mut size,
+            ptr::null(),
+            0,
         )
     };
+    if ret != 0 {
+        return Err(JailError::from_errno());
+    }
 
-    let err = unsafe { CStr::from_ptr(errmsg.as_ptr() as *mut libc::c_char) }
-        .to_string_lossy()
-        .to_string();
+    let mut buffer: Vec<u8> = vec![0; size];
+    let ret = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as u32,
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            &mut size,
+            ptr::null(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(JailError::from_errno());
+    }
+    buffer.truncate(size);
+
+    // The arguments are separated and terminated by nul bytes.
+    Ok(buffer
+        .split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect())
+}
 
-    match jid {
-        e if e < 0 => match errmsg[0] {
-            0 => Err(JailError::from_errno()),
-            _ => Err(JailError::JailGetError(err)),
+/// Run `f` in a forked child process attached to the jail with the given
+/// `jid`, and return the bytes it produced.
+///
+/// Nothing is logged in the child, since other threads of the parent may hold
+/// the locks involved. `f` should be kept small, and signal failure by
+/// returning `None`.
+#[cfg(target_os = "freebsd")]
+pub fn attach_in_fork<F>(jid: i32, f: F) -> Result<Vec<u8>, JailError>
+where
+    F: FnOnce() -> Option<Vec<u8>>,
+{
+    trace!("attach_in_fork(jid={})", jid);
+    let mut fds: [libc::c_int; 2] = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(JailError::from_errno());
+    }
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+
+    match unsafe { libc::fork() } {
+        -1 => {
+            let error = JailError::from_errno();
+            unsafe {
+                libc::close(read_fd);
+                libc::close(write_fd);
+            }
+            Err(error)
+        }
+        0 => unsafe {
+            libc::close(read_fd);
+            if libc::jail_attach(jid) != 0 {
+                libc::_exit(1);
+            }
+
+            let output = match f() {
+                Some(output) => output,
+                None => libc::_exit(2),
+            };
+
+            let mut written = 0;
+            while written < output.len() {
+                let n = libc::write(
+                    write_fd,
+                    output[written..].as_ptr() as *const libc::c_void,
+                    output.len() - written,
+                );
+                if n <= 0 {
+                    libc::_exit(2);
+                }
+                written += n as usize;
+            }
+
+            libc::_exit(0)
         },
-        _ => Ok(jid),
+        pid => {
+            unsafe { libc::close(write_fd) };
+            let mut pipe = unsafe { File::from_raw_fd(read_fd) };
+            let mut output = vec![];
+            let read = pipe.read_to_end(&mut output);
+
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+                return Err(JailError::from_errno());
+            }
+            read.map_err(JailError::IoError)?;
+
+            match (libc::WIFEXITED(status), libc::WEXITSTATUS(status)) {
+                (true, 0) => Ok(output),
+                (true, 1) => Err(JailError::JailAttachError(io::Error::other(format!(
+                    "could not attach to jail {}",
+                    jid
+                )))),
+                _ => Err(JailError::IoError(io::Error::other(format!(
+                    "child process in jail {} failed",
+                    jid
+                )))),
+            }
+        }
     }
 }
 
-/// Get the next `jid` given the last `jid`.
+/// Read a sysctl and optionally set it to `new`, returning its previous
+/// value or the errno.
+///
+/// Like [attach_in_fork], this is run in forked children attached to a jail,
+/// so nothing is logged.
 #[cfg(target_os = "freebsd")]
-pub fn jail_nextjid(lastjid: i32) -> Result<i32, JailError> {
-    trace!("jail_nextjid(lastjid={})", lastjid);
-    let mut errmsg: [u8; 256] = unsafe { mem::zeroed() };
-
-    let mut jiov: Vec<libc::iovec> = vec![
-        iovec!(b"lastjid\0" => (&lastjid as *const _, mem::size_of::<i32>())),
-        iovec!(b"errmsg\0" => mut errmsg),
-    ]
-    .into_iter()
-    .flatten()
-    .collect();
-
-    let jid = unsafe {
-        libc::jail_get(
-            jiov[..].as_mut_ptr() as *mut libc::iovec,
-            jiov.len() as u32,
-            JailFlags::empty().bits,
+pub fn sysctl_raw(name: &CStr, new: Option<&[u8]>) -> Result<Vec<u8>, i32> {
+    let errno = || {
+        io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EIO)
+    };
+
+    let mut size: libc::size_t = 0;
+    let ret =
+        unsafe { libc::sysctlbyname(name.as_ptr(), ptr::null_mut(), &mut size, ptr::null(), 0) };
+    if ret != 0 {
+        return Err(errno());
+    }
+
+    let mut buffer: Vec<u8> = vec![0; size];
+    let (new_ptr, new_len) = match new {
+        Some(new) => (new.as_ptr() as *const libc::c_void, new.len()),
+        None => (ptr::null(), 0),
+    };
+    let ret = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            &mut size,
+            new_ptr,
+            new_len,
         )
     };
+    if ret != 0 {
+        return Err(errno());
+    }
 
-    let err = unsafe { CStr::from_ptr(errmsg.as_ptr() as *mut libc::c_char) }
-        .to_string_lossy()
-        .to_string();
+    buffer.truncate(size);
+    Ok(buffer)
+}
 
-    match jid {
-        e if e < 0 => match errmsg[0] {
-            0 => Err(JailError::from_errno()),
-            _ => Err(JailError::JailGetError(err)),
-        },
-        _ => Ok(jid),
+/// Get the counters of all network interfaces visible to the calling
+/// process, one line per interface holding its name and the received and
+/// sent bytes, packets and errors.
+#[cfg(target_os = "freebsd")]
+pub fn interface_counters() -> Option<String> {
+    let mut ifaddrs: *mut libc::ifaddrs = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return None;
+    }
+
+    let mut lines = String::new();
+    let mut current = ifaddrs;
+    while let Some(ifa) = unsafe { current.as_ref() } {
+        current = ifa.ifa_next;
+
+        // Every interface has exactly one link level address, which carries
+        // the interface statistics.
+        let is_link = unsafe { ifa.ifa_addr.as_ref() }
+            .map(|addr| libc::c_int::from(addr.sa_family) == libc::AF_LINK)
+            .unwrap_or(false);
+        if !is_link || ifa.ifa_data.is_null() {
+            continue;
+        }
+
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy();
+        let data = unsafe { &*(ifa.ifa_data as *const libc::if_data) };
+        lines.push_str(&format!(
+            "{} {} {} {} {} {} {}\n",
+            name,
+            data.ifi_ibytes,
+            data.ifi_obytes,
+            data.ifi_ipackets,
+            data.ifi_opackets,
+            data.ifi_ierrors,
+            data.ifi_oerrors
+        ));
     }
+
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Some(lines)
 }
 
-/// Remove a jail with the given `jid`.
+/// Get the IPv4 and IPv6 addresses configured on the network interfaces
+/// visible to the calling process.
+#[cfg(target_os = "freebsd")]
+pub fn interface_addresses() -> Result<Vec<net::IpAddr>, JailError> {
+    trace!("interface_addresses()");
+    let mut ifaddrs: *mut libc::ifaddrs = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(JailError::from_errno());
+    }
+
+    let mut addresses = vec![];
+    let mut current = ifaddrs;
+    while let Some(ifa) = unsafe { current.as_ref() } {
+        current = ifa.ifa_next;
+
+        let addr = match unsafe { ifa.ifa_addr.as_ref() } {
+            Some(addr) => addr,
+            None => continue,
+        };
+
+        match libc::c_int::from(addr.sa_family) {
+            libc::AF_INET => {
+                let sin = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                addresses.push(net::IpAddr::V4(net::Ipv4Addr::from(u32::from_be(
+                    sin.sin_addr.s_addr,
+                ))));
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                addresses.push(net::IpAddr::V6(net::Ipv6Addr::from(sin6.sin6_addr.s6_addr)));
+            }
+            _ => {}
+        }
+    }
+
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(addresses)
+}
+
+/// Read a protocol control block list, e.g. `net.inet.tcp.pcblist`.
 ///
-/// This will kill all processes belonging to the jail, and remove any children
-/// of that jail.
+/// Like [interface_counters], this is run in forked children attached to a
+/// jail, and signals failure by returning `None`.
 #[cfg(target_os = "freebsd")]
-pub fn jail_remove(jid: i32) -> Result<(), JailError> {
-    trace!("jail_remove(jid={})", jid);
-    let ret = unsafe { libc::jail_remove(jid) };
-    match ret {
-        0 => Ok(()),
-        -1 => Err(JailError::from_errno()),
-        _ => Err(JailError::JailRemoveFailed),
+pub fn pcblist(name: &CStr) -> Option<Vec<u8>> {
+    loop {
+        let mut size: libc::size_t = 0;
+        let ret = unsafe {
+            libc::sysctlbyname(name.as_ptr(), ptr::null_mut(), &mut size, ptr::null(), 0)
+        };
+        if ret != 0 {
+            return None;
+        }
+
+        // Leave room for sockets created in the meantime.
+        size += size / 8;
+        let mut buffer: Vec<u8> = vec![0; size];
+        let ret = unsafe {
+            libc::sysctlbyname(
+                name.as_ptr(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                &mut size,
+                ptr::null(),
+                0,
+            )
+        };
+
+        if ret == 0 {
+            buffer.truncate(size);
+            return Some(buffer);
+        }
+
+        if io::Error::last_os_error().raw_os_error() != Some(libc::ENOMEM) {
+            return None;
+        }
     }
 }
 
@@ -0,0 +1,171 @@
+//! Reading and setting sysctls inside a jail
+//!
+//! Many sysctls, e.g. the `net.inet` ones of a VNET jail, have a value per
+//! jail. They are read and set by a forked child process attached to the
+//! jail, see
+//! [RunningJail::sysctl](../struct.RunningJail.html#method.sysctl). Values
+//! are typed like jail parameters, as [Value]s of the type the kernel
+//! reports for the node.
+//!
+//! Only the nodes in [WRITABLE] can be set, since writes to other nodes
+//! either fail inside a jail or change the host.
+use crate::param::Value;
+use crate::JailError;
+use log::trace;
+
+#[cfg(target_os = "freebsd")]
+use crate::{encoding, param, sys, RunningJail};
+#[cfg(target_os = "freebsd")]
+use std::convert::TryInto;
+#[cfg(target_os = "freebsd")]
+use std::ffi::CString;
+#[cfg(target_os = "freebsd")]
+use std::io;
+#[cfg(target_os = "freebsd")]
+use sysctl::{Ctl, Sysctl};
+
+/// The sysctls known to be writable from inside a jail, and whether they
+/// need the jail to have its own network stack.
+pub const WRITABLE: &[(&str, bool)] = &[
+    ("kern.securelevel", false),
+    ("net.inet.icmp.icmplim", true),
+    ("net.inet.icmp.drop_redirect", true),
+    ("net.inet.ip.forwarding", true),
+    ("net.inet.ip.portrange.first", true),
+    ("net.inet.ip.portrange.last", true),
+    ("net.inet.ip.random_id", true),
+    ("net.inet.ip.redirect", true),
+    ("net.inet.ip.ttl", true),
+    ("net.inet.tcp.blackhole", true),
+    ("net.inet.tcp.cc.algorithm", true),
+    ("net.inet.tcp.drop_synfin", true),
+    ("net.inet.tcp.keepidle", true),
+    ("net.inet.tcp.keepintvl", true),
+    ("net.inet.tcp.msl", true),
+    ("net.inet.tcp.recvspace", true),
+    ("net.inet.tcp.sendspace", true),
+    ("net.inet.udp.blackhole", true),
+    ("net.inet6.ip6.accept_rtadv", true),
+    ("net.inet6.ip6.forwarding", true),
+    ("net.inet6.ip6.hlim", true),
+];
+
+/// Check whether a sysctl can be set from inside a jail, returning whether
+/// it needs the jail to have its own network stack.
+///
+/// # Examples
+///
+/// ```
+/// use jail::sysctls;
+///
+/// assert_eq!(sysctls::writable("net.inet.ip.forwarding"), Some(true));
+/// assert_eq!(sysctls::writable("kern.securelevel"), Some(false));
+/// assert_eq!(sysctls::writable("kern.ipc.somaxconn"), None);
+/// ```
+pub fn writable(name: &str) -> Option<bool> {
+    trace!("sysctls::writable(name={:?})", name);
+    WRITABLE
+        .iter()
+        .find(|(writable, _)| *writable == name)
+        .map(|(_, vnet)| *vnet)
+}
+
+/// Check that a value can be written to a sysctl of a jail.
+fn check_write(name: &str, vnet: bool, value: &Value) -> Result<(), JailError> {
+    match (writable(name), value) {
+        (None, _) => Err(JailError::NotSupported(format!(
+            "setting sysctl {} inside a jail",
+            name
+        ))),
+        (Some(true), _) if !vnet => Err(JailError::NotSupported(format!(
+            "setting sysctl {} in a jail without its own network stack",
+            name
+        ))),
+        (_, Value::Ipv4Addrs(_) | Value::Ipv6Addrs(_) | Value::Raw(_) | Value::Custom(_)) => Err(
+            JailError::NotSupported(format!("setting sysctl {} to {:?}", name, value)),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// The type of a sysctl node, as reported by the kernel.
+#[cfg(target_os = "freebsd")]
+fn node_type(name: &str) -> Result<param::Type, JailError> {
+    let ctl = Ctl::new(name).map_err(JailError::SysctlError)?;
+    let ctl_type = ctl.value_type().map_err(JailError::SysctlError)?;
+    param::ctltype_to_type(name, ctl_type)
+}
+
+/// Read the sysctl `name` inside `jail` and optionally set it to `new`,
+/// returning the previous value.
+#[cfg(target_os = "freebsd")]
+fn exchange(jail: &RunningJail, name: &str, new: Option<&[u8]>) -> Result<Vec<u8>, JailError> {
+    let ctlname = CString::new(name).map_err(JailError::CStringError)?;
+
+    // The child reports the errno first, followed by the value.
+    let output = sys::attach_in_fork(jail.jid, || {
+        let (errno, value) = match sys::sysctl_raw(&ctlname, new) {
+            Ok(value) => (0, value),
+            Err(errno) => (errno, vec![]),
+        };
+
+        let mut output = errno.to_ne_bytes().to_vec();
+        output.extend(value);
+        Some(output)
+    })?;
+
+    let (errno, value) = output.split_at(4.min(output.len()));
+    match errno.try_into().map(i32::from_ne_bytes) {
+        Ok(0) => Ok(value.to_vec()),
+        Ok(errno) => Err(JailError::JailSysctlError {
+            name: name.to_string(),
+            jid: jail.jid,
+            error: io::Error::from_raw_os_error(errno),
+        }),
+        Err(_) => Err(JailError::ParameterDecodeError(format!(
+            "short sysctl output from jail {}",
+            jail.jid
+        ))),
+    }
+}
+
+/// Read a sysctl inside a jail.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn get(jail: &RunningJail, name: &str) -> Result<Value, JailError> {
+    let ty = node_type(name)?;
+    encoding::decode(ty, &exchange(jail, name, None)?)
+}
+
+/// Set a sysctl inside a jail, returning the previous value.
+#[cfg(target_os = "freebsd")]
+pub(crate) fn set(jail: &RunningJail, name: &str, value: Value) -> Result<Value, JailError> {
+    let vnet = jail.param("vnet")? == Value::Int(1);
+    check_write(name, vnet, &value)?;
+
+    let ty = node_type(name)?;
+    if value.get_type() != ty {
+        return Err(JailError::UnexpectedParameterType {
+            name: name.to_string(),
+            expected: ty.into(),
+            got: value,
+        });
+    }
+
+    let new = encoding::encode(&value)?;
+    encoding::decode(ty, &exchange(jail, name, Some(&new))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writable_sysctls() {
+        assert!(check_write("net.inet.ip.forwarding", true, &Value::Int(1)).is_ok());
+        assert!(check_write("kern.securelevel", false, &Value::Int(3)).is_ok());
+
+        assert!(check_write("net.inet.ip.forwarding", false, &Value::Int(1)).is_err());
+        assert!(check_write("kern.ipc.somaxconn", true, &Value::Uint(1024)).is_err());
+        assert!(check_write("net.inet.ip.ttl", true, &Value::Raw(vec![64])).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Helpers for tests and benchmarks that need a running jail
+use crate::{JailError, RunningJail, StoppedJail};
+use log::{trace, warn};
+use std::fmt;
+use std::ops::Deref;
+use std::path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A uniquely named jail that is removed when dropped.
+///
+/// The jail is killed when the `TempJail` goes out of scope, including when a
+/// test panics. It dereferences to a [RunningJail], so all of its methods are
+/// available.
+///
+/// Like [RunningJail], this is only available on FreeBSD. There, starting a
+/// `TempJail` fails with [JailError::NotSupported] when not running as root,
+/// so that tests can skip.
+///
+/// # Examples
+///
+/// ```
+/// use jail::testing::TempJail;
+/// use jail::JailError;
+///
+/// let jail = match TempJail::new() {
+///     Ok(jail) => jail,
+///     // Not running as root, skip.
+///     Err(JailError::NotSupported(_)) => return,
+///     Err(e) => panic!("could not start jail: {}", e),
+/// };
+///
+/// assert!(jail.name().unwrap().starts_with("testjail_tmp_"));
+/// ```
+#[cfg(target_os = "freebsd")]
+#[derive(Debug)]
+pub struct TempJail {
+    running: Option<RunningJail>,
+}
+
+#[cfg(target_os = "freebsd")]
+impl TempJail {
+    /// Start a uniquely named jail rooted at `/rescue`.
+    ///
+    /// Returns [JailError::NotSupported] if jails cannot be created with the
+    /// current credentials.
+    pub fn new() -> Result<TempJail, JailError> {
+        trace!("TempJail::new()");
+        Self::with_path("/rescue")
+    }
+
+    /// Start a uniquely named jail rooted at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::testing::TempJail;
+    /// # use jail::JailError;
+    /// # use std::path::PathBuf;
+    ///
+    /// # let jail = match TempJail::with_path("/") {
+    /// #     Ok(jail) => jail,
+    /// #     Err(JailError::NotSupported(_)) => return,
+    /// #     Err(e) => panic!("could not start jail: {}", e),
+    /// # };
+    /// assert_eq!(jail.path().unwrap(), PathBuf::from("/"));
+    /// ```
+    pub fn with_path<P: Into<path::PathBuf> + fmt::Debug>(path: P) -> Result<TempJail, JailError> {
+        trace!("TempJail::with_path(path={:?})", path);
+        Self::start(StoppedJail::new(path))
+    }
+
+    /// Start the given [StoppedJail] as a temporary jail.
+    ///
+    /// If the jail has no name, a unique name is assigned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::testing::TempJail;
+    /// use jail::{param, StoppedJail};
+    /// # use jail::JailError;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .param("allow.raw_sockets", param::Value::Int(1));
+    ///
+    /// # let jail = match TempJail::start(stopped) {
+    /// #     Ok(jail) => jail,
+    /// #     Err(JailError::NotSupported(_)) => return,
+    /// #     Err(e) => panic!("could not start jail: {}", e),
+    /// # };
+    /// assert_eq!(jail.param("allow.raw_sockets").unwrap(), param::Value::Int(1));
+    /// ```
+    pub fn start(mut stopped: StoppedJail) -> Result<TempJail, JailError> {
+        trace!("TempJail::start({:?})", stopped);
+        if unsafe { libc::geteuid() } != 0 {
+            return Err(JailError::NotSupported(
+                "creating jails requires root privileges".into(),
+            ));
+        }
+
+        if stopped.name.is_none() {
+            stopped.name = Some(format!(
+                "testjail_tmp_{}_{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+        }
+
+        Ok(TempJail {
+            running: Some(stopped.start()?),
+        })
+    }
+
+    /// Kill the jail, reporting any error.
+    pub fn kill(mut self) -> Result<(), JailError> {
+        trace!("TempJail::kill({:?})", self);
+        match self.running.take() {
+            Some(running) => running.kill(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Deref for TempJail {
+    type Target = RunningJail;
+
+    fn deref(&self) -> &RunningJail {
+        self.running
+            .as_ref()
+            .expect("TempJail used after being killed")
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+impl Drop for TempJail {
+    fn drop(&mut self) {
+        trace!("TempJail::drop({:?})", self);
+        if let Some(running) = self.running.take() {
+            if let Err(e) = running.kill() {
+                warn!("could not kill temporary jail {}: {}", running.jid, e);
+            }
+        }
+    }
+}
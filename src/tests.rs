@@ -1,12 +1,10 @@
 use crate::param;
-use crate::process::Jailed;
 use crate::running::RunningJail;
 use crate::stopped::StoppedJail;
-use rctl;
-use std::os::unix::process::ExitStatusExt;
-use std::process::Command;
+use crate::testing::TempJail;
+use crate::{sys, JailError};
 
-#[cfg(feature = "serialize")]
+#[cfg(all(feature = "serialize", feature = "rctl"))]
 #[test]
 fn test_serializing_jail() {
     let rctl_enabled = rctl::State::check().is_enabled();
@@ -50,8 +48,13 @@ fn test_serializing_jail() {
     }
 }
 
+#[cfg(feature = "rctl")]
 #[test]
 fn test_rctl_yes() {
+    use crate::process::Jailed;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
     if !rctl::State::check().is_enabled() {
         // If we don't have RCTL, let's just skip this test.
         return;
@@ -99,6 +102,115 @@ fn test_params_nonexistent_jail() {
         .expect_err("Could get name for jail 424242 which should not be running.");
 }
 
+#[test]
+fn test_cached_jail() {
+    let running = StoppedJail::new("/rescue")
+        .name("testjail_cached_refresh")
+        .hostname("before.example.org")
+        .start()
+        .expect("Could not start Jail");
+    let mut cached = crate::CachedJail::new(running);
+
+    assert_eq!(cached.hostname().unwrap(), "before.example.org");
+    param::set(
+        running.jid,
+        "host.hostname",
+        param::Value::String("after.example.org".into()),
+    )
+    .expect("could not set hostname");
+    assert_eq!(cached.hostname().unwrap(), "before.example.org");
+
+    cached.refresh();
+    assert_eq!(cached.hostname().unwrap(), "after.example.org");
+    assert_eq!(cached.path().unwrap(), std::path::Path::new("/rescue"));
+
+    running.kill().expect("Could not stop Jail");
+}
+
+#[test]
+fn test_notify_timeout() {
+    use crate::notify::{Readiness, Waiter};
+    use std::time::Duration;
+
+    let running = StoppedJail::new("/rescue")
+        .name("testjail_notify_timeout")
+        .start()
+        .expect("Could not start Jail");
+
+    let waiter = Waiter::new(Readiness::File("/var/run/never.pid".into()))
+        .timeout(Duration::from_millis(300))
+        .poll_interval(Duration::from_millis(50));
+    match waiter.wait(&running) {
+        Err(JailError::NotReady { jid, waited, .. }) => {
+            assert_eq!(jid, running.jid);
+            assert!(waited >= Duration::from_millis(300));
+        }
+        other => panic!("expected NotReady, got {:?}", other),
+    }
+
+    let ready = Waiter::new(Readiness::File("/sh".into()))
+        .wait(&running)
+        .expect("/sh should exist");
+    assert!(ready < Duration::from_secs(1));
+
+    running.kill().expect("Could not stop Jail");
+    assert!(matches!(
+        waiter.wait(&running),
+        Err(JailError::NoSuchJail(_))
+    ));
+}
+
+#[test]
+fn test_tempjail_cleanup() {
+    let jail = match TempJail::new() {
+        Ok(jail) => jail,
+        Err(JailError::NotSupported(_)) => return,
+        Err(e) => panic!("Could not start Jail: {}", e),
+    };
+
+    let jid = jail.jid;
+    assert!(sys::jail_exists(jid));
+
+    drop(jail);
+    assert!(!sys::jail_exists(jid));
+}
+
+#[test]
+fn test_start_many_with_ips() {
+    let result = StoppedJail::new("/rescue")
+        .ip("127.0.1.1".parse().unwrap())
+        .start_many(2, "testjail_start_many_ips_{}");
+    assert!(matches!(result, Err(JailError::NotSupported(_))));
+    assert!(RunningJail::from_name_strict("testjail_start_many_ips_0").is_err());
+}
+
+#[test]
+fn test_kill_dying_jail() {
+    use crate::process::Jailed;
+    use std::process::Command;
+
+    let running = StoppedJail::new("/")
+        .name("testjail_kill_dying")
+        .start()
+        .expect("Could not start Jail");
+
+    // An unreaped child keeps the jail dying after it has been removed.
+    let mut child = Command::new("/usr/bin/true")
+        .jail(&running)
+        .spawn()
+        .expect("could not spawn process");
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    sys::jail_remove(running.jid).expect("could not remove jail");
+
+    let report = RunningJail::from_jid_unchecked(running.jid)
+        .kill_with_report()
+        .expect("could not kill dying jail");
+    assert_eq!(report.name, "testjail_kill_dying");
+    assert!(report.was_dying);
+
+    child.wait().expect("could not reap process");
+}
+
 #[test]
 fn test_vnet_jail() {
     use sysctl::{Ctl, CtlValue::String, Sysctl};
@@ -127,3 +239,271 @@ fn test_vnet_jail() {
 
     running.stop().expect("Could not stop Jail");
 }
+
+#[test]
+fn test_schema_accessors() {
+    let schema = param::schema().expect("could not get parameter schema");
+
+    for allow in crate::allow::Allow::supported() {
+        let param = schema
+            .iter()
+            .find(|param| param.name == allow.name())
+            .expect("supported permission missing from schema");
+        assert_eq!(param.accessor, Some(format!("allow::Allow::{:?}", allow)));
+    }
+
+    for name in &["name", "path", "host.hostname", "osrelease"] {
+        let param = schema
+            .iter()
+            .find(|param| param.name == *name)
+            .expect("parameter missing from schema");
+        assert!(param.accessor.is_some(), "no accessor for {}", name);
+    }
+
+    let osrelease = schema.iter().find(|param| param.name == "osrelease");
+    assert_eq!(osrelease.map(|p| p.access), Some(param::Access::CreateOnly));
+}
+
+#[test]
+fn test_param_sizes() {
+    let abi = param::host_abi();
+    if !abi.is_compat32() {
+        assert_eq!(abi.kernel_size_t, Some(abi.size_t));
+    }
+
+    let running = StoppedJail::new("/rescue")
+        .name("testjail_param_sizes")
+        .start()
+        .expect("Could not start Jail");
+
+    // Every numeric parameter is exchanged with the size the kernel reports.
+    let mut buf = param::Buffer::new();
+    for (name, value) in param::get_all(running.jid).expect("could not get parameters") {
+        match value {
+            param::Value::String(_)
+            | param::Value::Ipv4Addrs(_)
+            | param::Value::Ipv6Addrs(_)
+            | param::Value::Raw(_)
+            | param::Value::Custom(_) => continue,
+            value => {
+                param::get_into(running.jid, &name, &mut buf).expect("could not get parameter");
+                let encoded = value.as_bytes().expect("could not encode parameter");
+                assert_eq!(encoded.len(), buf.bytes().len(), "size of {}", name);
+            }
+        }
+    }
+
+    running.kill().expect("Could not stop Jail");
+}
+
+#[test]
+fn test_exec_hooks() {
+    let marker = std::env::temp_dir().join("testjail_exec_hooks");
+    let running = StoppedJail::new("/rescue")
+        .name("testjail_exec_hooks")
+        .exec("poststart", format!("touch {}", marker.display()))
+        .exec("poststop", format!("rm {}", marker.display()))
+        .exec_hooks(true)
+        .start()
+        .expect("Could not start Jail");
+    assert!(marker.exists());
+
+    let saved = running.save().expect("could not save jail");
+    assert!(saved.exec_hooks);
+    assert_eq!(saved.exec.len(), 2);
+
+    running.kill().expect("could not stop jail");
+    assert!(!marker.exists());
+
+    // A failing hook removes the jail again.
+    let result = StoppedJail::new("/rescue")
+        .name("testjail_exec_hooks_failing")
+        .exec("created", "false")
+        .exec_hooks(true)
+        .start();
+    assert!(matches!(result, Err(JailError::ExecHookFailed { .. })));
+    assert!(RunningJail::from_name_strict("testjail_exec_hooks_failing").is_err());
+}
+
+#[test]
+fn test_daemonize_in() {
+    use crate::process::{daemonize_in, DaemonOptions, Daemonized};
+
+    let pidfile = std::env::temp_dir().join("testjail_daemonize.pid");
+    let running = StoppedJail::new("/")
+        .name("testjail_daemonize")
+        .start()
+        .expect("Could not start Jail");
+
+    // The test harness runs other threads, so the daemon exits right away.
+    let options = DaemonOptions::new().pidfile(&pidfile);
+    let pid = match daemonize_in(&running, options).expect("could not daemonize") {
+        Daemonized::Daemon => unsafe { libc::_exit(0) },
+        Daemonized::Parent { pid } => pid,
+    };
+    let written = std::fs::read_to_string(&pidfile).expect("could not read pidfile");
+    assert_eq!(written, format!("{}\n", pid));
+    std::fs::remove_file(&pidfile).expect("could not remove pidfile");
+
+    let options = DaemonOptions::new().working_dir("/nonexistent");
+    match daemonize_in(&running, options) {
+        Err(JailError::DaemonizeFailed { step, .. }) => assert_eq!(step, "chdir"),
+        Ok(Daemonized::Daemon) => unsafe { libc::_exit(0) },
+        result => panic!("unexpected {:?}", result),
+    }
+
+    running.kill().expect("could not stop jail");
+}
+
+#[test]
+fn test_unmount_policy() {
+    use crate::mount::{Mount, UnmountPolicy};
+    use std::time::Duration;
+
+    let root = std::env::temp_dir().join("testjail_unmount_force");
+    std::fs::create_dir_all(&root).expect("could not create jail root");
+    let running = StoppedJail::new(&root)
+        .name("testjail_unmount_force")
+        .mount(Mount::new("tmpfs", "tmpfs", "/tmp"))
+        .unmount_policy(
+            UnmountPolicy::default()
+                .grace(Duration::from_millis(100))
+                .force(true),
+        )
+        .start()
+        .expect("Could not start Jail");
+
+    // An open file keeps the tmpfs busy, until it is unmounted by force.
+    let busy = std::fs::File::create(root.join("tmp/busy")).expect("could not create file");
+    let report = running.kill_with_report().expect("could not kill jail");
+    assert_eq!(report.unmounted, vec![root.join("tmp")]);
+    assert!(report.still_mounted.is_empty());
+
+    drop(busy);
+    std::fs::remove_dir_all(&root).expect("could not remove jail root");
+}
+
+#[test]
+fn test_mount_flags() {
+    use crate::mount::{self, Mount};
+
+    let root = std::env::temp_dir().join("testjail_mount_flags");
+    std::fs::create_dir_all(&root).expect("could not create jail root");
+
+    let sensitive = StoppedJail::new(&root)
+        .name("testjail_mount_flags")
+        .mount(Mount::nullfs("/etc", "/mnt"))
+        .start();
+    assert!(matches!(sensitive, Err(JailError::SensitiveMount { .. })));
+
+    let running = StoppedJail::new(&root)
+        .name("testjail_mount_flags")
+        .mount(
+            Mount::new("tmpfs", "tmpfs", "/tmp")
+                .nosuid(true)
+                .noexec(true),
+        )
+        .start()
+        .expect("Could not start Jail");
+
+    let tmp = mount::mounts_under(&running)
+        .expect("could not read mounts")
+        .pop()
+        .expect("no mounts");
+    assert!(tmp.nosuid && tmp.noexec && !tmp.readonly);
+
+    running.kill().expect("could not kill jail");
+    std::fs::remove_dir_all(&root).expect("could not remove jail root");
+}
+
+#[test]
+fn test_pidfile_symlink_in_jail() {
+    use crate::pidfile::Pidfile;
+    use std::path::Path;
+
+    let root = std::env::temp_dir().join("testjail_pidfile_symlink");
+    let outside = std::env::temp_dir().join("testjail_pidfile_symlink_outside");
+    std::fs::create_dir_all(root.join("var/run")).expect("could not create jail root");
+    std::fs::create_dir_all(&outside).expect("could not create directory");
+    let running = StoppedJail::new(&root)
+        .name("testjail_pidfile_symlink")
+        .start()
+        .expect("Could not start Jail");
+
+    let pidfile = Pidfile::in_jail(&running, Path::new("/var/run/daemon.pid")).expect("no pidfile");
+
+    // The jail replaces a directory on the way by a link out of its root.
+    std::fs::remove_dir(root.join("var/run")).expect("could not remove directory");
+    std::os::unix::fs::symlink(&outside, root.join("var/run")).expect("could not link");
+
+    assert!(pidfile.lock().is_err());
+    assert!(!outside.join("daemon.pid").exists());
+
+    running.kill().expect("could not kill jail");
+    std::fs::remove_dir_all(&root).expect("could not remove jail root");
+    std::fs::remove_dir_all(&outside).expect("could not remove directory");
+}
+
+#[test]
+fn test_loopback_aliases_shared() {
+    let ip: std::net::IpAddr = "127.0.42.1".parse().unwrap();
+    let start = |name: &str| {
+        StoppedJail::new("/rescue")
+            .name(name)
+            .ip(ip)
+            .loopback_aliases(true)
+            .start()
+            .expect("Could not start Jail")
+    };
+    let configured = || sys::interface_addresses().unwrap().contains(&ip);
+
+    let first = start("testjail_loopback_first");
+    let second = start("testjail_loopback_second");
+    assert!(configured());
+
+    first.kill().expect("could not kill jail");
+    assert!(configured(), "alias removed while still in use");
+
+    second.kill().expect("could not kill jail");
+    assert!(!configured());
+}
+
+#[cfg(feature = "proptest")]
+mod proptests {
+    use crate::StoppedJail;
+    use proptest::prelude::*;
+
+    /// Sort addresses, as the kernel keeps only the first address of each
+    /// family in place.
+    fn sorted(mut ips: Vec<std::net::IpAddr>) -> Vec<std::net::IpAddr> {
+        ips.sort();
+        ips
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_start_stop_round_trip(stopped in any::<StoppedJail>()) {
+            let saved = stopped.clone().start().expect("could not start jail")
+                .stop().expect("could not stop jail");
+
+            prop_assert_eq!(&saved.path, &stopped.path);
+            prop_assert_eq!(&saved.name, &stopped.name);
+            if stopped.hostname.is_some() {
+                prop_assert_eq!(&saved.hostname, &stopped.hostname);
+            }
+            prop_assert_eq!(sorted(saved.ips.clone()), sorted(stopped.ips.clone()));
+            for (name, value) in &stopped.params {
+                prop_assert_eq!(saved.params.get(name), Some(value), "parameter {}", name);
+            }
+            prop_assert_eq!(&saved.meta, &stopped.meta);
+            prop_assert_eq!(&saved.env, &stopped.env);
+
+            // A saved configuration is a fixed point of the round trip.
+            let again = saved.clone().start().expect("could not restart jail")
+                .stop().expect("could not stop restarted jail");
+            prop_assert_eq!(again, saved);
+        }
+    }
+}
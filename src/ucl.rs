@@ -0,0 +1,626 @@
+//! Jail definitions in UCL
+//!
+//! [UCL](https://github.com/vstakhov/libucl) is a superset of JSON with an
+//! nginx-like syntax, used by `pkg`(8) among others. This module parses the
+//! subset of UCL needed for configuration files and translates jail
+//! definitions into [StoppedJail](../struct.StoppedJail.html)s:
+//!
+//! ```text
+//! jail "www" {
+//!     path = /usr/jails/www;
+//!     hostname = www.example.com;
+//!     ips = [ 192.0.2.1, "2001:db8::1" ];
+//!
+//!     params {
+//!         securelevel = 3;
+//!         allow.raw_sockets = true;
+//!     }
+//!
+//!     limits = [
+//!         { resource = memoryuse; amount = 1gb; action = deny; },
+//!         { resource = maxproc; amount = 100; per = process; },
+//!     ]
+//!
+//!     mounts = [
+//!         { source = /usr/ports; target = /usr/ports; readonly = true; },
+//!         { fstype = tmpfs; source = tmpfs; target = /tmp; nosuid = true; },
+//!     ]
+//! }
+//! ```
+//!
+//! Variables like `${name}` are expanded as in `jail.conf`(5), see
+//! [vars](../vars/index.html). Macros, includes and heredocs are not
+//! supported.
+//!
+//! This module requires the `ucl` feature.
+use crate::JailError;
+use log::trace;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[cfg(target_os = "freebsd")]
+use crate::mount::Mount;
+#[cfg(target_os = "freebsd")]
+use crate::vars::Variables;
+#[cfg(target_os = "freebsd")]
+use crate::{param, StoppedJail};
+#[cfg(all(target_os = "freebsd", feature = "rctl"))]
+use std::str::FromStr;
+
+/// A UCL value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+
+    /// An object, with its keys in order. Keys may be repeated, which UCL
+    /// treats as an implicit array.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Get the first value of `key`, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.get_all(key).into_iter().next()
+    }
+
+    /// Get all values of `key`, if this is an object, flattening implicit
+    /// arrays.
+    pub fn get_all(&self, key: &str) -> Vec<&Value> {
+        match self {
+            Value::Object(entries) => entries
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Render a scalar as a string, as it would be given in `jail`(8)
+    /// syntax. Arrays are joined with commas.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Value::Null | Value::Object(_) => None,
+            Value::Bool(b) => Some(b.to_string()),
+            Value::Int(i) => Some(i.to_string()),
+            Value::Float(f) => Some(f.to_string()),
+            Value::String(s) => Some(s.clone()),
+            Value::Array(values) => values
+                .iter()
+                .map(Value::to_text)
+                .collect::<Option<Vec<_>>>()
+                .map(|values| values.join(",")),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<S: Into<String>>(&self, message: S) -> JailError {
+        JailError::ConfigParseError {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    /// Skip whitespace and comments.
+    fn skip(&mut self) -> Result<(), JailError> {
+        loop {
+            let mut lookahead = self.chars.clone();
+            match (lookahead.next(), lookahead.next()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('#'), _) | (Some('/'), Some('/')) => {
+                    while !matches!(self.bump(), None | Some('\n')) {}
+                }
+                (Some('/'), Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match (self.bump(), self.peek()) {
+                            (None, _) => return Err(self.error("unterminated comment")),
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                depth -= 1;
+                            }
+                            (Some('/'), Some('*')) => {
+                                self.bump();
+                                depth += 1;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Skip an optional `,` or `;` after a value.
+    fn separator(&mut self) -> Result<(), JailError> {
+        self.skip()?;
+        if matches!(self.peek(), Some(',') | Some(';')) {
+            self.bump();
+        }
+        Ok(())
+    }
+
+    fn quoted(&mut self) -> Result<String, JailError> {
+        let quote = self.bump().expect("called on a quote");
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') if quote == '"' => match self.bump() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.bump()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error(format!("invalid escape \\u{}", hex)))?;
+                        s.push(c);
+                    }
+                    Some(c) => s.push(c),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some('\\') => match self.bump() {
+                    Some('\'') => s.push('\''),
+                    Some(c) => {
+                        s.push('\\');
+                        s.push(c);
+                    }
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Read an unquoted word, ending at whitespace or a structural
+    /// character.
+    fn word(&mut self, is_key: bool) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            let end =
+                c.is_whitespace() || ";,{}[]\"#".contains(c) || (is_key && (c == '=' || c == ':'));
+            if end {
+                break;
+            }
+            s.push(c);
+            self.bump();
+        }
+        s
+    }
+
+    fn key(&mut self) -> Result<String, JailError> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.quoted(),
+            next => match self.word(true) {
+                word if word.is_empty() => {
+                    Err(self.error(format!("expected a key, found {:?}", next)))
+                }
+                word => Ok(word),
+            },
+        }
+    }
+
+    /// Parse the entries of an object up to `end`, which is `None` for the
+    /// implicit top level object.
+    fn entries(&mut self, end: Option<char>) -> Result<Vec<(String, Value)>, JailError> {
+        let mut entries = vec![];
+        loop {
+            self.skip()?;
+            match (self.peek(), end) {
+                (None, None) => return Ok(entries),
+                (None, Some(_)) => return Err(self.error("unterminated object")),
+                (Some(c), Some(end)) if c == end => {
+                    self.bump();
+                    return Ok(entries);
+                }
+                _ => {}
+            }
+
+            // `a "b" { ... }` is a shorthand for `a { b { ... } }`.
+            let mut keys = vec![self.key()?];
+            let value = loop {
+                self.skip()?;
+                match self.peek() {
+                    Some('=') | Some(':') => {
+                        self.bump();
+                        self.skip()?;
+                        break self.value()?;
+                    }
+                    Some('{') => break self.value()?,
+                    Some('"') | Some('\'') => keys.push(self.quoted()?),
+                    Some(c) if !";,[]}".contains(c) => keys.push(self.key()?),
+                    c => return Err(self.error(format!("unexpected {:?} after key", c))),
+                }
+            };
+            self.separator()?;
+
+            let key = keys.remove(0);
+            let value = keys
+                .into_iter()
+                .rev()
+                .fold(value, |value, key| Value::Object(vec![(key, value)]));
+            entries.push((key, value));
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JailError> {
+        match self.peek() {
+            Some('{') => {
+                self.bump();
+                Ok(Value::Object(self.entries(Some('}'))?))
+            }
+            Some('[') => {
+                self.bump();
+                let mut values = vec![];
+                loop {
+                    self.skip()?;
+                    match self.peek() {
+                        None => return Err(self.error("unterminated array")),
+                        Some(']') => {
+                            self.bump();
+                            return Ok(Value::Array(values));
+                        }
+                        _ => {
+                            values.push(self.value()?);
+                            self.separator()?;
+                        }
+                    }
+                }
+            }
+            Some('"') | Some('\'') => Ok(Value::String(self.quoted()?)),
+            _ => match self.word(false) {
+                word if word.is_empty() => Err(self.error("expected a value")),
+                word => Ok(scalar(&word)),
+            },
+        }
+    }
+}
+
+/// Interpret an unquoted scalar: booleans, null, and numbers with the
+/// multiplier suffixes of libucl (`k`, `m`, `g` for powers of 1000 and `kb`,
+/// `mb`, `gb` for powers of 1024).
+fn scalar(word: &str) -> Value {
+    match word.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => return Value::Bool(true),
+        "false" | "no" | "off" => return Value::Bool(false),
+        "null" => return Value::Null,
+        _ => {}
+    }
+
+    let lower = word.to_ascii_lowercase();
+    let (number, multiplier) = [
+        ("kb", 1i64 << 10),
+        ("mb", 1 << 20),
+        ("gb", 1 << 30),
+        ("k", 1000),
+        ("m", 1_000_000),
+        ("g", 1_000_000_000),
+    ]
+    .iter()
+    .find_map(|(suffix, multiplier)| Some((lower.strip_suffix(suffix)?, *multiplier)))
+    .unwrap_or((&lower, 1));
+
+    if let Ok(i) = number.parse::<i64>() {
+        if let Some(i) = i.checked_mul(multiplier) {
+            return Value::Int(i);
+        }
+    }
+
+    match word.parse::<f64>() {
+        Ok(f) if word.contains('.') => Value::Float(f),
+        _ => Value::String(word.into()),
+    }
+}
+
+/// Parse a UCL document into its top level object.
+///
+/// # Examples
+///
+/// ```
+/// use jail::ucl::{self, Value};
+///
+/// let config = ucl::parse("jail www { limits = [ { amount = 1kb } ] }").unwrap();
+/// let www = config.get("jail").and_then(|jail| jail.get("www")).unwrap();
+/// assert_eq!(
+///     www.get("limits"),
+///     Some(&Value::Array(vec![Value::Object(vec![("amount".into(), Value::Int(1024))])]))
+/// );
+/// ```
+pub fn parse(input: &str) -> Result<Value, JailError> {
+    trace!("ucl::parse()");
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+        line: 1,
+    };
+
+    parser.skip()?;
+    if parser.peek() == Some('{') {
+        let value = parser.value()?;
+        parser.skip()?;
+        return match parser.peek() {
+            None => Ok(value),
+            Some(c) => Err(parser.error(format!("unexpected {:?} after object", c))),
+        };
+    }
+
+    Ok(Value::Object(parser.entries(None)?))
+}
+
+/// Parse the jail definitions in the `jail` object of a UCL document.
+///
+/// Parameter values are interpreted according to the type of the parameter
+/// in the running kernel, so unknown parameters are rejected.
+///
+/// # Examples
+///
+/// ```
+/// use jail::ucl;
+///
+/// let jails = ucl::parse_jails(r#"
+///     jail "www" {
+///         path = /rescue;
+///         hostname = www.example.com;
+///         ips = [ 192.0.2.1 ];
+///         params { securelevel = 3; }
+///         mounts = [ { source = /usr/ports; target = /usr/ports; readonly = true } ];
+///     }
+/// "#).unwrap();
+///
+/// assert_eq!(jails[0].name, Some("www".into()));
+/// assert_eq!(jails[0].mounts[0].readonly, true);
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn parse_jails(input: &str) -> Result<Vec<StoppedJail>, JailError> {
+    trace!("ucl::parse_jails()");
+    let config = parse(input)?;
+
+    let mut jails = vec![];
+    for jail in config.get_all("jail") {
+        let entries = match jail {
+            Value::Object(entries) => entries,
+            _ => return Err(invalid("jail", "expected an object of jails")),
+        };
+
+        for (name, definition) in entries {
+            jails.push(jail_from(name, definition)?.interpolate(&Variables::new())?);
+        }
+    }
+
+    Ok(jails)
+}
+
+#[cfg(target_os = "freebsd")]
+fn invalid<S: Into<String>>(key: &str, message: S) -> JailError {
+    JailError::ConfigParseError {
+        line: 0,
+        message: format!("{}: {}", key, message.into()),
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+fn text(value: &Value, key: &str) -> Result<String, JailError> {
+    value
+        .to_text()
+        .ok_or_else(|| invalid(key, "expected a scalar or array of scalars"))
+}
+
+#[cfg(target_os = "freebsd")]
+fn jail_from(name: &str, definition: &Value) -> Result<StoppedJail, JailError> {
+    let mut stopped = StoppedJail::default().name(name);
+
+    let entries = match definition {
+        Value::Object(entries) => entries,
+        _ => return Err(invalid(name, "expected an object")),
+    };
+
+    for (key, value) in entries {
+        let key = key.as_str();
+        match key {
+            "path" => stopped.path = Some(text(value, key)?.into()),
+            "hostname" => stopped = stopped.hostname(text(value, key)?),
+            "ips" => {
+                let values = match value {
+                    Value::Array(values) => values.iter().collect(),
+                    value => vec![value],
+                };
+
+                for value in values {
+                    let ip = text(value, key)?;
+                    stopped = stopped.ip(ip
+                        .parse()
+                        .map_err(|_| invalid(key, format!("invalid address {:?}", ip)))?);
+                }
+            }
+            "params" => {
+                let params = match value {
+                    Value::Object(params) => params,
+                    _ => return Err(invalid(key, "expected an object")),
+                };
+
+                for (param, value) in params {
+                    let value = param::Value::parse(param, &text(value, param)?)?;
+                    stopped = stopped.param(param.as_str(), value);
+                }
+            }
+            #[cfg(feature = "rctl")]
+            "limits" => {
+                for limit in array(value) {
+                    let (resource, limit, action) = limit_from(limit)?;
+                    stopped = stopped.limit(resource, limit, action);
+                }
+            }
+            #[cfg(not(feature = "rctl"))]
+            "limits" => {
+                return Err(JailError::NotSupported(format!(
+                    "limits of jail {:?} require the `rctl` feature",
+                    name
+                )))
+            }
+            "mounts" => {
+                for mount in array(value) {
+                    stopped = stopped.mount(mount_from(mount)?);
+                }
+            }
+            key => return Err(invalid(name, format!("unknown key {:?}", key))),
+        }
+    }
+
+    Ok(stopped)
+}
+
+/// Get the elements of an array, or the value itself otherwise.
+#[cfg(target_os = "freebsd")]
+fn array(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    }
+}
+
+#[cfg(all(target_os = "freebsd", feature = "rctl"))]
+fn limit_from(value: &Value) -> Result<(rctl::Resource, rctl::Limit, rctl::Action), JailError> {
+    let field = |key: &str| -> Result<Option<String>, JailError> {
+        value.get(key).map(|value| text(value, key)).transpose()
+    };
+
+    let resource = field("resource")?.ok_or_else(|| invalid("limits", "missing resource"))?;
+    let resource = rctl::Resource::from_str(&resource)
+        .map_err(|_| invalid("limits", format!("unknown resource {:?}", resource)))?;
+
+    let amount = field("amount")?.ok_or_else(|| invalid("limits", "missing amount"))?;
+    let amount = match field("per")? {
+        Some(per) => format!("{}/{}", amount, per),
+        None => amount,
+    };
+    let limit = rctl::Limit::from_str(&amount)
+        .map_err(|_| invalid("limits", format!("invalid amount {:?}", amount)))?;
+
+    let action = field("action")?.unwrap_or_else(|| "deny".into());
+    let action = rctl::Action::from_str(&action)
+        .map_err(|_| invalid("limits", format!("invalid action {:?}", action)))?;
+
+    Ok((resource, limit, action))
+}
+
+#[cfg(target_os = "freebsd")]
+fn mount_from(value: &Value) -> Result<Mount, JailError> {
+    let field = |key: &str| -> Result<Option<String>, JailError> {
+        value.get(key).map(|value| text(value, key)).transpose()
+    };
+
+    let source = field("source")?.ok_or_else(|| invalid("mounts", "missing source"))?;
+    let target = field("target")?.ok_or_else(|| invalid("mounts", "missing target"))?;
+    let fstype = field("fstype")?.unwrap_or_else(|| "nullfs".into());
+    let flag = |key: &str| match value.get(key) {
+        None | Some(Value::Bool(false)) => Ok(false),
+        Some(Value::Bool(true)) => Ok(true),
+        Some(_) => Err(invalid("mounts", format!("{} must be a boolean", key))),
+    };
+
+    Ok(Mount::new(fstype, source, target)
+        .readonly(flag("readonly")?)
+        .nosuid(flag("nosuid")?)
+        .noexec(flag("noexec")?)
+        .allow_sensitive(flag("allow_sensitive")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ucl() {
+        let config = parse(
+            r#"
+            # comment
+            jail "www" {
+                path = "/jails/www"; // comment
+                params { allow.raw_sockets: yes, securelevel = 3 }
+                /* nested /* comment */ */
+                limits = [
+                    { resource = memoryuse; amount = 2mb },
+                    { resource = "maxproc", amount = 10k, per = process },
+                ]
+            }
+            jail db { path = '/jails/db' }
+            "#,
+        )
+        .unwrap();
+
+        let jails = config.get_all("jail");
+        assert_eq!(jails.len(), 2);
+
+        let www = jails[0].get("www").unwrap();
+        assert_eq!(www.get("path"), Some(&Value::String("/jails/www".into())));
+
+        let params = www.get("params").unwrap();
+        assert_eq!(params.get("allow.raw_sockets"), Some(&Value::Bool(true)));
+        assert_eq!(params.get("securelevel"), Some(&Value::Int(3)));
+
+        let limits = match www.get("limits") {
+            Some(Value::Array(limits)) => limits,
+            _ => panic!("limits is not an array"),
+        };
+        assert_eq!(limits[0].get("amount"), Some(&Value::Int(2 << 20)));
+        assert_eq!(limits[1].get("amount"), Some(&Value::Int(10_000)));
+        assert_eq!(
+            limits[1].get("per").unwrap().to_text(),
+            Some("process".into())
+        );
+
+        let db = jails[1].get("db").unwrap();
+        assert_eq!(db.get("path"), Some(&Value::String("/jails/db".into())));
+
+        assert_eq!(
+            parse(r#"{"a": [1, 2.5, null, "x"]}"#).unwrap(),
+            Value::Object(vec![(
+                "a".into(),
+                Value::Array(vec![
+                    Value::Int(1),
+                    Value::Float(2.5),
+                    Value::Null,
+                    Value::String("x".into())
+                ])
+            )])
+        );
+
+        assert!(parse("jail www { path = /jails/www;").is_err());
+        assert!(matches!(
+            parse("a = 1;\nb = [1, 2\n"),
+            Err(JailError::ConfigParseError { line: 3, .. })
+        ));
+    }
+}
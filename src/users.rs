@@ -0,0 +1,410 @@
+//! Users and groups in the root of a jail
+//!
+//! The `master.passwd`(5) and `group`(5) databases of a jail root are edited
+//! by driving `pw -R`, which also rebuilds the password databases. This is
+//! typically done before the first start of a jail, e.g. to create the user
+//! a service runs as.
+//!
+//! The numeric ids of new users and groups are chosen according to an
+//! [IdPolicy].
+use crate::{passwd, JailError};
+use log::trace;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How the numeric id of a new user or group is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdPolicy {
+    /// Let `pw` choose the next free id, see `pw.conf`(5).
+    Next,
+
+    /// Use the given id, failing if it is in use in the jail root.
+    Fixed(u32),
+
+    /// Use the lowest id in the range that is free in the jail root.
+    Range { min: u32, max: u32 },
+
+    /// Use the id of the user or group of the same name on the host, so that
+    /// files on file systems shared with the host have the same owner.
+    Host,
+}
+
+impl IdPolicy {
+    /// Resolve the policy for `name` against the ids in use in the jail root
+    /// and on the host, returning the id to request, if any.
+    fn resolve(
+        &self,
+        name: &str,
+        used: &[passwd::Entry],
+        host: impl FnOnce() -> Result<Vec<passwd::Entry>, JailError>,
+    ) -> Result<Option<u32>, JailError> {
+        let in_use = |id: u32| used.iter().any(|entry| entry.id == id);
+
+        let id = match *self {
+            IdPolicy::Next => return Ok(None),
+            IdPolicy::Fixed(id) => id,
+            IdPolicy::Range { min, max } => {
+                return (min..=max)
+                    .find(|id| !in_use(*id))
+                    .map(Some)
+                    .ok_or_else(|| {
+                        JailError::UserError(format!("no free id between {} and {}", min, max))
+                    })
+            }
+            IdPolicy::Host => host()?
+                .into_iter()
+                .find(|entry| entry.name == name)
+                .map(|entry| entry.id)
+                .ok_or_else(|| {
+                    JailError::UserError(format!("{} does not exist on the host", name))
+                })?,
+        };
+
+        match in_use(id) {
+            true => Err(JailError::UserError(format!("id {} is already in use", id))),
+            false => Ok(Some(id)),
+        }
+    }
+}
+
+/// A user to add to a jail root.
+///
+/// # Examples
+///
+/// ```
+/// use jail::users::{IdPolicy, User};
+///
+/// let user = User::new("www")
+///     .uid(IdPolicy::Range { min: 1000, max: 1999 })
+///     .group("www")
+///     .shell("/usr/sbin/nologin");
+///
+/// assert_eq!(user.name, "www");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct User {
+    /// The login name
+    pub name: String,
+
+    /// How to choose the `uid`
+    pub uid: IdPolicy,
+
+    /// The primary group, which must exist. Defaults to a new group named
+    /// after the user.
+    pub group: Option<String>,
+
+    /// Additional groups, which must exist
+    pub groups: Vec<String>,
+
+    /// The full name
+    pub comment: Option<String>,
+
+    /// The home directory inside the jail
+    pub home: Option<PathBuf>,
+
+    /// The login shell inside the jail
+    pub shell: Option<PathBuf>,
+
+    /// Whether to create the home directory
+    pub create_home: bool,
+}
+
+impl User {
+    /// Create a user with a `uid` chosen by `pw`.
+    pub fn new<S: Into<String> + fmt::Debug>(name: S) -> Self {
+        trace!("User::new(name={:?})", name);
+        User {
+            name: name.into(),
+            uid: IdPolicy::Next,
+            group: None,
+            groups: vec![],
+            comment: None,
+            home: None,
+            shell: None,
+            create_home: false,
+        }
+    }
+
+    /// Set how the `uid` is chosen.
+    pub fn uid(mut self, uid: IdPolicy) -> Self {
+        trace!("User::uid({:?}, uid={:?})", self, uid);
+        self.uid = uid;
+        self
+    }
+
+    /// Set the primary group.
+    pub fn group<S: Into<String> + fmt::Debug>(mut self, group: S) -> Self {
+        trace!("User::group({:?}, group={:?})", self, group);
+        self.group = Some(group.into());
+        self
+    }
+
+    /// Add the user to an additional group.
+    pub fn member_of<S: Into<String> + fmt::Debug>(mut self, group: S) -> Self {
+        trace!("User::member_of({:?}, group={:?})", self, group);
+        self.groups.push(group.into());
+        self
+    }
+
+    /// Set the full name.
+    pub fn comment<S: Into<String> + fmt::Debug>(mut self, comment: S) -> Self {
+        trace!("User::comment({:?}, comment={:?})", self, comment);
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Set the home directory, and whether to create it.
+    pub fn home<P: Into<PathBuf> + fmt::Debug>(mut self, home: P, create: bool) -> Self {
+        trace!("User::home({:?}, home={:?}, create={})", self, home, create);
+        self.home = Some(home.into());
+        self.create_home = create;
+        self
+    }
+
+    /// Set the login shell.
+    pub fn shell<P: Into<PathBuf> + fmt::Debug>(mut self, shell: P) -> Self {
+        trace!("User::shell({:?}, shell={:?})", self, shell);
+        self.shell = Some(shell.into());
+        self
+    }
+
+    /// Build the `pw useradd` arguments, given the resolved `uid`.
+    fn args(&self, uid: Option<u32>) -> Vec<String> {
+        let mut args = vec!["useradd".to_string(), self.name.clone()];
+        let mut option = |flag: &str, value: String| {
+            args.push(flag.into());
+            args.push(value);
+        };
+
+        if let Some(uid) = uid {
+            option("-u", uid.to_string());
+        }
+        if let Some(ref group) = self.group {
+            option("-g", group.clone());
+        }
+        if !self.groups.is_empty() {
+            option("-G", self.groups.join(","));
+        }
+        if let Some(ref comment) = self.comment {
+            option("-c", comment.clone());
+        }
+        if let Some(ref home) = self.home {
+            option("-d", home.to_string_lossy().into_owned());
+        }
+        if let Some(ref shell) = self.shell {
+            option("-s", shell.to_string_lossy().into_owned());
+        }
+        if self.create_home {
+            args.push("-m".into());
+        }
+
+        args
+    }
+}
+
+/// A group to add to a jail root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    /// The name of the group
+    pub name: String,
+
+    /// How to choose the `gid`
+    pub gid: IdPolicy,
+
+    /// The members of the group, which must exist
+    pub members: Vec<String>,
+}
+
+impl Group {
+    /// Create a group with a `gid` chosen by `pw`.
+    pub fn new<S: Into<String> + fmt::Debug>(name: S) -> Self {
+        trace!("Group::new(name={:?})", name);
+        Group {
+            name: name.into(),
+            gid: IdPolicy::Next,
+            members: vec![],
+        }
+    }
+
+    /// Set how the `gid` is chosen.
+    pub fn gid(mut self, gid: IdPolicy) -> Self {
+        trace!("Group::gid({:?}, gid={:?})", self, gid);
+        self.gid = gid;
+        self
+    }
+
+    /// Add a member to the group.
+    pub fn member<S: Into<String> + fmt::Debug>(mut self, user: S) -> Self {
+        trace!("Group::member({:?}, user={:?})", self, user);
+        self.members.push(user.into());
+        self
+    }
+
+    /// Build the `pw groupadd` arguments, given the resolved `gid`.
+    fn args(&self, gid: Option<u32>) -> Vec<String> {
+        let mut args = vec!["groupadd".to_string(), self.name.clone()];
+        if let Some(gid) = gid {
+            args.extend(vec!["-g".into(), gid.to_string()]);
+        }
+        if !self.members.is_empty() {
+            args.extend(vec!["-M".into(), self.members.join(",")]);
+        }
+        args
+    }
+}
+
+/// Run `pw -R root` with the given arguments.
+fn pw(root: &Path, args: &[String]) -> Result<(), JailError> {
+    trace!("pw(root={:?}, args={:?})", root, args);
+    let output = Command::new("pw")
+        .arg("-R")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(JailError::IoError)?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(JailError::UserError(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+/// Look up the id of `name` after adding it.
+fn added(entries: Vec<passwd::Entry>, name: &str) -> Result<u32, JailError> {
+    entries
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.id)
+        .ok_or_else(|| JailError::UserError(format!("{} was not added", name)))
+}
+
+/// Add a user to the jail root at `root`, returning its `uid`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jail::users::{self, IdPolicy, User};
+/// use std::path::Path;
+///
+/// let root = Path::new("/jails/www");
+/// let uid = users::add_user(root, &User::new("app").uid(IdPolicy::Fixed(1001)))
+///     .expect("could not add user");
+/// assert_eq!(uid, 1001);
+///
+/// users::remove_user(root, "app", false).expect("could not remove user");
+/// ```
+#[cfg(target_os = "freebsd")]
+pub fn add_user(root: &Path, user: &User) -> Result<u32, JailError> {
+    trace!("users::add_user(root={:?}, user={:?})", root, user);
+    let uid = user.uid.resolve(&user.name, &passwd::users(root)?, || {
+        passwd::users(Path::new("/"))
+    })?;
+
+    pw(root, &user.args(uid))?;
+    added(passwd::users(root)?, &user.name)
+}
+
+/// Remove a user from the jail root at `root`, optionally removing its home
+/// directory.
+#[cfg(target_os = "freebsd")]
+pub fn remove_user(root: &Path, name: &str, remove_home: bool) -> Result<(), JailError> {
+    trace!(
+        "users::remove_user(root={:?}, name={:?}, remove_home={})",
+        root,
+        name,
+        remove_home
+    );
+    let mut args = vec!["userdel".to_string(), name.to_string()];
+    if remove_home {
+        args.push("-r".into());
+    }
+    pw(root, &args)
+}
+
+/// Add a group to the jail root at `root`, returning its `gid`.
+#[cfg(target_os = "freebsd")]
+pub fn add_group(root: &Path, group: &Group) -> Result<u32, JailError> {
+    trace!("users::add_group(root={:?}, group={:?})", root, group);
+    let gid = group.gid.resolve(&group.name, &passwd::groups(root)?, || {
+        passwd::groups(Path::new("/"))
+    })?;
+
+    pw(root, &group.args(gid))?;
+    added(passwd::groups(root)?, &group.name)
+}
+
+/// Remove a group from the jail root at `root`.
+#[cfg(target_os = "freebsd")]
+pub fn remove_group(root: &Path, name: &str) -> Result<(), JailError> {
+    trace!("users::remove_group(root={:?}, name={:?})", root, name);
+    pw(root, &["groupdel".to_string(), name.to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(ids: &[(&str, u32)]) -> Vec<passwd::Entry> {
+        ids.iter()
+            .map(|(name, id)| passwd::Entry {
+                name: name.to_string(),
+                id: *id,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolve_ids() {
+        let used = entries(&[("root", 0), ("alice", 1000), ("bob", 1001)]);
+        let host = || Ok(entries(&[("www", 80), ("alice", 1000)]));
+
+        assert_eq!(IdPolicy::Next.resolve("carol", &used, host).unwrap(), None);
+        assert_eq!(
+            IdPolicy::Fixed(2000).resolve("carol", &used, host).unwrap(),
+            Some(2000)
+        );
+        assert!(IdPolicy::Fixed(1001).resolve("carol", &used, host).is_err());
+        assert_eq!(
+            IdPolicy::Range {
+                min: 1000,
+                max: 1999
+            }
+            .resolve("carol", &used, host)
+            .unwrap(),
+            Some(1002)
+        );
+        assert!(IdPolicy::Range {
+            min: 1000,
+            max: 1001
+        }
+        .resolve("carol", &used, host)
+        .is_err());
+        assert_eq!(
+            IdPolicy::Host.resolve("www", &used, host).unwrap(),
+            Some(80)
+        );
+        assert!(IdPolicy::Host.resolve("carol", &used, host).is_err());
+    }
+
+    #[test]
+    fn useradd_args() {
+        let user = User::new("app")
+            .group("www")
+            .member_of("wheel")
+            .member_of("operator")
+            .home("/home/app", true)
+            .shell("/bin/sh");
+
+        assert_eq!(
+            user.args(Some(1001)).join(" "),
+            "useradd app -u 1001 -g www -G wheel,operator -d /home/app -s /bin/sh -m"
+        );
+        assert_eq!(
+            Group::new("www").member("app").args(None).join(" "),
+            "groupadd www -M app"
+        );
+    }
+}
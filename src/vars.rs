@@ -0,0 +1,246 @@
+//! Variable interpolation in jail definitions
+//!
+//! Like in `jail.conf`(5), values can refer to variables as `$name` or
+//! `${name}`. The short form takes the longest run of letters, digits and
+//! underscores after the `$`, so names containing dots, like
+//! `${host.hostname}`, need braces. A `$` not followed by a name is kept.
+//!
+//! Variables may refer to other variables, which are expanded in turn.
+//! Cycles, e.g. a `path` of `${path}/root`, and references to undefined
+//! variables are reported as [JailError::VariableError].
+//!
+//! [StoppedJail::interpolate](../struct.StoppedJail.html#method.interpolate)
+//! expands the variables in a definition, using its own parameters as
+//! variables, see [Variables::of_jail].
+use crate::JailError;
+use log::trace;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(target_os = "freebsd")]
+use crate::param::Value;
+#[cfg(target_os = "freebsd")]
+use crate::{pretty, StoppedJail};
+
+/// A set of variables that can be referenced in values.
+///
+/// # Examples
+///
+/// ```
+/// use jail::vars::Variables;
+///
+/// let vars = Variables::new()
+///     .set("name", "www")
+///     .set("root", "/usr/jails/${name}");
+///
+/// assert_eq!(vars.expand("$root/etc").unwrap(), "/usr/jails/www/etc");
+/// assert!(vars.expand("${domain}").is_err());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Variables {
+    values: BTreeMap<String, String>,
+}
+
+impl Variables {
+    /// Create an empty set of variables.
+    pub fn new() -> Self {
+        trace!("Variables::new()");
+        Variables::default()
+    }
+
+    /// Set a variable, replacing its previous value. The value may refer to
+    /// other variables.
+    pub fn set<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String> + fmt::Debug,
+        V: Into<String> + fmt::Debug,
+    {
+        trace!(
+            "Variables::set({:?}, name={:?}, value={:?})",
+            self,
+            name,
+            value
+        );
+        self.values.insert(name.into(), value.into());
+        self
+    }
+
+    /// Get the value of a variable, without expanding it.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        trace!("Variables::get({:?}, name={:?})", self, name);
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Iterate over the variables and their unexpanded values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        trace!("Variables::iter({:?})", self);
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Expand all variables referenced in `input`.
+    pub fn expand(&self, input: &str) -> Result<String, JailError> {
+        trace!("Variables::expand({:?}, input={:?})", self, input);
+        self.expand_with(input, &mut vec![])
+    }
+
+    /// Expand `input`, with `stack` holding the variables being expanded.
+    fn expand_with(&self, input: &str, stack: &mut Vec<String>) -> Result<String, JailError> {
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+
+        while let Some(start) = rest.find('$') {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+
+            let (name, len) = match after.strip_prefix('{') {
+                Some(braced) => {
+                    let end = braced.find('}').ok_or_else(|| JailError::VariableError {
+                        name: braced.into(),
+                        message: "missing closing brace".into(),
+                    })?;
+                    (&braced[..end], end + 2)
+                }
+                None => {
+                    let end = after
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(after.len());
+                    (&after[..end], end)
+                }
+            };
+
+            match (name.is_empty(), len) {
+                (true, 0) => output.push('$'),
+                (true, _) => {
+                    return Err(JailError::VariableError {
+                        name: String::new(),
+                        message: "empty variable name".into(),
+                    })
+                }
+                (false, _) => output.push_str(&self.resolve(name, stack)?),
+            }
+            rest = &after[len..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Get the expanded value of the variable `name`.
+    fn resolve(&self, name: &str, stack: &mut Vec<String>) -> Result<String, JailError> {
+        if let Some(position) = stack.iter().position(|n| n == name) {
+            let mut cycle = stack[position..].to_vec();
+            cycle.push(name.into());
+            return Err(JailError::VariableError {
+                name: name.into(),
+                message: format!("refers to itself via {}", cycle.join(" -> ")),
+            });
+        }
+
+        let value = self.get(name).ok_or_else(|| JailError::VariableError {
+            name: name.into(),
+            message: "not defined".into(),
+        })?;
+
+        stack.push(name.into());
+        let expanded = self.expand_with(value, stack);
+        stack.pop();
+        expanded
+    }
+
+    /// Get the variables defined by a jail definition.
+    ///
+    /// These are `name`, `path`, `host.hostname`, `ip4.addr` and `ip6.addr`
+    /// with the addresses separated by commas, and every parameter of the
+    /// jail by its name, e.g. `securelevel`. `ip4` and `ip6` are shorthands
+    /// for the addresses, unless the jail sets these parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::vars::Variables;
+    /// use jail::StoppedJail;
+    ///
+    /// let stopped = StoppedJail::new("/rescue")
+    ///     .name("www")
+    ///     .ip("192.0.2.1".parse().unwrap());
+    /// let vars = Variables::of_jail(&stopped);
+    ///
+    /// assert_eq!(vars.expand("$name: ${ip4}").unwrap(), "www: 192.0.2.1");
+    /// ```
+    #[cfg(target_os = "freebsd")]
+    pub fn of_jail(stopped: &StoppedJail) -> Variables {
+        trace!("Variables::of_jail({:?})", stopped);
+        let mut vars = Variables::new();
+
+        for (name, v4) in [("ip4", true), ("ip6", false)] {
+            let ips: Vec<String> = stopped
+                .ips
+                .iter()
+                .filter(|ip| ip.is_ipv4() == v4)
+                .map(ToString::to_string)
+                .collect();
+            if !ips.is_empty() {
+                let ips = ips.join(",");
+                vars = vars
+                    .set(name, ips.as_str())
+                    .set(format!("{}.addr", name), ips);
+            }
+        }
+
+        for (name, value) in &stopped.params {
+            match value {
+                Value::String(value) => vars = vars.set(name.as_str(), value.as_str()),
+                Value::Raw(_) | Value::Custom(_) => {}
+                value => vars = vars.set(name.as_str(), pretty::value(value)),
+            }
+        }
+
+        if let Some(ref name) = stopped.name {
+            vars = vars.set("name", name.as_str());
+        }
+        if let Some(ref path) = stopped.path {
+            vars = vars.set("path", path.to_string_lossy());
+        }
+        if let Some(ref hostname) = stopped.hostname {
+            vars = vars.set("host.hostname", hostname.as_str());
+        }
+
+        vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_variables() {
+        let vars = Variables::new()
+            .set("name", "www")
+            .set("host.hostname", "$name.example.com")
+            .set("path", "/jails/${name}")
+            .set("loop", "${path}/$loop")
+            .set("a", "$b")
+            .set("b", "${a}");
+
+        assert_eq!(vars.expand("plain").unwrap(), "plain");
+        assert_eq!(vars.expand("$name").unwrap(), "www");
+        assert_eq!(vars.expand("${host.hostname}").unwrap(), "www.example.com");
+        assert_eq!(vars.expand("$path/etc").unwrap(), "/jails/www/etc");
+        assert_eq!(vars.expand("cost: 5$, $").unwrap(), "cost: 5$, $");
+
+        assert!(vars.expand("$domain").is_err());
+        assert!(vars.expand("${name").is_err());
+        assert!(vars.expand("${}").is_err());
+
+        match vars.expand("$a") {
+            Err(JailError::VariableError { message, .. }) => {
+                assert_eq!(message, "refers to itself via a -> b -> a")
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(vars.expand("$loop").is_err());
+    }
+}
@@ -0,0 +1,617 @@
+//! Integrity verification of jail root directories
+//!
+//! A [Manifest] records the type, owner, mode, file flags and SHA-256 digest
+//! of every file below a jail root, in the style of `mtree(5)`. Verifying the
+//! manifest later reports every file that was added, removed or modified, for
+//! example to detect tampering in a long-lived jail before granting it
+//! network access again.
+//!
+//! Only the file system containing the jail root is recorded: mount points
+//! such as `devfs` on `/dev` are skipped.
+//!
+//! # Examples
+//!
+//! ```
+//! use jail::verify::{Change, Manifest};
+//! # let root = std::env::temp_dir().join("jail_verify_module");
+//! # std::fs::create_dir_all(root.join("etc")).unwrap();
+//! # std::fs::write(root.join("etc/rc.conf"), "sshd_enable=\"YES\"\n").unwrap();
+//!
+//! let manifest = Manifest::new()
+//!     .record(&root)
+//!     .expect("could not record manifest");
+//!
+//! // Store the manifest, e.g. with `manifest.to_string()`, and later:
+//! std::fs::write(root.join("etc/rc.conf"), "sshd_enable=\"NO\"\n").unwrap();
+//! let changes = manifest.verify(&root).expect("could not verify manifest");
+//!
+//! assert_eq!(changes, vec![Change::Modified {
+//!     path: "/etc/rc.conf".into(),
+//!     keywords: vec!["size", "sha256digest"],
+//! }]);
+//! # std::fs::remove_dir_all(&root).unwrap();
+//! ```
+use crate::JailError;
+use log::trace;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+/// File flags as set by `chflags(1)`, with their `mtree(5)` names.
+const FLAGS: &[(u32, &str)] = &[
+    (0x0000_0001, "nodump"),
+    (0x0000_0002, "uchg"),
+    (0x0000_0004, "uappnd"),
+    (0x0000_0008, "opaque"),
+    (0x0000_0010, "uunlnk"),
+    (0x0000_0080, "system"),
+    (0x0000_0100, "sparse"),
+    (0x0000_0200, "offline"),
+    (0x0000_0400, "reparse"),
+    (0x0000_0800, "uarch"),
+    (0x0000_1000, "urdonly"),
+    (0x0000_8000, "hidden"),
+    (0x0001_0000, "arch"),
+    (0x0002_0000, "schg"),
+    (0x0004_0000, "sappnd"),
+    (0x0010_0000, "sunlnk"),
+    (0x0020_0000, "snapshot"),
+];
+
+/// The type of a file in a [Manifest].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Kind {
+    File,
+    Dir,
+    Link,
+    Char,
+    Block,
+    Fifo,
+    Socket,
+}
+
+/// The recorded attributes of a single file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub flags: u32,
+
+    /// The size of a regular file.
+    pub size: Option<u64>,
+
+    /// The target of a symbolic link.
+    pub link: Option<PathBuf>,
+
+    /// The hex encoded SHA-256 digest of a regular file.
+    pub sha256: Option<String>,
+}
+
+impl Entry {
+    fn from_metadata(host_path: &Path, metadata: &fs::Metadata) -> Result<Entry, JailError> {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_file() {
+            Kind::File
+        } else if file_type.is_dir() {
+            Kind::Dir
+        } else if file_type.is_symlink() {
+            Kind::Link
+        } else if file_type.is_char_device() {
+            Kind::Char
+        } else if file_type.is_block_device() {
+            Kind::Block
+        } else if file_type.is_fifo() {
+            Kind::Fifo
+        } else {
+            Kind::Socket
+        };
+
+        let (size, sha256) = match kind {
+            Kind::File => (Some(metadata.size()), Some(digest(host_path)?)),
+            _ => (None, None),
+        };
+
+        let link = match kind {
+            Kind::Link => Some(fs::read_link(host_path).map_err(JailError::IoError)?),
+            _ => None,
+        };
+
+        Ok(Entry {
+            kind,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: metadata.mode() & 0o7777,
+            flags: file_flags(metadata),
+            size,
+            link,
+            sha256,
+        })
+    }
+
+    /// The names of the keywords that differ between two entries.
+    fn differences(&self, other: &Entry) -> Vec<&'static str> {
+        let mut keywords = vec![];
+        if self.kind != other.kind {
+            keywords.push("type");
+        }
+        if self.uid != other.uid {
+            keywords.push("uid");
+        }
+        if self.gid != other.gid {
+            keywords.push("gid");
+        }
+        if self.mode != other.mode {
+            keywords.push("mode");
+        }
+        if self.flags != other.flags {
+            keywords.push("flags");
+        }
+        if self.size != other.size {
+            keywords.push("size");
+        }
+        if self.link != other.link {
+            keywords.push("link");
+        }
+        if self.sha256 != other.sha256 {
+            keywords.push("sha256digest");
+        }
+        keywords
+    }
+}
+
+/// A difference between a [Manifest] and the current state of a jail root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// A file that is not part of the manifest.
+    Added(PathBuf),
+
+    /// A file in the manifest that no longer exists.
+    Removed(PathBuf),
+
+    /// A file whose attributes differ from the manifest, together with the
+    /// names of the differing `mtree(5)` keywords.
+    Modified {
+        path: PathBuf,
+        keywords: Vec<&'static str>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(path) => write!(f, "added: {}", path.display()),
+            Change::Removed(path) => write!(f, "removed: {}", path.display()),
+            Change::Modified { path, keywords } => {
+                write!(f, "modified: {} ({})", path.display(), keywords.join(", "))
+            }
+        }
+    }
+}
+
+/// A manifest of the files below a jail root.
+///
+/// Paths are relative to the jail root, i.e. as seen from inside the jail.
+/// A manifest can be stored in its `mtree(5)`-style text form using
+/// [ToString] and loaded again using [FromStr].
+///
+/// # Examples
+///
+/// ```
+/// use jail::verify::Manifest;
+/// use std::path::Path;
+/// # let root = std::env::temp_dir().join("jail_verify_manifest");
+/// # std::fs::create_dir_all(root.join("etc")).unwrap();
+/// # std::fs::write(root.join("etc/rc.conf"), "").unwrap();
+///
+/// let manifest = Manifest::new()
+///     .record(&root)
+///     .expect("could not record manifest");
+/// assert!(manifest.entries().contains_key(Path::new("/etc/rc.conf")));
+///
+/// let text = manifest.to_string();
+/// let parsed: Manifest = text.parse().expect("could not parse manifest");
+/// assert_eq!(parsed.entries(), manifest.entries());
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: BTreeMap<PathBuf, Entry>,
+    excludes: Vec<PathBuf>,
+}
+
+impl Manifest {
+    /// Create an empty manifest.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exclude a path inside the jail, and everything below it, from recording
+    /// and verification.
+    ///
+    /// Exclusions are not part of the text form and need to be set again
+    /// after parsing a manifest.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jail::verify::Manifest;
+    /// use std::path::Path;
+    /// # let root = std::env::temp_dir().join("jail_verify_exclude");
+    /// # std::fs::create_dir_all(root.join("var/log")).unwrap();
+    /// # std::fs::write(root.join("var/log/messages"), "").unwrap();
+    ///
+    /// let manifest = Manifest::new()
+    ///     .exclude("/var/log")
+    ///     .record(&root)
+    ///     .expect("could not record manifest");
+    /// assert!(manifest.entries().contains_key(Path::new("/var")));
+    /// assert!(!manifest.entries().contains_key(Path::new("/var/log/messages")));
+    /// # std::fs::remove_dir_all(&root).unwrap();
+    /// ```
+    pub fn exclude<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.excludes.push(Path::new("/").join(path.into()));
+        self
+    }
+
+    /// Record all files below `root`, replacing any previous entries.
+    pub fn record<P: AsRef<Path>>(mut self, root: P) -> Result<Self, JailError> {
+        trace!("Manifest::record({:?}, root={:?})", self, root.as_ref());
+        self.entries = self.scan(root.as_ref())?;
+        Ok(self)
+    }
+
+    /// Get the recorded entries, keyed by their path inside the jail.
+    pub fn entries(&self) -> &BTreeMap<PathBuf, Entry> {
+        &self.entries
+    }
+
+    /// Compare the manifest with the files currently below `root`.
+    ///
+    /// Returns the list of changes, ordered by path. An empty list means that
+    /// the jail root matches the manifest.
+    pub fn verify<P: AsRef<Path>>(&self, root: P) -> Result<Vec<Change>, JailError> {
+        trace!("Manifest::verify({:?}, root={:?})", self, root.as_ref());
+        let current = self.scan(root.as_ref())?;
+        let mut changes = vec![];
+
+        for (path, expected) in self.entries.iter().filter(|(p, _)| !self.excluded(p)) {
+            match current.get(path) {
+                None => changes.push(Change::Removed(path.clone())),
+                Some(actual) => {
+                    let keywords = expected.differences(actual);
+                    if !keywords.is_empty() {
+                        changes.push(Change::Modified {
+                            path: path.clone(),
+                            keywords,
+                        });
+                    }
+                }
+            }
+        }
+
+        changes.extend(
+            current
+                .keys()
+                .filter(|path| !self.entries.contains_key(*path))
+                .map(|path| Change::Added(path.clone())),
+        );
+
+        changes.sort_by(|a, b| change_path(a).cmp(change_path(b)));
+        Ok(changes)
+    }
+
+    fn excluded(&self, path: &Path) -> bool {
+        self.excludes.iter().any(|e| path.starts_with(e))
+    }
+
+    fn scan(&self, root: &Path) -> Result<BTreeMap<PathBuf, Entry>, JailError> {
+        let device = fs::symlink_metadata(root)
+            .map_err(JailError::IoError)?
+            .dev();
+        let mut entries = BTreeMap::new();
+        let mut pending = vec![PathBuf::from("/")];
+
+        while let Some(path) = pending.pop() {
+            let host_path = root.join(path.strip_prefix("/").unwrap_or(&path));
+            let metadata = match fs::symlink_metadata(&host_path) {
+                Ok(metadata) => metadata,
+                // The file was removed while scanning.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(JailError::IoError(e)),
+            };
+
+            // Do not cross into other file systems.
+            if metadata.dev() != device {
+                continue;
+            }
+
+            if metadata.is_dir() {
+                for entry in fs::read_dir(&host_path).map_err(JailError::IoError)? {
+                    let child = path.join(entry.map_err(JailError::IoError)?.file_name());
+                    if !self.excluded(&child) {
+                        pending.push(child);
+                    }
+                }
+            }
+
+            entries.insert(path, Entry::from_metadata(&host_path, &metadata)?);
+        }
+
+        Ok(entries)
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "#mtree 2.0")?;
+        for (path, entry) in &self.entries {
+            write!(
+                f,
+                "{} type={} uid={} gid={} mode={:04o} flags={}",
+                escape(
+                    Path::new(".")
+                        .join(path.strip_prefix("/").unwrap_or(path))
+                        .as_os_str()
+                ),
+                entry.kind,
+                entry.uid,
+                entry.gid,
+                entry.mode,
+                flags_to_string(entry.flags),
+            )?;
+            if let Some(size) = entry.size {
+                write!(f, " size={}", size)?;
+            }
+            if let Some(link) = &entry.link {
+                write!(f, " link={}", escape(link.as_os_str()))?;
+            }
+            if let Some(sha256) = &entry.sha256 {
+                write!(f, " sha256digest={}", sha256)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = JailError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = BTreeMap::new();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| JailError::ManifestParseError {
+                line: number + 1,
+                message,
+            };
+
+            let mut words = line.split_whitespace();
+            let name = unescape(words.next().unwrap_or_default()).map_err(error)?;
+            let path = match Path::new(&name).strip_prefix(".") {
+                Ok(relative) => Path::new("/").join(relative),
+                Err(_) => return Err(error(format!("path {:?} does not start with '.'", name))),
+            };
+
+            let mut keywords = BTreeMap::new();
+            for word in words {
+                match word.split_once('=') {
+                    Some((keyword, value)) => keywords.insert(keyword, value),
+                    None => return Err(error(format!("invalid keyword '{}'", word))),
+                };
+            }
+
+            let mut required = |keyword: &str| {
+                keywords
+                    .remove(keyword)
+                    .ok_or_else(|| error(format!("missing keyword '{}'", keyword)))
+            };
+            let kind = required("type")?;
+            let uid = required("uid")?;
+            let gid = required("gid")?;
+            let mode = required("mode")?;
+            let flags = required("flags")?;
+            let number = |keyword: &str, value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| error(format!("invalid {} '{}'", keyword, value)))
+            };
+
+            let entry = Entry {
+                kind: kind
+                    .parse()
+                    .map_err(|_| error(format!("invalid type '{}'", kind)))?,
+                uid: number("uid", uid)?,
+                gid: number("gid", gid)?,
+                mode: u32::from_str_radix(mode, 8)
+                    .map_err(|_| error(format!("invalid mode '{}'", mode)))?,
+                flags: flags_from_str(flags).map_err(error)?,
+                size: keywords
+                    .remove("size")
+                    .map(|size| {
+                        size.parse()
+                            .map_err(|_| error(format!("invalid size '{}'", size)))
+                    })
+                    .transpose()?,
+                link: keywords
+                    .remove("link")
+                    .map(|link| unescape(link).map(PathBuf::from).map_err(error))
+                    .transpose()?,
+                sha256: keywords.remove("sha256digest").map(str::to_string),
+            };
+
+            if let Some(keyword) = keywords.keys().next() {
+                return Err(error(format!("unsupported keyword '{}'", keyword)));
+            }
+
+            entries.insert(path, entry);
+        }
+
+        Ok(Manifest {
+            entries,
+            excludes: vec![],
+        })
+    }
+}
+
+fn change_path(change: &Change) -> &Path {
+    match change {
+        Change::Added(path) | Change::Removed(path) => path,
+        Change::Modified { path, .. } => path,
+    }
+}
+
+/// Compute the hex encoded SHA-256 digest of a file without following
+/// symbolic links.
+fn digest(path: &Path) -> Result<String, JailError> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .map_err(JailError::IoError)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(JailError::IoError)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(target_os = "freebsd")]
+fn file_flags(metadata: &fs::Metadata) -> u32 {
+    use std::os::freebsd::fs::MetadataExt;
+    metadata.st_flags()
+}
+
+#[cfg(not(target_os = "freebsd"))]
+fn file_flags(_metadata: &fs::Metadata) -> u32 {
+    0
+}
+
+fn flags_to_string(flags: u32) -> String {
+    let names: Vec<&str> = FLAGS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    match names.is_empty() {
+        true => "none".into(),
+        false => names.join(","),
+    }
+}
+
+fn flags_from_str(s: &str) -> Result<u32, String> {
+    if s == "none" {
+        return Ok(0);
+    }
+
+    s.split(',').try_fold(0, |flags, name| {
+        FLAGS
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(flag, _)| flags | flag)
+            .ok_or_else(|| format!("unknown file flag '{}'", name))
+    })
+}
+
+/// Escape whitespace, `#`, `\` and non-printable characters in a file name
+/// as octal sequences, like `vis(3)`.
+fn escape(name: &OsStr) -> String {
+    name.as_bytes()
+        .iter()
+        .map(|&b| match b {
+            b'!'..=b'~' if b != b'\\' && b != b'#' => (b as char).to_string(),
+            _ => format!("\\{:03o}", b),
+        })
+        .collect()
+}
+
+fn unescape(s: &str) -> Result<std::ffi::OsString, String> {
+    let bytes = s.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            let octal = s
+                .get(i + 1..i + 4)
+                .and_then(|o| u8::from_str_radix(o, 8).ok())
+                .ok_or_else(|| format!("invalid escape sequence in {:?}", s))?;
+            unescaped.push(octal);
+            i += 4;
+        } else {
+            unescaped.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Ok(OsStr::from_bytes(&unescaped).to_os_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_changes() {
+        let root = std::env::temp_dir().join(format!("jail_verify_{}", std::process::id()));
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::create_dir_all(root.join("var/log")).unwrap();
+        fs::write(root.join("etc/rc.conf"), "sshd_enable=YES\n").unwrap();
+        fs::write(root.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+        fs::write(root.join("etc/with space#"), "").unwrap();
+        std::os::unix::fs::symlink("/etc/rc.conf", root.join("rc.conf")).unwrap();
+
+        let manifest = Manifest::new().exclude("/var/log").record(&root).unwrap();
+        let manifest: Manifest = manifest.to_string().parse().unwrap();
+        let manifest = manifest.exclude("/var/log");
+        assert!(manifest.verify(&root).unwrap().is_empty());
+
+        fs::write(root.join("etc/rc.conf"), "sshd_enable=yes\n").unwrap();
+        fs::remove_file(root.join("etc/hosts")).unwrap();
+        fs::write(root.join("etc/backdoor"), "").unwrap();
+        fs::write(root.join("var/log/messages"), "ignored\n").unwrap();
+
+        assert_eq!(
+            manifest.verify(&root).unwrap(),
+            vec![
+                Change::Added("/etc/backdoor".into()),
+                Change::Removed("/etc/hosts".into()),
+                Change::Modified {
+                    path: "/etc/rc.conf".into(),
+                    keywords: vec!["sha256digest"],
+                },
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("./etc type=dir".parse::<Manifest>().is_err());
+        assert!("etc type=dir uid=0 gid=0 mode=0755 flags=none"
+            .parse::<Manifest>()
+            .is_err());
+        assert!("./etc type=dir uid=0 gid=0 mode=0755 flags=bogus"
+            .parse::<Manifest>()
+            .is_err());
+        assert!("./etc type=dir uid=0 gid=0 mode=0755 flags=schg,uarch"
+            .parse::<Manifest>()
+            .is_ok());
+    }
+}
@@ -0,0 +1,118 @@
+//! Point-in-time snapshots of running jails
+//!
+//! A [JailView] holds copies of the state of a jail, so it can be shared
+//! between threads and rendered any number of times without further
+//! syscalls, e.g. by a web interface caching the jails of a host.
+use crate::{param, sys, JailError, RunningJail};
+use log::trace;
+use std::collections::BTreeMap;
+use std::net;
+use std::path;
+use std::time::{Duration, SystemTime};
+
+/// The state of a running jail at a point in time, see [RunningJail::view].
+///
+/// The view is not updated when the jail changes. Since the kernel may give
+/// the `jid` of a removed jail to a new one, use [JailView::jail] rather
+/// than the `jid` to act on the jail later.
+///
+/// # Examples
+///
+/// ```
+/// # use jail::StoppedJail;
+/// # let running = StoppedJail::new("/rescue")
+/// #     .name("testjail_view")
+/// #     .start()
+/// #     .expect("could not start jail");
+/// let view = running.view().expect("could not read jail");
+/// assert_eq!(view.name, "testjail_view");
+///
+/// let shared = std::sync::Arc::new(view);
+/// let name = std::thread::spawn({
+///     let shared = shared.clone();
+///     move || shared.name.clone()
+/// });
+/// assert_eq!(name.join().unwrap(), "testjail_view");
+///
+/// assert_eq!(shared.jail(), Some(running));
+/// running.kill().expect("could not stop jail");
+/// assert_eq!(shared.jail(), None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct JailView {
+    /// The `jid` of the jail when the view was taken
+    pub jid: i32,
+
+    /// The name of the jail
+    pub name: String,
+
+    /// The path of the root file system of the jail
+    pub path: path::PathBuf,
+
+    /// The hostname of the jail
+    pub hostname: String,
+
+    /// The IP addresses of the jail
+    pub ips: Vec<net::IpAddr>,
+
+    /// Whether the jail was removed, but still holding resources
+    pub dying: bool,
+
+    /// The number of processes in the jail, not counting child jails
+    pub processes: usize,
+
+    /// All parameters of the jail
+    pub params: BTreeMap<String, param::Value>,
+
+    /// The metadata of the jail, see [RunningJail::meta]
+    pub meta: BTreeMap<String, String>,
+
+    /// When the view was taken
+    pub taken_at: SystemTime,
+}
+
+impl JailView {
+    /// Read the state of a running jail.
+    pub(crate) fn of(jail: &RunningJail) -> Result<Self, JailError> {
+        let params: BTreeMap<String, param::Value> =
+            param::get_all(jail.jid)?.into_iter().collect();
+
+        Ok(JailView {
+            jid: jail.jid,
+            name: jail.name()?,
+            path: jail.path()?,
+            hostname: jail.hostname()?,
+            ips: jail.ips()?,
+            dying: matches!(params.get("dying"), Some(param::Value::Int(dying)) if *dying != 0),
+            processes: sys::jail_pids(jail.jid)?.len(),
+            meta: jail.meta()?,
+            params,
+            taken_at: SystemTime::now(),
+        })
+    }
+
+    /// How long ago the view was taken.
+    pub fn age(&self) -> Duration {
+        trace!("JailView::age({:?})", self);
+        self.taken_at.elapsed().unwrap_or_default()
+    }
+
+    /// Get the jail the view was taken of, if it still runs.
+    ///
+    /// Jails have no identity other than their `jid`, so a jail with the
+    /// same `jid`, name and path is taken to be the same jail.
+    pub fn jail(&self) -> Option<RunningJail> {
+        trace!("JailView::jail({:?})", self);
+        let jail = RunningJail::from_jid(self.jid)?;
+        let same = jail.name().is_ok_and(|name| name == self.name)
+            && jail.path().is_ok_and(|path| path == self.path);
+        same.then_some(jail)
+    }
+}
+
+// Views are meant to be shared between threads.
+const _: fn() = || {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<JailView>();
+};
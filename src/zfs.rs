@@ -0,0 +1,175 @@
+//! Minimal wrapper around `zfs(8)` for the datasets backing jail roots
+use crate::JailError;
+use log::trace;
+use std::io::Read;
+use std::path::Path;
+#[cfg(feature = "interop")]
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A ZFS dataset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Dataset {
+    name: String,
+}
+
+impl Dataset {
+    /// Get the dataset mounted at `path`.
+    ///
+    /// Fails if `path` is not the mountpoint of a dataset, since setting
+    /// properties on a parent dataset would affect more than the jail.
+    pub(crate) fn at(path: &Path) -> Result<Dataset, JailError> {
+        trace!("Dataset::at(path={:?})", path);
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| JailError::NotSupported(format!("non-UTF-8 path {:?}", path)))?;
+        let output = zfs(&["list", "-H", "-o", "name,mountpoint", path_str])?;
+
+        let mut fields = output.trim_end().splitn(2, '\t');
+        match (fields.next(), fields.next()) {
+            (Some(name), Some(mountpoint)) if Path::new(mountpoint) == path => Ok(Dataset {
+                name: name.to_string(),
+            }),
+            _ => Err(JailError::NotSupported(format!(
+                "{:?} is not the mountpoint of a ZFS dataset",
+                path
+            ))),
+        }
+    }
+
+    /// Get the dataset with the given name.
+    #[cfg(feature = "interop")]
+    pub(crate) fn named<S: Into<String>>(name: S) -> Dataset {
+        Dataset { name: name.into() }
+    }
+
+    /// Get the name of the dataset.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the mountpoint of the dataset.
+    #[cfg(feature = "interop")]
+    pub(crate) fn mountpoint(&self) -> Result<PathBuf, JailError> {
+        trace!("Dataset::mountpoint({:?})", self);
+        let output = zfs(&["get", "-H", "-o", "value", "mountpoint", &self.name])?;
+        match output.trim_end() {
+            mountpoint if mountpoint.starts_with('/') => Ok(PathBuf::from(mountpoint)),
+            _ => Err(JailError::ZfsError(format!(
+                "dataset {} is not mounted",
+                self.name
+            ))),
+        }
+    }
+
+    /// Set the `quota` property, or remove it if `bytes` is `None`.
+    pub(crate) fn set_quota(&self, bytes: Option<u64>) -> Result<(), JailError> {
+        trace!("Dataset::set_quota({:?}, bytes={:?})", self, bytes);
+        let quota = match bytes {
+            Some(bytes) => format!("quota={}", bytes),
+            None => "quota=none".into(),
+        };
+        zfs(&["set", &quota, &self.name]).map(|_| ())
+    }
+
+    /// Get the space used by the dataset and its quota, if any.
+    pub(crate) fn usage(&self) -> Result<(u64, Option<u64>), JailError> {
+        trace!("Dataset::usage({:?})", self);
+        let output = zfs(&["get", "-H", "-p", "-o", "value", "used,quota", &self.name])?;
+        let values: Vec<u64> = output
+            .lines()
+            .map(|line| line.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| JailError::ZfsError(format!("unexpected output: {:?}", output)))?;
+
+        match values[..] {
+            [used, 0] => Ok((used, None)),
+            [used, quota] => Ok((used, Some(quota))),
+            _ => Err(JailError::ZfsError(format!(
+                "unexpected output: {:?}",
+                output
+            ))),
+        }
+    }
+
+    /// Create the snapshot `<dataset>@<snapshot>`.
+    pub(crate) fn snapshot(&self, snapshot: &str) -> Result<(), JailError> {
+        trace!("Dataset::snapshot({:?}, snapshot={:?})", self, snapshot);
+        zfs(&["snapshot", &format!("{}@{}", self.name, snapshot)]).map(|_| ())
+    }
+
+    /// Destroy the snapshot `<dataset>@<snapshot>`.
+    pub(crate) fn destroy_snapshot(&self, snapshot: &str) -> Result<(), JailError> {
+        trace!(
+            "Dataset::destroy_snapshot({:?}, snapshot={:?})",
+            self,
+            snapshot
+        );
+        zfs(&["destroy", &format!("{}@{}", self.name, snapshot)]).map(|_| ())
+    }
+
+    /// Pass the `zfs send` stream of a snapshot, incremental from the
+    /// snapshot `base` if given, to `receive`.
+    pub(crate) fn send<F>(
+        &self,
+        snapshot: &str,
+        base: Option<&str>,
+        receive: F,
+    ) -> Result<(), JailError>
+    where
+        F: FnOnce(&mut dyn Read) -> Result<(), JailError>,
+    {
+        trace!(
+            "Dataset::send({:?}, snapshot={:?}, base={:?})",
+            self,
+            snapshot,
+            base
+        );
+        let mut command = Command::new("zfs");
+        command.arg("send");
+        if let Some(base) = base {
+            command.arg("-i").arg(format!("@{}", base));
+        }
+
+        let mut child = command
+            .arg(format!("{}@{}", self.name, snapshot))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(JailError::IoError)?;
+
+        let mut stream = child.stdout.take().expect("stdout is piped");
+        if let Err(e) = receive(&mut stream) {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(e);
+        }
+        drop(stream);
+
+        let output = child.wait_with_output().map_err(JailError::IoError)?;
+        if !output.status.success() {
+            return Err(JailError::ZfsError(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `zfs` with the given arguments, returning its standard output.
+fn zfs(args: &[&str]) -> Result<String, JailError> {
+    trace!("zfs(args={:?})", args);
+    let output = Command::new("zfs")
+        .args(args)
+        .output()
+        .map_err(JailError::IoError)?;
+
+    if !output.status.success() {
+        return Err(JailError::ZfsError(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}